@@ -0,0 +1,284 @@
+//! Loop extraction cost on manhattanOlson3500.g2o, streamed through a sliding
+//! window the way a robot would build it, up to a window holding the whole
+//! dataset. The first line of each window replays the search the graph ran
+//! before the adjacency index (see `baseline`); `DepthFirst` is a depth-first
+//! scan of every factor that keeps dead ends marked, and the other strategies
+//! use the index. The last line of each window times a minimum cycle basis
+//! audit of it.
+//!
+//! Run with `cargo bench --bench cycle_search`.
+
+use algorithms_in_practice::algorithms::graphs::{
+    load_g2o_se2, CircularFactorGraph, CycleSearch, Factor, NodeType,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Time allowed for the baseline on one window before it is cut short
+const BASELINE_BUDGET: Duration = Duration::from_secs(10);
+
+/// Cycle extraction as `CircularFactorGraph::add_factor` did it before the
+/// adjacency index: a linear `nodes.contains`, then a depth-first `find_path`
+/// that scans every factor at each step and unmarks nodes on backtracking.
+/// `find_cycle_factors` and `find_path` are copied verbatim, apart from a
+/// deadline check so that one exponential search cannot stall the benchmark.
+/// Evicted nodes stay in the union-find, since removing them left their
+/// children pointing at missing parents and the next lookup panicked.
+mod baseline {
+    use super::*;
+
+    pub struct Graph {
+        nodes: VecDeque<NodeType>,
+        factors: VecDeque<Factor>,
+        max_nodes: usize,
+        max_factors: usize,
+        parent: HashMap<NodeType, NodeType>,
+        rank: HashMap<NodeType, usize>,
+        deadline: Instant,
+    }
+
+    impl Graph {
+        pub fn new(max_nodes: usize, max_factors: usize, deadline: Instant) -> Self {
+            Self {
+                nodes: VecDeque::new(),
+                factors: VecDeque::new(),
+                max_nodes,
+                max_factors,
+                parent: HashMap::new(),
+                rank: HashMap::new(),
+                deadline,
+            }
+        }
+
+        pub fn expired(&self) -> bool {
+            Instant::now() > self.deadline
+        }
+
+        pub fn add_node(&mut self, node: NodeType) {
+            if self.nodes.len() >= self.max_nodes {
+                if let Some(old_node) = self.nodes.pop_front() {
+                    self.factors
+                        .retain(|factor| factor.source != old_node && factor.target != old_node);
+                }
+            }
+            self.nodes.push_back(node.clone());
+            self.parent.insert(node.clone(), node.clone());
+            self.rank.insert(node, 0);
+        }
+
+        pub fn add_factor(&mut self, factor: Factor) -> Option<Vec<Factor>> {
+            if !self.nodes.contains(&factor.source) || !self.nodes.contains(&factor.target) {
+                return None;
+            }
+            let cycle = self.would_create_cycle(&factor);
+            if self.factors.len() >= self.max_factors {
+                self.factors.pop_front();
+            }
+            self.factors.push_back(factor);
+            cycle
+        }
+
+        fn find(&mut self, node: &NodeType) -> NodeType {
+            let parent_node = self.parent[node].clone();
+            if parent_node == *node {
+                return node.clone();
+            }
+            let root = self.find(&parent_node);
+            self.parent.insert(node.clone(), root.clone());
+            root
+        }
+
+        fn union(&mut self, node1: &NodeType, node2: &NodeType) {
+            let root1 = self.find(node1);
+            let root2 = self.find(node2);
+            if root1 != root2 {
+                let rank1 = self.rank[&root1];
+                let rank2 = self.rank[&root2];
+                if rank1 < rank2 {
+                    self.parent.insert(root1, root2);
+                } else if rank1 > rank2 {
+                    self.parent.insert(root2, root1);
+                } else {
+                    self.parent.insert(root2, root1.clone());
+                    self.rank.insert(root1, rank1 + 1);
+                }
+            }
+        }
+
+        fn would_create_cycle(&mut self, new_factor: &Factor) -> Option<Vec<Factor>> {
+            let root1 = self.find(&new_factor.source);
+            let root2 = self.find(&new_factor.target);
+            if root1 == root2 {
+                if let Some(cycle_factors) =
+                    self.find_cycle_factors(&new_factor.source, &new_factor.target)
+                {
+                    return Some(cycle_factors);
+                }
+            }
+            self.union(&new_factor.source, &new_factor.target);
+            None
+        }
+
+        fn find_cycle_factors(&self, start: &NodeType, end: &NodeType) -> Option<Vec<Factor>> {
+            let mut visited = HashSet::new();
+            let mut path = Vec::new();
+
+            if self.find_path(start, end, &mut visited, &mut path) {
+                return Some(path);
+            }
+            None
+        }
+
+        /// DFS to find path between nodes
+        fn find_path(
+            &self,
+            current: &NodeType,
+            target: &NodeType,
+            visited: &mut HashSet<NodeType>,
+            path: &mut Vec<Factor>
+        ) -> bool {
+            if current == target && !path.is_empty() {
+                return true;
+            }
+            if self.expired() {
+                return false;
+            }
+
+            visited.insert(current.clone());
+
+            for factor in &self.factors {
+                if factor.source == *current && !visited.contains(&factor.target) {
+                    path.push(factor.clone());
+                    if self.find_path(&factor.target, target, visited, path) {
+                        return true;
+                    }
+                    path.pop();
+                } else if factor.target == *current && !visited.contains(&factor.source) {
+                    path.push(factor.clone());
+                    if self.find_path(&factor.source, target, visited, path) {
+                        return true;
+                    }
+                    path.pop();
+                }
+            }
+
+            visited.remove(current);
+            false
+        }
+    }
+}
+
+/// Factors ordered by the later of their two poses, so each one arrives right
+/// after the node that completes it
+fn streamed_factors(factors: &[Factor]) -> Vec<(u64, Factor)> {
+    let mut stream: Vec<(u64, Factor)> = factors
+        .iter()
+        .filter_map(|factor| match (&factor.source, &factor.target) {
+            (NodeType::RobotPose(a), NodeType::RobotPose(b)) => {
+                Some(((*a).max(*b), factor.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    stream.sort_by_key(|(last, _)| *last);
+    stream
+}
+
+fn run(
+    stream: &[(u64, Factor)],
+    poses: u64,
+    window: usize,
+    search: CycleSearch,
+) -> (Duration, usize, CircularFactorGraph) {
+    let mut graph = CircularFactorGraph::new(window, 4 * window);
+    graph.set_cycle_search(search);
+    let mut pending = stream.iter().peekable();
+    let mut loops = 0;
+    let start = Instant::now();
+    for t in 0..poses {
+        graph.add_node(NodeType::RobotPose(t));
+        while let Some((_, factor)) = pending.next_if(|(last, _)| *last == t) {
+            if graph.add_factor(factor.clone()).is_some() {
+                loops += 1;
+            }
+        }
+    }
+    (start.elapsed(), loops, graph)
+}
+
+/// Replay `stream` through the baseline, giving up once `BASELINE_BUDGET` is
+/// spent. Returns the time taken, the loops found and the factors processed.
+fn run_baseline(stream: &[(u64, Factor)], poses: u64, window: usize) -> (Duration, usize, usize) {
+    let start = Instant::now();
+    let mut graph = baseline::Graph::new(window, 4 * window, start + BASELINE_BUDGET);
+    let mut pending = stream.iter().peekable();
+    let (mut loops, mut processed) = (0, 0);
+    for t in 0..poses {
+        if graph.expired() {
+            break;
+        }
+        graph.add_node(NodeType::RobotPose(t));
+        while let Some((_, factor)) = pending.next_if(|(last, _)| *last == t) {
+            processed += 1;
+            if graph.add_factor(factor.clone()).is_some() {
+                loops += 1;
+            }
+        }
+    }
+    (start.elapsed(), loops, processed)
+}
+
+fn main() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/g2o/manhattanOlson3500.g2o");
+    let g2o = load_g2o_se2(path).expect("failed to load manhattanOlson3500.g2o");
+    let stream = streamed_factors(&g2o.factors);
+    let poses = g2o.vertices.len() as u64;
+    println!("{}: {} poses, {} factors", path, poses, stream.len());
+
+    let strategies = [
+        ("depth-first", CycleSearch::DepthFirst),
+        ("fewest factors", CycleSearch::FewestFactors),
+        ("least uncertain", CycleSearch::LeastUncertain),
+    ];
+    for window in [100, 250, 500, g2o.vertices.len()] {
+        println!("\nwindow of {} poses", window);
+        let (elapsed, loops, processed) = run_baseline(&stream, poses, window);
+        println!(
+            "  {:<24} {:>9.1} ms  {:>5} loops  {:>7.1} us/factor{}",
+            "baseline (unindexed)",
+            elapsed.as_secs_f64() * 1000.0,
+            loops,
+            elapsed.as_secs_f64() * 1e6 / processed.max(1) as f64,
+            if processed < stream.len() {
+                format!("  (stopped after {} of {} factors)", processed, stream.len())
+            } else {
+                String::new()
+            },
+        );
+        let mut last_window = None;
+        for (name, search) in strategies {
+            let (elapsed, loops, graph) = run(&stream, poses, window, search);
+            println!(
+                "  {:<24} {:>9.1} ms  {:>5} loops  {:>7.1} us/factor",
+                name,
+                elapsed.as_secs_f64() * 1000.0,
+                loops,
+                elapsed.as_secs_f64() * 1e6 / stream.len() as f64,
+            );
+            last_window = Some(graph);
+        }
+
+        // Batch audit of every independent loop left in the final window
+        let graph = last_window.unwrap();
+        let start = Instant::now();
+        let audit = graph.audit_cycle_basis();
+        let elapsed = start.elapsed();
+        println!(
+            "  {:<24} {:>9.1} ms  {:>5} loops  {:>5} inconsistent",
+            "minimum cycle basis",
+            elapsed.as_secs_f64() * 1000.0,
+            audit.len(),
+            audit.iter().filter(|(_, test)| !test.is_consistent).count(),
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use algorithms_in_practice::algorithms::graphs::{BundleAdjuster, BundleAdjustmentConfig};
+use algorithms_in_practice::common::bal_io::load_bal;
+use algorithms_in_practice::common::robust_kernel::HuberKernel;
+use std::time::Instant;
+
+const PROBLEM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/ba/problem-49-7776-pre.txt");
+
+fn main() {
+    let mut problem = match load_bal(PROBLEM) {
+        Ok(problem) => problem,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", PROBLEM, err);
+            return;
+        }
+    };
+    println!(
+        "{} cameras, {} points, {} observations",
+        problem.cameras.len(),
+        problem.points.len(),
+        problem.observations.len()
+    );
+
+    // Huber on the squared pixel error, switching to linear beyond sqrt(5) pixels
+    let config = BundleAdjustmentConfig::default().with_kernel(HuberKernel::new(5f64.sqrt()));
+    let start = Instant::now();
+    let report = BundleAdjuster::new(config).solve(&mut problem);
+
+    for summary in &report.optimization.iterations {
+        println!(
+            "  iteration {:2}: cost {:.4e}, step {:.3e}, lambda {:.1e}{}",
+            summary.iteration,
+            summary.cost,
+            summary.step_norm,
+            summary.lambda,
+            if summary.accepted { "" } else { " (rejected)" }
+        );
+    }
+    println!(
+        "{:?} in {:.2} s: cost {:.4e} -> {:.4e}, reprojection rms {:.3} -> {:.3} px",
+        report.optimization.status,
+        start.elapsed().as_secs_f64(),
+        report.optimization.initial_cost,
+        report.optimization.final_cost,
+        report.initial_rms,
+        report.final_rms
+    );
+}
@@ -34,7 +34,7 @@ fn main() {
             
             println!("Task sequence:");
             for (i, &task_id) in sequence.iter().enumerate() {
-                let task = &planner.tasks[task_id];
+                let task = &planner.tasks()[task_id];
                 println!(
                     "{}. Task {} (Energy: {:.1}, Time: {}, Priority: {})",
                     i + 1,
@@ -1,10 +1,10 @@
-use algorithms_in_practice::algorithms::graphs::factor_graph::{
-    CircularFactorGraph, Factor, NodeType, Transform2D,
+use algorithms_in_practice::algorithms::graphs::{
+    CircularFactorGraph, Factor, NodeType, OptimizerConfig, PoseGraphOptimizer, Transform2D,
 };
 
 fn main() {
     // Create a factor graph with limited size
-    let mut graph = CircularFactorGraph::new(max_nodes: 10, max_factors: 15);
+    let mut graph = CircularFactorGraph::new(10, 15);
 
     println!("Simulating robot movement and landmark detection...\n");
 
@@ -19,17 +19,17 @@ fn main() {
     println!("Added landmark L1");
 
     // Add factor between robot and landmark
-    let factor1 = Factor {
-        source: pose1.clone(),
-        target: landmark1.clone(),
-        transform: Transform2D::new(2.0, 1.0, 0.1),
-        uncertainty: 0.1,
-        sensor_type: "LIDAR".to_string(),
-        timestamp: 1000,
-    };
+    let factor1 = Factor::new(
+        pose1.clone(),
+        landmark1.clone(),
+        Transform2D::new(2.0, 1.0, 0.1),
+        "LIDAR",
+        1000,
+    )
+    .with_isotropic_std(0.1);
     
     println!("\nAdding factor between pose1 and landmark1...");
-    if let Some(cycle) = graph.add_factor(factor1) {
+    if graph.add_factor(factor1).is_some() {
         println!("Found cycle! (unexpected at this point)");
     } else {
         println!("Factor added successfully");
@@ -41,31 +41,31 @@ fn main() {
     println!("\nAdded second robot pose");
 
     // Add odometry factor
-    let factor2 = Factor {
-        source: pose1,
-        target: pose2.clone(),
-        transform: Transform2D::new(1.0, 0.5, 0.2),
-        uncertainty: 0.05,
-        sensor_type: "ODOMETRY".to_string(),
-        timestamp: 2000,
-    };
+    let factor2 = Factor::new(
+        pose1,
+        pose2.clone(),
+        Transform2D::new(1.0, 0.5, 0.2),
+        "ODOMETRY",
+        2000,
+    )
+    .with_isotropic_std(0.05);
     
     println!("Adding odometry factor...");
-    if let Some(cycle) = graph.add_factor(factor2) {
+    if graph.add_factor(factor2).is_some() {
         println!("Found cycle! (unexpected at this point)");
     } else {
         println!("Factor added successfully");
     }
 
     // Add factor from second pose to landmark (creating a cycle)
-    let factor3 = Factor {
-        source: pose2,
-        target: landmark1,
-        transform: Transform2D::new(1.0, 0.5, -0.1),
-        uncertainty: 0.1,
-        sensor_type: "LIDAR".to_string(),
-        timestamp: 2000,
-    };
+    let factor3 = Factor::new(
+        pose2,
+        landmark1,
+        Transform2D::new(1.0, 0.5, -0.1),
+        "LIDAR",
+        2000,
+    )
+    .with_isotropic_std(0.1);
     
     println!("\nAdding factor that should create a cycle...");
     if let Some(cycle) = graph.add_factor(factor3) {
@@ -77,6 +77,20 @@ fn main() {
         println!("No cycle detected (unexpected!)");
     }
 
+    println!("\nOptimizing poses with Levenberg-Marquardt...");
+    let optimizer = PoseGraphOptimizer::new(OptimizerConfig::default());
+    let result = optimizer.optimize(&graph);
+    for summary in &result.report.iterations {
+        println!(
+            "  iter {}: cost {:.6} (lambda {:.1e})",
+            summary.iteration, summary.cost, summary.lambda
+        );
+    }
+    println!("Status: {:?}", result.report.status);
+    for (node, pose) in &result.poses {
+        println!("  {:?}: ({:.3}, {:.3}, {:.3})", node, pose.x, pose.y, pose.theta);
+    }
+
     println!("\nFinal graph state:");
     println!("Nodes: {}", graph.node_count());
     println!("Factors: {}", graph.factor_count());
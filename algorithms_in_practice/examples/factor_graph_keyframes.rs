@@ -0,0 +1,170 @@
+use algorithms_in_practice::algorithms::graphs::{
+    Attachment, CircularFactorGraph, Factor, KeyframeGraph, KeyframePolicy, LandmarkFactor,
+    LandmarkMeasurement, NodeType, OptimizerConfig, PoseGraphOptimizer, Transform2D,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ODOMETRY_PERIOD: u64 = 10;  // 100Hz wheel odometry, times in ms
+const LIDAR_PERIOD: u64 = 83;     // ~12Hz scan matcher, out of step with the odometry
+const LIDAR_LATENCY: u64 = 40;
+const CAMERA_PERIOD: u64 = 140;   // ~7Hz landmark detector
+const CAMERA_LATENCY: u64 = 65;
+
+const LANDMARKS: [(f64, f64); 3] = [(2.0, 3.0), (-1.0, 4.0), (1.0, -1.5)];
+
+/// True pose at `time`: a slow anticlockwise circle
+fn true_pose(time: u64) -> Transform2D {
+    let t = time as f64 / 1000.0;
+    let heading = 0.3 * t;
+    Transform2D::new(2.0 * heading.sin(), 2.0 - 2.0 * heading.cos(), heading)
+}
+
+fn between(a: u64, b: u64) -> Transform2D {
+    true_pose(a).inverse().compose(&true_pose(b))
+}
+
+fn noisy(rng: &mut StdRng, t: &Transform2D, sigma: (f64, f64)) -> Transform2D {
+    Transform2D::new(
+        t.x + rng.gen_range(-sigma.0..sigma.0),
+        t.y + rng.gen_range(-sigma.0..sigma.0),
+        t.theta + rng.gen_range(-sigma.1..sigma.1),
+    )
+}
+
+enum Event {
+    Lidar(Factor),
+    Camera(LandmarkFactor),
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let policy = KeyframePolicy { max_distance: 0.4, max_rotation: 0.3, max_interval: 1500 };
+    let mut graph = KeyframeGraph::new(CircularFactorGraph::new(60, 400), policy);
+    println!("Fusing 100Hz odometry, ~12Hz LIDAR and ~7Hz camera sightings by timestamp");
+    println!(
+        "- LIDAR results arrive {}ms late, camera results {}ms late\n",
+        LIDAR_LATENCY, CAMERA_LATENCY
+    );
+
+    // Sensor results queued by the time they are delivered
+    let mut deliveries: Vec<(u64, Event)> = Vec::new();
+    let mut last_scan = 5;
+    let (mut attached, mut pending, mut rejected) = (0, 0, 0);
+    let duration = 12_000;
+
+    for time in (ODOMETRY_PERIOD..=duration).step_by(ODOMETRY_PERIOD as usize) {
+        // LIDAR matches each scan against the previous one
+        let scan = last_scan + LIDAR_PERIOD;
+        if scan <= time {
+            let relative = noisy(&mut rng, &between(last_scan, scan), (0.005, 0.002));
+            let factor = Factor::new(
+                NodeType::RobotPose(last_scan),
+                NodeType::RobotPose(scan),
+                relative,
+                "LIDAR",
+                scan,
+            )
+            .with_diagonal_std(0.005, 0.005, 0.002);
+            deliveries.push((scan + LIDAR_LATENCY, Event::Lidar(factor)));
+            last_scan = scan;
+        }
+        if time % CAMERA_PERIOD == 30 {
+            let id = rng.gen_range(0..LANDMARKS.len());
+            let (x, y) = LANDMARKS[id];
+            let local = true_pose(time).inverse().compose(&Transform2D::new(x, y, 0.0));
+            let measurement = LandmarkMeasurement::RangeBearing {
+                range: local.x.hypot(local.y) + rng.gen_range(-0.03..0.03),
+                bearing: local.y.atan2(local.x) + rng.gen_range(-0.01..0.01),
+            };
+            let landmark = NodeType::Landmark(format!("L{}", id));
+            let pose = NodeType::RobotPose(time);
+            let sighting = LandmarkFactor::new(pose, landmark, measurement, "CAMERA", time)
+                .with_std_devs(&[0.03, 0.01]);
+            deliveries.push((time + CAMERA_LATENCY, Event::Camera(sighting)));
+        }
+
+        let start = time - ODOMETRY_PERIOD;
+        let increment = noisy(&mut rng, &between(start, time), (0.004, 0.006));
+        let odometry = Factor::new(
+            NodeType::RobotPose(start),
+            NodeType::RobotPose(time),
+            increment,
+            "ODOMETRY",
+            time,
+        )
+        .with_diagonal_std(0.004, 0.004, 0.006);
+        if let Some(NodeType::RobotPose(t)) = graph.add_odometry(odometry) {
+            if t % 2000 < 200 {
+                println!("t={:>5}ms keyframe, {} in window", t, graph.keyframes().count());
+            }
+        }
+
+        // Hand over whatever is due, whatever time it was measured at
+        let (due, later): (Vec<_>, Vec<_>) = deliveries.drain(..).partition(|(at, _)| *at <= time);
+        deliveries = later;
+        for (_, event) in due {
+            let outcome = match event {
+                Event::Lidar(factor) => graph.add_factor(factor),
+                Event::Camera(sighting) => graph.add_landmark_factor(sighting),
+            };
+            match outcome {
+                Attachment::Attached(_) => attached += 1,
+                Attachment::Pending => pending += 1,
+                Attachment::Rejected => rejected += 1,
+            }
+        }
+    }
+
+    let window = graph.graph();
+    println!(
+        "\nMeasurements: {} attached at once, {} waited, {} rejected",
+        attached, pending, rejected
+    );
+    println!(
+        "Window: {} keyframes, {} relative factors, {} landmark sightings, {} still waiting",
+        graph.keyframes().count(),
+        window.factor_count(),
+        window.landmark_factor_count(),
+        graph.pending_count(),
+    );
+
+    // Keyframe errors against the truth, with both trajectories anchored at the oldest keyframe
+    let first = match graph.keyframes().next() {
+        Some(NodeType::RobotPose(t)) => t,
+        _ => return,
+    };
+    let error = |poses: &dyn Fn(&NodeType) -> Option<Transform2D>| {
+        let mut total = 0.0;
+        let mut count = 0;
+        for node in graph.keyframes() {
+            let (NodeType::RobotPose(t), Some(pose)) = (&node, poses(&node)) else { continue };
+            let expected = between(first, *t);
+            total += (pose.x - expected.x).hypot(pose.y - expected.y);
+            count += 1;
+        }
+        total / count.max(1) as f64
+    };
+    let initial = PoseGraphOptimizer::initial_estimates(window);
+    let result = PoseGraphOptimizer::new(OptimizerConfig::default()).optimize(window);
+    let anchor = |poses: &std::collections::HashMap<NodeType, Transform2D>, node: &NodeType| {
+        let origin = poses.get(&NodeType::RobotPose(first))?;
+        Some(origin.inverse().compose(poses.get(node)?))
+    };
+    println!(
+        "Mean keyframe position error: {:.3} m from odometry, {:.3} m optimized",
+        error(&|node| anchor(&initial, node)),
+        error(&|node| anchor(&result.poses, node)),
+    );
+
+    println!("\nSensor report:");
+    for health in window.sensor_report() {
+        println!(
+            "  {:<9} factors: {:3}, cycles: {:3}, inconsistent: {:5.1}%",
+            health.sensor_type,
+            health.factor_count,
+            health.cycles,
+            100.0 * health.inconsistency_rate(),
+        );
+    }
+}
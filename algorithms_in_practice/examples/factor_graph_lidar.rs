@@ -1,79 +1,136 @@
-use algorithms_in_practice::algorithms::graphs::factor_graph::{
-    CircularFactorGraph, Factor, NodeType, Transform2D,
+use algorithms_in_practice::algorithms::graphs::{
+    CircularFactorGraph, Factor, Icp2D, IcpConfig, IcpResult, IncrementalSmoother, LandmarkFactor,
+    LandmarkMeasurement, NodeType, RegistrationPose, SmootherConfig, Transform2D,
 };
+use algorithms_in_practice::common::robust_kernel::CauchyKernel;
+use nalgebra as na;
+use std::collections::VecDeque;
 use std::{thread, time::Duration};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 struct LidarProcessor {
     current_timestamp: u64,
     processing_time_base: u64,  // Base processing time in ms
     scan_period: u64,           // Time between scans in ms
     is_faulty: bool,           // Simulated sensor fault
+    room: Vec<na::Vector2<f64>>,      // Wall points the scans are taken of
+    scans: VecDeque<Vec<na::Vector2<f64>>>,  // Most recent last
+    rng: StdRng,                      // Seeded, so every run simulates the same scans
 }
 
 impl LidarProcessor {
     fn new(processing_time_base: u64, scan_period: u64) -> Self {
+        // An 8 x 6 m room sampled every 5 cm
+        let mut room = Vec::new();
+        for i in 0..=160 {
+            let s = i as f64 * 0.05;
+            room.push(na::Vector2::new(s - 2.0, -3.0));
+            room.push(na::Vector2::new(s - 2.0, 3.0));
+        }
+        for i in 0..=120 {
+            let s = i as f64 * 0.05;
+            room.push(na::Vector2::new(-2.0, s - 3.0));
+            room.push(na::Vector2::new(6.0, s - 3.0));
+        }
         Self {
             current_timestamp: 0,
             processing_time_base,
             scan_period,
             is_faulty: false,
+            room,
+            scans: VecDeque::new(),
+            rng: StdRng::seed_from_u64(7),
         }
     }
 
-    fn process_scan(&mut self) -> Option<Transform2D> {
-        let mut rng = rand::thread_rng();
-        
+    /// Scan the room from `pose` and match the scan against the previous one,
+    /// which recovers the motion between them
+    fn process_scan(&mut self, pose: &Transform2D) -> Option<IcpResult<Transform2D, 3>> {
+        let rng = &mut self.rng;
+
         // Simulate variable processing time
-        let processing_time = self.processing_time_base as f64 * 
+        let processing_time = self.processing_time_base as f64 *
             (0.5 + rng.gen::<f64>());
         thread::sleep(Duration::from_millis(processing_time as u64));
 
-        // Generate transform with some noise
-        let base_noise = if self.is_faulty { 0.5 } else { 0.05 };
-        Some(Transform2D::new(
-            rng.gen::<f64>() * base_noise,
-            rng.gen::<f64>() * base_noise,
-            rng.gen::<f64>() * base_noise,
-        ))
+        let inverse = pose.inverse();
+        let scan: Vec<na::Vector2<f64>> = self
+            .room
+            .iter()
+            .map(|p| {
+                inverse.transform_point(p)
+                    + na::Vector2::new(rng.gen_range(-0.01..0.01), rng.gen_range(-0.01..0.01))
+            })
+            .collect();
+        self.scans.push_back(scan);
+        if self.scans.len() > 3 {
+            self.scans.pop_front();
+        }
+
+        // A faulty processor mixes up its buffers and matches against a stale scan
+        let lag = if self.is_faulty { 3 } else { 2 };
+        let reference = self.scans.get(self.scans.len().checked_sub(lag)?)?;
+        let config = IcpConfig::default().with_kernel(CauchyKernel::new(0.1));
+        let icp = Icp2D::new(reference.clone(), config);
+        Some(icp.align(&self.scans[self.scans.len() - 1], &Transform2D::new(0.0, 0.0, 0.0)))
     }
 }
 
-fn simulate_odometry(timestamp: u64) -> Transform2D {
-    let mut rng = rand::thread_rng();
-    // Simulate roughly constant forward motion with small noise
+/// True motion over one cycle: roughly constant forward motion with a slow turn
+fn simulate_motion(rng: &mut StdRng) -> Transform2D {
     Transform2D::new(
-        0.1 + rng.gen::<f64>() * 0.01,  // Mostly forward
-        rng.gen::<f64>() * 0.01,         // Small lateral drift
-        rng.gen::<f64>() * 0.01,         // Small rotation
+        0.1 + rng.gen::<f64>() * 0.01,
+        rng.gen::<f64>() * 0.01,
+        0.01 + rng.gen::<f64>() * 0.01,
     )
 }
 
-fn simulate_visual_landmark_detection(timestamp: u64, is_valid: bool) -> Option<(String, Transform2D)> {
-    let mut rng = rand::thread_rng();
-    
-    if !is_valid || rng.gen::<f64>() < 0.3 {  // 30% chance of no detection
+fn simulate_odometry(rng: &mut StdRng, motion: &Transform2D) -> Transform2D {
+    Transform2D::new(
+        motion.x + rng.gen_range(-0.01..0.01),  // Precise along track...
+        motion.y + rng.gen_range(-0.02..0.02),
+        motion.theta + rng.gen_range(-0.05..0.05),  // ...noisy in heading
+    )
+}
+
+/// Landmarks are points at fixed world positions
+fn landmark_position(landmark_id: usize) -> (f64, f64) {
+    match landmark_id {
+        1 => (1.0, 1.5),
+        2 => (2.0, -1.0),
+        _ => (3.0, 1.0),
+    }
+}
+
+fn simulate_visual_landmark_detection(
+    rng: &mut StdRng,
+    true_pose: &Transform2D,
+) -> Option<(String, LandmarkMeasurement)> {
+    if rng.gen::<f64>() < 0.3 {  // 30% chance of no detection
         return None;
     }
 
-    // Generate a random landmark ID from a small set
-    let landmark_id = format!("L{}", rng.gen_range(1..=3));
-    
-    // Generate transform to landmark with some noise
-    let transform = Transform2D::new(
-        rng.gen_range(-2.0..2.0),
-        rng.gen_range(-2.0..2.0),
-        rng.gen_range(-0.1..0.1),
-    );
+    // Pick one of a small set of landmarks and measure its range and bearing from the true pose
+    let landmark_id = rng.gen_range(1..=3);
+    let (x, y) = landmark_position(landmark_id);
+    let relative = true_pose.inverse().compose(&Transform2D::new(x, y, 0.0));
+    let measurement = LandmarkMeasurement::RangeBearing {
+        range: relative.x.hypot(relative.y) + rng.gen_range(-0.05..0.05),
+        bearing: relative.y.atan2(relative.x) + rng.gen_range(-0.03..0.03),
+    };
 
-    Some((landmark_id, transform))
+    Some((format!("L{}", landmark_id), measurement))
 }
 
 fn main() {
     let mut graph = CircularFactorGraph::new(100, 150);
+    // Down-weight LIDAR loops that disagree by more than a few sigma
+    graph.set_sensor_kernel("LIDAR", CauchyKernel::new(3.0));
     let mut lidar = LidarProcessor::new(40, 50);  // 40ms processing, 50ms between scans
-    let mut rng = rand::thread_rng();
-
+    let mut smoother = IncrementalSmoother::new(SmootherConfig::default());
+    let mut update_times = Vec::new();
+    let mut rng = StdRng::seed_from_u64(42);
     println!("Starting robot localization simulation...");
     println!("- LIDAR scanning at 20Hz (50ms period)");
     println!("- Processing time varies around 40ms");
@@ -83,8 +140,10 @@ fn main() {
     let mut total_cycles = 0;
 
     // Add initial robot pose
-    let initial_pose = NodeType::RobotPose(0);
+    let mut initial_pose = NodeType::RobotPose(0);
     graph.add_node(initial_pose.clone());
+    let mut true_pose = Transform2D::new(0.0, 0.0, 0.0);
+    lidar.process_scan(&true_pose);  // First reference scan
 
     // Run for 20 cycles
     for i in 0..20 {
@@ -101,15 +160,17 @@ fn main() {
         graph.add_node(current_pose.clone());
 
         // Add odometry factor
-        let odom_transform = simulate_odometry(lidar.current_timestamp);
-        let odom_factor = Factor {
-            source: initial_pose.clone(),
-            target: current_pose.clone(),
-            transform: odom_transform,
-            uncertainty: 0.1,
-            sensor_type: "ODOMETRY".to_string(),
-            timestamp: lidar.current_timestamp,
-        };
+        let motion = simulate_motion(&mut rng);
+        true_pose = true_pose.compose(&motion);
+        let odom_transform = simulate_odometry(&mut rng, &motion);
+        let odom_factor = Factor::new(
+            initial_pose.clone(),
+            current_pose.clone(),
+            odom_transform,
+            "ODOMETRY",
+            lidar.current_timestamp,
+        )
+        .with_diagonal_std(0.01, 0.02, 0.1);  // Precise along track, noisy in heading
 
         if let Some(cycle) = graph.add_factor(odom_factor) {
             println!("Found cycle from odometry!");
@@ -122,20 +183,29 @@ fn main() {
         }
 
         // Process LIDAR scan
-        if let Some(lidar_transform) = lidar.process_scan() {
-            let lidar_factor = Factor {
-                source: initial_pose.clone(),
-                target: current_pose.clone(),
-                transform: lidar_transform,
-                uncertainty: 0.2,
-                sensor_type: "LIDAR".to_string(),
-                timestamp: lidar.current_timestamp,
-            };
-
-            if let Some(cycle) = graph.add_factor(lidar_factor) {
+        if let Some(matched) = lidar.process_scan(&true_pose) {
+            println!(
+                "Scan matched: {:?} after {} iterations, rmse {:.3} m",
+                matched.report.status,
+                matched.report.iterations.len(),
+                matched.report.rmse,
+            );
+            // An alignment that stopped short of convergence is no constraint
+            let lidar_factor = matched.to_factor(
+                initial_pose.clone(),
+                current_pose.clone(),
+                "LIDAR",
+                lidar.current_timestamp,
+            );
+
+            if lidar_factor.is_none() {
+                println!("  Not converged, no LIDAR factor");
+            } else if let Some(cycle) = lidar_factor.and_then(|factor| graph.add_factor(factor)) {
                 println!("Found cycle from LIDAR!");
                 let (error, is_consistent) = graph.check_cycle_consistency(&cycle);
+                let (robust_cost, weight) = graph.robust_cycle_cost(&cycle);
                 println!("  Error: {:.3}, Consistent: {}", error, is_consistent);
+                println!("  Robust cost: {:.3}, Weight: {:.3}", robust_cost, weight);
                 total_cycles += 1;
                 if !is_consistent {
                     inconsistent_cycles += 1;
@@ -144,22 +214,24 @@ fn main() {
         }
 
         // Try to detect visual landmarks
-        if let Some((landmark_id, landmark_transform)) = 
-            simulate_visual_landmark_detection(lidar.current_timestamp, !lidar.is_faulty) 
-        {
+        let detection = simulate_visual_landmark_detection(&mut rng, &true_pose);
+        if let Some((landmark_id, measurement)) = detection {
             let landmark_node = NodeType::Landmark(landmark_id);
-            graph.add_node(landmark_node.clone());
-
-            let landmark_factor = Factor {
-                source: current_pose.clone(),
-                target: landmark_node,
-                transform: landmark_transform,
-                uncertainty: 0.15,
-                sensor_type: "VISUAL".to_string(),
-                timestamp: lidar.current_timestamp,
-            };
-
-            if let Some(cycle) = graph.add_factor(landmark_factor) {
+            // Re-adding a landmark seen before would cut it off from its observations
+            if !graph.nodes().any(|node| *node == landmark_node) {
+                graph.add_node(landmark_node.clone());
+            }
+
+            let landmark_factor = LandmarkFactor::new(
+                current_pose.clone(),
+                landmark_node,
+                measurement,
+                "VISUAL",
+                lidar.current_timestamp,
+            )
+            .with_std_devs(&[0.05, 0.03]);  // Range, bearing
+
+            if let Some(cycle) = graph.add_landmark_factor(landmark_factor) {
                 println!("Found cycle through landmark!");
                 let (error, is_consistent) = graph.check_cycle_consistency(&cycle);
                 println!("  Error: {:.3}, Consistent: {}", error, is_consistent);
@@ -170,6 +242,20 @@ fn main() {
             }
         }
 
+        // Fold this scan's factors into the smoother
+        let report = smoother.update(&graph);
+        update_times.push(report.elapsed);
+        println!(
+            "Smoother update: {:.3} ms, {} variables, {} new factors, {} relinearized",
+            report.elapsed.as_secs_f64() * 1000.0,
+            report.variables,
+            report.added_factors,
+            report.relinearized_variables,
+        );
+        if let Some(pose) = smoother.estimate(&current_pose) {
+            println!("Pose estimate: ({:.3}, {:.3}, {:.3})", pose.x, pose.y, pose.theta);
+        }
+
         initial_pose = current_pose;
         
         println!("Graph size: {} nodes, {} factors", 
@@ -181,10 +267,36 @@ fn main() {
     println!("Inconsistent cycles: {}", inconsistent_cycles);
     println!("Consistency rate: {:.1}%", 
              100.0 * (total_cycles - inconsistent_cycles) as f64 / total_cycles as f64);
-    println!("LIDAR status: {}", 
-             if inconsistent_cycles > total_cycles / 3 {
-                 "Likely faulty"
-             } else {
-                 "Likely working correctly"
-             });
+
+    let slowest = update_times.iter().max().copied().unwrap_or_default();
+    let mean = update_times.iter().sum::<Duration>() / update_times.len().max(1) as u32;
+    println!(
+        "Smoother updates: mean {:.3} ms, max {:.3} ms ({} the {} ms scan period)",
+        mean.as_secs_f64() * 1000.0,
+        slowest.as_secs_f64() * 1000.0,
+        if slowest < Duration::from_millis(lidar.scan_period) { "within" } else { "over" },
+        lidar.scan_period,
+    );
+
+    let report = graph.sensor_report();
+    println!("\nSensor report:");
+    for health in &report {
+        println!(
+            "  {:<9} factors: {:3}, cycles: {:3}, inconsistent: {:5.1}%, blamed: {:5.1}%, \
+             flagged: {:3}{}",
+            health.sensor_type,
+            health.factor_count,
+            health.cycles,
+            100.0 * health.inconsistency_rate(),
+            100.0 * health.blame_rate(),
+            health.flagged_factors,
+            if health.suspected_faulty { "  <- suspected faulty" } else { "" },
+        );
+    }
+
+    let lidar_faulty = report
+        .iter()
+        .any(|health| health.sensor_type == "LIDAR" && health.suspected_faulty);
+    println!("LIDAR status: {}",
+             if lidar_faulty { "Likely faulty" } else { "Likely working correctly" });
 }
\ No newline at end of file
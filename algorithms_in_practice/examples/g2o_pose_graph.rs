@@ -0,0 +1,55 @@
+use algorithms_in_practice::algorithms::graphs::{
+    load_g2o_se2, save_g2o_se2, CircularFactorGraph, OptimizerConfig, PoseGraphOptimizer,
+};
+use std::env;
+use std::time::Instant;
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/../data/g2o/manhattanOlson3500.g2o").to_string()
+    });
+    let output = env::args()
+        .nth(2)
+        .unwrap_or_else(|| "optimized.g2o".to_string());
+
+    let g2o = match load_g2o_se2(&path) {
+        Ok(graph) => graph,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", path, err);
+            return;
+        }
+    };
+    println!("Loaded {}: {} vertices, {} edges", path, g2o.vertices.len(), g2o.factors.len());
+
+    // Size the window to hold the whole dataset
+    let mut graph = CircularFactorGraph::new(g2o.vertices.len(), g2o.factors.len());
+    for (node, _) in &g2o.vertices {
+        graph.add_node(node.clone());
+    }
+    let mut loops = 0;
+    for factor in &g2o.factors {
+        if graph.add_factor(factor.clone()).is_some() {
+            loops += 1;
+        }
+    }
+    println!("Graph of {} poses with {} factors ({} loop closures)",
+             graph.node_count(), graph.factor_count(), loops);
+
+    let optimizer = PoseGraphOptimizer::new(OptimizerConfig::default());
+    let start = Instant::now();
+    let result = optimizer.optimize(&graph);
+    let elapsed = start.elapsed();
+    for summary in &result.report.iterations {
+        println!("iter {:>2}: cost {:>14.6} step {:.2e} lambda {:.1e}{}",
+                 summary.iteration, summary.cost, summary.step_norm, summary.lambda,
+                 if summary.accepted { "" } else { " (rejected)" });
+    }
+    println!("Status: {:?}, cost {:.6} -> {:.6} in {:.2?}",
+             result.report.status, result.report.initial_cost, result.report.final_cost, elapsed);
+
+    let vertices: Vec<_> = result.poses.into_iter().collect();
+    match save_g2o_se2(&output, &vertices, graph.factors()) {
+        Ok(()) => println!("Wrote optimized graph to {}", output),
+        Err(err) => eprintln!("Failed to write {}: {}", output, err),
+    }
+}
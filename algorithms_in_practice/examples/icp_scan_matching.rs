@@ -0,0 +1,74 @@
+use algorithms_in_practice::algorithms::graphs::{Icp3D, IcpConfig, IcpMetric, NodeType};
+use algorithms_in_practice::common::lie::{LieGroup, SE3};
+use algorithms_in_practice::common::pcd::load_pcd;
+use algorithms_in_practice::common::robust_kernel::CauchyKernel;
+use nalgebra as na;
+use std::time::Instant;
+
+const SCANS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../robot_geometry");
+
+fn load(name: &str) -> Option<Vec<na::Vector3<f64>>> {
+    let cloud = load_pcd(format!("{}/{}", SCANS, name))
+        .map_err(|err| eprintln!("Failed to load {}: {}", name, err))
+        .ok()?;
+    // Drop the returns a spinning lidar reports as NaN or at its origin
+    let points = cloud.positions()?;
+    Some(points.into_iter().filter(|p| p.iter().all(|v| v.is_finite()) && p.norm() > 0.5).collect())
+}
+
+fn main() {
+    let (Some(reference), Some(scan)) = (load("1.pcd"), load("2.pcd")) else { return };
+    // Every fourth point of the new scan is plenty to constrain six degrees of freedom
+    let source: Vec<na::Vector3<f64>> = scan.iter().step_by(4).copied().collect();
+    println!(
+        "Reference scan: {} points, new scan: {} points ({} used)",
+        reference.len(),
+        scan.len(),
+        source.len()
+    );
+
+    for metric in [IcpMetric::PointToPoint, IcpMetric::PointToLine, IcpMetric::PointToPlane] {
+        let config = IcpConfig { metric, max_correspondence_distance: 0.5, ..IcpConfig::default() }
+            .with_kernel(CauchyKernel::new(0.1));
+        let start = Instant::now();
+        let icp = Icp3D::new(reference.clone(), config);
+        let result = icp.align(&source, &SE3::identity());
+        let (translation, rotation) =
+            (result.transform.translation, result.transform.rotation.rotation.euler_angles());
+        println!(
+            "{:?}: {:?} after {} iterations in {:.0} ms",
+            metric,
+            result.report.status,
+            result.report.iterations.len(),
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+        println!(
+            "  translation ({:.3}, {:.3}, {:.3}) m, yaw {:.2} deg",
+            translation.x,
+            translation.y,
+            translation.z,
+            rotation.2.to_degrees()
+        );
+        let report = &result.report;
+        println!(
+            "  {} correspondences, rmse {:.3} m, cost {:.1} -> {:.1}",
+            report.correspondences, report.rmse, report.initial_cost, report.final_cost
+        );
+        let sigma = result.covariance.diagonal().map(f64::sqrt);
+        println!("  1-sigma: translation ({:.1e}, {:.1e}, {:.1e}) m", sigma[0], sigma[1], sigma[2]);
+        println!("           rotation ({:.1e}, {:.1e}, {:.1e}) rad", sigma[3], sigma[4], sigma[5]);
+
+        if metric == IcpMetric::PointToPlane {
+            match result.to_factor(NodeType::RobotPose(1), NodeType::RobotPose(2), "LIDAR", 2) {
+                Some(factor) => println!(
+                    "  -> {} factor {:?} -> {:?}, information trace {:.3e}",
+                    factor.sensor_type,
+                    factor.source,
+                    factor.target,
+                    factor.information.trace()
+                ),
+                None => println!("  -> not converged, no factor"),
+            }
+        }
+    }
+}
@@ -38,9 +38,9 @@ fn main() {
 
     // Create architecture searcher
     let mut searcher = ArchitectureSearcher::new(
-        max_layers: 8,    // Maximum depth
-        min_layers: 3,    // Minimum depth
-        max_params: 1_000_000, // Parameter budget
+        8,         // Maximum depth
+        3,         // Minimum depth
+        1_000_000, // Parameter budget
         layer_options,
     );
 
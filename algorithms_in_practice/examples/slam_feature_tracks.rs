@@ -0,0 +1,65 @@
+use algorithms_in_practice::common::slam_io::{load_slam_frames, FeatureTracks};
+
+const FRAMES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/slam");
+
+fn main() {
+    let frames = match load_slam_frames(FRAMES) {
+        Ok(frames) if !frames.is_empty() => frames,
+        Ok(_) => {
+            eprintln!("No frames in {}", FRAMES);
+            return;
+        }
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", FRAMES, err);
+            return;
+        }
+    };
+    let duration = frames[frames.len() - 1].stamp - frames[0].stamp;
+    let imu_samples: usize = frames.iter().map(|frame| frame.imu.len()).sum();
+    let observations: usize = frames.iter().map(|frame| frame.features.len()).sum();
+    println!(
+        "{} frames over {:.2} s ({:.1} Hz), {} feature observations, {} IMU samples ({:.0} Hz)",
+        frames.len(),
+        duration,
+        (frames.len() - 1) as f64 / duration,
+        observations,
+        imu_samples,
+        imu_samples as f64 / duration
+    );
+
+    let tracks = FeatureTracks::new(&frames);
+    println!("{} distinct features", tracks.len());
+    for min_frames in [2, 5, 10, 20, 50] {
+        let count = tracks.long_tracks(min_frames).count();
+        println!("  seen in >= {:2} frames: {}", min_frames, count);
+    }
+    if let Some((id, seen)) = tracks.iter().max_by_key(|(_, seen)| seen.len()) {
+        let (first, last) = (&frames[seen[0]], &frames[seen[seen.len() - 1]]);
+        let (from, to) = (first.features[&id].pixel, last.features[&id].pixel);
+        println!("Longest track: feature {} in {} frames", id, seen.len());
+        println!(
+            "  F{:04} ({:.1}, {:.1}) -> F{:04} ({:.1}, {:.1})",
+            first.index, from.x, from.y, last.index, to.x, to.y
+        );
+    }
+
+    // What a frame-to-frame tracker would have to work with
+    println!("Consecutive frames: covisible features and median pixel motion");
+    for a in (0..frames.len() - 1).step_by(25) {
+        let covisible = tracks.covisible(a, a + 1);
+        let mut motion: Vec<f64> = covisible
+            .iter()
+            .map(|id| (frames[a + 1].features[id].pixel - frames[a].features[id].pixel).norm())
+            .collect();
+        motion.sort_by(f64::total_cmp);
+        let median = motion.get(motion.len() / 2).copied().unwrap_or(f64::NAN);
+        println!(
+            "  F{:04} -> F{:04}: {:3} of {:3} features, {:.2} px",
+            frames[a].index,
+            frames[a + 1].index,
+            covisible.len(),
+            frames[a].features.len(),
+            median
+        );
+    }
+}
@@ -123,7 +123,7 @@ mod tests {
             .collect();
 
         let config = WindowConfig {
-            dilations: vec![10],  // Too large for sequence
+            dilations: vec![10, 1],  // 10 is too large for sequence
             snippet_lengths: vec![3],
             strides: vec![1],
             cap_dilation: true,
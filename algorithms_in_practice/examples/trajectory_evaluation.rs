@@ -0,0 +1,68 @@
+use algorithms_in_practice::algorithms::graphs::{
+    absolute_trajectory_error, relative_pose_error, Alignment, EvaluationConfig, RelativeDelta,
+    Trajectory,
+};
+use algorithms_in_practice::common::npy::load_npy_rows;
+use std::env;
+use std::path::Path;
+
+fn load(path: &Path) -> Option<Trajectory> {
+    let rows = load_npy_rows(path)
+        .map_err(|err| eprintln!("Failed to load {}: {}", path.display(), err))
+        .ok()?;
+    Trajectory::from_rows(&rows)
+        .map_err(|err| eprintln!("Failed to read {}: {}", path.display(), err))
+        .ok()
+}
+
+fn main() {
+    let data = env::args()
+        .nth(1)
+        .unwrap_or_else(|| concat!(env!("CARGO_MANIFEST_DIR"), "/../data").to_string());
+    let data = Path::new(&data);
+    let Some(truth) = load(&data.join("truth_pose.npy")) else { return };
+    println!("Reference: {} poses", truth.len());
+
+    for name in ["ndt_pose.npy", "vins_pose.npy"] {
+        let Some(estimate) = load(&data.join(name)) else { continue };
+        println!("\n{} ({} poses)", name, estimate.len());
+        for alignment in [Alignment::SE3, Alignment::Sim3] {
+            let config = EvaluationConfig { alignment, ..EvaluationConfig::default() };
+            let Some(ate) = absolute_trajectory_error(&estimate, &truth, &config) else {
+                println!("  too few poses associate with the reference");
+                break;
+            };
+            println!(
+                "  ATE {:<4}  {} pairs, rmse {:.3} m, max {:.3} m, rotation rmse {:.2} deg \
+                 (scale {:.3})",
+                format!("{:?}", alignment),
+                ate.translation.count,
+                ate.translation.rmse,
+                ate.translation.max,
+                ate.rotation.rmse.to_degrees(),
+                ate.alignment.scale,
+            );
+        }
+
+        let config = EvaluationConfig::default();
+        for delta in [RelativeDelta::Distance(1.0), RelativeDelta::Time(5.0)] {
+            let Some(rpe) = relative_pose_error(&estimate, &truth, delta, &config) else {
+                continue;
+            };
+            // Per-metre drift only means something when every pair spans the same distance
+            let drift = match delta {
+                RelativeDelta::Distance(_) => {
+                    format!(" ({:.1}% of distance)", 100.0 * rpe.translation_ratio.mean)
+                }
+                _ => String::new(),
+            };
+            println!(
+                "  RPE {:<14} translation rmse {:.3} m{}, rotation rmse {:.2} deg",
+                format!("{:?}", delta),
+                rpe.translation.rmse,
+                drift,
+                rpe.rotation.rmse.to_degrees(),
+            );
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use algorithms_in_practice::algorithms::graphs::NodeType;
+use algorithms_in_practice::algorithms::vision::{
+    estimate_fundamental, estimate_relative_pose, triangulate, CameraModel, PinholeCamera,
+    PointMatch, RelativePoseConfig, RelativePoseMethod, TriangulationConfig,
+};
+use algorithms_in_practice::common::lie::{LieGroup, SE3, SO3};
+use algorithms_in_practice::common::ransac::RansacConfig;
+use algorithms_in_practice::common::slam_io::{load_slam_frames, FeatureTracks};
+use nalgebra as na;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const FRAMES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/slam");
+
+/// Zero-mean normal sample (Box-Muller)
+fn gaussian(rng: &mut StdRng, sigma: f64) -> f64 {
+    let (u, v): (f64, f64) = (1.0 - rng.gen::<f64>(), rng.gen());
+    sigma * (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let camera = PinholeCamera::new(400.0, 400.0, 320.0, 240.0);
+
+    // A camera moving sideways past a cloud of points, seen three times
+    let poses: Vec<SE3> = (0..3)
+        .map(|i| {
+            let i = i as f64;
+            SE3::new(
+                SO3::new(na::UnitQuaternion::from_euler_angles(0.0, -0.04 * i, 0.01 * i)),
+                na::Vector3::new(0.5 * i, 0.05 * i, 0.1 * i),
+            )
+        })
+        .collect();
+    let points: Vec<na::Vector3<f64>> = (0..200)
+        .map(|_| {
+            let (x, y) = (rng.gen_range(-3.0..4.0), rng.gen_range(-2.0..2.0));
+            na::Vector3::new(x, y, rng.gen_range(4.0..10.0))
+        })
+        .collect();
+    let observations: Vec<Vec<Option<na::Vector2<f64>>>> = poses
+        .iter()
+        .map(|pose| {
+            points
+                .iter()
+                .map(|point| {
+                    let pixel = camera.project(pose, point)?
+                        + na::Vector2::new(gaussian(&mut rng, 0.5), gaussian(&mut rng, 0.5));
+                    (pixel.x >= 0.0 && pixel.x < 640.0 && pixel.y >= 0.0 && pixel.y < 480.0)
+                        .then_some(pixel)
+                })
+                .collect()
+        })
+        .collect();
+
+    // Matches between the first two views, a quarter of them wrong
+    let mut matched = Vec::new();
+    let mut matches: Vec<PointMatch> = Vec::new();
+    for (index, (a, b)) in observations[0].iter().zip(&observations[1]).enumerate() {
+        if let (Some(a), Some(b)) = (a, b) {
+            let b = if rng.gen_bool(0.25) {
+                na::Vector2::new(rng.gen_range(0.0..640.0), rng.gen_range(0.0..480.0))
+            } else {
+                *b
+            };
+            let (a, b) = (camera.unproject(a).unwrap().xy(), camera.unproject(&b).unwrap().xy());
+            matches.push((a, b));
+            matched.push(index);
+        }
+    }
+    println!("{} matches between the first two views", matches.len());
+
+    let truth = poses[0].inverse().compose(&poses[1]);
+    let baseline = truth.translation.norm();
+    for method in [RelativePoseMethod::FivePoint, RelativePoseMethod::EightPoint] {
+        // Two pixels at this focal length
+        let threshold = 2.0 / camera.fx;
+        let ransac = RansacConfig { threshold, seed: 1, ..RansacConfig::default() };
+        let config = RelativePoseConfig { method, ransac };
+        let Some(result) = estimate_relative_pose(&matches, &config) else {
+            println!("{:?}: no consistent motion", method);
+            continue;
+        };
+        let triangulated = result.points.iter().filter(|p| p.is_some()).count();
+        println!(
+            "{:?}: {} inliers after {} iterations, {} points triangulated",
+            method,
+            result.inlier_count(),
+            result.iterations,
+            triangulated
+        );
+        println!(
+            "  rotation error {:.3} deg, direction error {:.3} deg",
+            result.transform.rotation.rotation.angle_to(&truth.rotation.rotation).to_degrees(),
+            result.transform.translation.angle(&truth.translation).to_degrees()
+        );
+
+        if method == RelativePoseMethod::FivePoint {
+            // Seed a graph, with the scale taken from the true baseline as odometry would
+            let landmarks: Vec<NodeType> =
+                matched.iter().map(|i| NodeType::Landmark(format!("P{}", i))).collect();
+            let (first, second) = (NodeType::RobotPose(0), NodeType::RobotPose(1));
+            let Some((initial_poses, initial_points)) =
+                result.initial_values(first, second, &landmarks, &poses[0], baseline)
+            else {
+                println!("  one landmark per match is needed for initial values");
+                continue;
+            };
+            let error: f64 = landmarks
+                .iter()
+                .zip(&matched)
+                .filter_map(|(node, &index)| {
+                    initial_points.get(node).map(|p| (p - points[index]).norm())
+                })
+                .sum::<f64>()
+                / initial_points.len() as f64;
+            println!(
+                "  initial values: {} poses, {} landmarks, mean landmark error {:.3} m",
+                initial_poses.len(),
+                initial_points.len(),
+                error
+            );
+        }
+    }
+
+    // Refine the points seen in all three views, with the poses known
+    let config = TriangulationConfig::default();
+    let mut errors = Vec::new();
+    for (index, point) in points.iter().enumerate() {
+        let views: Option<Vec<(SE3, na::Vector2<f64>)>> = poses
+            .iter()
+            .zip(&observations)
+            .map(|(pose, seen)| seen[index].map(|pixel| (*pose, pixel)))
+            .collect();
+        if let Some(result) = views.and_then(|views| triangulate(&camera, &views, &config)) {
+            errors.push((result.point - point).norm());
+        }
+    }
+    errors.sort_by(f64::total_cmp);
+    if !errors.is_empty() {
+        println!(
+            "Three-view triangulation: {} points, median error {:.3} m, worst {:.3} m",
+            errors.len(),
+            errors[errors.len() / 2],
+            errors[errors.len() - 1]
+        );
+    }
+
+    // The recorded frames carry no calibration, so only the fundamental matrix applies
+    match load_slam_frames(FRAMES) {
+        Ok(frames) if frames.len() > 100 => {
+            let tracks = FeatureTracks::new(&frames);
+            for (a, b) in [(88, 92), (120, 124)] {
+                let matches: Vec<PointMatch> = tracks
+                    .covisible(a, b)
+                    .iter()
+                    .map(|id| (frames[a].features[id].pixel, frames[b].features[id].pixel))
+                    .collect();
+                let config = RansacConfig { threshold: 1.0, ..RansacConfig::default() };
+                if let Some((_, inliers)) = estimate_fundamental(&matches, &config) {
+                    println!(
+                        "Recorded F{:04} -> F{:04}: {} of {} tracks fit one F within 1 px",
+                        frames[a].index,
+                        frames[b].index,
+                        inliers.iter().filter(|&&inlier| inlier).count(),
+                        matches.len()
+                    );
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("Failed to load {}: {}", FRAMES, err),
+    }
+}
@@ -72,20 +72,19 @@ impl MissionPlanner {
 
         // Check memoization
         let state_key = (current_task, remaining_time, energy_states.len() as u32);
-        if let Some(&result) = self.memo.get(&state_key) {
-            return Some(result);
+        if let Some(result) = self.memo.get(&state_key) {
+            return Some(result.clone());
         }
 
         let mut best_value = 0.0;
         let mut best_sequence = Vec::new();
 
         // Try including current task at different energy levels
+        let task = self.tasks[current_task].clone();
         for &energy_level in energy_states {
-            let task = &self.tasks[current_task];
-            
             if task.time_cost <= remaining_time && energy_level >= task.energy_cost {
                 // Recursive case: include current task
-                if let Some((sub_value, mut sub_sequence)) = self.optimize_recursive(
+                if let Some((sub_value, sub_sequence)) = self.optimize_recursive(
                     current_task + 1,
                     remaining_time - task.time_cost,
                     energy_states,
@@ -114,7 +113,7 @@ impl MissionPlanner {
         }
 
         let result = (best_value, best_sequence);
-        self.memo.insert(state_key, result);
+        self.memo.insert(state_key, result.clone());
         Some(result)
     }
 
@@ -124,10 +123,8 @@ impl MissionPlanner {
         let mut stack = vec![false; self.tasks.len()];
 
         for task_id in 0..self.tasks.len() {
-            if !visited[task_id] {
-                if self.has_cycle(task_id, &mut visited, &mut stack) {
-                    return false;
-                }
+            if !visited[task_id] && self.has_cycle(task_id, &mut visited, &mut stack) {
+                return false;
             }
         }
         true
@@ -177,6 +174,10 @@ impl MissionPlanner {
         scheduled[task_id] = true;
     }
 
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
     /// Calculate total energy consumption for a sequence of tasks
     pub fn calculate_energy_consumption(&self, task_sequence: &[usize]) -> f64 {
         task_sequence
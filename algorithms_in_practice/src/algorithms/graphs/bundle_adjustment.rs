@@ -0,0 +1,479 @@
+use std::sync::Arc;
+use nalgebra as na;
+
+use crate::common::bal_io::{BalProblem, SnavelyCamera};
+use crate::common::lie::skew;
+use crate::common::robust_kernel::RobustKernel;
+
+use super::levenberg_marquardt::{minimize, LeastSquares};
+use super::pose_graph_optimizer::{OptimizationReport, OptimizerConfig};
+
+type Vector9 = na::SVector<f64, 9>;
+type Matrix9 = na::SMatrix<f64, 9, 9>;
+type Matrix2x9 = na::SMatrix<f64, 2, 9>;
+type Matrix9x3 = na::SMatrix<f64, 9, 3>;
+
+impl SnavelyCamera {
+    /// Projection and its Jacobians with respect to the camera, perturbed as
+    /// (exp(dphi) R, t + dt, f + df, k1 + dk1, k2 + dk2), and to the point
+    pub fn project_with_jacobians(
+        &self,
+        point: &na::Vector3<f64>,
+    ) -> (na::Vector2<f64>, Matrix2x9, na::Matrix2x3<f64>) {
+        let rotation = self.rotation_matrix();
+        let rotated = rotation * point;
+        let camera = rotated + self.translation;
+        let p = -camera.xy() / camera.z;
+        let r2 = p.norm_squared();
+        let radial = 1.0 + r2 * (self.k1 + self.k2 * r2);
+
+        // du/dp = f (radial I + 2 (k1 + 2 k2 |p|^2) p p^T)
+        let curvature = 2.0 * (self.k1 + 2.0 * self.k2 * r2);
+        let du_dp = (na::Matrix2::identity() * radial + p * p.transpose() * curvature) * self.focal;
+        let z = camera.z;
+        let (x, y) = (camera.x / (z * z), camera.y / (z * z));
+        let dp_dcamera = na::Matrix2x3::new(-1.0 / z, 0.0, x, 0.0, -1.0 / z, y);
+        let du_dcamera = du_dp * dp_dcamera;
+
+        let mut camera_jacobian = Matrix2x9::zeros();
+        camera_jacobian.fixed_view_mut::<2, 3>(0, 0).copy_from(&(du_dcamera * -skew(&rotated)));
+        camera_jacobian.fixed_view_mut::<2, 3>(0, 3).copy_from(&du_dcamera);
+        camera_jacobian.set_column(6, &(p * radial));
+        camera_jacobian.set_column(7, &(p * (self.focal * r2)));
+        camera_jacobian.set_column(8, &(p * (self.focal * r2 * r2)));
+        (p * (self.focal * radial), camera_jacobian, du_dcamera * rotation)
+    }
+
+    /// Apply an update ordered as in `project_with_jacobians`
+    pub fn retract(&self, delta: &Vector9) -> Self {
+        let rotation = na::UnitQuaternion::from_scaled_axis(delta.fixed_rows::<3>(0).into_owned())
+            * na::UnitQuaternion::from_scaled_axis(self.rotation);
+        Self {
+            rotation: rotation.scaled_axis(),
+            translation: self.translation + delta.fixed_rows::<3>(3),
+            focal: self.focal + delta[6],
+            k1: self.k1 + delta[7],
+            k2: self.k2 + delta[8],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleAdjustmentConfig {
+    pub solver: OptimizerConfig,
+    pub kernel: Option<Arc<dyn RobustKernel>>,  // Applied to the squared pixel error
+    /// Steps may not take a point further from the centroid of the cameras
+    /// than this many times their spread. Beyond it the parallax is too small
+    /// to pin down depth, and the cost barely changes as points drift away.
+    pub max_point_distance_ratio: f64,
+}
+
+impl Default for BundleAdjustmentConfig {
+    fn default() -> Self {
+        Self {
+            solver: OptimizerConfig {
+                max_iterations: 50,
+                min_lambda: 1e-6,
+                cost_tolerance: 1e-6,
+                ..OptimizerConfig::default()
+            },
+            kernel: None,
+            max_point_distance_ratio: 1e3,
+        }
+    }
+}
+
+impl BundleAdjustmentConfig {
+    pub fn with_kernel<K: RobustKernel + 'static>(mut self, kernel: K) -> Self {
+        self.kernel = Some(Arc::new(kernel));
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BundleAdjustmentReport {
+    pub optimization: OptimizationReport,  // Costs are robustified squared pixel errors
+    pub initial_rms: f64,                  // Reprojection error in pixels
+    pub final_rms: f64,
+}
+
+/// Normal equations split into camera and point blocks
+struct NormalEquations {
+    cameras: Vec<(Matrix9, Vector9)>,                    // U and gradient per camera
+    points: Vec<(na::Matrix3<f64>, na::Vector3<f64>)>,   // V and gradient per point
+    coupling: Vec<Matrix9x3>,                            // W per observation
+}
+
+/// Levenberg-Marquardt bundle adjustment of BAL problems. Points are
+/// eliminated through the Schur complement, leaving a dense reduced camera
+/// system of nine parameters per camera. The seven-dimensional gauge freedom
+/// (rotation, translation and scale) is removed by holding the first camera's
+/// pose and one translation component of the second camera fixed. Steps
+/// that carry points out past `max_point_distance_ratio` are rejected like a
+/// cost increase.
+pub struct BundleAdjuster {
+    config: BundleAdjustmentConfig,
+}
+
+impl BundleAdjuster {
+    pub fn new(config: BundleAdjustmentConfig) -> Self {
+        Self { config }
+    }
+
+    /// Refine the cameras and points of `problem` in place
+    pub fn solve(&self, problem: &mut BalProblem) -> BundleAdjustmentReport {
+        let initial_rms = problem.reprojection_rms();
+        let mut by_point: Vec<Vec<usize>> = vec![Vec::new(); problem.points.len()];
+        for (i, obs) in problem.observations.iter().enumerate() {
+            by_point[obs.point].push(i);
+        }
+
+        let centers: Vec<na::Vector3<f64>> = problem.cameras.iter().map(|c| c.center()).collect();
+        let centroid = centers.iter().sum::<na::Vector3<f64>>() / centers.len().max(1) as f64;
+        let spread = centers.iter().map(|c| (c - centroid).norm()).fold(0.0, f64::max);
+        let reduced = ReducedProblem {
+            adjuster: self,
+            by_point,
+            gauge: Self::gauge_parameters(problem),
+            centroid,
+            max_distance: spread * self.config.max_point_distance_ratio,
+        };
+        let optimization = minimize(&reduced, problem, &self.config.solver);
+
+        BundleAdjustmentReport {
+            optimization,
+            initial_rms,
+            final_rms: problem.reprojection_rms(),
+        }
+    }
+
+    fn robustify(&self, squared_error: f64) -> [f64; 3] {
+        match &self.config.kernel {
+            Some(kernel) => kernel.evaluate(squared_error),
+            None => [squared_error, 1.0, 0.0],
+        }
+    }
+
+    fn cost(&self, problem: &BalProblem) -> f64 {
+        problem.residuals().iter().map(|r| self.robustify(r.norm_squared())[0]).sum()
+    }
+
+    fn normal_equations(&self, problem: &BalProblem) -> NormalEquations {
+        let mut system = NormalEquations {
+            cameras: vec![(Matrix9::zeros(), Vector9::zeros()); problem.cameras.len()],
+            points: vec![(na::Matrix3::zeros(), na::Vector3::zeros()); problem.points.len()],
+            coupling: Vec::with_capacity(problem.observations.len()),
+        };
+        for obs in &problem.observations {
+            let (predicted, camera_jacobian, point_jacobian) =
+                problem.cameras[obs.camera].project_with_jacobians(&problem.points[obs.point]);
+            let residual = predicted - obs.pixel;
+            let weight = self.robustify(residual.norm_squared())[1];
+
+            let (u, camera_gradient) = &mut system.cameras[obs.camera];
+            *u += camera_jacobian.transpose() * camera_jacobian * weight;
+            *camera_gradient += camera_jacobian.transpose() * residual * weight;
+            let (v, point_gradient) = &mut system.points[obs.point];
+            *v += point_jacobian.transpose() * point_jacobian * weight;
+            *point_gradient += point_jacobian.transpose() * residual * weight;
+            system.coupling.push(camera_jacobian.transpose() * point_jacobian * weight);
+        }
+        system
+    }
+
+    /// Decrease of the cost the linear model predicts for a step of the damped
+    /// system (H + lambda D) dx = -g: -g^T dx + lambda dx^T D dx, as the cost
+    /// is a plain sum of squares
+    fn predicted_reduction(
+        system: &NormalEquations,
+        (camera_steps, point_steps): &(Vec<Vector9>, Vec<na::Vector3<f64>>),
+        lambda: f64,
+    ) -> f64 {
+        let mut reduction = 0.0;
+        for ((u, gradient), step) in system.cameras.iter().zip(camera_steps) {
+            let damped: f64 = (0..9).map(|i| u[(i, i)].max(1e-12) * step[i] * step[i]).sum();
+            reduction += lambda * damped - gradient.dot(step);
+        }
+        for ((v, gradient), step) in system.points.iter().zip(point_steps) {
+            let damped: f64 = (0..3).map(|i| v[(i, i)].max(1e-12) * step[i] * step[i]).sum();
+            reduction += lambda * damped - gradient.dot(step);
+        }
+        reduction
+    }
+
+    /// Camera parameters held fixed to remove the gauge: the first camera's
+    /// rotation and translation, and the component of the second camera's
+    /// translation that a scaling about the first camera's centre moves most
+    fn gauge_parameters(problem: &BalProblem) -> Vec<usize> {
+        let Some(first) = problem.cameras.first() else {
+            return Vec::new();
+        };
+        let mut fixed: Vec<usize> = (0..6).collect();
+        if let Some(second) = problem.cameras.get(1) {
+            // t1 = -R1 c1 moves along R1 (c1 - c0) as the scene scales about c0
+            let direction = second.rotation_matrix() * (second.center() - first.center());
+            if direction.norm() > 0.0 {
+                fixed.push(9 + 3 + direction.iamax());
+            }
+        }
+        fixed
+    }
+
+    /// Solve the damped system for camera and point steps by eliminating the
+    /// points: S = U - W V^-1 W^T, then back-substitute for each point.
+    /// The `gauge` parameters get no step.
+    fn solve_reduced(
+        problem: &BalProblem,
+        by_point: &[Vec<usize>],
+        system: &NormalEquations,
+        gauge: &[usize],
+        lambda: f64,
+    ) -> Option<(Vec<Vector9>, Vec<na::Vector3<f64>>)> {
+        let n = 9 * problem.cameras.len();
+        let mut reduced = na::DMatrix::<f64>::zeros(n, n);
+        let mut rhs = na::DVector::<f64>::zeros(n);
+        for (c, (u, gradient)) in system.cameras.iter().enumerate() {
+            let mut damped = *u;
+            for i in 0..9 {
+                damped[(i, i)] += lambda * u[(i, i)].max(1e-12);
+            }
+            reduced.fixed_view_mut::<9, 9>(9 * c, 9 * c).copy_from(&damped);
+            rhs.fixed_rows_mut::<9>(9 * c).copy_from(&-gradient);
+        }
+
+        let mut inverses = Vec::with_capacity(problem.points.len());
+        for (p, (v, gradient)) in system.points.iter().enumerate() {
+            let mut damped = *v;
+            for i in 0..3 {
+                damped[(i, i)] += lambda * v[(i, i)].max(1e-12);
+            }
+            let inverse = damped.cholesky()?.inverse();
+            for &a in &by_point[p] {
+                let ca = problem.observations[a].camera;
+                let coupled = system.coupling[a] * inverse;
+                let mut block = rhs.fixed_rows_mut::<9>(9 * ca);
+                block += coupled * gradient;
+                for &b in &by_point[p] {
+                    let cb = problem.observations[b].camera;
+                    let mut block = reduced.fixed_view_mut::<9, 9>(9 * ca, 9 * cb);
+                    block -= coupled * system.coupling[b].transpose();
+                }
+            }
+            inverses.push(inverse);
+        }
+
+        for &i in gauge {
+            reduced.row_mut(i).fill(0.0);
+            reduced.column_mut(i).fill(0.0);
+            reduced[(i, i)] = 1.0;
+            rhs[i] = 0.0;
+        }
+
+        let camera_steps = reduced.cholesky()?.solve(&rhs);
+        let camera_steps: Vec<Vector9> = (0..problem.cameras.len())
+            .map(|c| camera_steps.fixed_rows::<9>(9 * c).into_owned())
+            .collect();
+        let point_steps = system
+            .points
+            .iter()
+            .zip(&inverses)
+            .enumerate()
+            .map(|(p, ((_, gradient), inverse))| {
+                let coupled: na::Vector3<f64> = by_point[p]
+                    .iter()
+                    .map(|&a| {
+                        system.coupling[a].transpose()
+                            * camera_steps[problem.observations[a].camera]
+                    })
+                    .sum();
+                inverse * (-gradient - coupled)
+            })
+            .collect();
+        Some((camera_steps, point_steps))
+    }
+}
+
+/// One `solve` call, with the points eliminated from each damped system
+struct ReducedProblem<'a> {
+    adjuster: &'a BundleAdjuster,
+    by_point: Vec<Vec<usize>>,  // Observations of each point
+    gauge: Vec<usize>,
+    centroid: na::Vector3<f64>,  // Of the initial camera centres
+    max_distance: f64,
+}
+
+impl LeastSquares for ReducedProblem<'_> {
+    type State = BalProblem;
+    type System = NormalEquations;
+    type Step = (Vec<Vector9>, Vec<na::Vector3<f64>>);
+
+    fn cost(&self, problem: &BalProblem) -> f64 {
+        self.adjuster.cost(problem)
+    }
+
+    fn linearize(&self, problem: &BalProblem) -> NormalEquations {
+        self.adjuster.normal_equations(problem)
+    }
+
+    fn solve(
+        &self,
+        problem: &BalProblem,
+        system: &NormalEquations,
+        lambda: f64,
+    ) -> Option<Self::Step> {
+        BundleAdjuster::solve_reduced(problem, &self.by_point, system, &self.gauge, lambda)
+    }
+
+    fn step_norm(&self, (camera_steps, point_steps): &Self::Step) -> f64 {
+        (camera_steps.iter().map(|s| s.norm_squared()).sum::<f64>()
+            + point_steps.iter().map(|s| s.norm_squared()).sum::<f64>())
+        .sqrt()
+    }
+
+    fn predicted_reduction(&self, system: &NormalEquations, step: &Self::Step, lambda: f64) -> f64 {
+        BundleAdjuster::predicted_reduction(system, step, lambda)
+    }
+
+    /// Rejects steps that take a point out past `max_distance`. Points that
+    /// start out there may still move, as long as they come no further out.
+    fn apply(
+        &self,
+        problem: &BalProblem,
+        (camera_steps, point_steps): &Self::Step,
+    ) -> Option<BalProblem> {
+        let mut points = Vec::with_capacity(problem.points.len());
+        for (point, step) in problem.points.iter().zip(point_steps) {
+            let moved = point + step;
+            let distance = (moved - self.centroid).norm();
+            if distance > self.max_distance && distance > (point - self.centroid).norm() {
+                return None;
+            }
+            points.push(moved);
+        }
+        let cameras = problem.cameras.iter().zip(camera_steps);
+        Some(BalProblem {
+            cameras: cameras.map(|(camera, step)| camera.retract(step)).collect(),
+            points,
+            observations: problem.observations.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::graphs::ConvergenceStatus;
+    use crate::common::bal_io::BalObservation;
+
+    fn camera() -> SnavelyCamera {
+        SnavelyCamera::from_parameters(&[0.1, -0.2, 0.05, 0.3, -0.1, -4.0, 500.0, -0.05, 0.01])
+    }
+
+    #[test]
+    fn test_jacobians_match_finite_differences() {
+        let camera = camera();
+        let point = na::Vector3::new(0.4, -0.3, 0.5);
+        let (projected, camera_jacobian, point_jacobian) = camera.project_with_jacobians(&point);
+        assert!((projected - camera.project(&point)).norm() < 1e-12);
+
+        let eps = 1e-6;
+        for i in 0..9 {
+            let mut delta = Vector9::zeros();
+            delta[i] = eps;
+            let (plus, minus) = (camera.retract(&delta), camera.retract(&-delta));
+            let numeric = (plus.project(&point) - minus.project(&point)) / (2.0 * eps);
+            let error = (numeric - camera_jacobian.column(i)).norm();
+            assert!(error < 1e-4 * (1.0 + numeric.norm()), "camera column {}", i);
+        }
+        for i in 0..3 {
+            let mut delta = na::Vector3::zeros();
+            delta[i] = eps;
+            let numeric =
+                (camera.project(&(point + delta)) - camera.project(&(point - delta))) / (2.0 * eps);
+            let error = (numeric - point_jacobian.column(i)).norm();
+            assert!(error < 1e-4 * (1.0 + numeric.norm()), "point column {}", i);
+        }
+    }
+
+    #[test]
+    fn test_recovers_perturbed_scene() {
+        // Five cameras on an arc looking at a cloud of points around the origin
+        let truth_cameras: Vec<SnavelyCamera> = (0..5)
+            .map(|i| {
+                let yaw = (i as f64 - 2.0) * 0.15;
+                let rotation = na::Rotation3::new(na::Vector3::new(0.0, yaw, 0.0));
+                let center = rotation.inverse() * na::Vector3::new(0.0, 0.0, 5.0);
+                SnavelyCamera {
+                    rotation: rotation.scaled_axis(),
+                    translation: -(rotation * center),
+                    focal: 400.0,
+                    k1: 0.01,
+                    k2: 0.0,
+                }
+            })
+            .collect();
+        let truth_points: Vec<na::Vector3<f64>> = (0..60)
+            .map(|i| {
+                let i = i as f64;
+                na::Vector3::new((i * 0.7).sin(), (i * 1.3).cos() * 0.8, (i * 0.37).sin() * 0.5)
+            })
+            .collect();
+        let observations: Vec<BalObservation> = (0..5)
+            .flat_map(|c| (0..60).map(move |p| (c, p)))
+            .map(|(camera, point)| BalObservation {
+                camera,
+                point,
+                pixel: truth_cameras[camera].project(&truth_points[point]),
+            })
+            .collect();
+
+        let mut problem = BalProblem {
+            cameras: truth_cameras
+                .iter()
+                .enumerate()
+                .map(|(i, camera)| {
+                    camera.retract(&(Vector9::from_fn(|k, _| ((i * 9 + k) as f64).sin()) * 1e-3))
+                })
+                .collect(),
+            points: truth_points
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    p + na::Vector3::new((i as f64).cos(), (i as f64 * 2.0).sin(), 0.5) * 0.02
+                })
+                .collect(),
+            observations,
+        };
+        let first = problem.cameras[0].clone();
+        let report = BundleAdjuster::new(BundleAdjustmentConfig::default()).solve(&mut problem);
+        assert_eq!(report.optimization.status, ConvergenceStatus::Converged, "{:?}", report);
+        assert!(report.initial_rms > 1.0);
+        assert!(report.final_rms < 1e-4, "{:?}", report);
+        assert!(report.optimization.final_cost < report.optimization.initial_cost);
+
+        // The first camera holds the gauge, so the scene settles around it
+        assert!((problem.cameras[0].rotation - first.rotation).norm() < 1e-12);
+        assert!((problem.cameras[0].translation - first.translation).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_points_stay_in_range_on_bundled_problem() {
+        use crate::common::bal_io::load_bal;
+        use crate::common::robust_kernel::HuberKernel;
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../data/ba/problem-49-7776-pre.txt");
+        let mut problem = load_bal(path).unwrap();
+        let farthest =
+            |problem: &BalProblem| problem.points.iter().map(|p| p.norm()).fold(0.0, f64::max);
+        assert!(farthest(&problem) < 1e3);
+
+        let config = BundleAdjustmentConfig::default().with_kernel(HuberKernel::new(5f64.sqrt()));
+        let report = BundleAdjuster::new(config).solve(&mut problem);
+        assert_eq!(report.optimization.status, ConvergenceStatus::Converged);
+        assert!(report.final_rms < 1.0, "{}", report.final_rms);
+        // Points seen with little parallax used to drift out past 1e9 on a flat cost
+        assert!(farthest(&problem) < 1e4, "{}", farthest(&problem));
+        assert!(report.optimization.iterations.iter().all(|summary| summary.step_norm < 1e4));
+    }
+}
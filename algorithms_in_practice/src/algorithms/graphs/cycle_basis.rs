@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// One step of a cycle: an edge index and whether the walk follows the edge
+/// from its first endpoint to its second
+pub type CycleStep = (usize, bool);
+
+/// Shortest-path tree grown from one root
+struct PathTree {
+    distance: Vec<f64>,            // Infinite for nodes the root cannot reach
+    depth: Vec<usize>,
+    parent_edge: Vec<Option<usize>>,
+    branch: Vec<usize>,            // First node after the root on the way to each node
+}
+
+/// Minimum cycle basis of an undirected multigraph with non-negative edge
+/// weights, using Horton's algorithm. Every shortest-path tree contributes one
+/// candidate cycle per non-tree edge whose two tree paths only meet at the
+/// root; candidates are taken cheapest first and kept while they stay
+/// independent over GF(2), until the basis spans all `m - n + c` independent
+/// cycles. Parallel edges and self-loops are cycles of their own.
+///
+/// `edges` are `(first, second, weight)` over nodes `0..node_count`. Each cycle
+/// is returned as the ordered walk from its root back to itself.
+pub fn minimum_cycle_basis(
+    node_count: usize,
+    edges: &[(usize, usize, f64)],
+) -> Vec<Vec<CycleStep>> {
+    let rank = edges.len() + component_count(node_count, edges) - node_count;
+    if rank == 0 {
+        return Vec::new();
+    }
+
+    let mut incident = vec![Vec::new(); node_count];
+    for (k, &(a, b, _)) in edges.iter().enumerate() {
+        incident[a].push(k);
+        if b != a {
+            incident[b].push(k);
+        }
+    }
+    let trees: Vec<PathTree> =
+        (0..node_count).map(|root| path_tree(root, edges, &incident)).collect();
+
+    // Candidates are only weighed here; their edges are collected once they are examined
+    let mut candidates = Vec::new();
+    for (root, tree) in trees.iter().enumerate() {
+        for (k, &(a, b, weight)) in edges.iter().enumerate() {
+            if tree.parent_edge[a] == Some(k) || tree.parent_edge[b] == Some(k) {
+                continue;
+            }
+            if !tree.distance[a].is_finite() || !tree.distance[b].is_finite() {
+                continue;
+            }
+            if a != root && b != root && tree.branch[a] == tree.branch[b] {
+                continue;
+            }
+            let cost = tree.distance[a] + weight + tree.distance[b];
+            candidates.push((cost, tree.depth[a] + tree.depth[b] + 1, root, k));
+        }
+    }
+    candidates.sort_by(|x, y| x.0.total_cmp(&y.0).then(x.1.cmp(&y.1)));
+
+    let words = edges.len().div_ceil(64);
+    let mut reduced: Vec<(usize, Vec<u64>)> = Vec::new();  // Pivot bit and row, in insertion order
+    let mut basis = Vec::with_capacity(rank);
+    for &(_, _, root, k) in &candidates {
+        let cycle = trace_cycle(root, k, edges, &trees[root]);
+        let mut row = vec![0u64; words];
+        for &(edge, _) in &cycle {
+            row[edge / 64] ^= 1 << (edge % 64);
+        }
+        for (pivot, pivot_row) in &reduced {
+            if row[pivot / 64] & (1 << (pivot % 64)) != 0 {
+                row.iter_mut().zip(pivot_row).for_each(|(bits, other)| *bits ^= other);
+            }
+        }
+        let Some(pivot) = lowest_bit(&row) else {
+            continue;
+        };
+        reduced.push((pivot, row));
+        basis.push(cycle);
+        if basis.len() == rank {
+            break;
+        }
+    }
+    basis
+}
+
+fn component_count(node_count: usize, edges: &[(usize, usize, f64)]) -> usize {
+    let mut parent: Vec<usize> = (0..node_count).collect();
+    fn root(parent: &mut [usize], mut node: usize) -> usize {
+        while parent[node] != node {
+            parent[node] = parent[parent[node]];
+            node = parent[node];
+        }
+        node
+    }
+    let mut components = node_count;
+    for &(a, b, _) in edges {
+        let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+        if ra != rb {
+            parent[ra] = rb;
+            components -= 1;
+        }
+    }
+    components
+}
+
+/// Dijkstra from `root`, breaking ties towards fewer edges so unit weights
+/// give breadth-first trees
+fn path_tree(root: usize, edges: &[(usize, usize, f64)], incident: &[Vec<usize>]) -> PathTree {
+    let n = incident.len();
+    let mut tree = PathTree {
+        distance: vec![f64::INFINITY; n],
+        depth: vec![0; n],
+        parent_edge: vec![None; n],
+        branch: vec![root; n],
+    };
+    tree.distance[root] = 0.0;
+    let mut frontier = BinaryHeap::new();
+    frontier.push(Frontier(0.0, 0, root));
+    while let Some(Frontier(distance, depth, node)) = frontier.pop() {
+        if (distance, depth) > (tree.distance[node], tree.depth[node]) {
+            continue;
+        }
+        for &k in &incident[node] {
+            let (a, b, weight) = edges[k];
+            let next = if a == node { b } else { a };
+            let candidate = (distance + weight, depth + 1);
+            if candidate >= (tree.distance[next], tree.depth[next]) {
+                continue;
+            }
+            tree.distance[next] = candidate.0;
+            tree.depth[next] = candidate.1;
+            tree.parent_edge[next] = Some(k);
+            tree.branch[next] = if node == root { next } else { tree.branch[node] };
+            frontier.push(Frontier(candidate.0, candidate.1, next));
+        }
+    }
+    tree
+}
+
+/// Walk root -> first endpoint of edge `k` -> second endpoint -> root along the tree
+fn trace_cycle(
+    root: usize,
+    k: usize,
+    edges: &[(usize, usize, f64)],
+    tree: &PathTree,
+) -> Vec<CycleStep> {
+    let climb = |mut node: usize| {
+        let mut steps = Vec::with_capacity(tree.depth[node]);
+        while node != root {
+            let edge = tree.parent_edge[node].unwrap();
+            let (a, b, _) = edges[edge];
+            steps.push((edge, a == node));
+            node = if a == node { b } else { a };
+        }
+        steps
+    };
+    let (a, b, _) = edges[k];
+    let mut cycle: Vec<CycleStep> =
+        climb(a).into_iter().rev().map(|(edge, forward)| (edge, !forward)).collect();
+    cycle.push((k, true));
+    cycle.extend(climb(b));
+    cycle
+}
+
+fn lowest_bit(row: &[u64]) -> Option<usize> {
+    row.iter()
+        .position(|&bits| bits != 0)
+        .map(|word| word * 64 + row[word].trailing_zeros() as usize)
+}
+
+/// Min-heap entry keyed on (distance, depth)
+struct Frontier(f64, usize, usize);
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.total_cmp(&self.0).then(other.1.cmp(&self.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(edges: &[(usize, usize)]) -> Vec<(usize, usize, f64)> {
+        edges.iter().map(|&(a, b)| (a, b, 1.0)).collect()
+    }
+
+    /// Every step must leave from the node the previous one arrived at
+    fn is_closed_walk(cycle: &[CycleStep], edges: &[(usize, usize, f64)]) -> bool {
+        let ends = |(k, forward): CycleStep| {
+            let (a, b, _) = edges[k];
+            if forward { (a, b) } else { (b, a) }
+        };
+        let start = ends(cycle[0]).0;
+        let end = cycle.iter().try_fold(start, |at, &step| {
+            let (from, to) = ends(step);
+            (from == at).then_some(to)
+        });
+        end == Some(start)
+    }
+
+    #[test]
+    fn test_grid_basis_is_its_unit_squares() {
+        // 3x3 grid of nodes: four squares, plus a long way round that must not be picked
+        let mut grid = Vec::new();
+        for row in 0..3 {
+            for col in 0..3 {
+                let node = row * 3 + col;
+                if col < 2 {
+                    grid.push((node, node + 1));
+                }
+                if row < 2 {
+                    grid.push((node + 3, node));
+                }
+            }
+        }
+        let edges = unit(&grid);
+        let basis = minimum_cycle_basis(9, &edges);
+
+        assert_eq!(basis.len(), 4);
+        assert!(basis.iter().all(|cycle| cycle.len() == 4));
+        assert!(basis.iter().all(|cycle| is_closed_walk(cycle, &edges)));
+    }
+
+    #[test]
+    fn test_parallel_edges_and_self_loops_are_cycles() {
+        // Triangle 0-1-2 with a repeated 0-1 edge, a self-loop on 2 and a tree edge to 3
+        let edges = unit(&[(0, 1), (1, 2), (2, 0), (1, 0), (2, 2), (2, 3)]);
+        let mut basis = minimum_cycle_basis(4, &edges);
+        basis.sort_by_key(|cycle| cycle.len());
+
+        assert_eq!(basis.iter().map(Vec::len).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(basis[0], vec![(4, true)]);
+        assert!(basis.iter().all(|cycle| is_closed_walk(cycle, &edges)));
+        assert!(basis.iter().flatten().all(|&(k, _)| k != 5));
+
+        // A forest has no cycles
+        assert!(minimum_cycle_basis(4, &unit(&[(0, 1), (2, 3)])).is_empty());
+    }
+}
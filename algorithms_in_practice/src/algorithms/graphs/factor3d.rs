@@ -0,0 +1,27 @@
+use nalgebra as na;
+
+use crate::common::lie::{SE3, SO3};
+
+use super::factor_graph::{CircularFactorGraph, Factor, PoseGroup, Transform2D};
+
+pub type Factor3D = Factor<SE3, 6>;
+pub type FactorGraph3D = CircularFactorGraph<SE3, 6>;
+
+impl PoseGroup<6> for SE3 {}
+
+/// A planar pose lifted into the z = 0 plane
+impl From<Transform2D> for SE3 {
+    fn from(pose: Transform2D) -> Self {
+        let rotation = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), pose.theta);
+        SE3::new(SO3::new(rotation), na::Vector3::new(pose.x, pose.y, 0.0))
+    }
+}
+
+impl Factor<SE3, 6> {
+    /// Independent standard deviations for the translation and rotation axes
+    pub fn with_diagonal_std(self, sigma_translation: f64, sigma_rotation: f64) -> Self {
+        let t = sigma_translation;
+        let r = sigma_rotation;
+        self.with_std_devs(&na::Vector6::new(t, t, t, r, r, r))
+    }
+}
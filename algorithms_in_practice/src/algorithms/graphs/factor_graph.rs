@@ -1,6 +1,19 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use nalgebra as na;
 
+use crate::common::lie::{LieGroup, SE2};
+use crate::common::robust_kernel::RobustKernel;
+use crate::common::statistics::{chi_square_cdf, chi_square_quantile};
+
+use super::cycle_basis;
+use super::factor_health::{
+    CycleConsistency, FactorConsistency, FactorStatus, OutlierPolicy, SensorHealth,
+};
+use super::landmark_factor::{test_landmark_cycle, LandmarkFactor};
+use super::marginalization::{marginalize_node, EvictionPolicy, MarginalPrior};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Transform2D {
     pub x: f64,
@@ -15,32 +28,95 @@ impl Transform2D {
 
     /// Compose two transforms
     pub fn compose(&self, other: &Transform2D) -> Transform2D {
-        let cos_theta = self.theta.cos();
-        let sin_theta = self.theta.sin();
-        
-        Transform2D {
-            x: self.x + other.x * cos_theta - other.y * sin_theta,
-            y: self.y + other.x * sin_theta + other.y * cos_theta,
-            theta: (self.theta + other.theta) % (2.0 * std::f64::consts::PI),
-        }
+        Transform2D::from_se2(&self.to_se2().compose(&other.to_se2()))
     }
 
     /// Calculate the inverse transform
     pub fn inverse(&self) -> Transform2D {
-        let cos_theta = self.theta.cos();
-        let sin_theta = self.theta.sin();
-        
-        Transform2D {
-            x: -self.x * cos_theta - self.y * sin_theta,
-            y: self.x * sin_theta - self.y * cos_theta,
-            theta: -self.theta,
-        }
+        Transform2D::from_se2(&self.to_se2().inverse())
+    }
+
+    pub fn to_se2(&self) -> SE2 {
+        SE2::new(self.x, self.y, self.theta)
+    }
+
+    pub fn from_se2(pose: &SE2) -> Transform2D {
+        Transform2D::new(pose.translation.x, pose.translation.y, pose.angle())
+    }
+
+    /// Stack the pose into an (x, y, theta) vector
+    pub fn to_vector(&self) -> na::Vector3<f64> {
+        na::Vector3::new(self.x, self.y, self.theta)
     }
 
     /// Calculate difference from identity transform
     pub fn error_from_identity(&self) -> f64 {
         (self.x * self.x + self.y * self.y + self.theta.sin().powi(2)).sqrt()
     }
+
+    /// Mahalanobis distance from the identity transform under the given information matrix
+    pub fn mahalanobis_from_identity(&self, information: &na::Matrix3<f64>) -> f64 {
+        let e = na::Vector3::new(self.x, self.y, normalize_angle(self.theta));
+        (e.transpose() * information * e)[(0, 0)].max(0.0).sqrt()
+    }
+
+    /// Adjoint of the transform, mapping tangent vectors in the local frame to the parent frame
+    pub fn adjoint(&self) -> na::Matrix3<f64> {
+        self.to_se2().adjoint()
+    }
+}
+
+/// Wrap an angle into [-pi, pi)
+pub fn normalize_angle(theta: f64) -> f64 {
+    let two_pi = 2.0 * std::f64::consts::PI;
+    (theta + std::f64::consts::PI).rem_euclid(two_pi) - std::f64::consts::PI
+}
+
+/// Lie group the factor graph can be built over. `D` is the dimension of the
+/// tangent space that information matrices live in.
+pub trait PoseGroup<const D: usize>: LieGroup<D> + std::fmt::Debug {
+    /// The transform as a planar pose, for groups whose graphs can hold point
+    /// landmark factors
+    fn planar(&self) -> Option<Transform2D> {
+        None
+    }
+}
+
+impl LieGroup<3> for Transform2D {
+    fn identity() -> Self {
+        Transform2D::new(0.0, 0.0, 0.0)
+    }
+
+    fn compose(&self, other: &Self) -> Self {
+        Transform2D::compose(self, other)
+    }
+
+    fn inverse(&self) -> Self {
+        Transform2D::inverse(self)
+    }
+
+    fn exp(tangent: &na::Vector3<f64>) -> Self {
+        Transform2D::from_se2(&SE2::exp(tangent))
+    }
+
+    /// (rho_x, rho_y, theta) with rho = V(theta)^-1 t
+    fn log(&self) -> na::Vector3<f64> {
+        self.to_se2().log()
+    }
+
+    fn adjoint(&self) -> na::Matrix3<f64> {
+        Transform2D::adjoint(self)
+    }
+
+    fn left_jacobian(tangent: &na::Vector3<f64>) -> na::Matrix3<f64> {
+        SE2::left_jacobian(tangent)
+    }
+}
+
+impl PoseGroup<3> for Transform2D {
+    fn planar(&self) -> Option<Transform2D> {
+        Some(self.clone())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -49,74 +125,691 @@ pub enum NodeType {
     Landmark(String),     // Named landmark
 }
 
-#[derive(Debug)]
-pub struct Factor {
+#[derive(Debug, Clone)]
+pub struct Factor<P = Transform2D, const D: usize = 3> {
+    pub id: u64,  // Assigned by the graph when the factor is added
     pub source: NodeType,
     pub target: NodeType,
-    pub transform: Transform2D,
-    pub uncertainty: f64,
+    pub transform: P,
+    pub information: na::SMatrix<f64, D, D>,  // Inverse covariance in the tangent space of P
     pub sensor_type: String,
     pub timestamp: u64,
+    pub kernel: Option<Arc<dyn RobustKernel>>,  // Overrides the graph's per-sensor kernel
+    pub status: FactorStatus,
+}
+
+impl<P: PoseGroup<D>, const D: usize> Factor<P, D> {
+    /// Create a factor with unit information; chain one of the `with_*` methods
+    /// to set the noise model
+    pub fn new(
+        source: NodeType,
+        target: NodeType,
+        transform: P,
+        sensor_type: &str,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            id: 0,
+            source,
+            target,
+            transform,
+            information: na::SMatrix::identity(),
+            sensor_type: sensor_type.to_string(),
+            timestamp,
+            kernel: None,
+            status: FactorStatus::Active,
+        }
+    }
+
+    /// Set the full information matrix
+    pub fn with_information(mut self, information: na::SMatrix<f64, D, D>) -> Self {
+        self.information = information;
+        self
+    }
+
+    /// Set the noise model from a covariance matrix. A singular covariance is
+    /// regularized before inversion.
+    pub fn with_covariance(mut self, covariance: na::SMatrix<f64, D, D>) -> Self {
+        self.information = covariance
+            .try_inverse()
+            .or_else(|| (covariance + na::SMatrix::identity() * 1e-12).try_inverse())
+            .unwrap_or_else(na::SMatrix::zeros);
+        self
+    }
+
+    /// Independent standard deviation for every tangent-space axis
+    pub fn with_std_devs(self, sigmas: &na::SVector<f64, D>) -> Self {
+        self.with_covariance(na::SMatrix::from_diagonal(&sigmas.component_mul(sigmas)))
+    }
+
+    /// Same standard deviation for every tangent-space axis
+    pub fn with_isotropic_std(self, sigma: f64) -> Self {
+        self.with_std_devs(&na::SVector::repeat(sigma))
+    }
+
+    /// Attach a robust kernel to this factor only
+    pub fn with_kernel<K: RobustKernel + 'static>(mut self, kernel: K) -> Self {
+        self.kernel = Some(Arc::new(kernel));
+        self
+    }
+
+    /// Covariance implied by the information matrix, if it is invertible
+    pub fn covariance(&self) -> Option<na::SMatrix<f64, D, D>> {
+        self.information.try_inverse()
+    }
+
+    /// The same measurement expressed from target to source. Right-perturbation
+    /// noise on `T` becomes `-Ad(T) e` on `T^-1`, so the information is mapped
+    /// through `Ad(T^-1)`.
+    pub fn inverted(&self) -> Self {
+        let inverse = self.transform.inverse();
+        let adjoint = inverse.adjoint();
+        Self {
+            source: self.target.clone(),
+            target: self.source.clone(),
+            information: adjoint.transpose() * self.information * adjoint,
+            transform: inverse,
+            ..self.clone()
+        }
+    }
+}
+
+impl Factor<Transform2D, 3> {
+    /// Independent standard deviations for x, y and theta
+    pub fn with_diagonal_std(self, sigma_x: f64, sigma_y: f64, sigma_theta: f64) -> Self {
+        self.with_std_devs(&na::Vector3::new(sigma_x, sigma_y, sigma_theta))
+    }
+}
+
+/// One step of a cycle through the graph: a relative-pose factor or an
+/// observation of a point landmark
+#[derive(Debug, Clone)]
+pub enum CycleEdge<P = Transform2D, const D: usize = 3> {
+    Relative(Factor<P, D>),
+    Landmark(LandmarkFactor),
+}
+
+impl<P, const D: usize> CycleEdge<P, D> {
+    pub fn id(&self) -> u64 {
+        match self {
+            CycleEdge::Relative(factor) => factor.id,
+            CycleEdge::Landmark(factor) => factor.id,
+        }
+    }
+
+    pub fn source(&self) -> &NodeType {
+        match self {
+            CycleEdge::Relative(factor) => &factor.source,
+            CycleEdge::Landmark(factor) => &factor.source,
+        }
+    }
+
+    pub fn target(&self) -> &NodeType {
+        match self {
+            CycleEdge::Relative(factor) => &factor.target,
+            CycleEdge::Landmark(factor) => &factor.target,
+        }
+    }
+
+    pub fn sensor_type(&self) -> &str {
+        match self {
+            CycleEdge::Relative(factor) => &factor.sensor_type,
+            CycleEdge::Landmark(factor) => &factor.sensor_type,
+        }
+    }
+
+    pub fn status(&self) -> FactorStatus {
+        match self {
+            CycleEdge::Relative(factor) => factor.status,
+            CycleEdge::Landmark(factor) => factor.status,
+        }
+    }
+
+    fn kernel(&self) -> Option<&Arc<dyn RobustKernel>> {
+        match self {
+            CycleEdge::Relative(factor) => factor.kernel.as_ref(),
+            CycleEdge::Landmark(factor) => factor.kernel.as_ref(),
+        }
+    }
+}
+
+impl<P: PoseGroup<D>, const D: usize> CycleEdge<P, D> {
+    /// Trace of the measurement covariance, the cost of the edge when searching
+    /// for the least uncertain loop. Mixes units across components, so it only
+    /// ranks factors from comparable noise models.
+    pub fn uncertainty(&self) -> f64 {
+        let trace = match self {
+            CycleEdge::Relative(factor) => factor.covariance().map(|c| c.trace()),
+            CycleEdge::Landmark(factor) => factor.covariance().map(|c| c.trace()),
+        };
+        trace.filter(|t| t.is_finite() && *t >= 0.0).unwrap_or(1e9)
+    }
+}
+
+impl<P, const D: usize> From<Factor<P, D>> for CycleEdge<P, D> {
+    fn from(factor: Factor<P, D>) -> Self {
+        CycleEdge::Relative(factor)
+    }
+}
+
+impl<P, const D: usize> From<LandmarkFactor> for CycleEdge<P, D> {
+    fn from(factor: LandmarkFactor) -> Self {
+        CycleEdge::Landmark(factor)
+    }
+}
+
+/// A factor as stored in the graph, together with the direction a loop walks
+/// it in. A reversed relative factor is composed as its inverse.
+#[derive(Debug, Clone)]
+pub struct OrientedEdge<P = Transform2D, const D: usize = 3> {
+    pub edge: CycleEdge<P, D>,
+    pub reversed: bool,  // Walked from target to source
+}
+
+impl<P, const D: usize> OrientedEdge<P, D> {
+    pub fn forward(edge: impl Into<CycleEdge<P, D>>) -> Self {
+        Self { edge: edge.into(), reversed: false }
+    }
+
+    pub fn backward(edge: impl Into<CycleEdge<P, D>>) -> Self {
+        Self { edge: edge.into(), reversed: true }
+    }
+
+    /// Walk `edge` away from `node`, which should be one of its endpoints
+    pub fn leaving(edge: CycleEdge<P, D>, node: &NodeType) -> Self {
+        let reversed = edge.source() != node;
+        Self { edge, reversed }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.edge.id()
+    }
+
+    /// Node the walk leaves from
+    pub fn start(&self) -> &NodeType {
+        if self.reversed { self.edge.target() } else { self.edge.source() }
+    }
+
+    /// Node the walk arrives at
+    pub fn end(&self) -> &NodeType {
+        if self.reversed { self.edge.source() } else { self.edge.target() }
+    }
+
+    /// The same edge walked the other way
+    pub fn flipped(self) -> Self {
+        Self { reversed: !self.reversed, ..self }
+    }
+}
+
+impl<P: PoseGroup<D>, const D: usize> OrientedEdge<P, D> {
+    /// The relative factor as walked, inverted if reversed. `None` for
+    /// landmark observations, which keep their measurement model's direction.
+    pub fn walked_factor(&self) -> Option<Factor<P, D>> {
+        match &self.edge {
+            CycleEdge::Relative(factor) if self.reversed => Some(factor.inverted()),
+            CycleEdge::Relative(factor) => Some(factor.clone()),
+            CycleEdge::Landmark(_) => None,
+        }
+    }
 }
 
-pub struct CircularFactorGraph {
+impl<P, const D: usize> From<CycleEdge<P, D>> for OrientedEdge<P, D> {
+    fn from(edge: CycleEdge<P, D>) -> Self {
+        OrientedEdge::forward(edge)
+    }
+}
+
+impl<P, const D: usize> From<Factor<P, D>> for OrientedEdge<P, D> {
+    fn from(factor: Factor<P, D>) -> Self {
+        OrientedEdge::forward(factor)
+    }
+}
+
+impl<P, const D: usize> From<LandmarkFactor> for OrientedEdge<P, D> {
+    fn from(factor: LandmarkFactor) -> Self {
+        OrientedEdge::forward(factor)
+    }
+}
+
+/// How `add_factor` picks the path that a new factor closes into a loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CycleSearch {
+    FewestFactors,   // Breadth-first by hop count
+    LeastUncertain,  // Dijkstra on each factor's `CycleEdge::uncertainty`
+    DepthFirst,      // First path of a depth-first scan over every factor, without the index
+}
+
+pub struct CircularFactorGraph<P = Transform2D, const D: usize = 3> {
     nodes: VecDeque<NodeType>,
-    factors: VecDeque<Factor>,
+    factors: VecDeque<Factor<P, D>>,
+    landmark_factors: VecDeque<LandmarkFactor>,  // Share the factor budget and id sequence
     max_nodes: usize,
     max_factors: usize,
+    adjacency: HashMap<NodeType, Vec<u64>>,  // Ids of the factors touching each node in the window
+    cycle_search: CycleSearch,
     parent: HashMap<NodeType, NodeType>,  // For Union-Find
     rank: HashMap<NodeType, usize>,       // For Union-Find optimization
+    connectivity_stale: bool,             // Evictions since the Union-Find was last rebuilt
+    sensor_kernels: HashMap<String, Arc<dyn RobustKernel>>,
+    next_factor_id: u64,
+    consistency: HashMap<u64, FactorConsistency>,  // Keyed by factor id
+    outlier_policy: OutlierPolicy,
+    consistency_confidence: f64,  // Chi-square confidence used by check_cycle_consistency
+    eviction_policy: EvictionPolicy,
+    priors: Vec<MarginalPrior<P, D>>,  // Information left behind by marginalized nodes
 }
 
-impl CircularFactorGraph {
+impl<P: PoseGroup<D>, const D: usize> CircularFactorGraph<P, D> {
     pub fn new(max_nodes: usize, max_factors: usize) -> Self {
         Self {
             nodes: VecDeque::new(),
             factors: VecDeque::new(),
+            landmark_factors: VecDeque::new(),
             max_nodes,
             max_factors,
+            adjacency: HashMap::new(),
+            cycle_search: CycleSearch::FewestFactors,
             parent: HashMap::new(),
             rank: HashMap::new(),
+            connectivity_stale: false,
+            sensor_kernels: HashMap::new(),
+            next_factor_id: 0,
+            consistency: HashMap::new(),
+            outlier_policy: OutlierPolicy::default(),
+            consistency_confidence: 0.99,
+            eviction_policy: EvictionPolicy::Drop,
+            priors: Vec::new(),
         }
     }
 
-    /// Add a new node to the graph
+    /// Choose whether nodes leaving the window are dropped or marginalized
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.eviction_policy = policy;
+    }
+
+    /// Choose how the loop closed by a new factor is extracted
+    pub fn set_cycle_search(&mut self, search: CycleSearch) {
+        self.cycle_search = search;
+    }
+
+    /// Confidence level of the chi-square gate used when scoring cycles
+    pub fn set_consistency_confidence(&mut self, confidence: f64) {
+        self.consistency_confidence = confidence.clamp(0.0, 1.0);
+    }
+
+    /// Replace the rule used to flag factors that keep closing inconsistent cycles
+    pub fn set_outlier_policy(&mut self, policy: OutlierPolicy) {
+        self.outlier_policy = policy;
+    }
+
+    /// Use a robust kernel for every factor from `sensor_type` that has no kernel of its own
+    pub fn set_sensor_kernel<K: RobustKernel + 'static>(&mut self, sensor_type: &str, kernel: K) {
+        self.sensor_kernels.insert(sensor_type.to_string(), Arc::new(kernel));
+    }
+
+    /// Kernel that applies to a factor: its own, else its sensor's, else none (plain L2)
+    pub fn kernel_for<'a>(&'a self, factor: &'a Factor<P, D>) -> Option<&'a dyn RobustKernel> {
+        factor
+            .kernel
+            .as_deref()
+            .or_else(|| self.sensor_kernels.get(&factor.sensor_type).map(|k| k.as_ref()))
+    }
+
+    /// Kernel that applies to a relative or landmark factor, by the same rule as `kernel_for`
+    pub fn kernel_for_edge<'a>(
+        &'a self,
+        edge: &'a CycleEdge<P, D>,
+    ) -> Option<&'a dyn RobustKernel> {
+        edge.kernel()
+            .map(|k| k.as_ref())
+            .or_else(|| self.sensor_kernels.get(edge.sensor_type()).map(|k| k.as_ref()))
+    }
+
+    /// Kernel that applies to a landmark factor, by the same rule as `kernel_for`
+    pub fn kernel_for_landmark<'a>(
+        &'a self,
+        factor: &'a LandmarkFactor,
+    ) -> Option<&'a dyn RobustKernel> {
+        factor
+            .kernel
+            .as_deref()
+            .or_else(|| self.sensor_kernels.get(&factor.sensor_type).map(|k| k.as_ref()))
+    }
+
+    /// Add a new node to the graph. Adding a node that is already in the window
+    /// does nothing.
     pub fn add_node(&mut self, node: NodeType) {
+        if self.adjacency.contains_key(&node) {
+            return;
+        }
         if self.nodes.len() >= self.max_nodes {
             // Remove oldest node and its associated factors
             if let Some(old_node) = self.nodes.pop_front() {
-                self.factors.retain(|factor| 
-                    factor.source != old_node && factor.target != old_node
-                );
-                self.parent.remove(&old_node);
-                self.rank.remove(&old_node);
+                self.evict_node(&old_node);
             }
         }
         
+        // Evicted nodes linger in the Union-Find until it is rebuilt. Nodes still
+        // in the window may point through an evicted one, so it is rebuilt before
+        // an evicted id comes back rather than resetting that id's parent.
+        let returning = self.parent.contains_key(&node);
+        if returning
+            || (self.connectivity_stale && self.parent.len() >= 2 * self.max_nodes.max(1))
+        {
+            self.rebuild_union_find();
+        }
+
         self.nodes.push_back(node.clone());
+        self.adjacency.insert(node.clone(), Vec::new());
         self.parent.insert(node.clone(), node.clone());
         self.rank.insert(node, 0);
     }
 
-    /// Add a new factor (edge) to the graph
-    pub fn add_factor(&mut self, factor: Factor) -> Option<Vec<Factor>> {
+    /// Remove every factor touching `node`, first folding the relative factors
+    /// and any prior on `node` into a new prior on its neighbours when
+    /// marginalizing. Landmark observations of or from `node` are dropped.
+    fn evict_node(&mut self, node: &NodeType) {
+        let incident = self.adjacency.remove(node).unwrap_or_default();
+        if self.eviction_policy == EvictionPolicy::Marginalize {
+            let (absorbed, kept): (Vec<_>, Vec<_>) =
+                self.priors.drain(..).partition(|prior| prior.contains(node));
+            self.priors = kept;
+
+            let touching: Vec<(&Factor<P, D>, Option<&dyn RobustKernel>)> = incident
+                .iter()
+                .filter_map(|&id| self.factor(id))
+                .filter(|f| f.status.weight() > 0.0)
+                .map(|f| (f, self.kernel_for(f)))
+                .collect();
+            if let Some(prior) = marginalize_node(node, &touching, &absorbed) {
+                self.priors.push(prior);
+            }
+        } else {
+            self.priors.retain(|prior| !prior.contains(node));
+        }
+
+        for &id in &incident {
+            if let Some((source, target)) = self.endpoints(id) {
+                self.unlink(id, &source, &target);
+            }
+        }
+        let consistency = &mut self.consistency;
+        self.factors.retain(|factor| {
+            let keep = factor.source != *node && factor.target != *node;
+            if !keep {
+                consistency.remove(&factor.id);
+            }
+            keep
+        });
+        self.landmark_factors.retain(|factor| {
+            let keep = factor.source != *node && factor.target != *node;
+            if !keep {
+                consistency.remove(&factor.id);
+            }
+            keep
+        });
+        self.connectivity_stale = true;
+    }
+
+    /// Recompute connectivity from the factors and priors left in the window
+    fn rebuild_union_find(&mut self) {
+        self.connectivity_stale = false;
+        self.parent = self.nodes.iter().map(|n| (n.clone(), n.clone())).collect();
+        self.rank = self.nodes.iter().map(|n| (n.clone(), 0)).collect();
+
+        let mut links: Vec<(NodeType, NodeType)> = self
+            .factors
+            .iter()
+            .filter(|f| f.status != FactorStatus::Disabled)
+            .map(|f| (f.source.clone(), f.target.clone()))
+            .collect();
+        links.extend(
+            self.landmark_factors
+                .iter()
+                .filter(|f| f.status != FactorStatus::Disabled)
+                .map(|f| (f.source.clone(), f.target.clone())),
+        );
+        for prior in &self.priors {
+            for node in &prior.nodes[1..] {
+                links.push((prior.nodes[0].clone(), node.clone()));
+            }
+        }
+        for (a, b) in links {
+            if self.parent.contains_key(&a) && self.parent.contains_key(&b) {
+                self.union(&a, &b);
+            }
+        }
+    }
+
+    /// Priors created by marginalizing evicted nodes
+    pub fn priors(&self) -> impl Iterator<Item = &MarginalPrior<P, D>> {
+        self.priors.iter()
+    }
+
+    /// Add a new factor (edge) to the graph. If the factor closes a cycle,
+    /// returns the loop it closed: the existing path from the factor's source
+    /// to its target, then the new factor walked back. The loop is scored and
+    /// recorded against every factor in it.
+    pub fn add_factor(&mut self, factor: Factor<P, D>) -> Option<Vec<OrientedEdge<P, D>>> {
+        self.insert_edge(CycleEdge::Relative(factor))
+    }
+
+    fn insert_edge(&mut self, mut edge: CycleEdge<P, D>) -> Option<Vec<OrientedEdge<P, D>>> {
         // Ensure nodes exist
-        if !self.nodes.contains(&factor.source) || !self.nodes.contains(&factor.target) {
+        if !self.adjacency.contains_key(edge.source())
+            || !self.adjacency.contains_key(edge.target())
+        {
             return None;
         }
 
+        let id = self.next_factor_id;
+        self.next_factor_id += 1;
+        match &mut edge {
+            CycleEdge::Relative(factor) => factor.id = id,
+            CycleEdge::Landmark(factor) => factor.id = id,
+        }
+
+        // Make room first, so the loop cannot run through the factor it evicts
+        if self.factors.len() + self.landmark_factors.len() >= self.max_factors {
+            let oldest_relative = self.factors.front().map(|f| f.id);
+            let oldest_landmark = self.landmark_factors.front().map(|f| f.id);
+            let old = match (oldest_relative, oldest_landmark) {
+                (Some(relative), Some(landmark)) if landmark < relative => {
+                    self.landmark_factors.pop_front().map(|f| (f.id, f.source, f.target))
+                }
+                (Some(_), _) => self.factors.pop_front().map(|f| (f.id, f.source, f.target)),
+                (None, _) => self.landmark_factors.pop_front().map(|f| (f.id, f.source, f.target)),
+            };
+            if let Some((old_id, source, target)) = old {
+                self.unlink(old_id, &source, &target);
+                self.consistency.remove(&old_id);
+                self.connectivity_stale = true;
+            }
+        }
+
         // Check for cycle before adding factor
-        let cycle = self.would_create_cycle(&factor);
-        
+        let path = self.would_create_cycle(edge.source(), edge.target());
+
         // Add factor to graph
-        if self.factors.len() >= self.max_factors {
-            self.factors.pop_front();
+        for end in [edge.source(), edge.target()] {
+            let incident = self.adjacency.get_mut(end).unwrap();
+            if incident.last() != Some(&id) {
+                incident.push(id);
+            }
+        }
+        match &edge {
+            CycleEdge::Relative(factor) => self.factors.push_back(factor.clone()),
+            CycleEdge::Landmark(factor) => self.landmark_factors.push_back(factor.clone()),
         }
-        self.factors.push_back(factor);
 
+        let start = edge.source().clone();
+        let cycle = path.map(|path| Self::close_loop(&start, path, edge));
+        if let Some(cycle) = &cycle {
+            self.record_cycle(cycle);
+        }
         cycle
     }
 
+    /// Drop a factor leaving the window from the adjacency of its endpoints
+    fn unlink(&mut self, id: u64, source: &NodeType, target: &NodeType) {
+        for end in [source, target] {
+            if let Some(incident) = self.adjacency.get_mut(end) {
+                if let Ok(k) = incident.binary_search(&id) {
+                    incident.remove(k);
+                }
+            }
+        }
+    }
+
+    /// Orient a path walked from `start`, then close it with the factor that
+    /// linked its two ends
+    fn close_loop(
+        start: &NodeType,
+        path: Vec<CycleEdge<P, D>>,
+        closing: CycleEdge<P, D>,
+    ) -> Vec<OrientedEdge<P, D>> {
+        let mut current = start.clone();
+        let mut oriented = Vec::with_capacity(path.len() + 1);
+        for edge in path {
+            let step = OrientedEdge::leaving(edge, &current);
+            current = step.end().clone();
+            oriented.push(step);
+        }
+        oriented.push(OrientedEdge::leaving(closing, &current));
+        oriented
+    }
+
+    /// Score a closed loop and add the result to the consistency record of every
+    /// factor in it, then re-judge those factors under the outlier policy
+    pub fn record_cycle<E>(&mut self, cycle: &[E]) -> (f64, bool)
+    where
+        E: Clone + Into<OrientedEdge<P, D>>,
+    {
+        let cycle: Vec<OrientedEdge<P, D>> = cycle.iter().cloned().map(Into::into).collect();
+        let (error, is_consistent) = self.check_cycle_consistency(&cycle);
+
+        let ids: HashSet<u64> = cycle.iter().map(|edge| edge.id()).collect();
+        for id in &ids {
+            let record = self.consistency.entry(*id).or_default();
+            record.record(error, is_consistent);
+            if !is_consistent {
+                let others = ids.iter().copied().filter(|other| other != id).collect();
+                record.inconsistent_loops.push(others);
+            }
+        }
+
+        // Blame moves between factors that failed a loop together as their
+        // records change, so those are re-judged along with the loop itself
+        let mut affected = ids.clone();
+        for id in &ids {
+            affected.extend(self.consistency[id].inconsistent_loops.iter().flatten());
+        }
+        affected.retain(|id| self.consistency.contains_key(id));
+        let blamed: Vec<(u64, usize)> =
+            affected.iter().map(|&id| (id, self.blamed_cycles(id))).collect();
+        for (id, blamed_cycles) in blamed {
+            self.consistency.get_mut(&id).unwrap().blamed_cycles = blamed_cycles;
+        }
+
+        let judge = |id: u64, status: &mut FactorStatus| {
+            if !affected.contains(&id) || *status == FactorStatus::Disabled {
+                return;
+            }
+            if let Some(judged) = self.outlier_policy.judge(&self.consistency[&id]) {
+                *status = judged;
+            }
+        };
+        for factor in self.factors.iter_mut() {
+            judge(factor.id, &mut factor.status);
+        }
+        for factor in self.landmark_factors.iter_mut() {
+            judge(factor.id, &mut factor.status);
+        }
+
+        (error, is_consistent)
+    }
+
+    /// Inconsistent cycles of a factor in which it failed more often than every
+    /// other factor still in the window
+    fn blamed_cycles(&self, id: u64) -> usize {
+        let record = &self.consistency[&id];
+        let rate = record.inconsistency_rate();
+        record
+            .inconsistent_loops
+            .iter()
+            .filter(|others| {
+                others.iter().all(|other| {
+                    self.consistency.get(other).is_none_or(|o| o.inconsistency_rate() < rate)
+                })
+            })
+            .count()
+    }
+
+    /// Consistency history of a factor still in the window
+    pub fn factor_consistency(&self, id: u64) -> Option<&FactorConsistency> {
+        self.consistency.get(&id)
+    }
+
+    /// Override a factor's status by hand, e.g. to restore a quarantined factor.
+    /// Returns false if the factor is no longer in the window.
+    pub fn set_factor_status(&mut self, id: u64, status: FactorStatus) -> bool {
+        let relative = self.factors.iter_mut().find(|factor| factor.id == id);
+        if let Some(factor) = relative {
+            factor.status = status;
+            return true;
+        }
+        match self.landmark_factors.iter_mut().find(|factor| factor.id == id) {
+            Some(factor) => {
+                factor.status = status;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Per-sensor consistency summary over the factors in the window, sorted by
+    /// sensor type. A sensor is suspected faulty once its factors have been in
+    /// enough cycles and are blamed for more of them than the outlier policy
+    /// tolerates.
+    pub fn sensor_report(&self) -> Vec<SensorHealth> {
+        let mut by_sensor: HashMap<&str, SensorHealth> = HashMap::new();
+        let factors = self
+            .factors
+            .iter()
+            .map(|f| (f.id, f.sensor_type.as_str(), f.status))
+            .chain(self.landmark_factors.iter().map(|f| (f.id, f.sensor_type.as_str(), f.status)));
+        for (id, sensor_type, status) in factors {
+            let health = by_sensor
+                .entry(sensor_type)
+                .or_insert_with(|| SensorHealth {
+                    sensor_type: sensor_type.to_string(),
+                    factor_count: 0,
+                    cycles: 0,
+                    inconsistent_cycles: 0,
+                    blamed_cycles: 0,
+                    flagged_factors: 0,
+                    suspected_faulty: false,
+                });
+            health.factor_count += 1;
+            if let Some(record) = self.consistency.get(&id) {
+                health.cycles += record.cycles;
+                health.inconsistent_cycles += record.inconsistent_cycles;
+                health.blamed_cycles += record.blamed_cycles;
+            }
+            if status != FactorStatus::Active {
+                health.flagged_factors += 1;
+            }
+        }
+
+        let mut report: Vec<SensorHealth> = by_sensor.into_values().collect();
+        for health in &mut report {
+            health.suspected_faulty = health.cycles >= self.outlier_policy.min_cycles
+                && health.blame_rate() >= self.outlier_policy.max_inconsistency_rate;
+        }
+        report.sort_by(|a, b| a.sensor_type.cmp(&b.sensor_type));
+        report
+    }
+
     /// Find the root node in the Union-Find structure
     fn find(&mut self, node: &NodeType) -> NodeType {
         let parent_node = self.parent.get(node).unwrap().clone();
@@ -143,86 +836,380 @@ impl CircularFactorGraph {
             } else if rank1 > rank2 {
                 self.parent.insert(root2, root1);
             } else {
-                self.parent.insert(root2, root1);
+                self.parent.insert(root2, root1.clone());
                 self.rank.insert(root1, rank1 + 1);
             }
         }
     }
 
-    /// Check if adding a factor would create a cycle
-    fn would_create_cycle(&mut self, new_factor: &Factor) -> Option<Vec<Factor>> {
-        let root1 = self.find(&new_factor.source);
-        let root2 = self.find(&new_factor.target);
+    /// Check if linking `source` and `target` would create a cycle. Evictions
+    /// only ever split components, so a stale Union-Find can claim two nodes
+    /// are connected when they no longer are, but never the reverse; it is
+    /// rebuilt only when the path search shows it is out of date.
+    fn would_create_cycle(
+        &mut self,
+        source: &NodeType,
+        target: &NodeType,
+    ) -> Option<Vec<CycleEdge<P, D>>> {
+        let root1 = self.find(source);
+        let root2 = self.find(target);
         
         if root1 == root2 {
             // Found a cycle, find the factors in it
-            if let Some(cycle_factors) = self.find_cycle_factors(&new_factor.source, &new_factor.target) {
+            if let Some(cycle_factors) = self.find_cycle_factors(source, target) {
                 return Some(cycle_factors);
             }
+            if self.connectivity_stale {
+                self.rebuild_union_find();
+            }
         }
         
         // No cycle found, union the nodes
-        self.union(&new_factor.source, &new_factor.target);
+        self.union(source, target);
         None
     }
 
-    /// Find factors that form a cycle
-    fn find_cycle_factors(&self, start: &NodeType, end: &NodeType) -> Option<Vec<Factor>> {
-        let mut visited = HashSet::new();
+    /// Find factors that form a cycle, using the graph's `CycleSearch`. The
+    /// cycle may pass through at most one landmark, counting the endpoints,
+    /// since loops through more leave the relative pose between their chains
+    /// unobserved.
+    fn find_cycle_factors(&self, start: &NodeType, end: &NodeType) -> Option<Vec<CycleEdge<P, D>>> {
+        match self.cycle_search {
+            CycleSearch::FewestFactors => self.cheapest_path(start, end, |_| 1.0),
+            CycleSearch::LeastUncertain => {
+                self.cheapest_path(start, end, |edge| edge.uncertainty())
+            }
+            CycleSearch::DepthFirst => {
+                let mut visited = HashSet::new();
+                let mut path = Vec::new();
+                let through_landmark = Self::is_landmark(start) || Self::is_landmark(end);
+                if self.find_path(start, end, through_landmark, &mut visited, &mut path) {
+                    return Some(path);
+                }
+                None
+            }
+        }
+    }
+
+    fn is_landmark(node: &NodeType) -> bool {
+        matches!(node, NodeType::Landmark(_))
+    }
+
+    /// Dijkstra over the adjacency index from `start` to `end`, with `cost` per
+    /// edge. The search state pairs a node with whether the path has already
+    /// passed through a landmark, so each node is settled at most twice and
+    /// the search is O(F log F) in the factors of the window.
+    fn cheapest_path(
+        &self,
+        start: &NodeType,
+        end: &NodeType,
+        cost: impl Fn(&CycleEdge<P, D>) -> f64,
+    ) -> Option<Vec<CycleEdge<P, D>>> {
+        if start == end {
+            return None;
+        }
+        let through_landmark = Self::is_landmark(start) || Self::is_landmark(end);
+        let origin = (start.clone(), through_landmark);
+
+        let mut distance: HashMap<(NodeType, bool), f64> = HashMap::new();
+        let mut previous: HashMap<(NodeType, bool), ((NodeType, bool), u64)> = HashMap::new();
+        let mut states = vec![origin.clone()];
+        let mut frontier = BinaryHeap::new();
+        distance.insert(origin, 0.0);
+        frontier.push(Frontier { cost: 0.0, state: 0 });
+
+        let mut reached = None;
+        while let Some(Frontier { cost: settled, state }) = frontier.pop() {
+            let (current, through) = states[state].clone();
+            if distance.get(&(current.clone(), through)).is_some_and(|&d| d < settled) {
+                continue;
+            }
+            if current == *end {
+                reached = Some((current, through));
+                break;
+            }
+            for &id in &self.adjacency[&current] {
+                let Some(edge) = self.edge(id) else {
+                    continue;
+                };
+                if edge.status() == FactorStatus::Disabled {
+                    continue;
+                }
+                let next = if edge.source() == &current { edge.target() } else { edge.source() };
+                let enters_landmark = Self::is_landmark(next) && next != end;
+                if enters_landmark && through {
+                    continue;
+                }
+                let key = (next.clone(), through || enters_landmark);
+                let candidate = settled + cost(&edge);
+                if distance.get(&key).is_some_and(|&d| d <= candidate) {
+                    continue;
+                }
+                distance.insert(key.clone(), candidate);
+                previous.insert(key.clone(), ((current.clone(), through), id));
+                states.push(key);
+                frontier.push(Frontier { cost: candidate, state: states.len() - 1 });
+            }
+        }
+
+        let mut state = reached?;
         let mut path = Vec::new();
-        
-        if self.find_path(start, end, &mut visited, &mut path) {
-            return Some(path);
+        while let Some((before, id)) = previous.get(&state) {
+            path.push(self.edge(*id)?);
+            state = before.clone();
         }
-        None
+        path.reverse();
+        Some(path)
     }
 
-    /// DFS to find path between nodes
+    /// Depth-first search that scans every factor at each step. `through_landmark`
+    /// records whether the cycle already contains a landmark, in which case no
+    /// other is entered.
     fn find_path(
         &self,
         current: &NodeType,
         target: &NodeType,
-        visited: &mut HashSet<NodeType>,
-        path: &mut Vec<Factor>
+        through_landmark: bool,
+        visited: &mut HashSet<(NodeType, bool)>,
+        path: &mut Vec<CycleEdge<P, D>>
     ) -> bool {
         if current == target && !path.is_empty() {
             return true;
         }
 
-        visited.insert(current.clone());
+        visited.insert((current.clone(), through_landmark));
 
-        for factor in &self.factors {
-            if factor.source == *current && !visited.contains(&factor.target) {
-                path.push(factor.clone());
-                if self.find_path(&factor.target, target, visited, path) {
-                    return true;
-                }
-                path.pop();
-            } else if factor.target == *current && !visited.contains(&factor.source) {
-                path.push(factor.clone());
-                if self.find_path(&factor.source, target, visited, path) {
-                    return true;
-                }
-                path.pop();
+        let relative = self.factors.iter().map(|f| (&f.source, &f.target, f.status));
+        let landmark = self.landmark_factors.iter().map(|f| (&f.source, &f.target, f.status));
+        for (k, (source, target_node, status)) in relative.chain(landmark).enumerate() {
+            if status == FactorStatus::Disabled {
+                continue;
+            }
+            let next = if source == current {
+                target_node
+            } else if target_node == current {
+                source
+            } else {
+                continue;
+            };
+            let enters_landmark = Self::is_landmark(next) && next != target;
+            if enters_landmark && through_landmark {
+                continue;
             }
+            let next_through = through_landmark || enters_landmark;
+            // A dead end with the budget unspent is also one with it spent
+            if visited.contains(&(next.clone(), next_through))
+                || visited.contains(&(next.clone(), false))
+            {
+                continue;
+            }
+            path.push(self.edge_at(k));
+            if self.find_path(next, target, next_through, visited, path) {
+                return true;
+            }
+            path.pop();
         }
 
-        visited.remove(current);
+        // Nodes stay marked once explored; a dead end cannot lead to the target on a later branch
         false
     }
 
-    /// Check consistency of a cycle of factors
-    pub fn check_cycle_consistency(&self, cycle: &[Factor]) -> (f64, bool) {
-        let mut combined_transform = Transform2D::new(0.0, 0.0, 0.0);
+    /// The `k`-th factor, counting relative factors first and landmark factors after them
+    fn edge_at(&self, k: usize) -> CycleEdge<P, D> {
+        match self.factors.get(k) {
+            Some(factor) => CycleEdge::Relative(factor.clone()),
+            None => CycleEdge::Landmark(self.landmark_factors[k - self.factors.len()].clone()),
+        }
+    }
+
+    /// Check consistency of a cycle of factors. The error is the Mahalanobis
+    /// distance of the composed loop transform from identity, gated by the
+    /// chi-square test at the graph's confidence level. Cycles through a
+    /// landmark are scored on the predicted landmark measurement instead.
+    pub fn check_cycle_consistency<E>(&self, cycle: &[E]) -> (f64, bool)
+    where
+        E: Clone + Into<OrientedEdge<P, D>>,
+    {
+        let test = self.test_cycle(cycle, self.consistency_confidence);
+        (test.mahalanobis_distance, test.is_consistent)
+    }
+
+    /// Chi-square test of a closed loop. Factors are composed in walk order,
+    /// reversed ones as their inverse, and their covariances are propagated
+    /// along the loop as right perturbations, as in `compose_chain`, giving
+    /// the marginal covariance of the loop residual; the squared
+    /// Mahalanobis distance is then compared with the chi-square quantile at
+    /// `confidence` with one degree of freedom per tangent dimension. A loop
+    /// through a point landmark is tested as described on `test_landmark_cycle`.
+    /// Plain factors are taken as walked forward.
+    pub fn test_cycle<E>(&self, cycle: &[E], confidence: f64) -> CycleConsistency
+    where
+        E: Clone + Into<OrientedEdge<P, D>>,
+    {
+        let cycle: Vec<OrientedEdge<P, D>> = cycle.iter().cloned().map(Into::into).collect();
+        let mut factors = Vec::with_capacity(cycle.len());
+        for step in &cycle {
+            match step.walked_factor() {
+                Some(factor) => factors.push(factor),
+                None => return test_landmark_cycle(&cycle, confidence),
+            }
+        }
+
+        let mut combined_transform = P::identity();
+        let mut combined_covariance = na::SMatrix::<f64, D, D>::zeros();
         
-        for factor in cycle {
+        for factor in factors {
+            let covariance = factor
+                .covariance()
+                .unwrap_or_else(|| na::SMatrix::identity() * 1e9);
+            let adjoint = factor.transform.inverse().adjoint();
+            combined_covariance = adjoint * combined_covariance * adjoint.transpose() + covariance;
             combined_transform = combined_transform.compose(&factor.transform);
         }
         
-        let error = combined_transform.error_from_identity();
-        let is_consistent = error < 1.0; // Threshold can be adjusted
-        
-        (error, is_consistent)
+        let residual = combined_transform.log();
+        let chi_square = match combined_covariance.try_inverse() {
+            Some(information) => (residual.transpose() * information * residual)[(0, 0)].max(0.0),
+            None => residual.norm_squared(),
+        };
+        let threshold = chi_square_quantile(confidence, D);
+
+        CycleConsistency {
+            residual: na::DVector::from_column_slice(residual.as_slice()),
+            loop_covariance: na::DMatrix::from_column_slice(D, D, combined_covariance.as_slice()),
+            mahalanobis_distance: chi_square.sqrt(),
+            chi_square,
+            degrees_of_freedom: D,
+            p_value: 1.0 - chi_square_cdf(chi_square, D),
+            confidence,
+            threshold,
+            is_consistent: chi_square <= threshold,
+        }
+    }
+
+    /// Robust cost `rho(e^2)` of a cycle and its IRLS weight `rho'(e^2)`, where `e`
+    /// is the error from `check_cycle_consistency`. When factors in the cycle carry
+    /// different kernels the one that down-weights the loop most is used, so a loop
+    /// through any outlier-prone sensor is scored robustly. Without kernels this is
+    /// plain L2: `(e^2, 1.0)`.
+    pub fn robust_cycle_cost<E>(&self, cycle: &[E]) -> (f64, f64)
+    where
+        E: Clone + Into<OrientedEdge<P, D>>,
+    {
+        let cycle: Vec<OrientedEdge<P, D>> = cycle.iter().cloned().map(Into::into).collect();
+        let (error, _) = self.check_cycle_consistency(&cycle);
+        let squared_error = error * error;
+        cycle
+            .iter()
+            .filter_map(|step| self.kernel_for_edge(&step.edge))
+            .map(|kernel| {
+                let [rho, weight, _] = kernel.evaluate(squared_error);
+                (rho, weight)
+            })
+            .fold((squared_error, 1.0), |best, candidate| {
+                if candidate.1 < best.1 { candidate } else { best }
+            })
+    }
+
+    /// Minimum cycle basis of the factors in the window: a set of independent
+    /// loops from which every other loop is a combination, with the fewest
+    /// factors in total, or the least total uncertainty under
+    /// `CycleSearch::LeastUncertain`. Disabled factors are left out. Each loop
+    /// is ordered and oriented like the ones `add_factor` scores. Unlike the
+    /// loops found at insertion, a basis loop may run through several
+    /// landmarks; such loops come out of `test_cycle` unconstrained.
+    pub fn minimum_cycle_basis(&self) -> Vec<Vec<OrientedEdge<P, D>>> {
+        let index: HashMap<&NodeType, usize> =
+            self.nodes.iter().enumerate().map(|(i, node)| (node, i)).collect();
+        let edges: Vec<CycleEdge<P, D>> = self
+            .factors
+            .iter()
+            .cloned()
+            .map(CycleEdge::Relative)
+            .chain(self.landmark_factors.iter().cloned().map(CycleEdge::Landmark))
+            .filter(|edge| edge.status() != FactorStatus::Disabled)
+            .collect();
+        let weighted: Vec<(usize, usize, f64)> = edges
+            .iter()
+            .map(|edge| {
+                let weight = match self.cycle_search {
+                    CycleSearch::LeastUncertain => edge.uncertainty(),
+                    CycleSearch::FewestFactors | CycleSearch::DepthFirst => 1.0,
+                };
+                (index[edge.source()], index[edge.target()], weight)
+            })
+            .collect();
+
+        cycle_basis::minimum_cycle_basis(self.nodes.len(), &weighted)
+            .into_iter()
+            .map(|cycle| {
+                cycle
+                    .into_iter()
+                    .map(|(k, forward)| OrientedEdge { edge: edges[k].clone(), reversed: !forward })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Test every loop of the minimum cycle basis at the graph's confidence.
+    /// Unlike `record_cycle` this leaves consistency records and factor
+    /// statuses alone, so an audit can be run at any time.
+    pub fn audit_cycle_basis(&self) -> Vec<(Vec<OrientedEdge<P, D>>, CycleConsistency)> {
+        self.minimum_cycle_basis()
+            .into_iter()
+            .map(|cycle| {
+                let test = self.test_cycle(&cycle, self.consistency_confidence);
+                (cycle, test)
+            })
+            .collect()
+    }
+
+    /// Iterate over the nodes currently in the window, oldest first
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeType> {
+        self.nodes.iter()
+    }
+
+    /// Whether a node is currently in the window
+    pub fn contains_node(&self, node: &NodeType) -> bool {
+        self.adjacency.contains_key(node)
+    }
+
+    /// Iterate over the factors currently in the window, oldest first
+    pub fn factors(&self) -> impl Iterator<Item = &Factor<P, D>> {
+        self.factors.iter()
+    }
+
+    /// Look up a relative factor still in the window by id
+    pub fn factor(&self, id: u64) -> Option<&Factor<P, D>> {
+        let k = self.factors.binary_search_by_key(&id, |factor| factor.id).ok()?;
+        self.factors.get(k)
+    }
+
+    /// Look up a landmark factor still in the window by id
+    pub fn landmark_factor(&self, id: u64) -> Option<&LandmarkFactor> {
+        let k = self.landmark_factors.binary_search_by_key(&id, |factor| factor.id).ok()?;
+        self.landmark_factors.get(k)
+    }
+
+    fn edge(&self, id: u64) -> Option<CycleEdge<P, D>> {
+        match self.factor(id) {
+            Some(factor) => Some(CycleEdge::Relative(factor.clone())),
+            None => self.landmark_factor(id).map(|factor| CycleEdge::Landmark(factor.clone())),
+        }
+    }
+
+    fn endpoints(&self, id: u64) -> Option<(NodeType, NodeType)> {
+        match self.factor(id) {
+            Some(factor) => Some((factor.source.clone(), factor.target.clone())),
+            None => self
+                .landmark_factor(id)
+                .map(|factor| (factor.source.clone(), factor.target.clone())),
+        }
+    }
+
+    /// Iterate over the landmark observations currently in the window, oldest first
+    pub fn landmark_factors(&self) -> impl Iterator<Item = &LandmarkFactor> {
+        self.landmark_factors.iter()
     }
 
     /// Get current number of nodes
@@ -230,8 +1217,634 @@ impl CircularFactorGraph {
         self.nodes.len()
     }
 
-    /// Get current number of factors
+    /// Get current number of relative factors
     pub fn factor_count(&self) -> usize {
         self.factors.len()
     }
-}
\ No newline at end of file
+
+    /// Get current number of landmark factors
+    pub fn landmark_factor_count(&self) -> usize {
+        self.landmark_factors.len()
+    }
+}
+
+/// Entry of the Dijkstra frontier, ordered so the cheapest pops first
+struct Frontier {
+    cost: f64,
+    state: usize,  // Index into the search's state list
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed for the max-heap; earlier states win ties
+        other.cost.total_cmp(&self.cost).then_with(|| other.state.cmp(&self.state))
+    }
+}
+
+impl CircularFactorGraph<Transform2D, 3> {
+    /// Add an observation of a point landmark. Like `add_factor`, returns the
+    /// loop the observation closes, after scoring and recording it.
+    pub fn add_landmark_factor(&mut self, factor: LandmarkFactor) -> Option<Vec<OrientedEdge>> {
+        self.insert_edge(CycleEdge::Landmark(factor))
+    }
+}
+
+/// Compose a chain of relative factors walked from `start`, applying each
+/// factor forwards or inverted to follow the walk. Returns the node reached,
+/// the composed transform and its covariance on right perturbations
+/// `T exp(xi)`, the convention of factor information: composing `A * B` maps
+/// the covariance so far through `Ad(B^-1)` and adds `B`'s own. `None` if the
+/// chain does not connect.
+pub(super) fn compose_chain<P: PoseGroup<D>, const D: usize>(
+    start: &NodeType,
+    chain: &[&Factor<P, D>],
+) -> Option<(NodeType, P, na::SMatrix<f64, D, D>)> {
+    let mut current = start.clone();
+    let mut combined_transform = P::identity();
+    let mut combined_covariance = na::SMatrix::<f64, D, D>::zeros();
+    for factor in chain {
+        let step = if factor.source == current {
+            (*factor).clone()
+        } else if factor.target == current {
+            factor.inverted()
+        } else {
+            return None;
+        };
+        let covariance = step.covariance().unwrap_or_else(|| na::SMatrix::identity() * 1e9);
+        let adjoint = step.transform.inverse().adjoint();
+        combined_covariance = adjoint * combined_covariance * adjoint.transpose() + covariance;
+        combined_transform = combined_transform.compose(&step.transform);
+        current = step.target;
+    }
+    Some((current, combined_transform, combined_covariance))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::graphs::LandmarkMeasurement;
+    use crate::common::lie::SE3;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_cycle_error_is_weighted_by_information() {
+        let graph = CircularFactorGraph::new(10, 10);
+        let a = NodeType::RobotPose(0);
+        let b = NodeType::RobotPose(1);
+
+        // Loop closes with a 0.1 rad heading error and no translation error
+        let forward = Transform2D::new(1.0, 0.0, 0.0);
+        let back = Transform2D::new(-1.0, 0.0, 0.1);
+
+        let heading_noisy = vec![
+            Factor::new(a.clone(), b.clone(), forward.clone(), "ODOMETRY", 0)
+                .with_diagonal_std(0.01, 0.01, 0.2),
+            Factor::new(b.clone(), a.clone(), back.clone(), "ODOMETRY", 1)
+                .with_diagonal_std(0.01, 0.01, 0.2),
+        ];
+        let heading_precise = vec![
+            Factor::new(a.clone(), b.clone(), forward, "ODOMETRY", 0)
+                .with_diagonal_std(0.2, 0.2, 0.01),
+            Factor::new(b, a, back, "ODOMETRY", 1).with_diagonal_std(0.2, 0.2, 0.01),
+        ];
+
+        let (noisy_error, noisy_ok) = graph.check_cycle_consistency(&heading_noisy);
+        let (precise_error, precise_ok) = graph.check_cycle_consistency(&heading_precise);
+
+        assert!(noisy_ok);
+        assert!(!precise_ok);
+        assert!(noisy_error < precise_error);
+    }
+
+    #[test]
+    fn test_chi_square_gate_follows_confidence() {
+        let graph = CircularFactorGraph::new(10, 10);
+        let a = NodeType::RobotPose(0);
+        let b = NodeType::RobotPose(1);
+
+        // Two-factor loop whose x error is 2.8 sigma of the loop covariance
+        let sigma = 0.1;
+        let loop_sigma = sigma * 2.0f64.sqrt();
+        let cycle = vec![
+            Factor::new(a.clone(), b.clone(), Transform2D::new(1.0, 0.0, 0.0), "ODOMETRY", 0)
+                .with_isotropic_std(sigma),
+            Factor::new(b, a, Transform2D::new(-1.0 + 2.8 * loop_sigma, 0.0, 0.0), "ODOMETRY", 1)
+                .with_isotropic_std(sigma),
+        ];
+
+        let loose = graph.test_cycle(&cycle, 0.99);
+        let strict = graph.test_cycle(&cycle, 0.90);
+
+        assert_eq!(loose.degrees_of_freedom, 3);
+        assert!((loose.mahalanobis_distance - 2.8).abs() < 1e-9);
+        assert!((loose.loop_covariance[(0, 0)] - loop_sigma * loop_sigma).abs() < 1e-12);
+        assert!((loose.p_value - 0.0494).abs() < 1e-3);
+        assert!(loose.is_consistent);
+        assert!(!strict.is_consistent);
+    }
+
+    #[test]
+    fn test_faulty_sensor_is_quarantined_and_reported() {
+        let mut graph = CircularFactorGraph::new(10, 40);
+        graph.set_outlier_policy(OutlierPolicy { min_cycles: 1, ..OutlierPolicy::default() });
+        graph.add_node(NodeType::RobotPose(0));
+
+        // Odometry, wheel and visual agree on every step; LIDAR is off by half a meter
+        let step = Transform2D::new(1.0, 0.2, 0.3);
+        let faulty = Transform2D::new(1.5, -0.3, 0.3);
+        for t in 0..5 {
+            graph.add_node(NodeType::RobotPose(t + 1));
+            let (a, b) = (NodeType::RobotPose(t), NodeType::RobotPose(t + 1));
+            let sensors =
+                [("ODOMETRY", &step), ("WHEEL", &step), ("VISUAL", &step), ("LIDAR", &faulty)];
+            for (sensor, transform) in sensors {
+                let factor = Factor::new(a.clone(), b.clone(), transform.clone(), sensor, t)
+                    .with_isotropic_std(0.05);
+                graph.add_factor(factor);
+            }
+        }
+
+        for factor in graph.factors() {
+            let expected = if factor.sensor_type == "LIDAR" {
+                FactorStatus::Quarantined
+            } else {
+                FactorStatus::Active
+            };
+            assert_eq!(factor.status, expected, "{:?}", factor.sensor_type);
+        }
+
+        let report = graph.sensor_report();
+        let faulty: Vec<&str> = report
+            .iter()
+            .filter(|health| health.suspected_faulty)
+            .map(|health| health.sensor_type.as_str())
+            .collect();
+        assert_eq!(faulty, vec!["LIDAR"]);
+        assert_eq!(report.iter().find(|h| h.sensor_type == "LIDAR").unwrap().flagged_factors, 5);
+    }
+
+    #[test]
+    fn test_blame_falls_on_faulty_factor_in_a_single_loop() {
+        let mut graph = CircularFactorGraph::new(10, 20);
+        let node = NodeType::RobotPose;
+        let step = Transform2D::new(1.0, 0.2, 0.3);
+        let relative = |n: u64| (1..n).fold(step.clone(), |pose, _| pose.compose(&step));
+        graph.add_node(node(0));
+        for t in 0..6 {
+            graph.add_node(node(t + 1));
+            let odometry = Factor::new(node(t), node(t + 1), step.clone(), "ODOMETRY", t)
+                .with_isotropic_std(0.05);
+            graph.add_factor(odometry);
+        }
+
+        // Off by half a meter, closing a loop with the odometry from 2 to 4 only
+        let mut faulty = relative(2);
+        faulty.x += 0.5;
+        let faulty = Factor::new(node(2), node(4), faulty, "LIDAR", 6).with_isotropic_std(0.05);
+        assert_eq!(graph.add_factor(faulty).unwrap().len(), 3);
+        let faulty_id = graph.factors().last().unwrap().id;
+
+        // Nothing tells the three factors apart yet, so none is blamed
+        assert!(graph.factors().all(|factor| factor.status == FactorStatus::Active));
+
+        // Consistent loops over either odometry factor clear them
+        for (from, to) in [(0, 3), (3, 6)] {
+            let closure = Factor::new(node(from), node(to), relative(to - from), "LOOP", 7)
+                .with_isotropic_std(0.05);
+            assert!(graph.add_factor(closure).is_some());
+        }
+        for factor in graph.factors() {
+            let record = graph.factor_consistency(factor.id);
+            if factor.id == faulty_id {
+                assert_eq!(factor.status, FactorStatus::Quarantined);
+                assert_eq!(record.unwrap().blamed_cycles, 1);
+            } else {
+                assert_eq!(factor.status, FactorStatus::Active, "{:?}", factor.sensor_type);
+                assert!(record.is_none_or(|record| record.blamed_cycles == 0));
+            }
+        }
+
+        let report = graph.sensor_report();
+        let suspected: Vec<&str> = report
+            .iter()
+            .filter(|health| health.suspected_faulty)
+            .map(|health| health.sensor_type.as_str())
+            .collect();
+        assert_eq!(suspected, vec!["LIDAR"]);
+    }
+
+    #[test]
+    fn test_factor_kernel_overrides_sensor_kernel() {
+        use crate::common::robust_kernel::{CauchyKernel, HuberKernel};
+
+        let mut graph = CircularFactorGraph::new(10, 10);
+        graph.set_sensor_kernel("LIDAR", CauchyKernel::new(1.0));
+        let a = NodeType::RobotPose(0);
+        let b = NodeType::RobotPose(1);
+        let transform = Transform2D::new(1.0, 0.0, 0.0);
+
+        let odometry = Factor::new(a.clone(), b.clone(), transform.clone(), "ODOMETRY", 0);
+        let lidar = Factor::new(a.clone(), b.clone(), transform.clone(), "LIDAR", 0);
+        let tagged = Factor::new(a, b, transform, "LIDAR", 0).with_kernel(HuberKernel::new(1.0));
+
+        assert!(graph.kernel_for(&odometry).is_none());
+        assert!((graph.kernel_for(&lidar).unwrap().weight(3.0) - 0.25).abs() < 1e-12);
+        assert!((graph.kernel_for(&tagged).unwrap().weight(4.0) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_full_window_evicts_before_closing_loops() {
+        let pose = NodeType::RobotPose;
+        let step = |a: u64, b: u64| {
+            Factor::new(pose(a), pose(b), Transform2D::new(1.0, 0.0, 0.0), "ODOMETRY", b)
+        };
+        for max_factors in [2, 3] {
+            let mut graph = CircularFactorGraph::new(10, max_factors);
+            for t in 0..3 {
+                graph.add_node(pose(t));
+            }
+            assert!(graph.add_factor(step(0, 1)).is_none());
+            assert!(graph.add_factor(step(1, 2)).is_none());
+            let cycle = graph.add_factor(step(2, 0));
+            if max_factors == 2 {
+                // The only path back to pose 0 leaves with the oldest factor, so no
+                // loop is closed and nothing is recorded against the evicted factor
+                assert!(cycle.is_none());
+                assert_eq!(graph.factor_count(), 2);
+                assert!(graph.factor_consistency(0).is_none());
+            } else {
+                assert!(cycle.is_some());
+                assert_eq!(graph.factor_consistency(0).unwrap().cycles, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cycle_search_picks_shortest_or_least_uncertain_loop() {
+        let pose = NodeType::RobotPose;
+        let step = |a: u64, b: u64, sigma: f64| {
+            Factor::new(pose(a), pose(b), Transform2D::new(1.0, 0.0, 0.0), "ODOMETRY", b)
+                .with_isotropic_std(sigma)
+        };
+        let build = |search: CycleSearch| {
+            let mut graph = CircularFactorGraph::new(10, 20);
+            graph.set_cycle_search(search);
+            for t in 0..5 {
+                graph.add_node(pose(t));
+            }
+            // A precise four-step chain and a noisy two-step shortcut from 0 to 4
+            for t in 0..4 {
+                graph.add_factor(step(t, t + 1, 0.01));
+            }
+            graph.add_node(pose(9));
+            graph.add_factor(step(0, 9, 1.0));
+            graph.add_factor(step(9, 4, 1.0));
+            graph
+        };
+
+        let mut fewest = build(CycleSearch::FewestFactors);
+        let cycle = fewest.add_factor(step(4, 0, 0.01)).unwrap();
+        assert_eq!(cycle.iter().map(|e| e.id()).collect::<Vec<_>>(), vec![5, 4, 6]);
+
+        let mut least_uncertain = build(CycleSearch::LeastUncertain);
+        let cycle = least_uncertain.add_factor(step(4, 0, 0.01)).unwrap();
+        assert_eq!(cycle.iter().map(|e| e.id()).collect::<Vec<_>>(), vec![3, 2, 1, 0, 6]);
+
+        let mut depth_first = build(CycleSearch::DepthFirst);
+        assert!(depth_first.add_factor(step(4, 0, 0.01)).is_some());
+    }
+
+    #[test]
+    fn test_cycle_basis_audit_isolates_the_bad_loop() {
+        // Two squares side by side, poses 0-1-2 along the bottom and 3-4-5 along the top
+        let pose = NodeType::RobotPose;
+        let truth: Vec<Transform2D> = (0..6)
+            .map(|i| Transform2D::new((i % 3) as f64, (i / 3) as f64, 0.3 * i as f64))
+            .collect();
+        let mut graph = CircularFactorGraph::new(10, 20);
+        for t in 0..6 {
+            graph.add_node(pose(t));
+        }
+        // Some factors point against the walk around either square
+        let links = [(1, 0), (1, 2), (3, 4), (5, 4), (0, 3), (4, 1), (2, 5)];
+        for (a, b) in links {
+            let mut relative = truth[a].inverse().compose(&truth[b]);
+            if (a, b) == (2, 5) {
+                relative.theta += 0.5;
+            }
+            let factor = Factor::new(pose(a as u64), pose(b as u64), relative, "ODOMETRY", 0)
+                .with_isotropic_std(0.05);
+            graph.add_factor(factor);
+        }
+
+        let audit = graph.audit_cycle_basis();
+        assert_eq!(audit.len(), 2);
+        for (cycle, test) in &audit {
+            assert_eq!(cycle.len(), 4);
+            // Each loop is a closed walk, every relative factor turned to follow it
+            for (edge, next) in cycle.iter().zip(cycle.iter().cycle().skip(1)) {
+                assert_eq!(edge.end(), next.start());
+            }
+            let bad = cycle.iter().any(|edge| edge.id() == 6);
+            assert_eq!(test.is_consistent, !bad);
+            if !bad {
+                assert!(test.mahalanobis_distance < 1e-9);
+            }
+        }
+        // The audit only reads the graph
+        assert_eq!(graph.factor_consistency(6).unwrap().cycles, 1);
+    }
+
+    /// A loop through random poses, each factor exact but stored in a random
+    /// direction, walked 0 -> 1 -> ... -> 0
+    fn random_loop<P: PoseGroup<D>, const D: usize>(
+        rng: &mut StdRng,
+        length: usize,
+    ) -> Vec<OrientedEdge<P, D>> {
+        let poses: Vec<P> = (0..length)
+            .map(|_| P::exp(&na::SVector::from_fn(|_, _| rng.gen_range(-2.0..2.0))))
+            .collect();
+        (0..length)
+            .map(|i| {
+                let j = (i + 1) % length;
+                let sigmas = na::SVector::from_fn(|_, _| rng.gen_range(0.01..0.5));
+                let stored = |a: usize, b: usize| {
+                    let relative = poses[a].inverse().compose(&poses[b]);
+                    let (a, b) = (NodeType::RobotPose(a as u64), NodeType::RobotPose(b as u64));
+                    Factor::new(a, b, relative, "ODOMETRY", i as u64).with_std_devs(&sigmas)
+                };
+                if rng.gen_bool(0.5) {
+                    OrientedEdge::forward(stored(i, j))
+                } else {
+                    OrientedEdge::backward(stored(j, i))
+                }
+            })
+            .collect()
+    }
+
+    fn assert_scores_zero_both_ways<P: PoseGroup<D>, const D: usize>(
+        graph: &CircularFactorGraph<P, D>,
+        walk: &[OrientedEdge<P, D>],
+    ) {
+        let back: Vec<OrientedEdge<P, D>> =
+            walk.iter().rev().cloned().map(OrientedEdge::flipped).collect();
+        let mut rotated = walk.to_vec();
+        rotated.rotate_left(walk.len() / 2);
+        for cycle in [walk, &back, &rotated] {
+            for (step, next) in cycle.iter().zip(cycle.iter().cycle().skip(1)) {
+                assert_eq!(step.end(), next.start());
+            }
+            let test = graph.test_cycle(cycle, 0.99);
+            assert!(test.chi_square < 1e-9, "{} factors: {}", cycle.len(), test.chi_square);
+            assert!(test.is_consistent);
+        }
+    }
+
+    #[test]
+    fn test_consistent_loops_score_zero_in_either_direction() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let planar = CircularFactorGraph::<Transform2D, 3>::new(10, 10);
+        let spatial = CircularFactorGraph::<SE3, 6>::new(10, 10);
+        for length in (2..9).cycle().take(70) {
+            assert_scores_zero_both_ways(&planar, &random_loop(&mut rng, length));
+            assert_scores_zero_both_ways(&spatial, &random_loop(&mut rng, length));
+        }
+    }
+
+    fn assert_same_score_both_ways<P: PoseGroup<D>, const D: usize>(
+        graph: &CircularFactorGraph<P, D>,
+        walk: &[OrientedEdge<P, D>],
+    ) -> f64 {
+        let back: Vec<OrientedEdge<P, D>> =
+            walk.iter().rev().cloned().map(OrientedEdge::flipped).collect();
+        let forward = graph.test_cycle(walk, 0.99).chi_square;
+        let backward = graph.test_cycle(&back, 0.99).chi_square;
+        let tolerance = 1e-9 * forward.max(1.0);
+        assert!((forward - backward).abs() < tolerance, "{} vs {}", forward, backward);
+        forward
+    }
+
+    #[test]
+    fn test_noisy_loops_score_the_same_in_either_direction() {
+        let a = NodeType::RobotPose(0);
+        let b = NodeType::RobotPose(1);
+        let out = Factor::new(a.clone(), b.clone(), Transform2D::new(2.0, 0.0, 0.0), "LIDAR", 1)
+            .with_diagonal_std(0.01, 0.01, 0.3);
+        let back = Factor::new(b, a, Transform2D::new(-2.0, 0.1, 0.0), "ODOMETRY", 2)
+            .with_diagonal_std(0.05, 0.05, 0.01);
+        let planar = CircularFactorGraph::<Transform2D, 3>::new(10, 10);
+        let chi_square =
+            assert_same_score_both_ways(&planar, &[OrientedEdge::forward(out), back.into()]);
+        assert!(chi_square > 1.0);
+
+        // Random loops whose factors are each off by a random perturbation
+        fn perturbed<P: PoseGroup<D>, const D: usize>(
+            rng: &mut StdRng,
+            walk: Vec<OrientedEdge<P, D>>,
+        ) -> Vec<OrientedEdge<P, D>> {
+            walk.into_iter()
+                .map(|mut step| {
+                    if let CycleEdge::Relative(factor) = &mut step.edge {
+                        let noise = na::SVector::from_fn(|_, _| rng.gen_range(-0.2..0.2));
+                        factor.transform = factor.transform.compose(&P::exp(&noise));
+                    }
+                    step
+                })
+                .collect()
+        }
+        let mut rng = StdRng::seed_from_u64(13);
+        let spatial = CircularFactorGraph::<SE3, 6>::new(10, 10);
+        for length in (2..9).cycle().take(70) {
+            let walk = random_loop(&mut rng, length);
+            assert!(assert_same_score_both_ways(&planar, &perturbed(&mut rng, walk)) > 1e-6);
+            let walk = random_loop(&mut rng, length);
+            assert!(assert_same_score_both_ways(&spatial, &perturbed(&mut rng, walk)) > 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_loops_closed_against_stored_directions_score_zero() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for length in (2..9).cycle().take(35) {
+            let walk: Vec<OrientedEdge<SE3, 6>> = random_loop(&mut rng, length);
+            let mut graph = CircularFactorGraph::new(10, 10);
+            for t in 0..length {
+                graph.add_node(NodeType::RobotPose(t as u64));
+            }
+            let (closing, chain) = walk.split_last().unwrap();
+            for step in chain {
+                let CycleEdge::Relative(factor) = &step.edge else { unreachable!() };
+                assert!(graph.add_factor(factor.clone()).is_none());
+            }
+            let CycleEdge::Relative(factor) = &closing.edge else { unreachable!() };
+            let cycle = graph.add_factor(factor.clone()).unwrap();
+
+            assert_eq!(cycle.len(), length);
+            assert_scores_zero_both_ways(&graph, &cycle);
+            let record = graph.factor_consistency(cycle[0].id()).unwrap();
+            assert_eq!((record.cycles, record.inconsistent_cycles), (1, 0));
+        }
+    }
+
+    #[test]
+    fn test_adjacency_follows_evictions() {
+        let pose = NodeType::RobotPose;
+        let step = |a: u64, b: u64| {
+            Factor::new(pose(a), pose(b), Transform2D::new(1.0, 0.0, 0.0), "ODOMETRY", b)
+        };
+        let mut graph = CircularFactorGraph::new(4, 10);
+        graph.add_node(pose(0));
+        for t in 1..8 {
+            graph.add_node(pose(t));
+            graph.add_factor(step(t - 1, t));
+        }
+
+        // Poses 0-3 and their factors have left the window
+        let window: Vec<NodeType> = graph.nodes().cloned().collect();
+        assert_eq!(window, (4..8).map(pose).collect::<Vec<_>>());
+        assert!(graph.factor(2).is_none());
+        assert_eq!(graph.factor(5).unwrap().source, pose(5));
+        assert!(graph.add_factor(step(3, 7)).is_none());
+        assert_eq!(graph.factor_count(), 3);
+
+        let cycle = graph.add_factor(step(7, 4)).unwrap();
+        assert_eq!(cycle.iter().map(|e| e.id()).collect::<Vec<_>>(), vec![6, 5, 4, 7]);
+    }
+
+    /// Range and bearing to `landmark` as seen from `pose`, with the range scaled by `range_scale`
+    fn range_bearing(
+        pose: &Transform2D,
+        landmark: (f64, f64),
+        range_scale: f64,
+    ) -> LandmarkMeasurement {
+        let local = pose.inverse().compose(&Transform2D::new(landmark.0, landmark.1, 0.0));
+        LandmarkMeasurement::RangeBearing {
+            range: range_scale * local.x.hypot(local.y),
+            bearing: local.y.atan2(local.x),
+        }
+    }
+
+    #[test]
+    fn test_returning_node_keeps_window_connected() {
+        let pose = NodeType::RobotPose;
+        let step = |a: u64, b: u64| {
+            Factor::new(pose(a), pose(b), Transform2D::new(1.0, 0.0, 0.0), "ODOMETRY", b)
+        };
+        let mut graph = CircularFactorGraph::new(4, 10);
+        for t in 0..4 {
+            graph.add_node(pose(t));
+        }
+        // Union by rank leaves pose 2 pointing at pose 0, and pose 0 at pose 3
+        graph.add_factor(step(0, 2));
+        graph.add_factor(step(3, 1));
+        graph.add_factor(step(3, 2));
+
+        // Pose 0 leaves the window, then comes back and pushes pose 1 out
+        graph.add_node(pose(4));
+        graph.add_node(pose(0));
+        assert!(graph.factor(0).is_none() && graph.factor(1).is_none());
+
+        // Poses 2 and 3 are still linked, so a second factor between them closes a loop
+        let cycle = graph.add_factor(step(2, 3)).expect("the loop through factor 2");
+        assert_eq!(cycle.iter().map(|edge| edge.id()).collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_landmark_loops_are_scored_on_the_predicted_measurement() {
+        let pose = NodeType::RobotPose;
+        let landmark = NodeType::Landmark("L1".to_string());
+        let point = (2.0, 1.0);
+        let step = Transform2D::new(1.0, 0.0, 0.3);
+        let poses = [Transform2D::new(0.0, 0.0, 0.0), step.clone(), step.compose(&step)];
+
+        let mut graph = CircularFactorGraph::new(10, 10);
+        for t in 0..3 {
+            graph.add_node(pose(t));
+        }
+        graph.add_node(landmark.clone());
+        for t in 0..2 {
+            let odometry = Factor::new(pose(t), pose(t + 1), step.clone(), "ODOMETRY", t)
+                .with_isotropic_std(0.05);
+            assert!(graph.add_factor(odometry).is_none());
+        }
+
+        let observe = |t: u64, measurement: LandmarkMeasurement| {
+            LandmarkFactor::new(pose(t), landmark.clone(), measurement, "VISUAL", t)
+                .with_std_devs(&[0.05, 0.02])
+        };
+        let first = observe(0, range_bearing(&poses[0], point, 1.0));
+        assert!(graph.add_landmark_factor(first).is_none());
+
+        // A second exact sighting closes a loop through the landmark and agrees with it
+        let cycle = graph.add_landmark_factor(observe(1, range_bearing(&poses[1], point, 1.0)));
+        let cycle = cycle.unwrap();
+        assert!(cycle.iter().any(|step| matches!(step.edge, CycleEdge::Landmark(_))));
+        let test = graph.test_cycle(&cycle, 0.99);
+        assert_eq!(test.degrees_of_freedom, 2);
+        assert!(test.chi_square < 1e-12);
+
+        // A sighting whose range is off by half is not
+        graph.add_landmark_factor(observe(2, range_bearing(&poses[2], point, 1.5)));
+        let biased = graph.landmark_factors().last().unwrap();
+        let record = graph.factor_consistency(biased.id).unwrap();
+        assert_eq!((record.cycles, record.inconsistent_cycles), (1, 1));
+        assert_eq!(graph.landmark_factor_count(), 3);
+    }
+
+    #[test]
+    fn test_odometry_closes_loops_through_one_landmark_only() {
+        let pose = NodeType::RobotPose;
+        let (l1, l2) = (NodeType::Landmark("L1".to_string()), NodeType::Landmark("L2".to_string()));
+        let step = Transform2D::new(1.0, 0.5, -0.2);
+        let origin = Transform2D::new(0.0, 0.0, 0.0);
+        let observe = |t: u64, landmark: &NodeType, measurement| {
+            LandmarkFactor::new(pose(t), landmark.clone(), measurement, "VISUAL", t)
+        };
+
+        let mut graph = CircularFactorGraph::new(10, 10);
+        for node in [pose(0), pose(1), l1.clone(), l2.clone()] {
+            graph.add_node(node);
+        }
+
+        // Pose 0 only gets a bearing to L1, pose 1 its range too
+        let bearing = match range_bearing(&origin, (3.0, 2.0), 1.0) {
+            LandmarkMeasurement::RangeBearing { bearing, .. } => bearing,
+            _ => unreachable!(),
+        };
+        let sightings = [
+            observe(0, &l1, LandmarkMeasurement::Bearing(bearing)).with_isotropic_std(0.01),
+            observe(1, &l1, range_bearing(&step, (3.0, 2.0), 1.0)).with_std_devs(&[0.05, 0.01]),
+            observe(1, &l2, range_bearing(&step, (0.0, 4.0), 1.0)),
+        ];
+        for sighting in sightings {
+            assert!(graph.add_landmark_factor(sighting).is_none());
+        }
+
+        // Pose 0 reaches L2 only through L1, which leaves the loop unobserved
+        let unscored = observe(0, &l2, range_bearing(&origin, (0.0, 4.0), 1.0));
+        assert!(graph.add_landmark_factor(unscored).is_none());
+
+        // Closing 0 -> 1 runs through L1; the bearing is tested against the range-bearing sighting
+        let odometry = Factor::new(pose(0), pose(1), step, "ODOMETRY", 1).with_isotropic_std(0.05);
+        let cycle = graph.add_factor(odometry).unwrap();
+        assert_eq!(cycle.len(), 3);
+        assert!(cycle[..2].iter().all(|step| step.edge.target() == &l1));
+        assert!(cycle[2].reversed);
+        let record = graph.factor_consistency(graph.factors().last().unwrap().id).unwrap();
+        assert_eq!((record.cycles, record.inconsistent_cycles), (1, 0));
+    }
+}
@@ -0,0 +1,163 @@
+use nalgebra as na;
+
+/// How much a factor is trusted by the solver
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FactorStatus {
+    Active,
+    DownWeighted(f64),  // Information is scaled by this factor
+    Quarantined,        // Left out of optimization, but still scored so it can recover
+    Disabled,           // Left out of optimization and cycle detection for good
+}
+
+impl FactorStatus {
+    /// Scale applied to the factor's information matrix
+    pub fn weight(&self) -> f64 {
+        match self {
+            FactorStatus::Active => 1.0,
+            FactorStatus::DownWeighted(weight) => *weight,
+            FactorStatus::Quarantined | FactorStatus::Disabled => 0.0,
+        }
+    }
+}
+
+/// What to do with a factor once it is judged an outlier
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlierAction {
+    Disable,
+    DownWeight(f64),
+    Quarantine,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutlierPolicy {
+    pub min_cycles: usize,              // Cycles a factor must be in before it can be judged
+    pub max_inconsistency_rate: f64,    // Share of its cycles blamed on it at which it is flagged
+    pub action: OutlierAction,
+}
+
+impl Default for OutlierPolicy {
+    fn default() -> Self {
+        Self {
+            min_cycles: 1,
+            max_inconsistency_rate: 0.5,
+            action: OutlierAction::Quarantine,
+        }
+    }
+}
+
+impl OutlierPolicy {
+    /// Status a factor should have given the cycles blamed on it. Returns `None`
+    /// while there is not enough evidence to judge it.
+    pub fn judge(&self, record: &FactorConsistency) -> Option<FactorStatus> {
+        if record.cycles < self.min_cycles {
+            return None;
+        }
+        if record.blame_rate() < self.max_inconsistency_rate {
+            return Some(FactorStatus::Active);
+        }
+        Some(match self.action {
+            OutlierAction::Disable => FactorStatus::Disabled,
+            OutlierAction::DownWeight(weight) => FactorStatus::DownWeighted(weight),
+            OutlierAction::Quarantine => FactorStatus::Quarantined,
+        })
+    }
+}
+
+/// Consistency history of a single factor across every cycle it closed or was part of.
+/// An inconsistent cycle only shows that one of its factors is wrong, so it is
+/// blamed on a factor that fails more often than every other factor in it. A
+/// healthy factor that shares a loop with a faulty one, but also closes
+/// consistent loops, is then not penalised. When no factor stands out, the
+/// cycle is blamed on nobody until later cycles tell them apart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FactorConsistency {
+    pub cycles: usize,
+    pub inconsistent_cycles: usize,
+    pub blamed_cycles: usize,  // Inconsistent cycles in which this factor failed most often
+    pub last_error: f64,
+    pub(super) inconsistent_loops: Vec<Vec<u64>>,  // Ids of the other factors in each one
+}
+
+impl FactorConsistency {
+    pub fn record(&mut self, error: f64, is_consistent: bool) {
+        self.cycles += 1;
+        if !is_consistent {
+            self.inconsistent_cycles += 1;
+        }
+        self.last_error = error;
+    }
+
+    pub fn inconsistency_rate(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.inconsistent_cycles as f64 / self.cycles as f64
+    }
+
+    pub fn blame_rate(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.blamed_cycles as f64 / self.cycles as f64
+    }
+}
+
+/// Consistency of all factors in the window that came from one sensor
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorHealth {
+    pub sensor_type: String,
+    pub factor_count: usize,
+    pub cycles: usize,                  // Cycle memberships summed over the sensor's factors
+    pub inconsistent_cycles: usize,
+    pub blamed_cycles: usize,
+    pub flagged_factors: usize,         // Factors not currently Active
+    pub suspected_faulty: bool,
+}
+
+impl SensorHealth {
+    pub fn inconsistency_rate(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.inconsistent_cycles as f64 / self.cycles as f64
+    }
+
+    pub fn blame_rate(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.blamed_cycles as f64 / self.cycles as f64
+    }
+}
+
+/// Outcome of a chi-square test on a closed loop
+#[derive(Debug, Clone)]
+pub struct CycleConsistency {
+    pub residual: na::DVector<f64>,                 // Error of the composed loop
+    pub loop_covariance: na::DMatrix<f64>,          // Marginal covariance of the residual
+    pub mahalanobis_distance: f64,
+    pub chi_square: f64,                            // Squared Mahalanobis distance
+    pub degrees_of_freedom: usize,
+    pub p_value: f64,                               // Probability of an error at least this large
+    pub confidence: f64,
+    pub threshold: f64,                             // Chi-square quantile at `confidence`
+    pub is_consistent: bool,
+}
+
+impl CycleConsistency {
+    /// Outcome for a loop that constrains nothing, such as two bearing-only
+    /// sightings of a landmark in front of both poses
+    pub fn unconstrained(confidence: f64) -> Self {
+        Self {
+            residual: na::DVector::zeros(0),
+            loop_covariance: na::DMatrix::zeros(0, 0),
+            mahalanobis_distance: 0.0,
+            chi_square: 0.0,
+            degrees_of_freedom: 0,
+            p_value: 1.0,
+            confidence,
+            threshold: 0.0,
+            is_consistent: true,
+        }
+    }
+}
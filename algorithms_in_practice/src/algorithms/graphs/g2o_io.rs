@@ -0,0 +1,551 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use nalgebra as na;
+
+use crate::common::lie::{SE3, SO3};
+
+use super::factor_graph::{CircularFactorGraph, Factor, NodeType, PoseGroup, Transform2D};
+
+const SENSOR_TYPE: &str = "G2O";
+
+#[derive(Debug)]
+pub enum G2oError {
+    Io(io::Error),
+    FieldCount { line: usize, tag: String, expected: usize, found: usize },
+    InvalidNumber { line: usize, token: String },
+    UnsupportedTag { line: usize, tag: String },
+    UnsupportedNode(NodeType),
+}
+
+impl fmt::Display for G2oError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            G2oError::Io(err) => write!(f, "I/O error: {}", err),
+            G2oError::FieldCount { line, tag, expected, found } => write!(
+                f,
+                "line {}: {} expects {} fields, found {}",
+                line, tag, expected, found
+            ),
+            G2oError::InvalidNumber { line, token } => {
+                write!(f, "line {}: cannot parse '{}' as a number", line, token)
+            }
+            G2oError::UnsupportedTag { line, tag } => {
+                write!(f, "line {}: unsupported tag '{}'", line, tag)
+            }
+            G2oError::UnsupportedNode(node) => {
+                write!(f, "g2o vertices need integer ids, cannot write {:?}", node)
+            }
+        }
+    }
+}
+
+impl std::error::Error for G2oError {}
+
+impl From<io::Error> for G2oError {
+    fn from(err: io::Error) -> Self {
+        G2oError::Io(err)
+    }
+}
+
+/// Vertices and edges of a pose graph as stored in a g2o file
+#[derive(Debug, Clone)]
+pub struct G2oGraph<P = Transform2D, const D: usize = 3> {
+    pub vertices: Vec<(NodeType, P)>,
+    pub factors: Vec<Factor<P, D>>,
+}
+
+pub type G2oGraph2D = G2oGraph<Transform2D, 3>;
+pub type G2oGraph3D = G2oGraph<SE3, 6>;
+
+impl<P, const D: usize> Default for G2oGraph<P, D> {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            factors: Vec::new(),
+        }
+    }
+}
+
+impl<P: PoseGroup<D>, const D: usize> G2oGraph<P, D> {
+    /// Build a factor graph large enough to hold every vertex and edge
+    pub fn to_factor_graph(&self) -> CircularFactorGraph<P, D> {
+        let mut graph = CircularFactorGraph::new(
+            self.vertices.len().max(1),
+            self.factors.len().max(1),
+        );
+        for (node, _) in &self.vertices {
+            graph.add_node(node.clone());
+        }
+        for factor in &self.factors {
+            graph.add_factor(factor.clone());
+        }
+        graph
+    }
+}
+
+/// Read `VERTEX_SE2` / `EDGE_SE2` lines. Blank lines, `#` comments and `FIX`
+/// lines are skipped.
+pub fn read_g2o_se2<R: BufRead>(reader: R) -> Result<G2oGraph2D, G2oError> {
+    let mut graph = G2oGraph2D::default();
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_no = idx + 1;
+        let mut tokens = line.split_whitespace();
+        let tag = match tokens.next() {
+            Some(tag) if !tag.starts_with('#') => tag,
+            _ => continue,
+        };
+        let fields: Vec<&str> = tokens.collect();
+
+        match tag {
+            "VERTEX_SE2" => {
+                expect_fields(line_no, tag, &fields, 4)?;
+                let id = parse_id(line_no, fields[0])?;
+                let v = parse_numbers(line_no, &fields[1..4])?;
+                graph
+                    .vertices
+                    .push((NodeType::RobotPose(id), Transform2D::new(v[0], v[1], v[2])));
+            }
+            "EDGE_SE2" => {
+                expect_fields(line_no, tag, &fields, 11)?;
+                let source = parse_id(line_no, fields[0])?;
+                let target = parse_id(line_no, fields[1])?;
+                let v = parse_numbers(line_no, &fields[2..11])?;
+                let information = na::Matrix3::new(
+                    v[3], v[4], v[5],
+                    v[4], v[6], v[7],
+                    v[5], v[7], v[8],
+                );
+                graph.factors.push(
+                    Factor::new(
+                        NodeType::RobotPose(source),
+                        NodeType::RobotPose(target),
+                        Transform2D::new(v[0], v[1], v[2]),
+                        SENSOR_TYPE,
+                        target,
+                    )
+                    .with_information(information),
+                );
+            }
+            "FIX" => continue,
+            _ => {
+                return Err(G2oError::UnsupportedTag {
+                    line: line_no,
+                    tag: tag.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(graph)
+}
+
+/// Write poses and factors as `VERTEX_SE2` / `EDGE_SE2` lines. Only
+/// `NodeType::RobotPose` nodes can be represented.
+pub fn write_g2o_se2<'a, W, I>(
+    mut writer: W,
+    vertices: &[(NodeType, Transform2D)],
+    factors: I,
+) -> Result<(), G2oError>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Factor>,
+{
+    for (node, pose) in vertices {
+        let id = vertex_id(node)?;
+        writeln!(writer, "VERTEX_SE2 {} {} {} {}", id, pose.x, pose.y, pose.theta)?;
+    }
+
+    for factor in factors {
+        let source = vertex_id(&factor.source)?;
+        let target = vertex_id(&factor.target)?;
+        let t = &factor.transform;
+        let info = &factor.information;
+        writeln!(
+            writer,
+            "EDGE_SE2 {} {} {} {} {} {} {} {} {} {} {}",
+            source, target, t.x, t.y, t.theta,
+            info[(0, 0)], info[(0, 1)], info[(0, 2)],
+            info[(1, 1)], info[(1, 2)],
+            info[(2, 2)],
+        )?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read `VERTEX_SE3:QUAT` / `EDGE_SE3:QUAT` lines. g2o measures rotation
+/// error with the quaternion's vector part, roughly half the rotation vector,
+/// so the rotation rows and columns of the information block are rescaled
+/// to the rotation-vector tangent space used by `SE3::log`.
+pub fn read_g2o_se3<R: BufRead>(reader: R) -> Result<G2oGraph3D, G2oError> {
+    let mut graph = G2oGraph3D::default();
+    let scale = quaternion_error_scale();
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_no = idx + 1;
+        let mut tokens = line.split_whitespace();
+        let tag = match tokens.next() {
+            Some(tag) if !tag.starts_with('#') => tag,
+            _ => continue,
+        };
+        let fields: Vec<&str> = tokens.collect();
+
+        match tag {
+            "VERTEX_SE3:QUAT" => {
+                expect_fields(line_no, tag, &fields, 8)?;
+                let id = parse_id(line_no, fields[0])?;
+                let v = parse_numbers(line_no, &fields[1..8])?;
+                graph.vertices.push((
+                    NodeType::RobotPose(id),
+                    pose_from_fields(&v),
+                ));
+            }
+            "EDGE_SE3:QUAT" => {
+                expect_fields(line_no, tag, &fields, 30)?;
+                let source = parse_id(line_no, fields[0])?;
+                let target = parse_id(line_no, fields[1])?;
+                let v = parse_numbers(line_no, &fields[2..30])?;
+
+                let mut information = na::Matrix6::zeros();
+                let mut k = 7;
+                for i in 0..6 {
+                    for j in i..6 {
+                        information[(i, j)] = v[k];
+                        information[(j, i)] = v[k];
+                        k += 1;
+                    }
+                }
+
+                graph.factors.push(
+                    Factor::new(
+                        NodeType::RobotPose(source),
+                        NodeType::RobotPose(target),
+                        pose_from_fields(&v),
+                        SENSOR_TYPE,
+                        target,
+                    )
+                    .with_information(scale * information * scale),
+                );
+            }
+            "FIX" => continue,
+            _ => {
+                return Err(G2oError::UnsupportedTag {
+                    line: line_no,
+                    tag: tag.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(graph)
+}
+
+/// Write poses and factors as `VERTEX_SE3:QUAT` / `EDGE_SE3:QUAT` lines,
+/// converting information back to g2o's quaternion-error convention.
+pub fn write_g2o_se3<'a, W, I>(
+    mut writer: W,
+    vertices: &[(NodeType, SE3)],
+    factors: I,
+) -> Result<(), G2oError>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Factor<SE3, 6>>,
+{
+    let unscale = quaternion_error_scale().try_inverse().unwrap();
+
+    for (node, pose) in vertices {
+        let id = vertex_id(node)?;
+        writeln!(writer, "VERTEX_SE3:QUAT {} {}", id, pose_fields(pose))?;
+    }
+
+    for factor in factors {
+        let source = vertex_id(&factor.source)?;
+        let target = vertex_id(&factor.target)?;
+        let info = unscale * factor.information * unscale;
+        let mut line = format!(
+            "EDGE_SE3:QUAT {} {} {}",
+            source,
+            target,
+            pose_fields(&factor.transform)
+        );
+        for i in 0..6 {
+            for j in i..6 {
+                line.push_str(&format!(" {}", info[(i, j)]));
+            }
+        }
+        writeln!(writer, "{}", line)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Load a 2D g2o file from disk
+pub fn load_g2o_se2<P: AsRef<Path>>(path: P) -> Result<G2oGraph2D, G2oError> {
+    read_g2o_se2(BufReader::new(File::open(path)?))
+}
+
+/// Save poses and factors to a 2D g2o file, with vertices sorted by id
+pub fn save_g2o_se2<'a, P, I>(
+    path: P,
+    vertices: &[(NodeType, Transform2D)],
+    factors: I,
+) -> Result<(), G2oError>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'a Factor>,
+{
+    let mut sorted = vertices.to_vec();
+    sorted.sort_by_key(|(node, _)| match node {
+        NodeType::RobotPose(id) => *id,
+        NodeType::Landmark(_) => u64::MAX,
+    });
+    write_g2o_se2(BufWriter::new(File::create(path)?), &sorted, factors)
+}
+
+/// Load a 3D g2o file from disk
+pub fn load_g2o_se3<P: AsRef<Path>>(path: P) -> Result<G2oGraph3D, G2oError> {
+    read_g2o_se3(BufReader::new(File::open(path)?))
+}
+
+/// Save poses and factors to a 3D g2o file, with vertices sorted by id
+pub fn save_g2o_se3<'a, P, I>(
+    path: P,
+    vertices: &[(NodeType, SE3)],
+    factors: I,
+) -> Result<(), G2oError>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'a Factor<SE3, 6>>,
+{
+    let mut sorted = vertices.to_vec();
+    sorted.sort_by_key(|(node, _)| match node {
+        NodeType::RobotPose(id) => *id,
+        NodeType::Landmark(_) => u64::MAX,
+    });
+    write_g2o_se3(BufWriter::new(File::create(path)?), &sorted, factors)
+}
+
+/// Maps rotation-vector error to g2o's quaternion vector-part error
+fn quaternion_error_scale() -> na::Matrix6<f64> {
+    na::Matrix6::from_diagonal(&na::Vector6::new(1.0, 1.0, 1.0, 0.5, 0.5, 0.5))
+}
+
+/// Pose from its g2o fields (x, y, z, qx, qy, qz, qw)
+fn pose_from_fields(v: &[f64]) -> SE3 {
+    let rotation = SO3::from_quaternion(v[6], v[3], v[4], v[5]);
+    SE3::new(rotation, na::Vector3::new(v[0], v[1], v[2]))
+}
+
+fn pose_fields(pose: &SE3) -> String {
+    let t = &pose.translation;
+    let q = pose.rotation.rotation.quaternion();
+    format!("{} {} {} {} {} {} {}", t[0], t[1], t[2], q.i, q.j, q.k, q.w)
+}
+
+fn vertex_id(node: &NodeType) -> Result<u64, G2oError> {
+    match node {
+        NodeType::RobotPose(id) => Ok(*id),
+        other => Err(G2oError::UnsupportedNode(other.clone())),
+    }
+}
+
+fn expect_fields(line: usize, tag: &str, fields: &[&str], expected: usize) -> Result<(), G2oError> {
+    if fields.len() != expected {
+        return Err(G2oError::FieldCount {
+            line,
+            tag: tag.to_string(),
+            expected,
+            found: fields.len(),
+        });
+    }
+    Ok(())
+}
+
+fn parse_id(line: usize, token: &str) -> Result<u64, G2oError> {
+    token.parse().map_err(|_| G2oError::InvalidNumber {
+        line,
+        token: token.to_string(),
+    })
+}
+
+fn parse_numbers(line: usize, tokens: &[&str]) -> Result<Vec<f64>, G2oError> {
+    tokens
+        .iter()
+        .map(|token| {
+            token.parse().map_err(|_| G2oError::InvalidNumber {
+                line,
+                token: token.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::graphs::FactorGraph3D;
+
+    fn data_path(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/g2o").join(name)
+    }
+
+    #[test]
+    fn test_load_intel() {
+        let graph = load_g2o_se2(data_path("intel.g2o")).unwrap();
+        assert_eq!(graph.vertices.len(), 1228);
+        assert_eq!(graph.factors.len(), 1483);
+
+        let first = &graph.factors[0];
+        assert_eq!(first.source, NodeType::RobotPose(0));
+        assert_eq!(first.target, NodeType::RobotPose(1));
+        assert!((first.information[(0, 0)] - 11.111271).abs() < 1e-9);
+        assert!((first.information[(0, 1)] + 0.249667).abs() < 1e-9);
+        assert_eq!(first.information[(0, 1)], first.information[(1, 0)]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "VERTEX_SE2 0 0 0 0\nVERTEX_SE2 1 1.5 -0.25 0.1\n\
+                    EDGE_SE2 0 1 1.5 -0.25 0.1 10 1 0 20 2 30\n";
+        let graph = read_g2o_se2(text.as_bytes()).unwrap();
+
+        let mut out = Vec::new();
+        write_g2o_se2(&mut out, &graph.vertices, &graph.factors).unwrap();
+        let reread = read_g2o_se2(out.as_slice()).unwrap();
+
+        assert_eq!(reread.vertices, graph.vertices);
+        assert_eq!(reread.factors[0].transform, graph.factors[0].transform);
+        assert_eq!(reread.factors[0].information, graph.factors[0].information);
+    }
+
+    #[test]
+    fn test_sphere2500_loop_consistency() {
+        let g2o = load_g2o_se3(data_path("sphere2500.g2o")).unwrap();
+        assert_eq!(g2o.vertices.len(), 2500);
+        assert_eq!(g2o.factors.len(), 4949);
+
+        // First rings of the sphere, including the loop closures between them
+        let window = 300;
+        let mut graph = FactorGraph3D::new(window, 2 * window);
+        for (node, _) in g2o.vertices.iter().take(window) {
+            graph.add_node(node.clone());
+        }
+
+        // Judge each loop as closed by its factor, not the open path returned
+        let mut cycles = 0;
+        let mut consistent = 0;
+        for factor in &g2o.factors {
+            if graph.add_factor(factor.clone()).is_some() {
+                cycles += 1;
+                let closing = graph.factors().last().unwrap().id;
+                if graph.factor_consistency(closing).unwrap().inconsistent_cycles == 0 {
+                    consistent += 1;
+                }
+            }
+        }
+
+        // The noise matches the stated information, so a covariance or
+        // quaternion scaling error shows up as failed loops
+        assert!(cycles > 200, "{} cycles", cycles);
+        assert_eq!(consistent, cycles);
+    }
+
+    #[test]
+    fn test_se3_round_trip() {
+        let text = "VERTEX_SE3:QUAT 0 0 0 0 0 0 0 1\n\
+                    VERTEX_SE3:QUAT 1 1 2 3 0 0 0.70710678 0.70710678\n\
+                    EDGE_SE3:QUAT 0 1 1 2 3 0 0 0.70710678 0.70710678 \
+                    10 0 0 0 0 0 10 0 0 0 0 10 0 0 0 400 1 2 400 3 100\n";
+        let graph = read_g2o_se3(text.as_bytes()).unwrap();
+        assert!((graph.factors[0].information[(3, 3)] - 100.0).abs() < 1e-12);
+
+        let mut out = Vec::new();
+        write_g2o_se3(&mut out, &graph.vertices, &graph.factors).unwrap();
+        let reread = read_g2o_se3(out.as_slice()).unwrap();
+
+        assert_eq!(reread.vertices, graph.vertices);
+        assert!((reread.factors[0].information - graph.factors[0].information).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_se3_datasets_round_trip() {
+        for (name, vertex_count, edge_count) in
+            [("sphere2500.g2o", 2500, 4949), ("parking-garage.g2o", 1661, 6275)]
+        {
+            let text = std::fs::read_to_string(data_path(name)).unwrap();
+            let graph = read_g2o_se3(text.as_bytes()).unwrap();
+            assert_eq!(graph.vertices.len(), vertex_count, "{}", name);
+            assert_eq!(graph.factors.len(), edge_count, "{}", name);
+
+            // The rotation block of the file's upper triangle is scaled by 0.5^2,
+            // the translation-rotation block by 0.5
+            let raw: Vec<f64> = text
+                .lines()
+                .find(|line| line.starts_with("EDGE_SE3:QUAT"))
+                .unwrap()
+                .split_whitespace()
+                .skip(10)
+                .map(|token| token.parse().unwrap())
+                .collect();
+            let information = &graph.factors[0].information;
+            let mut k = 0;
+            for i in 0..6 {
+                for j in i..6 {
+                    let scale = match (i < 3, j < 3) {
+                        (true, true) => 1.0,
+                        (false, false) => 0.25,
+                        _ => 0.5,
+                    };
+                    assert_eq!(information[(i, j)], raw[k] * scale, "{} ({}, {})", name, i, j);
+                    assert_eq!(information[(j, i)], information[(i, j)]);
+                    k += 1;
+                }
+            }
+
+            let mut out = Vec::new();
+            write_g2o_se3(&mut out, &graph.vertices, &graph.factors).unwrap();
+            let reread = read_g2o_se3(out.as_slice()).unwrap();
+            assert_eq!(reread.vertices.len(), vertex_count);
+            assert_eq!(reread.factors.len(), edge_count);
+            for (a, b) in graph.factors.iter().zip(&reread.factors) {
+                assert_eq!((&a.source, &a.target), (&b.source, &b.target));
+                let scale = a.information.norm().max(1.0);
+                assert!((a.information - b.information).norm() < 1e-12 * scale, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_lines_report_line_numbers() {
+        let short = "VERTEX_SE2 0 0 0 0\nEDGE_SE2 0 1 1.0 0.0\n";
+        match read_g2o_se2(short.as_bytes()) {
+            Err(G2oError::FieldCount { line, found, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(found, 4);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let long = "VERTEX_SE2 0 0 0 0 0\n";
+        assert!(matches!(
+            read_g2o_se2(long.as_bytes()),
+            Err(G2oError::FieldCount { line: 1, expected: 4, found: 5, .. })
+        ));
+
+        let bad_number = "# header\nVERTEX_SE2 0 0 zero 0\n";
+        match read_g2o_se2(bad_number.as_bytes()) {
+            Err(G2oError::InvalidNumber { line, token }) => {
+                assert_eq!(line, 2);
+                assert_eq!(token, "zero");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,574 @@
+use std::sync::Arc;
+use nalgebra as na;
+
+use crate::algorithms::trees::KdTree;
+use crate::common::lie::{skew, SE3};
+use crate::common::robust_kernel::RobustKernel;
+
+use super::factor_graph::{Factor, NodeType, PoseGroup, Transform2D};
+
+/// Eigenvalue ratio below which neighbouring target points count as lying
+/// on a line or plane
+const FLATNESS: f64 = 0.25;
+
+/// Poses ICP can estimate: rigid transforms acting on N-dimensional points
+pub trait RegistrationPose<const N: usize, const D: usize>: PoseGroup<D> {
+    fn transform_point(&self, point: &na::SVector<f64, N>) -> na::SVector<f64, N>;
+
+    /// Jacobian of `T exp(delta) p` with respect to delta at zero
+    fn point_jacobian(&self, point: &na::SVector<f64, N>) -> na::SMatrix<f64, N, D>;
+}
+
+impl RegistrationPose<2, 3> for Transform2D {
+    fn transform_point(&self, point: &na::Vector2<f64>) -> na::Vector2<f64> {
+        na::Rotation2::new(self.theta) * point + na::Vector2::new(self.x, self.y)
+    }
+
+    fn point_jacobian(&self, point: &na::Vector2<f64>) -> na::Matrix2x3<f64> {
+        let rotation = na::Rotation2::new(self.theta).into_inner();
+        let mut jacobian = na::Matrix2x3::zeros();
+        jacobian.fixed_view_mut::<2, 2>(0, 0).copy_from(&rotation);
+        jacobian.set_column(2, &(rotation * na::Vector2::new(-point.y, point.x)));
+        jacobian
+    }
+}
+
+impl RegistrationPose<3, 6> for SE3 {
+    fn transform_point(&self, point: &na::Vector3<f64>) -> na::Vector3<f64> {
+        SE3::transform_point(self, point)
+    }
+
+    fn point_jacobian(&self, point: &na::Vector3<f64>) -> na::Matrix3x6<f64> {
+        let rotation = self.rotation.matrix();
+        let mut jacobian = na::Matrix3x6::zeros();
+        jacobian.fixed_view_mut::<3, 3>(0, 0).copy_from(&rotation);
+        jacobian.fixed_view_mut::<3, 3>(0, 3).copy_from(&(-rotation * skew(point)));
+        jacobian
+    }
+}
+
+/// Distance minimized between a source point and its matched target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcpMetric {
+    PointToPoint,
+    PointToLine,   // Distance to the line through the target's neighbours
+    PointToPlane,  // Distance along the neighbours' normal; in 2D the same as PointToLine
+}
+
+#[derive(Debug, Clone)]
+pub struct IcpConfig {
+    pub metric: IcpMetric,
+    pub max_iterations: usize,
+    pub max_correspondence_distance: f64,  // Pairs further apart are left out
+    pub neighbours: usize,                 // Target points fitted to each line or plane
+    pub min_correspondences: usize,
+    pub cost_tolerance: f64,               // Stop when relative cost change drops below this
+    pub step_tolerance: f64,               // Stop when the update norm drops below this
+    pub kernel: Option<Arc<dyn RobustKernel>>,  // Applied to the squared point distance
+    pub min_translation_sigma: f64,        // Added to the covariance, see `Icp::align`
+    pub min_rotation_sigma: f64,           // Radians
+}
+
+impl Default for IcpConfig {
+    fn default() -> Self {
+        Self {
+            metric: IcpMetric::PointToPlane,
+            max_iterations: 50,
+            max_correspondence_distance: 1.0,
+            neighbours: 8,
+            min_correspondences: 10,
+            cost_tolerance: 1e-4,
+            step_tolerance: 1e-6,
+            kernel: None,
+            min_translation_sigma: 1e-4,
+            min_rotation_sigma: 1e-5,
+        }
+    }
+}
+
+impl IcpConfig {
+    pub fn with_kernel<K: RobustKernel + 'static>(mut self, kernel: K) -> Self {
+        self.kernel = Some(Arc::new(kernel));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IcpStatus {
+    Converged,
+    MaxIterationsReached,
+    TooFewCorrespondences,
+    SingularSystem,  // The correspondences leave some direction unconstrained
+}
+
+#[derive(Debug, Clone)]
+pub struct IcpIteration {
+    pub iteration: usize,
+    pub cost: f64,               // Robustified, before the step
+    pub correspondences: usize,
+    pub step_norm: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IcpReport {
+    pub initial_cost: f64,
+    pub final_cost: f64,
+    pub iterations: Vec<IcpIteration>,
+    pub status: IcpStatus,
+    pub correspondences: usize,  // At the final transform
+    pub rmse: f64,               // Of the metric distance over those correspondences
+}
+
+impl IcpReport {
+    pub fn converged(&self) -> bool {
+        self.status == IcpStatus::Converged
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IcpResult<P, const D: usize> {
+    pub transform: P,                         // Takes source points onto the target
+    pub covariance: na::SMatrix<f64, D, D>,   // In the tangent space of `transform`
+    pub report: IcpReport,
+}
+
+impl<P: PoseGroup<D>, const D: usize> IcpResult<P, D> {
+    /// Scan-matching factor between the pose the target was scanned from and
+    /// the pose the source was scanned from, `None` unless the alignment
+    /// converged
+    pub fn to_factor(
+        &self,
+        target_pose: NodeType,
+        source_pose: NodeType,
+        sensor_type: &str,
+        timestamp: u64,
+    ) -> Option<Factor<P, D>> {
+        if !self.report.converged() {
+            return None;
+        }
+        let factor =
+            Factor::new(target_pose, source_pose, self.transform.clone(), sensor_type, timestamp);
+        Some(factor.with_covariance(self.covariance))
+    }
+}
+
+/// Gauss-Newton system of one set of correspondences
+struct Linearization<const D: usize> {
+    hessian: na::SMatrix<f64, D, D>,
+    gradient: na::SVector<f64, D>,
+    cost: f64,
+    squared_sum: f64,   // Unweighted squared distances
+    weighted_sum: f64,  // Squared distances weighted by the kernel
+    dimensions: usize,  // Scalar residuals, each correspondence counting its projection's rank
+    count: usize,
+}
+
+/// Iterative closest point registration against a fixed target cloud. The
+/// target is indexed once, along with the line or plane through each target
+/// point's neighbours, so many source clouds can be aligned to it.
+#[derive(Debug, Clone)]
+pub struct Icp<const N: usize> {
+    config: IcpConfig,
+    tree: KdTree<N>,
+    projections: Vec<Option<na::SMatrix<f64, N, N>>>,  // Onto the residual space if the fit is flat
+}
+
+pub type Icp2D = Icp<2>;
+pub type Icp3D = Icp<3>;
+
+impl<const N: usize> Icp<N> {
+    pub fn new(target: Vec<na::SVector<f64, N>>, config: IcpConfig) -> Self {
+        let tree = KdTree::new(target);
+        let projections = tree
+            .points()
+            .iter()
+            .map(|point| match config.metric {
+                IcpMetric::PointToPoint => Some(na::SMatrix::identity()),
+                IcpMetric::PointToLine | IcpMetric::PointToPlane => {
+                    Self::fit_projection(&tree, point, &config)
+                }
+            })
+            .collect();
+        Self { config, tree, projections }
+    }
+
+    pub fn config(&self) -> &IcpConfig {
+        &self.config
+    }
+
+    pub fn target(&self) -> &[na::SVector<f64, N>] {
+        self.tree.points()
+    }
+
+    /// Estimate the transform taking `source` onto the target, starting from `initial`.
+    ///
+    /// The covariance is the inverse Gauss-Newton Hessian scaled by the residual
+    /// variance left at the solution, so it grows along directions the
+    /// correspondences barely constrain, such as along a corridor. It assumes
+    /// each correspondence errs independently. The configured minimum sigmas
+    /// only keep a noise-free fit from claiming unbounded information.
+    pub fn align<P, const D: usize>(
+        &self,
+        source: &[na::SVector<f64, N>],
+        initial: &P,
+    ) -> IcpResult<P, D>
+    where
+        P: RegistrationPose<N, D>,
+    {
+        let mut transform = initial.clone();
+        let mut iterations = Vec::new();
+        let mut status = IcpStatus::MaxIterationsReached;
+        let mut initial_cost = None;
+        for iteration in 0..self.config.max_iterations {
+            let system = self.linearize(source, &transform);
+            initial_cost.get_or_insert(system.cost);
+            if system.count < self.config.min_correspondences.max(D) {
+                status = IcpStatus::TooFewCorrespondences;
+                break;
+            }
+            let Some(step) = system.hessian.cholesky().map(|c| -c.solve(&system.gradient)) else {
+                status = IcpStatus::SingularSystem;
+                break;
+            };
+            transform = transform.compose(&P::exp(&step));
+            // Correspondences can flip back and forth near the solution, so the
+            // cost may settle before the steps vanish
+            let settled = iterations.last().is_some_and(|last: &IcpIteration| {
+                (last.cost - system.cost).abs()
+                    <= self.config.cost_tolerance * last.cost.max(f64::MIN_POSITIVE)
+            });
+            iterations.push(IcpIteration {
+                iteration,
+                cost: system.cost,
+                correspondences: system.count,
+                step_norm: step.norm(),
+            });
+            if step.norm() < self.config.step_tolerance || settled {
+                status = IcpStatus::Converged;
+                break;
+            }
+        }
+
+        // Noise scale from the residuals left at the solution
+        let system = self.linearize(source, &transform);
+        let variance = system.weighted_sum / system.dimensions.saturating_sub(D).max(1) as f64;
+        let floor = na::SVector::<f64, D>::from_fn(|k, _| {
+            // Tangent vectors put the translation first
+            if k < N { self.config.min_translation_sigma } else { self.config.min_rotation_sigma }
+        });
+        let covariance = system.hessian.try_inverse().map_or_else(
+            || na::SMatrix::from_diagonal_element(f64::INFINITY),
+            |inverse| inverse * variance + na::SMatrix::from_diagonal(&floor.component_mul(&floor)),
+        );
+        let rmse = if system.count > 0 {
+            (system.squared_sum / system.count as f64).sqrt()
+        } else {
+            f64::INFINITY
+        };
+        IcpResult {
+            transform,
+            covariance,
+            report: IcpReport {
+                initial_cost: initial_cost.unwrap_or(system.cost),
+                final_cost: system.cost,
+                iterations,
+                status,
+                correspondences: system.count,
+                rmse,
+            },
+        }
+    }
+
+    fn linearize<P, const D: usize>(
+        &self,
+        source: &[na::SVector<f64, N>],
+        transform: &P,
+    ) -> Linearization<D>
+    where
+        P: RegistrationPose<N, D>,
+    {
+        let mut system = Linearization {
+            hessian: na::SMatrix::zeros(),
+            gradient: na::SVector::zeros(),
+            cost: 0.0,
+            squared_sum: 0.0,
+            weighted_sum: 0.0,
+            dimensions: 0,
+            count: 0,
+        };
+        let max_distance2 = self.config.max_correspondence_distance.powi(2);
+        for point in source {
+            let moved = transform.transform_point(point);
+            let Some((index, distance2)) = self.tree.nearest(&moved) else { break };
+            let Some(projection) = self.projections[index].filter(|_| distance2 <= max_distance2)
+            else {
+                continue;
+            };
+
+            let residual = projection * (moved - self.tree.points()[index]);
+            let jacobian = projection * transform.point_jacobian(point);
+            let squared = residual.norm_squared();
+            let [rho, weight, _] = match &self.config.kernel {
+                Some(kernel) => kernel.evaluate(squared),
+                None => [squared, 1.0, 0.0],
+            };
+            system.hessian += jacobian.transpose() * jacobian * weight;
+            system.gradient += jacobian.transpose() * residual * weight;
+            system.cost += rho;
+            system.squared_sum += squared;
+            system.weighted_sum += squared * weight;
+            system.dimensions += projection.trace().round() as usize;
+            system.count += 1;
+        }
+        system
+    }
+
+    /// Projection onto the normal space of the line or plane fitted to a
+    /// target point's neighbours
+    fn fit_projection(
+        tree: &KdTree<N>,
+        point: &na::SVector<f64, N>,
+        config: &IcpConfig,
+    ) -> Option<na::SMatrix<f64, N, N>> {
+        let neighbours = tree.k_nearest(point, config.neighbours.max(N));
+        if neighbours.len() < N {
+            return None;
+        }
+        let mean = neighbours.iter().map(|&(i, _)| tree.points()[i]).sum::<na::SVector<f64, N>>()
+            / neighbours.len() as f64;
+        let scatter =
+            neighbours.iter().fold(na::SMatrix::<f64, N, N>::zeros(), |scatter, &(i, _)| {
+                let offset = tree.points()[i] - mean;
+                scatter + offset * offset.transpose()
+            });
+        // Dynamic storage, since the fixed-size decomposition needs a typenum dimension
+        let eigen = na::DMatrix::from_column_slice(N, N, scatter.as_slice()).symmetric_eigen();
+        let mut axes: Vec<usize> = (0..N).collect();
+        axes.sort_by(|&a, &b| eigen.eigenvalues[a].total_cmp(&eigen.eigenvalues[b]));
+        let spread = |k: usize| eigen.eigenvalues[axes[k]].max(0.0);
+
+        match config.metric {
+            IcpMetric::PointToLine if spread(N - 2) <= FLATNESS * spread(N - 1) => {
+                let direction = na::SVector::<f64, N>::from_column_slice(
+                    eigen.eigenvectors.column(axes[N - 1]).as_slice(),
+                );
+                Some(na::SMatrix::identity() - direction * direction.transpose())
+            }
+            IcpMetric::PointToPlane if spread(0) <= FLATNESS * spread(1) => {
+                let normal = na::SVector::<f64, N>::from_column_slice(
+                    eigen.eigenvectors.column(axes[0]).as_slice(),
+                );
+                Some(normal * normal.transpose())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::lie::LieGroup;
+    use crate::common::robust_kernel::CauchyKernel;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Walls of a 6 x 4 m room with a pillar, sampled every 5 cm
+    fn room() -> Vec<na::Vector2<f64>> {
+        sampled_room(0.0)
+    }
+
+    /// The room with its samples shifted `phase` of the spacing along the walls
+    fn sampled_room(phase: f64) -> Vec<na::Vector2<f64>> {
+        let mut points = Vec::new();
+        for i in 0..=120 {
+            let s = (i as f64 + phase) * 0.05;
+            points.push(na::Vector2::new(s, 0.0));
+            points.push(na::Vector2::new(s, 4.0));
+        }
+        for i in 0..=80 {
+            let s = (i as f64 + phase) * 0.05;
+            points.push(na::Vector2::new(0.0, s));
+            points.push(na::Vector2::new(6.0, s));
+            let (sin, cos) = (s * 1.57).sin_cos();
+            points.push(na::Vector2::new(2.0 + 0.25 * cos, 2.0 + 0.25 * sin));
+        }
+        points
+    }
+
+    #[test]
+    fn test_planar_metrics_recover_the_motion() {
+        let truth = Transform2D::new(0.08, -0.05, 0.03);
+        let inverse = truth.inverse();
+        // The same walls seen from the moved pose, with a few spurious returns
+        let mut scan: Vec<na::Vector2<f64>> =
+            room().iter().map(|p| inverse.transform_point(p)).collect();
+        scan.extend(
+            (0..20).map(|i| na::Vector2::new(3.0 + 0.01 * i as f64, 1.0 + 0.02 * i as f64)),
+        );
+
+        for metric in [IcpMetric::PointToPoint, IcpMetric::PointToLine, IcpMetric::PointToPlane] {
+            let config = IcpConfig { metric, neighbours: 5, ..IcpConfig::default() }
+                .with_kernel(CauchyKernel::new(0.2));
+            let result = Icp2D::new(room(), config).align(&scan, &Transform2D::new(0.0, 0.0, 0.0));
+            assert!(result.report.converged(), "{:?}: {:?}", metric, result.report.status);
+            let error = truth.inverse().compose(&result.transform);
+            if metric == IcpMetric::PointToPoint {
+                // Matched to the nearest sample, points cannot slide along a wall
+                // by less than the 5 cm sample spacing
+                assert!(error.x.hypot(error.y) < 0.06 && error.theta.abs() < 0.02, "{:?}", error);
+            } else {
+                assert!(error.error_from_identity() < 1e-6, "{:?}: {:?}", metric, error);
+            }
+            assert!(result.report.final_cost < result.report.initial_cost);
+        }
+
+        let factor = Icp2D::new(room(), IcpConfig::default())
+            .align(&scan, &truth)
+            .to_factor(NodeType::RobotPose(0), NodeType::RobotPose(100), "LIDAR", 100)
+            .unwrap();
+        assert_eq!(factor.sensor_type, "LIDAR");
+        assert!(factor.information.diagonal().iter().all(|&info| info > 0.0));
+
+        // Stopped short of convergence: no factor
+        let config = IcpConfig { max_iterations: 1, ..IcpConfig::default() };
+        let stopped = Icp2D::new(room(), config).align(&scan, &Transform2D::new(0.0, 0.0, 0.0));
+        assert_eq!(stopped.report.status, IcpStatus::MaxIterationsReached);
+        assert!(stopped
+            .to_factor(NodeType::RobotPose(0), NodeType::RobotPose(100), "LIDAR", 100)
+            .is_none());
+    }
+
+    #[test]
+    fn test_covariance_covers_monte_carlo_spread() {
+        // Each trial scans the room from a random pose, with its own sample
+        // positions along the walls and 1 cm of range noise
+        let trials = 100;
+        let run = |floors: bool| {
+            let defaults = IcpConfig::default();
+            let config = IcpConfig {
+                metric: IcpMetric::PointToLine,
+                neighbours: 5,
+                // Noisy points can flip between two matchings 0.1 mm apart
+                step_tolerance: 1e-4,
+                min_translation_sigma: if floors { defaults.min_translation_sigma } else { 0.0 },
+                min_rotation_sigma: if floors { defaults.min_rotation_sigma } else { 0.0 },
+                ..defaults
+            };
+            let icp = Icp2D::new(room(), config);
+            let mut rng = StdRng::seed_from_u64(5);
+            let mut sampled = na::Matrix3::zeros();
+            let mut predicted = na::Matrix3::zeros();
+            for _ in 0..trials {
+                let truth = Transform2D::new(
+                    rng.gen_range(-0.1..0.1),
+                    rng.gen_range(-0.1..0.1),
+                    rng.gen_range(-0.05..0.05),
+                );
+                let inverse = truth.inverse();
+                let scan: Vec<na::Vector2<f64>> = sampled_room(rng.gen_range(0.0..1.0))
+                    .iter()
+                    .map(|p| {
+                        // Uniform noise with a 1 cm standard deviation
+                        let noise = na::Vector2::from_fn(|_, _| rng.gen_range(-1.0..1.0) * 0.0173);
+                        inverse.transform_point(&(p + noise))
+                    })
+                    .collect();
+                let result = icp.align(&scan, &Transform2D::new(0.0, 0.0, 0.0));
+                assert!(result.report.converged(), "{:?}", result.report.status);
+                let error = truth.inverse().compose(&result.transform).log();
+                sampled += error * error.transpose() / trials as f64;
+                predicted += result.covariance / trials as f64;
+            }
+            (sampled.diagonal(), predicted.diagonal())
+        };
+
+        // Independent range noise is what the residual estimate describes
+        let (sampled, predicted) = run(false);
+        for k in 0..3 {
+            let ratio = sampled[k] / predicted[k];
+            assert!((0.5..2.0).contains(&ratio), "axis {}: ratio {}", k, ratio);
+        }
+
+        // The default floors sit well below what a real match reports
+        let (_, floored) = run(true);
+        for k in 0..3 {
+            assert!(floored[k] < 1.1 * predicted[k], "axis {}: {}", k, floored[k] / predicted[k]);
+        }
+    }
+
+    #[test]
+    fn test_covariance_grows_along_degenerate_directions() {
+        // 10 m of corridor along x, closed by a 20 cm stub of wall at one end
+        let mut corridor = Vec::new();
+        for i in 0..=200 {
+            let s = i as f64 * 0.05;
+            corridor.push(na::Vector2::new(s, 0.0));
+            corridor.push(na::Vector2::new(s, 1.5));
+        }
+        corridor.extend((1..=4).map(|i| na::Vector2::new(10.0, i as f64 * 0.05)));
+
+        let config =
+            IcpConfig { metric: IcpMetric::PointToLine, neighbours: 5, ..IcpConfig::default() };
+        let mut rng = StdRng::seed_from_u64(2);
+        let truth = Transform2D::new(0.05, 0.03, 0.01);
+        let inverse = truth.inverse();
+        let noisy_scan = |cloud: &[na::Vector2<f64>], rng: &mut StdRng| -> Vec<na::Vector2<f64>> {
+            cloud
+                .iter()
+                .map(|p| {
+                    let noise = na::Vector2::from_fn(|_, _| rng.gen_range(-1.0..1.0) * 0.0173);
+                    inverse.transform_point(&(p + noise))
+                })
+                .collect()
+        };
+        let identity = Transform2D::new(0.0, 0.0, 0.0);
+        let scan = noisy_scan(&room(), &mut rng);
+        let in_room = Icp2D::new(room(), config.clone()).align(&scan, &identity);
+        let scan = noisy_scan(&corridor, &mut rng);
+        let in_corridor = Icp2D::new(corridor.clone(), config.clone()).align(&scan, &identity);
+        assert!(in_room.report.converged() && in_corridor.report.converged());
+
+        // Only the four points of the stub pin down sliding along the corridor
+        let room_sigma = in_room.covariance.diagonal().map(f64::sqrt);
+        let corridor_sigma = in_corridor.covariance.diagonal().map(f64::sqrt);
+        assert!(corridor_sigma[0] > 3.0 * room_sigma[0], "{} vs {}", corridor_sigma, room_sigma);
+        assert!(corridor_sigma[0] > 3.0 * corridor_sigma[1], "{}", corridor_sigma);
+
+        // Without the stub nothing constrains it at all
+        let wall: Vec<na::Vector2<f64>> = corridor.iter().filter(|p| p.x < 10.0).copied().collect();
+        let scan = noisy_scan(&wall, &mut rng);
+        let in_tunnel = Icp2D::new(wall, config).align(&scan, &identity);
+        assert_eq!(in_tunnel.report.status, IcpStatus::SingularSystem);
+    }
+
+    #[test]
+    fn test_point_to_plane_in_3d() {
+        // Floor and two walls meeting in a corner
+        let mut target = Vec::new();
+        for i in 0..30 {
+            for j in 0..30 {
+                let (a, b) = (i as f64 * 0.1, j as f64 * 0.1);
+                target.push(na::Vector3::new(a, b, 0.0));
+                target.push(na::Vector3::new(a, 0.0, b));
+                target.push(na::Vector3::new(0.0, a, b));
+            }
+        }
+        let truth = SE3::exp(&na::Vector6::new(0.05, -0.04, 0.03, 0.02, -0.01, 0.03));
+        let inverse = truth.inverse();
+        let source: Vec<na::Vector3<f64>> =
+            target.iter().step_by(2).map(|p| inverse.transform_point(p)).collect();
+
+        let icp = Icp3D::new(target.clone(), IcpConfig::default());
+        let result = icp.align(&source, &SE3::identity());
+        assert!(result.report.converged());
+        assert!((truth.inverse().compose(&result.transform)).log().norm() < 1e-6);
+        assert!(result.report.rmse < 1e-6);
+
+        // A single plane cannot pin down sliding along it
+        let floor: Vec<na::Vector3<f64>> = target.iter().step_by(3).copied().collect();
+        let result =
+            Icp3D::new(floor.clone(), IcpConfig::default()).align(&floor, &SE3::identity());
+        assert_eq!(result.report.status, IcpStatus::SingularSystem);
+    }
+}
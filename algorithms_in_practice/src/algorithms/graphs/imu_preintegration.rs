@@ -0,0 +1,701 @@
+use nalgebra as na;
+
+use crate::common::lie::{skew, LieGroup, SE3, SO3};
+
+use super::factor_graph::NodeType;
+use super::factor3d::Factor3D;
+
+type Vector9 = na::SVector<f64, 9>;
+type Matrix9 = na::SMatrix<f64, 9, 9>;
+type Vector15 = na::SVector<f64, 15>;
+type Matrix15 = na::SMatrix<f64, 15, 15>;
+
+/// Attitude, position and velocity of the IMU in the navigation frame.
+/// Tangent vectors are ordered (rotation, position, velocity) and expressed in
+/// the body frame: `(R, p, v) + d = (R exp(d_r), p + R d_p, v + R d_v)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavState {
+    pub rotation: SO3,
+    pub position: na::Vector3<f64>,
+    pub velocity: na::Vector3<f64>,
+}
+
+impl NavState {
+    pub fn new(rotation: SO3, position: na::Vector3<f64>, velocity: na::Vector3<f64>) -> Self {
+        Self { rotation, position, velocity }
+    }
+
+    /// Apply a body-frame perturbation
+    pub fn retract(&self, delta: &Vector9) -> NavState {
+        let rotation = self.rotation.matrix();
+        NavState {
+            rotation: self.rotation.plus(&delta.fixed_rows::<3>(0).into_owned()),
+            position: self.position + rotation * delta.fixed_rows::<3>(3),
+            velocity: self.velocity + rotation * delta.fixed_rows::<3>(6),
+        }
+    }
+
+    /// Body-frame difference `d` such that `self.retract(d) == other`
+    pub fn local(&self, other: &NavState) -> Vector9 {
+        let rotation_t = self.rotation.matrix().transpose();
+        let mut delta = Vector9::zeros();
+        delta.fixed_rows_mut::<3>(0).copy_from(&other.rotation.minus(&self.rotation));
+        delta.fixed_rows_mut::<3>(3).copy_from(&(rotation_t * (other.position - self.position)));
+        delta.fixed_rows_mut::<3>(6).copy_from(&(rotation_t * (other.velocity - self.velocity)));
+        delta
+    }
+
+    pub fn pose(&self) -> SE3 {
+        SE3::new(self.rotation, self.position)
+    }
+}
+
+/// Additive accelerometer and gyroscope biases, ordered (accelerometer, gyroscope)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImuBias {
+    pub accelerometer: na::Vector3<f64>,
+    pub gyroscope: na::Vector3<f64>,
+}
+
+impl ImuBias {
+    pub fn new(accelerometer: na::Vector3<f64>, gyroscope: na::Vector3<f64>) -> Self {
+        Self { accelerometer, gyroscope }
+    }
+
+    pub fn to_vector(&self) -> na::Vector6<f64> {
+        let mut vector = na::Vector6::zeros();
+        vector.fixed_rows_mut::<3>(0).copy_from(&self.accelerometer);
+        vector.fixed_rows_mut::<3>(3).copy_from(&self.gyroscope);
+        vector
+    }
+
+    pub fn from_vector(vector: &na::Vector6<f64>) -> Self {
+        Self::new(vector.fixed_rows::<3>(0).into_owned(), vector.fixed_rows::<3>(3).into_owned())
+    }
+}
+
+/// Value of a pose/velocity/bias node. Tangent vectors are the navigation
+/// state's nine components followed by the six bias components.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImuState {
+    pub nav: NavState,
+    pub bias: ImuBias,
+}
+
+impl ImuState {
+    pub fn new(nav: NavState, bias: ImuBias) -> Self {
+        Self { nav, bias }
+    }
+
+    pub fn retract(&self, delta: &Vector15) -> ImuState {
+        let bias = self.bias.to_vector() + delta.fixed_rows::<6>(9);
+        ImuState {
+            nav: self.nav.retract(&delta.fixed_rows::<9>(0).into_owned()),
+            bias: ImuBias::from_vector(&bias),
+        }
+    }
+}
+
+/// Continuous-time noise model of an IMU
+#[derive(Debug, Clone)]
+pub struct ImuNoise {
+    pub accelerometer_density: f64,  // White noise, m/s^2/sqrt(Hz)
+    pub gyroscope_density: f64,      // White noise, rad/s/sqrt(Hz)
+    pub accelerometer_walk: f64,     // Bias random walk, m/s^3/sqrt(Hz)
+    pub gyroscope_walk: f64,         // Bias random walk, rad/s^2/sqrt(Hz)
+    pub gravity: na::Vector3<f64>,   // In the navigation frame
+}
+
+impl Default for ImuNoise {
+    fn default() -> Self {
+        Self {
+            accelerometer_density: 0.02,
+            gyroscope_density: 0.002,
+            accelerometer_walk: 0.001,
+            gyroscope_walk: 0.0001,
+            gravity: na::Vector3::new(0.0, 0.0, -9.8),
+        }
+    }
+}
+
+/// Accumulates accelerometer and gyroscope samples between two states into a
+/// bias-free relative motion (Forster et al., "On-Manifold Preintegration for
+/// Real-Time Visual-Inertial Odometry"). Gravity and the initial velocity are
+/// only applied in `predict`, so the integration never depends on the states
+/// it connects. The increment is linearized at the bias it was integrated
+/// with; other biases are applied to first order through the bias Jacobians.
+#[derive(Debug, Clone)]
+pub struct ImuPreintegrator {
+    noise: ImuNoise,
+    bias: ImuBias,                    // Linearization point
+    extrinsic_rotation: SO3,          // IMU to body
+    extrinsic_translation: na::Vector3<f64>,  // IMU position in the body frame
+    samples: Vec<(na::Vector3<f64>, na::Vector3<f64>, f64)>,  // Raw (accel, gyro, dt)
+    delta_rotation: SO3,
+    delta_position: na::Vector3<f64>,
+    delta_velocity: na::Vector3<f64>,
+    delta_time: f64,
+    jacobian_accelerometer: na::SMatrix<f64, 9, 3>,  // Increment w.r.t. the accelerometer bias
+    jacobian_gyroscope: na::SMatrix<f64, 9, 3>,      // Increment w.r.t. the gyroscope bias
+    covariance: Matrix9,
+}
+
+impl ImuPreintegrator {
+    pub fn new(bias: ImuBias, noise: ImuNoise) -> Self {
+        Self {
+            noise,
+            bias,
+            extrinsic_rotation: SO3::identity(),
+            extrinsic_translation: na::Vector3::zeros(),
+            samples: Vec::new(),
+            delta_rotation: SO3::identity(),
+            delta_position: na::Vector3::zeros(),
+            delta_velocity: na::Vector3::zeros(),
+            delta_time: 0.0,
+            jacobian_accelerometer: na::SMatrix::zeros(),
+            jacobian_gyroscope: na::SMatrix::zeros(),
+            covariance: Matrix9::zeros(),
+        }
+    }
+
+    /// Mount the IMU away from the body origin. Must be set before the first sample.
+    pub fn with_extrinsics(mut self, rotation: SO3, translation: na::Vector3<f64>) -> Self {
+        self.extrinsic_rotation = rotation;
+        self.extrinsic_translation = translation;
+        self
+    }
+
+    /// Add one sample held for `dt` seconds
+    pub fn integrate(
+        &mut self,
+        accelerometer: &na::Vector3<f64>,
+        gyroscope: &na::Vector3<f64>,
+        dt: f64,
+    ) {
+        self.samples.push((*accelerometer, *gyroscope, dt));
+        self.step(accelerometer, gyroscope, dt);
+    }
+
+    /// Integrate the stored samples again at a new bias, for when the estimate
+    /// has moved too far for the first-order correction
+    pub fn reset_bias(&mut self, bias: ImuBias) {
+        let samples = std::mem::take(&mut self.samples);
+        *self = Self::new(bias, self.noise.clone())
+            .with_extrinsics(self.extrinsic_rotation, self.extrinsic_translation);
+        for (accelerometer, gyroscope, dt) in samples {
+            self.integrate(&accelerometer, &gyroscope, dt);
+        }
+    }
+
+    fn step(&mut self, accelerometer: &na::Vector3<f64>, gyroscope: &na::Vector3<f64>, dt: f64) {
+        // Move the sample to the body frame; the lever arm adds a centripetal term
+        let gyroscope = self.extrinsic_rotation.rotate(gyroscope);
+        let spin = skew(&gyroscope);
+        let accelerometer = self.extrinsic_rotation.rotate(accelerometer)
+            - spin * spin * self.extrinsic_translation;
+        let acceleration = accelerometer - self.bias.accelerometer;
+        let rate = gyroscope - self.bias.gyroscope;
+
+        let rotation = self.delta_rotation.matrix();
+        let turn = SO3::exp(&(rate * dt));
+        let dt22 = 0.5 * dt * dt;
+
+        // Error-state transition and noise input matrices
+        let rotated_skew = rotation * skew(&acceleration);
+        let mut transition = Matrix9::identity();
+        transition.fixed_view_mut::<3, 3>(0, 0).copy_from(&turn.matrix().transpose());
+        transition.fixed_view_mut::<3, 3>(3, 0).copy_from(&(-rotated_skew * dt22));
+        transition.fixed_view_mut::<3, 3>(3, 6).copy_from(&(na::Matrix3::identity() * dt));
+        transition.fixed_view_mut::<3, 3>(6, 0).copy_from(&(-rotated_skew * dt));
+        let mut input_accelerometer = na::SMatrix::<f64, 9, 3>::zeros();
+        input_accelerometer.fixed_view_mut::<3, 3>(3, 0).copy_from(&(rotation * dt22));
+        input_accelerometer.fixed_view_mut::<3, 3>(6, 0).copy_from(&(rotation * dt));
+        let mut input_gyroscope = na::SMatrix::<f64, 9, 3>::zeros();
+        input_gyroscope
+            .fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&(SO3::right_jacobian(&(rate * dt)) * dt));
+
+        self.delta_position += self.delta_velocity * dt + rotation * acceleration * dt22;
+        self.delta_velocity += rotation * acceleration * dt;
+        self.delta_rotation = self.delta_rotation.compose(&turn);
+        self.delta_time += dt;
+
+        // Biases are subtracted from the samples, hence the minus signs
+        self.jacobian_accelerometer =
+            transition * self.jacobian_accelerometer - input_accelerometer;
+        self.jacobian_gyroscope = transition * self.jacobian_gyroscope - input_gyroscope;
+
+        // White noise densities become per-sample variances of density^2 / dt
+        let accelerometer_variance = self.noise.accelerometer_density.powi(2) / dt;
+        let gyroscope_variance = self.noise.gyroscope_density.powi(2) / dt;
+        self.covariance = transition * self.covariance * transition.transpose()
+            + input_accelerometer * input_accelerometer.transpose() * accelerometer_variance
+            + input_gyroscope * input_gyroscope.transpose() * gyroscope_variance;
+    }
+
+    /// Seconds integrated so far
+    pub fn delta_time(&self) -> f64 {
+        self.delta_time
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Bias the samples were integrated with
+    pub fn bias(&self) -> ImuBias {
+        self.bias
+    }
+
+    pub fn noise(&self) -> &ImuNoise {
+        &self.noise
+    }
+
+    /// Preintegrated (rotation, position, velocity) increment, without gravity
+    pub fn delta(&self) -> (SO3, na::Vector3<f64>, na::Vector3<f64>) {
+        (self.delta_rotation, self.delta_position, self.delta_velocity)
+    }
+
+    /// Covariance of the increment in its (rotation, position, velocity) tangent space
+    pub fn covariance(&self) -> Matrix9 {
+        self.covariance
+    }
+
+    /// Jacobian of the increment with respect to the (accelerometer, gyroscope) bias
+    pub fn bias_jacobian(&self) -> na::SMatrix<f64, 9, 6> {
+        let mut jacobian = na::SMatrix::<f64, 9, 6>::zeros();
+        jacobian.fixed_view_mut::<9, 3>(0, 0).copy_from(&self.jacobian_accelerometer);
+        jacobian.fixed_view_mut::<9, 3>(0, 3).copy_from(&self.jacobian_gyroscope);
+        jacobian
+    }
+
+    /// Increment corrected to `bias`, with its Jacobian with respect to the bias
+    fn corrected_delta(
+        &self,
+        bias: &ImuBias,
+    ) -> (SO3, na::Vector3<f64>, na::Vector3<f64>, na::SMatrix<f64, 9, 6>) {
+        let mut jacobian = self.bias_jacobian();
+        let correction = jacobian * (bias.to_vector() - self.bias.to_vector());
+        let phi = correction.fixed_rows::<3>(0).into_owned();
+        let right = SO3::right_jacobian(&phi);
+        let rotation_rows = right * jacobian.fixed_rows::<3>(0);
+        jacobian.fixed_rows_mut::<3>(0).copy_from(&rotation_rows);
+        (
+            self.delta_rotation.plus(&phi),
+            self.delta_position + correction.fixed_rows::<3>(3),
+            self.delta_velocity + correction.fixed_rows::<3>(6),
+            jacobian,
+        )
+    }
+
+    /// State at the end of the interval given the state at its start
+    pub fn predict(&self, state: &NavState, bias: &ImuBias) -> NavState {
+        self.predict_with_jacobians(state, bias).0
+    }
+
+    /// `predict`, with the Jacobians of the prediction with respect to the
+    /// starting state and the bias
+    pub fn predict_with_jacobians(
+        &self,
+        state: &NavState,
+        bias: &ImuBias,
+    ) -> (NavState, Matrix9, na::SMatrix<f64, 9, 6>) {
+        let (delta_rotation, delta_position, delta_velocity, jacobian_bias) =
+            self.corrected_delta(bias);
+        let t = self.delta_time;
+        let rotation_t = state.rotation.matrix().transpose();
+        let local_velocity = rotation_t * state.velocity;
+        let local_gravity = rotation_t * self.noise.gravity;
+
+        // Motion over the interval in the starting body frame, gravity included
+        let position = delta_position + local_velocity * t + local_gravity * (0.5 * t * t);
+        let velocity = delta_velocity + local_gravity * t;
+        let mut jacobian_motion = Matrix9::zeros();
+        jacobian_motion
+            .fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&(skew(&local_velocity) * t + skew(&local_gravity) * (0.5 * t * t)));
+        jacobian_motion.fixed_view_mut::<3, 3>(3, 6).copy_from(&(na::Matrix3::identity() * t));
+        jacobian_motion.fixed_view_mut::<3, 3>(6, 0).copy_from(&(skew(&local_gravity) * t));
+
+        let mut motion = Vector9::zeros();
+        motion.fixed_rows_mut::<3>(3).copy_from(&position);
+        motion.fixed_rows_mut::<3>(6).copy_from(&velocity);
+        let rotation = state.rotation.matrix();
+        let predicted = NavState {
+            rotation: state.rotation.compose(&delta_rotation),
+            position: state.position + rotation * position,
+            velocity: state.velocity + rotation * velocity,
+        };
+
+        // Composing with the motion, seen from the end of the interval
+        let back = delta_rotation.matrix().transpose();
+        let mut jacobian_state = Matrix9::zeros();
+        for k in 0..3 {
+            jacobian_state.fixed_view_mut::<3, 3>(3 * k, 3 * k).copy_from(&back);
+        }
+        jacobian_state.fixed_view_mut::<3, 3>(3, 0).copy_from(&(-back * skew(&position)));
+        jacobian_state.fixed_view_mut::<3, 3>(6, 0).copy_from(&(-back * skew(&velocity)));
+        let mut jacobian_delta = Matrix9::identity();
+        jacobian_delta.fixed_view_mut::<3, 3>(3, 3).copy_from(&back);
+        jacobian_delta.fixed_view_mut::<3, 3>(6, 6).copy_from(&back);
+
+        (
+            predicted,
+            jacobian_state + jacobian_delta * jacobian_motion,
+            jacobian_delta * jacobian_bias,
+        )
+    }
+}
+
+/// Preintegrated IMU measurement between two pose/velocity/bias nodes. The
+/// residual stacks the navigation error of the predicted end state (9) and
+/// the bias random walk between the nodes (6). `InertialGraph` optimizes
+/// such nodes together with pose measurements from other sensors.
+#[derive(Debug, Clone)]
+pub struct ImuFactor {
+    pub source: NodeType,
+    pub target: NodeType,
+    pub preintegrated: ImuPreintegrator,
+    pub information: Matrix15,  // Inverse covariance of the residual
+    pub sensor_type: String,
+    pub timestamp: u64,
+}
+
+impl ImuFactor {
+    /// Create a factor weighted by the preintegrated covariance and the bias
+    /// random walk over the interval
+    pub fn new(
+        source: NodeType,
+        target: NodeType,
+        preintegrated: ImuPreintegrator,
+        sensor_type: &str,
+        timestamp: u64,
+    ) -> Self {
+        let noise = preintegrated.noise();
+        let t = preintegrated.delta_time().max(f64::EPSILON);
+        let mut covariance = Matrix15::zeros();
+        covariance.fixed_view_mut::<9, 9>(0, 0).copy_from(&preintegrated.covariance());
+        for k in 0..3 {
+            covariance[(9 + k, 9 + k)] = noise.accelerometer_walk.powi(2) * t;
+            covariance[(12 + k, 12 + k)] = noise.gyroscope_walk.powi(2) * t;
+        }
+        let information = covariance
+            .try_inverse()
+            .or_else(|| (covariance + Matrix15::identity() * 1e-12).try_inverse())
+            .unwrap_or_else(Matrix15::zeros);
+        Self {
+            source,
+            target,
+            preintegrated,
+            information,
+            sensor_type: sensor_type.to_string(),
+            timestamp,
+        }
+    }
+
+    pub fn residual(&self, source: &ImuState, target: &ImuState) -> Vector15 {
+        self.linearize(source, target).0
+    }
+
+    /// Residual with its Jacobians with respect to the source and target states
+    pub fn linearize(
+        &self,
+        source: &ImuState,
+        target: &ImuState,
+    ) -> (Vector15, Matrix15, Matrix15) {
+        let (predicted, predict_state, predict_bias) =
+            self.preintegrated.predict_with_jacobians(&source.nav, &source.bias);
+        let error = target.nav.local(&predicted);
+        let phi = error.fixed_rows::<3>(0).into_owned();
+        let right_inverse = SO3::right_jacobian_inverse(&phi);
+        let relative = target.nav.rotation.matrix().transpose() * predicted.rotation.matrix();
+
+        // Error with respect to the predicted state and to the target state
+        let mut error_predicted = Matrix9::zeros();
+        error_predicted.fixed_view_mut::<3, 3>(0, 0).copy_from(&right_inverse);
+        error_predicted.fixed_view_mut::<3, 3>(3, 3).copy_from(&relative);
+        error_predicted.fixed_view_mut::<3, 3>(6, 6).copy_from(&relative);
+        let mut error_target = -Matrix9::identity();
+        error_target
+            .fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&(-right_inverse * relative.transpose()));
+        error_target
+            .fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&skew(&error.fixed_rows::<3>(3).into_owned()));
+        error_target
+            .fixed_view_mut::<3, 3>(6, 0)
+            .copy_from(&skew(&error.fixed_rows::<3>(6).into_owned()));
+
+        let mut residual = Vector15::zeros();
+        residual.fixed_rows_mut::<9>(0).copy_from(&error);
+        residual
+            .fixed_rows_mut::<6>(9)
+            .copy_from(&(target.bias.to_vector() - source.bias.to_vector()));
+
+        let mut jacobian_source = Matrix15::zeros();
+        jacobian_source
+            .fixed_view_mut::<9, 9>(0, 0)
+            .copy_from(&(error_predicted * predict_state));
+        jacobian_source
+            .fixed_view_mut::<9, 6>(0, 9)
+            .copy_from(&(error_predicted * predict_bias));
+        jacobian_source.fixed_view_mut::<6, 6>(9, 9).copy_from(&(-na::Matrix6::identity()));
+        let mut jacobian_target = Matrix15::zeros();
+        jacobian_target.fixed_view_mut::<9, 9>(0, 0).copy_from(&error_target);
+        jacobian_target.fixed_view_mut::<6, 6>(9, 9).copy_from(&na::Matrix6::identity());
+        (residual, jacobian_source, jacobian_target)
+    }
+
+    /// Squared Mahalanobis norm of the residual
+    pub fn error(&self, source: &ImuState, target: &ImuState) -> f64 {
+        let residual = self.residual(source, target);
+        (residual.transpose() * self.information * residual)[0]
+    }
+
+    /// Relative pose factor predicted from the source state, for pose graphs
+    /// without velocity or bias nodes. The relative pose depends on the source
+    /// velocity, bias and tilt (through gravity), so their uncertainty
+    /// `source_covariance`, e.g. from `InertialGraph::marginal_covariance`, is
+    /// added to the preintegrated noise. The covariance is in the (translation,
+    /// rotation) tangent space of `SE3`.
+    pub fn pose_factor(&self, source: &ImuState, source_covariance: &Matrix15) -> Factor3D {
+        let predicted = self.preintegrated.predict(&source.nav, &source.bias);
+        let relative = source.nav.pose().inverse().compose(&predicted.pose());
+
+        // The increment with gravity and the starting velocity applied, in the
+        // start frame, as a function of the source state
+        let t = self.preintegrated.delta_time();
+        let rotation_t = source.nav.rotation.matrix().transpose();
+        let local_velocity = rotation_t * source.nav.velocity;
+        let local_gravity = rotation_t * self.preintegrated.noise.gravity;
+        let (_, _, _, jacobian_bias) = self.preintegrated.corrected_delta(&source.bias);
+        let mut jacobian = na::SMatrix::<f64, 9, 15>::zeros();
+        jacobian
+            .fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&(skew(&local_velocity) * t + skew(&local_gravity) * (0.5 * t * t)));
+        jacobian.fixed_view_mut::<3, 3>(3, 6).copy_from(&(na::Matrix3::identity() * t));
+        jacobian.fixed_view_mut::<9, 6>(0, 9).copy_from(&jacobian_bias);
+        let increment = self.preintegrated.covariance()
+            + jacobian * source_covariance * jacobian.transpose();
+
+        // Position error is in the start frame, SE3's in the end frame
+        let back = relative.rotation.matrix().transpose();
+        let mut selection = na::SMatrix::<f64, 6, 9>::zeros();
+        selection.fixed_view_mut::<3, 3>(0, 3).copy_from(&back);
+        selection.fixed_view_mut::<3, 3>(3, 0).copy_from(&na::Matrix3::identity());
+        let covariance = selection * increment * selection.transpose();
+        Factor3D::new(
+            self.source.clone(),
+            self.target.clone(),
+            relative,
+            &self.sensor_type,
+            self.timestamp,
+        )
+        .with_covariance(covariance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const RATE: na::Vector3<f64> = na::Vector3::new(0.3, -0.2, 0.5);
+
+    /// Spinning at a constant body rate while sliding along a curve
+    fn truth(t: f64) -> NavState {
+        NavState::new(
+            SO3::exp(&(RATE * t)),
+            na::Vector3::new(2.0 * t.sin(), t.cos(), 0.3 * t * t),
+            na::Vector3::new(2.0 * t.cos(), -t.sin(), 0.6 * t),
+        )
+    }
+
+    /// Ideal (accelerometer, gyroscope) readings at time `t`
+    fn reading(t: f64, gravity: &na::Vector3<f64>) -> (na::Vector3<f64>, na::Vector3<f64>) {
+        let acceleration = na::Vector3::new(-2.0 * t.sin(), -t.cos(), 0.6);
+        let specific_force = truth(t).rotation.inverse().rotate(&(acceleration - gravity));
+        (specific_force, RATE)
+    }
+
+    fn sensor_bias() -> ImuBias {
+        ImuBias::new(na::Vector3::new(0.05, -0.03, 0.02), na::Vector3::new(0.01, 0.0, -0.01))
+    }
+
+    /// 5ms steps from `start`, integrated at `bias` from a sensor reading `offset` high
+    fn preintegrate(bias: ImuBias, offset: &ImuBias, start: f64, steps: usize) -> ImuPreintegrator {
+        let dt = 0.005;
+        let mut preintegrator = ImuPreintegrator::new(bias, ImuNoise::default());
+        for k in 0..steps {
+            let (accelerometer, gyroscope) =
+                reading(start + (k as f64 + 0.5) * dt, &preintegrator.noise().gravity);
+            preintegrator.integrate(
+                &(accelerometer + offset.accelerometer),
+                &(gyroscope + offset.gyroscope),
+                dt,
+            );
+        }
+        preintegrator
+    }
+
+    #[test]
+    fn test_prediction_follows_the_trajectory() {
+        let bias = sensor_bias();
+        let start = ImuState::new(truth(0.5), bias);
+        let end = ImuState::new(truth(1.5), bias);
+
+        // Integrated at the true bias; mid-step samples held over 5ms steps leave a few mm
+        let exact = preintegrate(bias, &bias, 0.5, 200);
+        let expected = exact.predict(&start.nav, &bias);
+        assert!((exact.delta_time() - 1.0).abs() < 1e-9);
+        assert!(end.nav.local(&expected).norm() < 0.02);
+
+        // Integrated at zero bias, corrected to first order, then reintegrated
+        let mut shifted = preintegrate(ImuBias::default(), &bias, 0.5, 200);
+        let uncorrected = expected.local(&shifted.predict(&start.nav, &ImuBias::default()));
+        let corrected = expected.local(&shifted.predict(&start.nav, &bias));
+        assert!(uncorrected.norm() > 0.02, "{}", uncorrected);
+        assert!(corrected.norm() < 2e-3, "{}", corrected);
+        shifted.reset_bias(bias);
+        assert_eq!(shifted.sample_count(), 200);
+        assert!(expected.local(&shifted.predict(&start.nav, &bias)).norm() < 1e-9);
+
+        let (first, second) = (NodeType::RobotPose(500), NodeType::RobotPose(1500));
+        let factor = ImuFactor::new(first, second, exact, "IMU", 1500);
+        assert!(factor.error(&start, &end) < 1.0);
+        let pose = factor.pose_factor(&start, &Matrix15::zeros());
+        let relative = start.nav.pose().inverse().compose(&end.nav.pose());
+        assert_eq!(pose.target, NodeType::RobotPose(1500));
+        assert!(relative.inverse().compose(&pose.transform).log().norm() < 0.02);
+        assert!(pose.covariance().is_some());
+    }
+
+    #[test]
+    fn test_pose_factor_covers_source_uncertainty() {
+        let bias = sensor_bias();
+        let factor = ImuFactor::new(
+            NodeType::RobotPose(0),
+            NodeType::RobotPose(1),
+            preintegrate(bias, &bias, 0.5, 200),
+            "IMU",
+            1,
+        );
+        let source = ImuState::new(truth(0.5), bias);
+        let sigmas = Vector15::from_column_slice(&[
+            0.01, 0.01, 0.01, 0.5, 0.5, 0.5, 0.1, 0.1, 0.1, 0.02, 0.02, 0.02, 0.002, 0.002, 0.002,
+        ]);
+        let source_covariance = Matrix15::from_diagonal(&sigmas.component_mul(&sigmas));
+        let exact = factor.pose_factor(&source, &Matrix15::zeros());
+        let uncertain = factor.pose_factor(&source, &source_covariance);
+
+        // Relative poses predicted from sources drawn with that covariance
+        let mut rng = StdRng::seed_from_u64(3);
+        let trials = 2000;
+        let mut sampled = na::Matrix6::zeros();
+        for _ in 0..trials {
+            let delta =
+                Vector15::from_fn(|k, _| rng.gen_range(-1.0..1.0) * 3f64.sqrt() * sigmas[k]);
+            let drawn = source.retract(&delta);
+            let predicted = factor.preintegrated.predict(&drawn.nav, &drawn.bias);
+            let relative = drawn.nav.pose().inverse().compose(&predicted.pose());
+            let error = exact.transform.inverse().compose(&relative).log();
+            sampled += error * error.transpose() / trials as f64;
+        }
+        let propagated = uncertain.covariance().unwrap() - exact.covariance().unwrap();
+        for k in 0..6 {
+            let ratio = sampled[(k, k)] / propagated[(k, k)];
+            assert!((0.8..1.25).contains(&ratio), "axis {}: ratio {}", k, ratio);
+        }
+    }
+
+    #[test]
+    fn test_factor_jacobians_match_numerical_derivatives() {
+        let bias = sensor_bias();
+        let factor = ImuFactor::new(
+            NodeType::RobotPose(0),
+            NodeType::RobotPose(1),
+            preintegrate(ImuBias::default(), &bias, 0.2, 60),
+            "IMU",
+            1,
+        );
+        // States away from the truth, so that every residual component is non-zero
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut wobble = || Vector15::from_fn(|_, _| rng.gen_range(-0.1..0.1));
+        let source = ImuState::new(truth(0.2), bias).retract(&wobble());
+        let target = ImuState::new(truth(0.5), ImuBias::default()).retract(&wobble());
+
+        let (_, jacobian_source, jacobian_target) = factor.linearize(&source, &target);
+        let step = 1e-6;
+        for k in 0..15 {
+            let mut d = Vector15::zeros();
+            d[k] = step;
+            let numerical_source = (factor.residual(&source.retract(&d), &target)
+                - factor.residual(&source.retract(&-d), &target))
+                / (2.0 * step);
+            let numerical_target = (factor.residual(&source, &target.retract(&d))
+                - factor.residual(&source, &target.retract(&-d)))
+                / (2.0 * step);
+            assert!(
+                (numerical_source - jacobian_source.column(k)).norm() < 1e-5,
+                "source column {}: {} vs {}",
+                k,
+                numerical_source,
+                jacobian_source.column(k)
+            );
+            assert!(
+                (numerical_target - jacobian_target.column(k)).norm() < 1e-5,
+                "target column {}",
+                k
+            );
+        }
+    }
+
+    #[test]
+    fn test_covariance_matches_monte_carlo() {
+        let noise = ImuNoise {
+            accelerometer_density: 0.2,
+            gyroscope_density: 0.05,
+            ..ImuNoise::default()
+        };
+        let dt = 0.01;
+        let readings: Vec<_> = (0..50).map(|k| reading(k as f64 * dt, &noise.gravity)).collect();
+        let run = |mut rng: Option<&mut StdRng>| {
+            let mut preintegrator = ImuPreintegrator::new(ImuBias::default(), noise.clone());
+            for (accelerometer, gyroscope) in &readings {
+                let (mut a, mut g) = (*accelerometer, *gyroscope);
+                if let Some(rng) = rng.as_deref_mut() {
+                    // Uniform noise with the per-sample variance density^2 / dt
+                    let spread = 3f64.sqrt() / dt.sqrt();
+                    let mut jitter = |density: f64| {
+                        na::Vector3::from_fn(|_, _| rng.gen_range(-1.0..1.0) * density * spread)
+                    };
+                    a += jitter(noise.accelerometer_density);
+                    g += jitter(noise.gyroscope_density);
+                }
+                preintegrator.integrate(&a, &g, dt);
+            }
+            preintegrator
+        };
+        let nominal = run(None);
+        let (reference_rotation, reference_position, reference_velocity) = nominal.delta();
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let trials = 1000;
+        let mut sampled = Matrix9::zeros();
+        for _ in 0..trials {
+            let (rotation, position, velocity) = run(Some(&mut rng)).delta();
+            // The increment's position and velocity errors are additive
+            let mut error = Vector9::zeros();
+            error.fixed_rows_mut::<3>(0).copy_from(&rotation.minus(&reference_rotation));
+            error.fixed_rows_mut::<3>(3).copy_from(&(position - reference_position));
+            error.fixed_rows_mut::<3>(6).copy_from(&(velocity - reference_velocity));
+            sampled += error * error.transpose() / trials as f64;
+        }
+        let predicted = nominal.covariance();
+        for k in 0..9 {
+            let ratio = sampled[(k, k)] / predicted[(k, k)];
+            assert!((0.8..1.25).contains(&ratio), "axis {}: ratio {}", k, ratio);
+        }
+    }
+}
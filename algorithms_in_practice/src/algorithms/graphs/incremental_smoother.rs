@@ -0,0 +1,630 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use nalgebra as na;
+
+use crate::common::robust_kernel::RobustKernel;
+
+use super::factor_graph::{normalize_angle, CircularFactorGraph, Factor, NodeType, Transform2D};
+use super::marginalization::MarginalPrior;
+use super::pose_graph_optimizer::PoseGraphOptimizer;
+
+#[derive(Debug, Clone)]
+pub struct SmootherConfig {
+    pub relinearize_threshold: f64,  // Relinearize once any update component exceeds this
+}
+
+impl Default for SmootherConfig {
+    fn default() -> Self {
+        Self {
+            relinearize_threshold: 0.1,
+        }
+    }
+}
+
+/// Timing and bookkeeping of a single incremental update. Marginalization
+/// priors are counted as factors.
+#[derive(Debug, Clone)]
+pub struct UpdateReport {
+    pub elapsed: Duration,
+    pub variables: usize,               // Variables in the factorization after the update
+    pub added_factors: usize,
+    pub removed_factors: usize,
+    pub relinearized_variables: usize,
+    pub relinearized_factors: usize,
+    pub full_refactor: bool,            // The incremental factorization broke down and was rebuilt
+    pub solved: bool,                   // False if even the rebuilt system was singular
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TermKey {
+    Factor(u64),
+    Prior(Vec<NodeType>),
+}
+
+/// Where a term comes from in the graph, so it can be relinearized
+enum TermSource<'a> {
+    Factor(&'a Factor, Option<&'a dyn RobustKernel>),
+    Prior(&'a MarginalPrior),
+}
+
+impl TermSource<'_> {
+    fn weight(&self) -> f64 {
+        match self {
+            TermSource::Factor(factor, _) => factor.status.weight(),
+            TermSource::Prior(_) => 1.0,
+        }
+    }
+}
+
+/// A factor or prior linearized at the smoother's linearization point:
+/// cost = 0.5 |L^T (r + sum_k J_k dx_k)|^2
+struct LinearTerm {
+    nodes: Vec<NodeType>,
+    jacobians: Vec<na::DMatrix<f64>>,       // m x 3 per node
+    residual: na::DVector<f64>,
+    sqrt_information: na::DMatrix<f64>,     // L with L L^T the weighted information
+    weight: f64,                            // Status weight the term was built with
+}
+
+/// Incremental Gauss-Newton smoother over a `CircularFactorGraph`. It keeps a
+/// dense Cholesky factor of the normal equations and, on every `update`,
+/// applies rank-one updates for new, removed and relinearized factors and
+/// inserts or removes columns for variables entering or leaving the window.
+/// Only variables whose update exceeds the relinearization threshold are
+/// relinearized, together with the factors touching them. The factor is
+/// dense, so each of those edits and the solve cost O(n^2) in the n window
+/// variables, and memory is O(n^2) too: it suits sliding windows of a few
+/// hundred poses, while whole datasets belong to the sparse
+/// `PoseGraphOptimizer`. The oldest node of every connected component is held
+/// fixed, matching `PoseGraphOptimizer`.
+pub struct IncrementalSmoother {
+    config: SmootherConfig,
+    linearization: HashMap<NodeType, na::Vector3<f64>>,
+    delta: HashMap<NodeType, na::Vector3<f64>>,     // Solution relative to the linearization point
+    order: Vec<NodeType>,                           // Elimination order, three columns each
+    slots: HashMap<NodeType, usize>,
+    fixed: HashSet<NodeType>,                       // Gauge nodes
+    terms: HashMap<TermKey, LinearTerm>,
+    cholesky: na::Cholesky<f64, na::Dyn>,
+}
+
+impl IncrementalSmoother {
+    pub fn new(config: SmootherConfig) -> Self {
+        Self {
+            config,
+            linearization: HashMap::new(),
+            delta: HashMap::new(),
+            order: Vec::new(),
+            slots: HashMap::new(),
+            fixed: HashSet::new(),
+            terms: HashMap::new(),
+            cholesky: na::Cholesky::new(na::DMatrix::zeros(0, 0)).unwrap(),
+        }
+    }
+
+    /// Bring the factorization in line with the graph's current window and
+    /// take one Gauss-Newton step. New nodes start chained from the current
+    /// estimates of their neighbours.
+    pub fn update(&mut self, graph: &CircularFactorGraph) -> UpdateReport {
+        let start = Instant::now();
+        let nodes: Vec<NodeType> = graph.nodes().cloned().collect();
+        let present: HashSet<&NodeType> = nodes.iter().collect();
+        let links = PoseGraphOptimizer::links(graph);
+        let gauge: HashSet<NodeType> = nodes
+            .iter()
+            .zip(PoseGraphOptimizer::gauge_nodes(&nodes, &links))
+            .filter(|(_, is_fixed)| *is_fixed)
+            .map(|(node, _)| node.clone())
+            .collect();
+
+        let mut sources: HashMap<TermKey, TermSource> = HashMap::new();
+        for factor in graph.factors() {
+            if factor.status.weight() > 0.0
+                && present.contains(&factor.source)
+                && present.contains(&factor.target)
+            {
+                let kernel = graph.kernel_for(factor);
+                sources.insert(TermKey::Factor(factor.id), TermSource::Factor(factor, kernel));
+            }
+        }
+        for prior in graph.priors() {
+            if prior.nodes.iter().all(|n| present.contains(n)) {
+                sources.insert(TermKey::Prior(prior.nodes.clone()), TermSource::Prior(prior));
+            }
+        }
+        let removed: Vec<TermKey> = self
+            .terms
+            .keys()
+            .filter(|key| !sources.contains_key(*key))
+            .cloned()
+            .collect();
+        let reweighted: Vec<TermKey> = self
+            .terms
+            .iter()
+            .filter(|(key, term)| sources.get(*key).is_some_and(|s| s.weight() != term.weight))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        // Variables that left the window take their columns with them
+        let stale: Vec<NodeType> =
+            self.order.iter().filter(|n| !present.contains(n)).cloned().collect();
+        for node in &stale {
+            self.remove_variable(node);
+        }
+        self.linearization.retain(|n, _| present.contains(n));
+        self.delta.retain(|n, _| present.contains(n));
+        self.seed_new_variables(&nodes, &links);
+
+        // Variables that start or stop anchoring their component get new columns
+        let mut relinearize: HashSet<NodeType> = HashSet::new();
+        let regauged: Vec<NodeType> = self
+            .order
+            .iter()
+            .filter(|n| self.fixed.contains(*n) != gauge.contains(*n))
+            .cloned()
+            .collect();
+        for node in &regauged {
+            self.remove_variable(node);
+            if gauge.contains(node) {
+                relinearize.insert(node.clone());
+            }
+        }
+        self.fixed = gauge;
+
+        for (node, delta) in &self.delta {
+            if delta.amax() > self.config.relinearize_threshold {
+                relinearize.insert(node.clone());
+            }
+        }
+        for node in &relinearize {
+            if let Some(delta) = self.delta.get_mut(node) {
+                let x = self.linearization.get_mut(node).unwrap();
+                *x += *delta;
+                x[2] = normalize_angle(x[2]);
+                *delta = na::Vector3::zeros();
+            }
+        }
+
+        // Replace relinearized terms, adding the new one before removing the
+        // old so the factored matrix stays positive definite throughout
+        let mut affected: Vec<TermKey> = self
+            .terms
+            .iter()
+            .filter(|(key, term)| {
+                sources.contains_key(*key) && term.nodes.iter().any(|n| relinearize.contains(n))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in reweighted {
+            if !affected.contains(&key) {
+                affected.push(key);
+            }
+        }
+        for key in &affected {
+            let term = self.linearize(&sources[key]);
+            self.apply(&term, 1.0);
+            if let Some(old) = self.terms.insert(key.clone(), term) {
+                self.apply(&old, -1.0);
+            }
+        }
+
+        let mut added = 0;
+        for (key, source) in &sources {
+            if !self.terms.contains_key(key) {
+                let term = self.linearize(source);
+                self.apply(&term, 1.0);
+                self.terms.insert(key.clone(), term);
+                added += 1;
+            }
+        }
+
+        let touched: HashSet<&NodeType> =
+            self.terms.values().flat_map(|t| t.nodes.iter()).collect();
+        let entering: Vec<NodeType> = nodes
+            .iter()
+            .filter(|n| !self.slots.contains_key(*n))
+            .filter(|n| self.fixed.contains(*n) || touched.contains(n))
+            .cloned()
+            .collect();
+        for node in &entering {
+            self.insert_variable(node);
+        }
+
+        for key in &removed {
+            if let Some(term) = self.terms.remove(key) {
+                self.apply(&term, -1.0);
+            }
+        }
+
+        let mut full_refactor = false;
+        if !self.is_healthy() {
+            full_refactor = true;
+            self.refactor();
+        }
+        let solved = self.is_healthy() && self.solve();
+
+        UpdateReport {
+            elapsed: start.elapsed(),
+            variables: self.order.len(),
+            added_factors: added,
+            removed_factors: removed.len(),
+            relinearized_variables: relinearize.len(),
+            relinearized_factors: affected.len(),
+            full_refactor,
+            solved,
+        }
+    }
+
+    /// Current estimate of every node seen so far
+    pub fn estimates(&self) -> HashMap<NodeType, Transform2D> {
+        self.linearization
+            .keys()
+            .filter_map(|node| Some((node.clone(), self.estimate(node)?)))
+            .collect()
+    }
+
+    pub fn estimate(&self, node: &NodeType) -> Option<Transform2D> {
+        let x = self.linearization.get(node)? + self.delta.get(node)?;
+        Some(Transform2D::new(x[0], x[1], normalize_angle(x[2])))
+    }
+
+    pub fn variable_count(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Give nodes new to the window a linearization point by chaining links
+    /// out from nodes that already have an estimate. Nodes unreachable from
+    /// them start new components at the origin.
+    fn seed_new_variables(
+        &mut self,
+        nodes: &[NodeType],
+        links: &[(NodeType, NodeType, Transform2D)],
+    ) {
+        let mut queue: VecDeque<NodeType> = nodes
+            .iter()
+            .filter(|n| self.linearization.contains_key(*n))
+            .cloned()
+            .collect();
+        let mut roots = nodes.iter();
+        loop {
+            while let Some(current) = queue.pop_front() {
+                let current_pose = self.estimate(&current).unwrap();
+                for (source, target, transform) in links {
+                    let (next, step) = if *source == current {
+                        (target, transform.clone())
+                    } else if *target == current {
+                        (source, transform.inverse())
+                    } else {
+                        continue;
+                    };
+                    if !self.linearization.contains_key(next) {
+                        self.linearization
+                            .insert(next.clone(), current_pose.compose(&step).to_vector());
+                        self.delta.insert(next.clone(), na::Vector3::zeros());
+                        queue.push_back(next.clone());
+                    }
+                }
+            }
+            match roots.find(|n| !self.linearization.contains_key(*n)) {
+                Some(root) => {
+                    self.linearization.insert(root.clone(), na::Vector3::zeros());
+                    self.delta.insert(root.clone(), na::Vector3::zeros());
+                    queue.push_back(root.clone());
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn linearize(&self, source: &TermSource) -> LinearTerm {
+        match source {
+            TermSource::Factor(factor, kernel) => {
+                let xi = &self.linearization[&factor.source];
+                let xj = &self.linearization[&factor.target];
+                let (e, a, b) = PoseGraphOptimizer::linearize(xi, xj, &factor.transform);
+                let information = factor.information * factor.status.weight();
+                let squared_error = (e.transpose() * information * e)[(0, 0)];
+                let omega = information * PoseGraphOptimizer::robustify(*kernel, squared_error).1;
+
+                let (nodes, jacobians) = if factor.source == factor.target {
+                    (
+                        vec![factor.source.clone()],
+                        vec![na::DMatrix::from_iterator(3, 3, (a + b).iter().cloned())],
+                    )
+                } else {
+                    (
+                        vec![factor.source.clone(), factor.target.clone()],
+                        vec![
+                            na::DMatrix::from_iterator(3, 3, a.iter().cloned()),
+                            na::DMatrix::from_iterator(3, 3, b.iter().cloned()),
+                        ],
+                    )
+                };
+                let sqrt_information =
+                    square_root(&na::DMatrix::from_column_slice(3, 3, omega.as_slice()));
+                LinearTerm {
+                    nodes,
+                    jacobians,
+                    residual: na::DVector::from_iterator(3, e.iter().cloned()),
+                    sqrt_information,
+                    weight: factor.status.weight(),
+                }
+            }
+            TermSource::Prior(prior) => {
+                let (expected, information, offset) = prior.anchored();
+                let m = information.nrows();
+                let anchor = &self.linearization[&prior.nodes[0]];
+                let mut residual = -offset;
+                let mut jacobians = vec![na::DMatrix::zeros(m, 3)];
+                for (p, (node, z)) in prior.nodes[1..].iter().zip(&expected).enumerate() {
+                    let (e, a, b) =
+                        PoseGraphOptimizer::linearize(anchor, &self.linearization[node], z);
+                    let mut rows = residual.fixed_rows_mut::<3>(3 * p);
+                    rows += e;
+                    jacobians[0].fixed_view_mut::<3, 3>(3 * p, 0).copy_from(&a);
+                    let mut jacobian = na::DMatrix::zeros(m, 3);
+                    jacobian.fixed_view_mut::<3, 3>(3 * p, 0).copy_from(&b);
+                    jacobians.push(jacobian);
+                }
+                LinearTerm {
+                    nodes: prior.nodes.clone(),
+                    jacobians,
+                    residual,
+                    sqrt_information: square_root(&information),
+                    weight: 1.0,
+                }
+            }
+        }
+    }
+
+    /// Columns `J^T L` of a term over the free variables currently in the
+    /// factorization; the term contributes their outer products to H
+    fn term_vectors(&self, term: &LinearTerm) -> na::DMatrix<f64> {
+        let mut vectors = na::DMatrix::zeros(3 * self.order.len(), term.sqrt_information.ncols());
+        for (node, jacobian) in term.nodes.iter().zip(&term.jacobians) {
+            if let Some(&slot) = self.slots.get(node) {
+                if !self.fixed.contains(node) {
+                    let mut rows = vectors.rows_mut(3 * slot, 3);
+                    rows += jacobian.transpose() * &term.sqrt_information;
+                }
+            }
+        }
+        vectors
+    }
+
+    /// Add (`sigma = 1`) or remove (`sigma = -1`) a term through rank-one updates
+    fn apply(&mut self, term: &LinearTerm, sigma: f64) {
+        let vectors = self.term_vectors(term);
+        for column in vectors.column_iter() {
+            if column.amax() > 0.0 {
+                self.cholesky.rank_one_update(&column, sigma);
+            }
+        }
+    }
+
+    /// Append a variable's three columns, built from every term touching it
+    fn insert_variable(&mut self, node: &NodeType) {
+        let slot = self.order.len();
+        self.order.push(node.clone());
+        self.slots.insert(node.clone(), slot);
+
+        let n = 3 * (slot + 1);
+        let mut block = na::DMatrix::zeros(n, 3);
+        if self.fixed.contains(node) {
+            block.view_mut((3 * slot, 0), (3, 3)).fill_with_identity();
+        } else {
+            for term in self.terms.values() {
+                let Some(k) = term.nodes.iter().position(|v| v == node) else {
+                    continue;
+                };
+                let right = term.sqrt_information.transpose() * &term.jacobians[k];
+                let vectors = self.term_vectors(term);
+                block += vectors * right;
+            }
+        }
+
+        for t in 0..3 {
+            let j = 3 * slot + t;
+            let column = block.column(t).rows(0, j + 1).into_owned();
+            self.cholesky = self.cholesky.insert_column(j, column);
+        }
+    }
+
+    fn remove_variable(&mut self, node: &NodeType) {
+        let Some(slot) = self.slots.remove(node) else {
+            return;
+        };
+        for _ in 0..3 {
+            self.cholesky = self.cholesky.remove_column(3 * slot);
+        }
+        self.order.remove(slot);
+        for (k, n) in self.order.iter().enumerate().skip(slot) {
+            self.slots.insert(n.clone(), k);
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.cholesky
+            .l_dirty()
+            .diagonal()
+            .iter()
+            .all(|d| d.is_finite() && *d > 0.0)
+    }
+
+    /// Factor the whole system from scratch
+    fn refactor(&mut self) {
+        let n = 3 * self.order.len();
+        let mut h = na::DMatrix::zeros(n, n);
+        for term in self.terms.values() {
+            let vectors = self.term_vectors(term);
+            h += &vectors * vectors.transpose();
+        }
+        for node in &self.fixed {
+            if let Some(&slot) = self.slots.get(node) {
+                h.view_mut((3 * slot, 3 * slot), (3, 3)).fill_with_identity();
+            }
+        }
+        // On failure the broken factor is kept, and rebuilt again next update
+        if let Some(cholesky) = na::Cholesky::new(h) {
+            self.cholesky = cholesky;
+        }
+    }
+
+    /// Solve H dx = -b for the update relative to the linearization point
+    fn solve(&mut self) -> bool {
+        let mut b = na::DVector::zeros(3 * self.order.len());
+        for term in self.terms.values() {
+            let whitened = term.sqrt_information.transpose() * &term.residual;
+            b += self.term_vectors(term) * whitened;
+        }
+        let dx = self.cholesky.solve(&(-b));
+        if dx.iter().any(|v| !v.is_finite()) {
+            return false;
+        }
+        for (slot, node) in self.order.iter().enumerate() {
+            self.delta.insert(node.clone(), dx.fixed_rows::<3>(3 * slot).into_owned());
+        }
+        true
+    }
+}
+
+/// L with L L^T = `information`, tolerating a positive semi-definite input
+fn square_root(information: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let eigen = information.clone().symmetric_eigen();
+    let mut root = eigen.eigenvectors;
+    for (mut column, value) in root.column_iter_mut().zip(eigen.eigenvalues.iter()) {
+        column *= value.max(0.0).sqrt();
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::graphs::{EvictionPolicy, OptimizerConfig};
+
+    fn factor(source: u64, target: u64, transform: Transform2D) -> Factor {
+        Factor::new(
+            NodeType::RobotPose(source),
+            NodeType::RobotPose(target),
+            transform,
+            "ODOMETRY",
+            target,
+        )
+        .with_isotropic_std(0.1)
+    }
+
+    #[test]
+    fn test_incremental_updates_match_batch_solution() {
+        // Noisy hexagon driven twice around, in a window that evicts the start
+        let turn = std::f64::consts::PI / 3.0;
+        let mut graph = CircularFactorGraph::new(8, 30);
+        graph.set_eviction_policy(EvictionPolicy::Marginalize);
+        let mut smoother = IncrementalSmoother::new(SmootherConfig {
+            relinearize_threshold: 1e-9,
+        });
+
+        graph.add_node(NodeType::RobotPose(0));
+        smoother.update(&graph);
+        for t in 1..10u64 {
+            let wobble = 0.03 * if t % 2 == 0 { 1.0 } else { -1.0 };
+            graph.add_node(NodeType::RobotPose(t));
+            let step = Transform2D::new(1.0 + wobble, wobble, turn - wobble);
+            graph.add_factor(factor(t - 1, t, step));
+            if t >= 6 {
+                graph.add_factor(factor(t - 6, t, Transform2D::new(0.0, 0.0, 0.0)));
+            }
+            let report = smoother.update(&graph);
+            assert!(report.solved && !report.full_refactor, "update {}: {:?}", t, report);
+        }
+        assert!(graph.priors().count() > 0);
+        for _ in 0..5 {
+            smoother.update(&graph);
+        }
+
+        let batch = PoseGraphOptimizer::new(OptimizerConfig::default()).optimize(&graph);
+        let anchor = graph.nodes().next().unwrap().clone();
+        let relative = |poses: &HashMap<NodeType, Transform2D>, node: &NodeType| {
+            poses[&anchor].inverse().compose(&poses[node]).to_vector()
+        };
+        let estimates = smoother.estimates();
+        for node in graph.nodes() {
+            let difference = relative(&estimates, node) - relative(&batch.poses, node);
+            assert!(difference.norm() < 1e-6, "{:?}: {}", node, difference.norm());
+        }
+    }
+
+    #[test]
+    fn test_new_factor_relinearizes_only_nearby_variables() {
+        let mut graph = CircularFactorGraph::new(200, 400);
+        let mut smoother = IncrementalSmoother::new(SmootherConfig::default());
+        graph.add_node(NodeType::RobotPose(0));
+        for t in 1..100u64 {
+            graph.add_node(NodeType::RobotPose(t));
+            graph.add_factor(factor(t - 1, t, Transform2D::new(1.0, 0.0, 0.0)));
+            smoother.update(&graph);
+        }
+
+        // A slightly inconsistent shortcut across the last few poses
+        graph.add_factor(factor(95, 99, Transform2D::new(4.2, 0.0, 0.0)));
+        let report = smoother.update(&graph);
+        assert_eq!(report.added_factors, 1);
+        assert_eq!(report.variables, 100);
+        assert!(!report.full_refactor);
+        assert!(report.relinearized_variables < 5);
+
+        let end = smoother.estimate(&NodeType::RobotPose(99)).unwrap();
+        assert!(end.x > 99.0 && end.x < 99.2);
+    }
+
+    #[test]
+    fn test_long_run_of_updates_matches_batch_solution() {
+        use crate::algorithms::graphs::FactorStatus;
+
+        // A wobbly spiral with loop closures, in a window that marginalizes
+        // over a hundred nodes. Every fifth closure is down-weighted by hand
+        // until the graph's next consistency check restores it.
+        let mut graph = CircularFactorGraph::new(12, 40);
+        graph.set_eviction_policy(EvictionPolicy::Marginalize);
+        let mut smoother = IncrementalSmoother::new(SmootherConfig {
+            relinearize_threshold: 1e-6,
+        });
+
+        let nominal = Transform2D::new(1.0, 0.0, 0.3);
+        let identity = Transform2D::new(0.0, 0.0, 0.0);
+        let closure = (0..4).fold(identity, |pose, _| pose.compose(&nominal));
+
+        graph.add_node(NodeType::RobotPose(0));
+        smoother.update(&graph);
+        for t in 1..120u64 {
+            let wobble = 0.02 * ((t * 7) % 5) as f64 - 0.04;
+            graph.add_node(NodeType::RobotPose(t));
+            graph.add_factor(factor(t - 1, t, Transform2D::new(1.0 + wobble, wobble, 0.3)));
+            if t >= 4 {
+                graph.add_factor(factor(t - 4, t, closure.clone()));
+                if t % 5 == 0 {
+                    let id = graph.factors().last().unwrap().id;
+                    graph.set_factor_status(id, FactorStatus::DownWeighted(0.2));
+                }
+            }
+            let report = smoother.update(&graph);
+            assert!(report.solved && !report.full_refactor, "update {}: {:?}", t, report);
+            assert_eq!(report.variables, graph.node_count());
+        }
+        assert!(graph.priors().count() > 0);
+        for _ in 0..5 {
+            smoother.update(&graph);
+        }
+
+        let batch = PoseGraphOptimizer::new(OptimizerConfig::default()).optimize(&graph);
+        let anchor = graph.nodes().next().unwrap().clone();
+        let relative = |poses: &HashMap<NodeType, Transform2D>, node: &NodeType| {
+            poses[&anchor].inverse().compose(&poses[node]).to_vector()
+        };
+        let estimates = smoother.estimates();
+        for node in graph.nodes() {
+            let difference = relative(&estimates, node) - relative(&batch.poses, node);
+            assert!(difference.norm() < 1e-6, "{:?}: {}", node, difference.norm());
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use nalgebra as na;
+
+use crate::common::lie::{LieGroup, SE3, SO3};
+
+use super::factor_graph::NodeType;
+use super::imu_preintegration::{ImuFactor, ImuState, NavState};
+use super::levenberg_marquardt::{minimize, LeastSquares};
+use super::pose_graph_optimizer::{OptimizationReport, OptimizerConfig, PoseGraphOptimizer};
+use super::sparse_cholesky::BlockSparseMatrix;
+
+type Vector15 = na::SVector<f64, 15>;
+type Matrix15 = na::SMatrix<f64, 15, 15>;
+
+/// Measurement of a single node's state, weighted per tangent component
+#[derive(Debug, Clone)]
+struct UnaryTerm {
+    node: usize,
+    expected: ImuState,
+    information: Matrix15,
+}
+
+impl UnaryTerm {
+    /// Error of `state` against the expected one, with its Jacobian
+    fn linearize(&self, state: &ImuState) -> (Vector15, Matrix15) {
+        let error_nav = self.expected.nav.local(&state.nav);
+        let phi = error_nav.fixed_rows::<3>(0).into_owned();
+        let relative =
+            self.expected.nav.rotation.matrix().transpose() * state.nav.rotation.matrix();
+
+        let mut error = Vector15::zeros();
+        error.fixed_rows_mut::<9>(0).copy_from(&error_nav);
+        error
+            .fixed_rows_mut::<6>(9)
+            .copy_from(&(state.bias.to_vector() - self.expected.bias.to_vector()));
+        let mut jacobian = Matrix15::identity();
+        jacobian.fixed_view_mut::<3, 3>(0, 0).copy_from(&SO3::right_jacobian_inverse(&phi));
+        jacobian.fixed_view_mut::<3, 3>(3, 3).copy_from(&relative);
+        jacobian.fixed_view_mut::<3, 3>(6, 6).copy_from(&relative);
+        (error, jacobian)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InertialResult {
+    pub states: HashMap<NodeType, ImuState>,
+    pub report: OptimizationReport,
+}
+
+/// Pose/velocity/bias nodes tied together by preintegrated IMU factors and
+/// anchored by priors or pose measurements from other sensors, such as lidar
+/// localization or GNSS. Without an anchor the position and heading are
+/// unobservable. The normal equations are kept as 15x15 blocks and solved with
+/// the same sparse Cholesky and Levenberg-Marquardt loop as pose graphs.
+#[derive(Debug, Clone, Default)]
+pub struct InertialGraph {
+    nodes: Vec<NodeType>,
+    index: HashMap<NodeType, usize>,
+    initial: Vec<ImuState>,
+    imu_factors: Vec<(usize, usize, ImuFactor)>,
+    unary: Vec<UnaryTerm>,
+}
+
+impl InertialGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node with its starting estimate. Returns false if it already exists.
+    pub fn add_state(&mut self, node: NodeType, initial: ImuState) -> bool {
+        if self.index.contains_key(&node) {
+            return false;
+        }
+        self.index.insert(node.clone(), self.nodes.len());
+        self.nodes.push(node);
+        self.initial.push(initial);
+        true
+    }
+
+    /// Returns false unless both ends of the factor are nodes of the graph
+    pub fn add_imu_factor(&mut self, factor: ImuFactor) -> bool {
+        let (Some(&i), Some(&j)) = (self.index.get(&factor.source), self.index.get(&factor.target))
+        else {
+            return false;
+        };
+        self.imu_factors.push((i, j, factor));
+        true
+    }
+
+    /// Prior on a whole state, with a covariance in its tangent space
+    pub fn add_prior(
+        &mut self,
+        node: &NodeType,
+        expected: ImuState,
+        covariance: &Matrix15,
+    ) -> bool {
+        let Some(&node) = self.index.get(node) else { return false };
+        let information = covariance.try_inverse().unwrap_or_else(Matrix15::zeros);
+        self.unary.push(UnaryTerm { node, expected, information });
+        true
+    }
+
+    /// Measured pose of a node, with a covariance in the (translation,
+    /// rotation) tangent space of `SE3`. Velocity and bias are left free.
+    pub fn add_pose_measurement(
+        &mut self,
+        node: &NodeType,
+        pose: &SE3,
+        covariance: &na::Matrix6<f64>,
+    ) -> bool {
+        let Some(&node) = self.index.get(node) else { return false };
+        let Some(pose_information) = covariance.try_inverse() else { return false };
+
+        // The state's tangent space puts rotation before position
+        let mut information = Matrix15::zeros();
+        let blocks = [(0, 3), (3, 0)];
+        for &(row, state_row) in &blocks {
+            for &(col, state_col) in &blocks {
+                information
+                    .fixed_view_mut::<3, 3>(state_row, state_col)
+                    .copy_from(&pose_information.fixed_view::<3, 3>(row, col));
+            }
+        }
+        let nav = NavState::new(pose.rotation, pose.translation, na::Vector3::zeros());
+        let expected = ImuState::new(nav, Default::default());
+        self.unary.push(UnaryTerm { node, expected, information });
+        true
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn imu_factor_count(&self) -> usize {
+        self.imu_factors.len()
+    }
+
+    pub fn initial_state(&self, node: &NodeType) -> Option<&ImuState> {
+        self.index.get(node).map(|&k| &self.initial[k])
+    }
+
+    /// Optimize every state, starting from the estimates the nodes were added with
+    pub fn optimize(&self, config: &OptimizerConfig) -> InertialResult {
+        let mut states = self.initial.clone();
+        let report = minimize(self, &mut states, config);
+        InertialResult { states: self.nodes.iter().cloned().zip(states).collect(), report }
+    }
+
+    /// Covariance of one node's state at `states`, such as an optimized
+    /// solution, from the inverse of the full information matrix
+    pub fn marginal_covariance(
+        &self,
+        states: &HashMap<NodeType, ImuState>,
+        node: &NodeType,
+    ) -> Option<Matrix15> {
+        let k = *self.index.get(node)?;
+        let states: Vec<ImuState> =
+            self.nodes.iter().map(|n| states.get(n).cloned()).collect::<Option<_>>()?;
+        let (h, _, _) = self.linearize(&states);
+        let factor = h.cholesky()?;
+        let mut covariance = Matrix15::zeros();
+        for column in 0..15 {
+            let mut unit = na::DVector::zeros(15 * states.len());
+            unit[15 * k + column] = 1.0;
+            let solved = factor.solve(&unit);
+            covariance.set_column(column, &solved.fixed_rows::<15>(15 * k));
+        }
+        Some(covariance)
+    }
+}
+
+impl LeastSquares for InertialGraph {
+    type State = Vec<ImuState>;
+    type System = (BlockSparseMatrix<15>, na::DVector<f64>, na::DVector<f64>);  // H, b, D
+    type Step = na::DVector<f64>;
+
+    fn cost(&self, states: &Self::State) -> f64 {
+        let imu: f64 = self
+            .imu_factors
+            .iter()
+            .map(|(i, j, factor)| factor.error(&states[*i], &states[*j]))
+            .sum();
+        let unary: f64 = self
+            .unary
+            .iter()
+            .map(|term| {
+                let (e, _) = term.linearize(&states[term.node]);
+                (e.transpose() * term.information * e)[0]
+            })
+            .sum();
+        0.5 * (imu + unary)
+    }
+
+    fn linearize(&self, states: &Self::State) -> Self::System {
+        let mut h = BlockSparseMatrix::new(states.len());
+        let mut b = na::DVector::zeros(15 * states.len());
+        for (i, j, factor) in &self.imu_factors {
+            let (e, ji, jj) = factor.linearize(&states[*i], &states[*j]);
+            let blocks = [(*i, ji), (*j, jj)];
+            for &(k, jk) in &blocks {
+                let mut rows = b.fixed_rows_mut::<15>(15 * k);
+                rows += jk.transpose() * factor.information * e;
+                for &(l, jl) in &blocks {
+                    h.add(k, l, &(jk.transpose() * factor.information * jl));
+                }
+            }
+        }
+        for term in &self.unary {
+            let (e, jacobian) = term.linearize(&states[term.node]);
+            let mut rows = b.fixed_rows_mut::<15>(15 * term.node);
+            rows += jacobian.transpose() * term.information * e;
+            h.add(term.node, term.node, &(jacobian.transpose() * term.information * jacobian));
+        }
+        let diagonal = h.diagonal().map(|d| d.max(1e-12));
+        (h, b, diagonal)
+    }
+
+    fn solve(
+        &self,
+        _: &Self::State,
+        (h, b, diagonal): &Self::System,
+        lambda: f64,
+    ) -> Option<Self::Step> {
+        let mut damped = h.clone();
+        damped.add_to_diagonal(&(diagonal * lambda));
+        Some(damped.cholesky()?.solve(&(-b)))
+    }
+
+    fn step_norm(&self, step: &Self::Step) -> f64 {
+        step.norm()
+    }
+
+    fn predicted_reduction(
+        &self,
+        (_, b, diagonal): &Self::System,
+        dx: &Self::Step,
+        lambda: f64,
+    ) -> f64 {
+        PoseGraphOptimizer::predicted_reduction(diagonal, b, dx, lambda)
+    }
+
+    fn apply(&self, states: &Self::State, dx: &Self::Step) -> Option<Self::State> {
+        let updated = states
+            .iter()
+            .enumerate()
+            .map(|(k, state)| state.retract(&dx.fixed_rows::<15>(15 * k).into_owned()))
+            .collect();
+        Some(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::imu_preintegration::{ImuBias, ImuNoise, ImuPreintegrator};
+    use super::super::pose_graph_optimizer::ConvergenceStatus;
+    use super::super::trajectory_evaluation::Trajectory;
+    use crate::common::npy::load_npy_rows;
+    use std::path::Path;
+
+    #[test]
+    fn test_unary_jacobian_matches_numerical_derivative() {
+        let expected = ImuState::new(
+            NavState::new(
+                SO3::exp(&na::Vector3::new(0.3, -0.2, 0.5)),
+                na::Vector3::new(1.0, 2.0, 3.0),
+                na::Vector3::new(0.5, -0.5, 0.1),
+            ),
+            ImuBias::new(na::Vector3::new(0.1, 0.0, -0.1), na::Vector3::new(0.01, 0.0, 0.02)),
+        );
+        let information = Matrix15::identity();
+        let term = UnaryTerm { node: 0, expected: expected.clone(), information };
+        let state = expected.retract(&Vector15::from_fn(|k, _| 0.1 * ((k as f64) * 1.3).sin()));
+
+        let (error, jacobian) = term.linearize(&state);
+        let h = 1e-6;
+        for k in 0..15 {
+            let mut delta = Vector15::zeros();
+            delta[k] = h;
+            let (perturbed, _) = term.linearize(&state.retract(&delta));
+            let numerical = (perturbed - error) / h;
+            assert!((numerical - jacobian.column(k)).norm() < 1e-4, "column {}", k);
+        }
+    }
+
+    #[test]
+    fn test_bundled_imu_data_recovers_velocity_and_bias() {
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data");
+        let load = |name: &str| {
+            Trajectory::from_rows(&load_npy_rows(data.join(name)).unwrap()).unwrap()
+        };
+        let truth = load("truth_pose.npy");
+        let ndt = load("ndt_pose.npy");
+        let imu = load_npy_rows(data.join("imu_data.npy")).unwrap();
+
+        // Simulated readings are bias-free, so add a known one to recover
+        let bias = ImuBias::new(
+            na::Vector3::new(0.05, -0.03, 0.02),
+            na::Vector3::new(0.002, -0.001, 0.0015),
+        );
+        let truth_at = |t: f64| {
+            let nearest = |t: f64| {
+                truth
+                    .poses
+                    .iter()
+                    .min_by(|a, b| (a.0 - t).abs().total_cmp(&(b.0 - t).abs()))
+                    .copied()
+                    .unwrap()
+            };
+            let (_, pose) = nearest(t);
+            let ((t1, after), (t0, before)) = (nearest(t + 0.05), nearest(t - 0.05));
+            let velocity = (after.translation - before.translation) / (t1 - t0);
+            (pose, velocity)
+        };
+
+        // Localization poses about a second apart, starting at rest with no bias
+        let mut keyframes: Vec<(f64, SE3)> = Vec::new();
+        for (t, pose) in &ndt.poses {
+            if keyframes.last().is_none_or(|(last, _)| t - last >= 1.0) {
+                keyframes.push((*t, *pose));
+            }
+        }
+        let node = |t: f64| NodeType::RobotPose((t * 1000.0).round() as u64);
+        let sigmas = na::Vector6::new(0.1, 0.1, 0.1, 0.02, 0.02, 0.02);
+        let pose_covariance = na::Matrix6::from_diagonal(&sigmas.component_mul(&sigmas));
+        let mut graph = InertialGraph::new();
+        for (t, pose) in &keyframes {
+            let nav = NavState::new(pose.rotation, pose.translation, na::Vector3::zeros());
+            assert!(graph.add_state(node(*t), ImuState::new(nav, ImuBias::default())));
+            assert!(graph.add_pose_measurement(&node(*t), pose, &pose_covariance));
+        }
+        for pair in keyframes.windows(2) {
+            let (start, end) = (pair[0].0, pair[1].0);
+            let mut preintegrator = ImuPreintegrator::new(ImuBias::default(), ImuNoise::default());
+            for row in imu.iter().filter(|row| row[0] >= start - 0.005 && row[0] < end - 0.005) {
+                let accelerometer = na::Vector3::new(row[1], row[2], row[3]) + bias.accelerometer;
+                let gyroscope = na::Vector3::new(row[4], row[5], row[6]) + bias.gyroscope;
+                preintegrator.integrate(&accelerometer, &gyroscope, 0.01);
+            }
+            let factor = ImuFactor::new(node(start), node(end), preintegrator, "IMU", 0);
+            assert!(graph.add_imu_factor(factor));
+        }
+        assert!(keyframes.len() > 100);
+        assert_eq!(graph.imu_factor_count(), keyframes.len() - 1);
+
+        let result = graph.optimize(&OptimizerConfig::default());
+        assert_eq!(result.report.status, ConvergenceStatus::Converged, "{:?}", result.report);
+
+        let count = keyframes.len() as f64;
+        let (mut position, mut localization, mut velocity) = (0.0, 0.0, 0.0);
+        let mut mean_bias = na::Vector6::zeros();
+        for (t, pose) in &keyframes {
+            let state = &result.states[&node(*t)];
+            let (truth_pose, truth_velocity) = truth_at(*t);
+            position += (state.nav.position - truth_pose.translation).norm_squared() / count;
+            localization += (pose.translation - truth_pose.translation).norm_squared() / count;
+            velocity += (state.nav.velocity - truth_velocity).norm_squared() / count;
+            mean_bias += state.bias.to_vector() / count;
+        }
+        assert!(position.sqrt() < localization.sqrt() * 1.05, "{} {}", position, localization);
+        assert!(velocity.sqrt() < 0.05, "velocity rms {}", velocity.sqrt());
+        let error = mean_bias - bias.to_vector();
+        assert!(error.fixed_rows::<3>(0).amax() < 0.02, "{:?}", mean_bias);
+        assert!(error.fixed_rows::<3>(3).amax() < 2e-4, "{:?}", mean_bias);
+
+        // Pose factors from the optimized states, carrying their uncertainty,
+        // agree with the true relative motion
+        for pair in keyframes.windows(2).step_by(10) {
+            let (start, end) = (node(pair[0].0), node(pair[1].0));
+            let covariance = graph.marginal_covariance(&result.states, &start).unwrap();
+            let (_, _, factor) =
+                graph.imu_factors.iter().find(|(_, _, factor)| factor.source == start).unwrap();
+            let pose = factor.pose_factor(&result.states[&start], &covariance);
+            assert_eq!(pose.target, end);
+
+            let (from, _) = truth_at(pair[0].0);
+            let (to, _) = truth_at(pair[1].0);
+            let error = pose.transform.inverse().compose(&from.inverse().compose(&to)).log();
+            let chi2 = (error.transpose() * pose.information * error)[0];
+            assert!(chi2 < 22.5, "chi2 {} at {:?}", chi2, start);  // 99.9% for 6 dof
+        }
+    }
+}
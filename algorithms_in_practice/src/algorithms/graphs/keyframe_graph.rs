@@ -0,0 +1,497 @@
+use std::collections::VecDeque;
+use nalgebra as na;
+
+use crate::common::lie::LieGroup;
+
+use super::factor_graph::{CircularFactorGraph, Factor, NodeType, OrientedEdge, Transform2D};
+use super::landmark_factor::{LandmarkFactor, LandmarkMeasurement};
+
+/// When to start a new keyframe: as soon as the odometry since the last one
+/// crosses any of the thresholds
+#[derive(Debug, Clone)]
+pub struct KeyframePolicy {
+    pub max_distance: f64,  // Translation since the last keyframe
+    pub max_rotation: f64,  // Heading change since the last keyframe, in radians
+    pub max_interval: u64,  // Time since the last keyframe, in timestamp units
+}
+
+impl Default for KeyframePolicy {
+    fn default() -> Self {
+        Self {
+            max_distance: 0.5,
+            max_rotation: 0.35,
+            max_interval: 1000,
+        }
+    }
+}
+
+impl KeyframePolicy {
+    /// Whether `motion` over `elapsed` since the last keyframe calls for a new one
+    pub fn is_due(&self, motion: &Transform2D, elapsed: u64) -> bool {
+        motion.x.hypot(motion.y) >= self.max_distance
+            || motion.theta.abs() >= self.max_rotation
+            || elapsed >= self.max_interval
+    }
+}
+
+/// What became of a measurement handed to a `KeyframeGraph`
+#[derive(Debug, Clone)]
+pub enum Attachment {
+    Attached(Option<Vec<OrientedEdge>>),  // In the graph, with the loop it closed if any
+    Pending,                              // Waits for the odometry, or a later keyframe
+    Rejected,                             // Older than the odometry kept, or not from a pose
+}
+
+/// One odometry increment over `(start, end]`
+#[derive(Debug, Clone)]
+struct OdometrySample {
+    start: u64,
+    end: u64,
+    delta: Transform2D,
+    covariance: na::Matrix3<f64>,
+}
+
+#[derive(Debug, Clone)]
+enum Measurement {
+    Relative(Factor),
+    Landmark(LandmarkFactor),
+}
+
+/// A `CircularFactorGraph` fed by time-stamped sensor streams. `RobotPose`
+/// nodes are keyed by timestamp and only created at keyframes picked by a
+/// `KeyframePolicy`, and the odometry between two keyframes is preintegrated
+/// into one factor. Other measurements may arrive at their own rates: each end
+/// is moved onto the nearest keyframe by the odometry interpolated to its
+/// timestamp, and measurements newer than the odometry wait for it.
+pub struct KeyframeGraph {
+    graph: CircularFactorGraph,
+    policy: KeyframePolicy,
+    keyframes: VecDeque<u64>,            // Keyframe timestamps still in the window, oldest first
+    odometry: VecDeque<OdometrySample>,  // Increments since the oldest keyframe
+    odometry_sensor: String,
+    pending: Vec<Measurement>,
+}
+
+impl KeyframeGraph {
+    /// Wrap an empty graph, already set up with its kernels and policies
+    pub fn new(graph: CircularFactorGraph, policy: KeyframePolicy) -> Self {
+        Self {
+            graph,
+            policy,
+            keyframes: VecDeque::new(),
+            odometry: VecDeque::new(),
+            odometry_sensor: "ODOMETRY".to_string(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn graph(&self) -> &CircularFactorGraph {
+        &self.graph
+    }
+
+    /// Keyframe nodes still in the window, oldest first
+    pub fn keyframes(&self) -> impl Iterator<Item = NodeType> + '_ {
+        self.keyframes.iter().map(|t| NodeType::RobotPose(*t))
+    }
+
+    /// Timestamp the odometry has reached
+    pub fn horizon(&self) -> Option<u64> {
+        self.odometry.back().map(|sample| sample.end)
+    }
+
+    /// Number of measurements waiting to be attached
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Add one odometry increment, given as a factor between the `RobotPose`s
+    /// at its start and end timestamps. The first increment's start becomes
+    /// the first keyframe; every later one must continue from where the last
+    /// ended, and is ignored otherwise. Returns the keyframe it started, if
+    /// the policy called for one.
+    pub fn add_odometry(&mut self, increment: Factor) -> Option<NodeType> {
+        let (NodeType::RobotPose(start), NodeType::RobotPose(end)) =
+            (&increment.source, &increment.target)
+        else {
+            return None;
+        };
+        let (start, end) = (*start, *end);
+        if end <= start {
+            return None;
+        }
+        match self.horizon() {
+            Some(horizon) if horizon != start => return None,
+            Some(_) => {}
+            None => {
+                self.graph.add_node(NodeType::RobotPose(start));
+                self.keyframes.push_back(start);
+            }
+        }
+        self.odometry.push_back(OdometrySample {
+            start,
+            end,
+            covariance: increment.covariance().unwrap_or_else(|| na::Matrix3::identity() * 1e9),
+            delta: increment.transform,
+        });
+        self.odometry_sensor = increment.sensor_type;
+
+        let last = *self.keyframes.back()?;
+        let (motion, _) = self.preintegrate(last, end)?;
+        let keyframe = self.policy.is_due(&motion, end - last).then(|| self.insert_keyframe(end));
+        self.flush_pending();
+        keyframe
+    }
+
+    /// Add a relative-pose measurement between the `RobotPose`s at two
+    /// timestamps, e.g. a scan match. Both ends are moved to their nearest
+    /// keyframes; if that is the same one, the later end moves on to the next
+    /// keyframe, waiting for it if need be. The odometry offsets are composed
+    /// into the measurement along with their covariance.
+    pub fn add_factor(&mut self, factor: Factor) -> Attachment {
+        let (NodeType::RobotPose(a), NodeType::RobotPose(b)) = (&factor.source, &factor.target)
+        else {
+            return Attachment::Rejected;
+        };
+        let (a, b) = (*a, *b);
+        if a == b {
+            return Attachment::Rejected;
+        }
+        if self.horizon().is_none_or(|horizon| a.max(b) > horizon) {
+            self.pending.push(Measurement::Relative(factor));
+            return Attachment::Pending;
+        }
+        let (Some(mut anchor_a), Some(mut anchor_b)) = (self.anchor(a), self.anchor(b)) else {
+            return Attachment::Rejected;
+        };
+        if anchor_a == anchor_b {
+            let later = if a > b { &mut anchor_a } else { &mut anchor_b };
+            match self.keyframes.iter().copied().find(|t| *t > *later) {
+                Some(next) => *later = next,
+                None => {
+                    self.pending.push(Measurement::Relative(factor));
+                    return Attachment::Pending;
+                }
+            }
+        }
+
+        let (Some(offset_a), Some(offset_b)) =
+            (self.preintegrate(anchor_a, a), self.preintegrate(anchor_b, b))
+        else {
+            return Attachment::Rejected;
+        };
+        let measured = (
+            factor.transform.clone(),
+            factor.covariance().unwrap_or_else(|| na::Matrix3::identity() * 1e9),
+        );
+        // anchor_a -> a -> b -> anchor_b
+        let (transform, covariance) = compose(&compose(&offset_a, &measured), &invert(&offset_b));
+        let anchored = Factor {
+            source: NodeType::RobotPose(anchor_a),
+            target: NodeType::RobotPose(anchor_b),
+            transform,
+            ..factor
+        }
+        .with_covariance(covariance);
+        Attachment::Attached(self.graph.add_factor(anchored))
+    }
+
+    /// Add an observation of a point landmark from the `RobotPose` at its
+    /// timestamp, adding the landmark node if it is new. A sighting that pins
+    /// the point down is re-expressed from the nearest keyframe; a bearing
+    /// carries no depth to move it by, so one that falls after the latest
+    /// keyframe gets a keyframe of its own, and an older one is rejected.
+    pub fn add_landmark_factor(&mut self, factor: LandmarkFactor) -> Attachment {
+        let NodeType::RobotPose(time) = factor.source else {
+            return Attachment::Rejected;
+        };
+        if self.horizon().is_none_or(|horizon| time > horizon) {
+            self.pending.push(Measurement::Landmark(factor));
+            return Attachment::Pending;
+        }
+        let Some(keyframe) = self.anchor(time) else {
+            return Attachment::Rejected;
+        };
+
+        let factor = if keyframe == time {
+            factor
+        } else if factor.measurement.back_project().is_some() {
+            match self.transfer(&factor, keyframe, time) {
+                Some(moved) => moved,
+                None => return Attachment::Rejected,
+            }
+        } else if self.keyframes.back().is_some_and(|latest| time > *latest) {
+            self.insert_keyframe(time);
+            factor
+        } else {
+            return Attachment::Rejected;
+        };
+
+        if !self.graph.contains_node(&factor.target) {
+            self.graph.add_node(factor.target.clone());
+            self.forget_evicted();
+        }
+        Attachment::Attached(self.graph.add_landmark_factor(factor))
+    }
+
+    /// Start a keyframe at `time`, linked to the last one by the preintegrated odometry
+    fn insert_keyframe(&mut self, time: u64) -> NodeType {
+        let last = *self.keyframes.back().expect("keyframes start with the odometry");
+        let (delta, covariance) = self.preintegrate(last, time).expect("odometry reaches `time`");
+        let node = NodeType::RobotPose(time);
+        self.graph.add_node(node.clone());
+        let odometry =
+            Factor::new(NodeType::RobotPose(last), node.clone(), delta, &self.odometry_sensor, time)
+                .with_covariance(covariance);
+        self.graph.add_factor(odometry);
+        self.keyframes.push_back(time);
+        self.forget_evicted();
+        node
+    }
+
+    /// Drop keyframes the window has pushed out, and the odometry before the oldest left
+    fn forget_evicted(&mut self) {
+        while let Some(oldest) = self.keyframes.front() {
+            if self.graph.contains_node(&NodeType::RobotPose(*oldest)) {
+                break;
+            }
+            self.keyframes.pop_front();
+        }
+        if let Some(&oldest) = self.keyframes.front() {
+            while self.odometry.front().is_some_and(|sample| sample.end <= oldest) {
+                self.odometry.pop_front();
+            }
+        }
+    }
+
+    /// Retry waiting measurements; those still not placeable wait again
+    fn flush_pending(&mut self) {
+        for measurement in std::mem::take(&mut self.pending) {
+            match measurement {
+                Measurement::Relative(factor) => self.add_factor(factor),
+                Measurement::Landmark(factor) => self.add_landmark_factor(factor),
+            };
+        }
+    }
+
+    /// Keyframe nearest in time, preferring the earlier on a tie. `None` for
+    /// times before the oldest keyframe.
+    fn anchor(&self, time: u64) -> Option<u64> {
+        let after = self.keyframes.partition_point(|t| *t < time);
+        let before = after.checked_sub(1).map(|k| self.keyframes[k]);
+        match (before, self.keyframes.get(after)) {
+            (Some(before), Some(&after)) => {
+                Some(if time - before <= after - time { before } else { after })
+            }
+            (Some(before), None) => Some(before),
+            (None, Some(&after)) if after == time => Some(after),
+            _ => None,
+        }
+    }
+
+    /// Pose at `to` in the frame of the pose at `from`, composed from the
+    /// odometry increments with the covariance of their right-perturbations.
+    /// An increment only partly inside the interval contributes that fraction
+    /// of its motion and covariance, i.e. velocity is taken as constant over
+    /// each increment.
+    fn preintegrate(&self, from: u64, to: u64) -> Option<(Transform2D, na::Matrix3<f64>)> {
+        if to < from {
+            return self.preintegrate(to, from).map(|offset| invert(&offset));
+        }
+        if from < self.odometry.front()?.start || to > self.horizon()? {
+            return None;
+        }
+        let mut integrated = (Transform2D::identity(), na::Matrix3::zeros());
+        for sample in &self.odometry {
+            let (start, end) = (sample.start.max(from), sample.end.min(to));
+            if end <= start {
+                continue;
+            }
+            let fraction = (end - start) as f64 / (sample.end - sample.start) as f64;
+            let piece = (
+                Transform2D::exp(&(sample.delta.log() * fraction)),
+                sample.covariance * fraction,
+            );
+            integrated = compose(&integrated, &piece);
+        }
+        Some(integrated)
+    }
+
+    /// Express a sighting taken at `time` from `keyframe` instead, propagating
+    /// the measurement and odometry-offset covariances through the move
+    fn transfer(
+        &self,
+        factor: &LandmarkFactor,
+        keyframe: u64,
+        time: u64,
+    ) -> Option<LandmarkFactor> {
+        let point = factor.measurement.back_project()?;
+        let (offset, offset_covariance) = self.preintegrate(keyframe, time)?;
+
+        // Point covariance in the sighting's frame, from its measurement at the origin
+        let (_, _, back_jacobian) = factor.measurement.linearize(&na::Vector3::zeros(), &point);
+        let point_covariance = (back_jacobian.transpose() * &factor.information * &back_jacobian)
+            .try_inverse()
+            .map(|c| na::Matrix2::from_iterator(c.iter().copied()))
+            .unwrap_or_else(|| na::Matrix2::identity() * 1e9);
+
+        // moved = R p + t, and its Jacobian for a right-perturbation of the offset
+        let rotation = *na::Rotation2::new(offset.theta).matrix();
+        let moved = rotation * point + na::Vector2::new(offset.x, offset.y);
+        let offset_jacobian = na::Matrix2x3::from_columns(&[
+            rotation.column(0).into_owned(),
+            rotation.column(1).into_owned(),
+            rotation * na::Vector2::new(-point.y, point.x),
+        ]);
+        let moved_covariance = rotation * point_covariance * rotation.transpose()
+            + offset_jacobian * offset_covariance * offset_jacobian.transpose();
+
+        let measurement = match factor.measurement {
+            LandmarkMeasurement::RangeBearing { .. } => LandmarkMeasurement::RangeBearing {
+                range: moved.norm(),
+                bearing: moved.y.atan2(moved.x),
+            },
+            LandmarkMeasurement::Position(_) => LandmarkMeasurement::Position(moved),
+            LandmarkMeasurement::Bearing(_) => return None,
+        };
+        let (_, _, jacobian) = measurement.linearize(&na::Vector3::zeros(), &moved);
+        let covariance = &jacobian
+            * na::DMatrix::from_column_slice(2, 2, moved_covariance.as_slice())
+            * jacobian.transpose();
+        let moved = LandmarkFactor {
+            source: NodeType::RobotPose(keyframe),
+            measurement,
+            ..factor.clone()
+        };
+        Some(moved.with_covariance(covariance))
+    }
+}
+
+/// `a * b` with right-perturbation covariance: `Ad(b^-1) Sa Ad(b^-1)^T + Sb`
+fn compose(
+    a: &(Transform2D, na::Matrix3<f64>),
+    b: &(Transform2D, na::Matrix3<f64>),
+) -> (Transform2D, na::Matrix3<f64>) {
+    let adjoint = b.0.inverse().adjoint();
+    (a.0.compose(&b.0), adjoint * a.1 * adjoint.transpose() + b.1)
+}
+
+/// `a^-1`, whose right-perturbation is `-Ad(a) e`
+fn invert(a: &(Transform2D, na::Matrix3<f64>)) -> (Transform2D, na::Matrix3<f64>) {
+    let adjoint = a.0.adjoint();
+    (a.0.inverse(), adjoint * a.1 * adjoint.transpose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constant twist of 0.1 m forward and 0.05 rad per 10 time units
+    fn truth(time: u64) -> Transform2D {
+        Transform2D::exp(&(na::Vector3::new(0.1, 0.0, 0.05) * (time as f64 / 10.0)))
+    }
+
+    fn odometry(start: u64, end: u64) -> Factor {
+        let delta = truth(start).inverse().compose(&truth(end));
+        Factor::new(NodeType::RobotPose(start), NodeType::RobotPose(end), delta, "WHEEL", end)
+            .with_isotropic_std(0.01)
+    }
+
+    fn keyframe_graph(policy: KeyframePolicy) -> KeyframeGraph {
+        KeyframeGraph::new(CircularFactorGraph::new(50, 100), policy)
+    }
+
+    #[test]
+    fn test_policy_starts_keyframes_on_distance_rotation_or_time() {
+        let never = KeyframePolicy { max_distance: 10.0, max_rotation: 10.0, max_interval: 10_000 };
+        let by_distance = KeyframePolicy { max_distance: 0.45, ..never.clone() };
+        let by_rotation = KeyframePolicy { max_rotation: 0.28, ..never.clone() };
+        let by_time = KeyframePolicy { max_interval: 30, ..never };
+        for (policy, expected) in [(by_distance, 50), (by_rotation, 60), (by_time, 30)] {
+            let mut graph = keyframe_graph(policy);
+            let started: Vec<u64> = (0..12)
+                .filter_map(|t| graph.add_odometry(odometry(10 * t, 10 * t + 10)))
+                .map(|node| match node {
+                    NodeType::RobotPose(t) => t,
+                    NodeType::Landmark(_) => unreachable!(),
+                })
+                .collect();
+            let spacing: Vec<u64> = started.windows(2).map(|w| w[1] - w[0]).collect();
+            assert!(spacing.iter().all(|gap| *gap == expected), "{:?}", started);
+            assert_eq!(started[0], expected);
+        }
+
+        // The preintegrated factors carry the true motion between keyframes
+        let mut graph = keyframe_graph(KeyframePolicy::default());
+        for t in 0..30 {
+            graph.add_odometry(odometry(10 * t, 10 * t + 10));
+        }
+        assert!(graph.graph().factor_count() >= 2);
+        for factor in graph.graph().factors() {
+            let (NodeType::RobotPose(a), NodeType::RobotPose(b)) = (&factor.source, &factor.target)
+            else {
+                unreachable!()
+            };
+            let expected = truth(*a).inverse().compose(&truth(*b));
+            assert!(expected.inverse().compose(&factor.transform).to_vector().norm() < 1e-9);
+            assert_eq!(factor.sensor_type, "WHEEL");
+        }
+        assert!(graph.add_odometry(odometry(500, 510)).is_none());  // Leaves a gap
+    }
+
+    #[test]
+    fn test_asynchronous_measurements_land_on_keyframes() {
+        let policy = KeyframePolicy { max_distance: 0.45, ..KeyframePolicy::default() };
+        let mut graph = keyframe_graph(policy);
+        let pose = NodeType::RobotPose;
+        let landmark = NodeType::Landmark("L1".to_string());
+        let point = na::Vector2::new(1.5, 2.0);
+        let sighting = |time: u64, bearing_only: bool| {
+            let local = truth(time).inverse().compose(&Transform2D::new(point.x, point.y, 0.0));
+            let bearing = local.y.atan2(local.x);
+            let measurement = if bearing_only {
+                LandmarkMeasurement::Bearing(bearing)
+            } else {
+                LandmarkMeasurement::RangeBearing { range: local.x.hypot(local.y), bearing }
+            };
+            LandmarkFactor::new(pose(time), landmark.clone(), measurement, "VISUAL", time)
+                .with_isotropic_std(0.01)
+        };
+        let scan_match = |a: u64, b: u64| {
+            let relative = truth(a).inverse().compose(&truth(b));
+            Factor::new(pose(a), pose(b), relative, "LIDAR", b).with_isotropic_std(0.01)
+        };
+
+        // Measurements from the future wait for the odometry
+        assert!(matches!(graph.add_landmark_factor(sighting(33, false)), Attachment::Pending));
+        assert!(matches!(graph.add_factor(scan_match(7, 123)), Attachment::Pending));
+        for t in 0..15 {
+            graph.add_odometry(odometry(10 * t, 10 * t + 10));
+        }
+        assert_eq!(graph.pending_count(), 0);
+        let keyframes: Vec<NodeType> = graph.keyframes().collect();
+        assert_eq!(keyframes, vec![pose(0), pose(50), pose(100), pose(150)]);
+
+        // Off-keyframe sightings are moved to the nearest keyframe and agree with odometry
+        let attached = graph.add_landmark_factor(sighting(58, false));
+        assert!(matches!(attached, Attachment::Attached(Some(_))));
+        let moved = graph.graph().landmark_factors().last().unwrap();
+        assert_eq!(moved.source, pose(50));
+        assert!(moved.measurement.residual(&truth(50), &point).norm() < 1e-9);
+        let record = graph.graph().factor_consistency(moved.id).unwrap();
+        assert_eq!((record.cycles, record.inconsistent_cycles), (1, 0));
+
+        // The scan match now spans keyframes 0 and 100 (its ends at 7 and 123)
+        let lidar = graph.graph().factors().find(|f| f.sensor_type == "LIDAR").unwrap();
+        assert_eq!((&lidar.source, &lidar.target), (&pose(0), &pose(100)));
+        let record = graph.graph().factor_consistency(lidar.id).unwrap();
+        assert_eq!(record.inconsistent_cycles, 0);
+
+        // A bearing cannot be moved: late ones get their own keyframe, stale ones are dropped
+        graph.add_odometry(odometry(150, 160));
+        assert!(matches!(graph.add_landmark_factor(sighting(144, true)), Attachment::Rejected));
+        let attached = graph.add_landmark_factor(sighting(153, true));
+        assert!(matches!(attached, Attachment::Attached(_)));
+        assert_eq!(graph.keyframes().last(), Some(pose(153)));
+        assert!(matches!(graph.add_landmark_factor(sighting(1, false)), Attachment::Attached(_)));
+    }
+}
@@ -0,0 +1,435 @@
+use std::sync::Arc;
+use nalgebra as na;
+
+use crate::common::robust_kernel::RobustKernel;
+use crate::common::statistics::{chi_square_cdf, chi_square_quantile};
+
+use super::factor_graph::{
+    compose_chain, normalize_angle, CycleEdge, Factor, NodeType, OrientedEdge, PoseGroup,
+    Transform2D,
+};
+use super::factor_health::{CycleConsistency, FactorStatus};
+
+/// What a robot pose measured of a point landmark, in the robot's frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum LandmarkMeasurement {
+    RangeBearing { range: f64, bearing: f64 },
+    Bearing(f64),                   // Direction only, e.g. from a monocular camera
+    Position(na::Vector2<f64>),     // Full point, e.g. from a stereo camera
+}
+
+impl LandmarkMeasurement {
+    /// Number of residual components
+    pub fn dimension(&self) -> usize {
+        match self {
+            LandmarkMeasurement::RangeBearing { .. } | LandmarkMeasurement::Position(_) => 2,
+            LandmarkMeasurement::Bearing(_) => 1,
+        }
+    }
+
+    /// The landmark in the robot frame, if the measurement pins it down
+    pub fn back_project(&self) -> Option<na::Vector2<f64>> {
+        match self {
+            LandmarkMeasurement::RangeBearing { range, bearing } => {
+                Some(na::Vector2::new(range * bearing.cos(), range * bearing.sin()))
+            }
+            LandmarkMeasurement::Bearing(_) => None,
+            LandmarkMeasurement::Position(point) => Some(*point),
+        }
+    }
+
+    /// Residual of the measurement against a pose (x, y, theta) and a landmark
+    /// position, with its Jacobians with respect to both. The landmark in the
+    /// robot frame is q = R(theta)^T (l - t); bearing errors are wrapped.
+    pub fn linearize(
+        &self,
+        pose: &na::Vector3<f64>,
+        landmark: &na::Vector2<f64>,
+    ) -> (na::DVector<f64>, na::DMatrix<f64>, na::DMatrix<f64>) {
+        let (s, c) = pose[2].sin_cos();
+        let d = na::Vector2::new(landmark[0] - pose[0], landmark[1] - pose[1]);
+        let rotation_t = na::Matrix2::new(c, s, -s, c);
+        let q = rotation_t * d;
+
+        // dq/d(x, y, theta) and dq/dl
+        let mut dq_dpose = na::Matrix2x3::zeros();
+        dq_dpose.fixed_view_mut::<2, 2>(0, 0).copy_from(&(-rotation_t));
+        dq_dpose[(0, 2)] = -s * d[0] + c * d[1];
+        dq_dpose[(1, 2)] = -c * d[0] - s * d[1];
+        let dq_dlandmark = rotation_t;
+
+        let range = q.norm().max(f64::EPSILON);
+        let range_row = na::RowVector2::new(q[0] / range, q[1] / range);
+        let bearing_row = na::RowVector2::new(-q[1], q[0]) / (range * range);
+        let bearing = q[1].atan2(q[0]);
+
+        let (residual, dh_dq) = match self {
+            LandmarkMeasurement::RangeBearing { range: z_range, bearing: z_bearing } => {
+                let residual = na::DVector::from_vec(vec![
+                    range - z_range,
+                    normalize_angle(bearing - z_bearing),
+                ]);
+                let mut dh_dq = na::DMatrix::zeros(2, 2);
+                dh_dq.row_mut(0).copy_from(&range_row);
+                dh_dq.row_mut(1).copy_from(&bearing_row);
+                (residual, dh_dq)
+            }
+            LandmarkMeasurement::Bearing(z_bearing) => (
+                na::DVector::from_element(1, normalize_angle(bearing - z_bearing)),
+                na::DMatrix::from_row_slice(1, 2, bearing_row.as_slice()),
+            ),
+            LandmarkMeasurement::Position(point) => (
+                na::DVector::from_column_slice((q - point).as_slice()),
+                na::DMatrix::identity(2, 2),
+            ),
+        };
+
+        let jacobian_pose = &dh_dq * na::DMatrix::from_column_slice(2, 3, dq_dpose.as_slice());
+        let jacobian_landmark =
+            &dh_dq * na::DMatrix::from_column_slice(2, 2, dq_dlandmark.as_slice());
+        (residual, jacobian_pose, jacobian_landmark)
+    }
+
+    /// Residual of the measurement against a pose and a landmark position
+    pub fn residual(&self, pose: &Transform2D, landmark: &na::Vector2<f64>) -> na::DVector<f64> {
+        self.linearize(&pose.to_vector(), landmark).0
+    }
+}
+
+/// Observation of a point `Landmark` from a `RobotPose`. Unlike a relative-pose
+/// `Factor` it carries no orientation for the landmark, so its residual has
+/// one or two components depending on the measurement model.
+#[derive(Debug, Clone)]
+pub struct LandmarkFactor {
+    pub id: u64,  // Assigned by the graph when the factor is added
+    pub source: NodeType,  // Observing robot pose
+    pub target: NodeType,  // Observed landmark
+    pub measurement: LandmarkMeasurement,
+    pub information: na::DMatrix<f64>,  // Inverse covariance of the residual
+    pub sensor_type: String,
+    pub timestamp: u64,
+    pub kernel: Option<Arc<dyn RobustKernel>>,  // Overrides the graph's per-sensor kernel
+    pub status: FactorStatus,
+}
+
+impl LandmarkFactor {
+    /// Create a factor with unit information; chain one of the `with_*` methods
+    /// to set the noise model
+    pub fn new(
+        source: NodeType,
+        target: NodeType,
+        measurement: LandmarkMeasurement,
+        sensor_type: &str,
+        timestamp: u64,
+    ) -> Self {
+        let dimension = measurement.dimension();
+        Self {
+            id: 0,
+            source,
+            target,
+            measurement,
+            information: na::DMatrix::identity(dimension, dimension),
+            sensor_type: sensor_type.to_string(),
+            timestamp,
+            kernel: None,
+            status: FactorStatus::Active,
+        }
+    }
+
+    /// Set the full information matrix
+    pub fn with_information(mut self, information: na::DMatrix<f64>) -> Self {
+        self.information = information;
+        self
+    }
+
+    /// Set the noise model from a covariance matrix. A singular covariance is
+    /// regularized before inversion.
+    pub fn with_covariance(mut self, covariance: na::DMatrix<f64>) -> Self {
+        let dimension = covariance.nrows();
+        self.information = covariance
+            .clone()
+            .try_inverse()
+            .or_else(|| {
+                (covariance + na::DMatrix::identity(dimension, dimension) * 1e-12).try_inverse()
+            })
+            .unwrap_or_else(|| na::DMatrix::zeros(dimension, dimension));
+        self
+    }
+
+    /// Independent standard deviation for every residual component, in the
+    /// measurement's order: (range, bearing), (bearing) or (x, y)
+    pub fn with_std_devs(self, sigmas: &[f64]) -> Self {
+        let variances = na::DVector::from_iterator(sigmas.len(), sigmas.iter().map(|s| s * s));
+        self.with_covariance(na::DMatrix::from_diagonal(&variances))
+    }
+
+    /// Same standard deviation for every residual component
+    pub fn with_isotropic_std(self, sigma: f64) -> Self {
+        let sigmas = vec![sigma; self.measurement.dimension()];
+        self.with_std_devs(&sigmas)
+    }
+
+    /// Attach a robust kernel to this factor only
+    pub fn with_kernel<K: RobustKernel + 'static>(mut self, kernel: K) -> Self {
+        self.kernel = Some(Arc::new(kernel));
+        self
+    }
+
+    /// Covariance implied by the information matrix, if it is invertible
+    pub fn covariance(&self) -> Option<na::DMatrix<f64>> {
+        self.information.clone().try_inverse()
+    }
+}
+
+/// Chi-square test of a loop that runs through exactly one point landmark:
+/// L -> A -> ... -> B -> L. One observation of L is back-projected into its
+/// robot's frame, carried along the pose chain and compared with the other
+/// observation under that observation's own model, so the test has one degree
+/// of freedom per residual component of the predicted measurement. Pose-chain,
+/// back-projection and measurement covariances are all propagated through the
+/// measurement Jacobians. Two bearing-only observations always intersect in
+/// the plane, so such a loop only fails if the landmark would lie behind one of
+/// the poses. Loops through several landmarks leave the relative pose between
+/// their chains unobserved and are not scored.
+pub(super) fn test_landmark_cycle<P: PoseGroup<D>, const D: usize>(
+    cycle: &[OrientedEdge<P, D>],
+    confidence: f64,
+) -> CycleConsistency {
+    let landmark_edges: Vec<usize> = cycle
+        .iter()
+        .enumerate()
+        .filter(|(_, step)| matches!(step.edge, CycleEdge::Landmark(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let n = cycle.len();
+    let (enter, leave) = match landmark_edges[..] {
+        [0, j] if j == n - 1 => (0, j),
+        [i, j] if j == i + 1 => (j, i),
+        _ => return CycleConsistency::unconstrained(confidence),
+    };
+    let (CycleEdge::Landmark(first), CycleEdge::Landmark(last)) =
+        (&cycle[enter].edge, &cycle[leave].edge)
+    else {
+        unreachable!()
+    };
+    if first.target != last.target {
+        return CycleConsistency::unconstrained(confidence);
+    }
+
+    // Relative factors from the pose that saw the landmark first round to the one that saw it last
+    let chain: Vec<&Factor<P, D>> = (1..n - 1)
+        .map(|k| match &cycle[(enter + k) % n].edge {
+            CycleEdge::Relative(factor) => factor,
+            CycleEdge::Landmark(_) => unreachable!(),
+        })
+        .collect();
+
+    let (anchor, observer, chain): (_, _, Vec<&Factor<P, D>>) =
+        if first.measurement.back_project().is_some() {
+            (first, last, chain)
+        } else if last.measurement.back_project().is_some() {
+            (last, first, chain.into_iter().rev().collect())
+        } else {
+            return test_bearing_pair(first, last, &chain, confidence);
+        };
+
+    let Some((pose, pose_covariance)) = planar_chain(&anchor.source, &observer.source, &chain)
+    else {
+        return CycleConsistency::unconstrained(confidence);
+    };
+    let point = anchor.measurement.back_project().unwrap();
+
+    // Point covariance in the anchor frame, from the anchor's measurement at the origin
+    let (_, _, back_jacobian) = anchor.measurement.linearize(&na::Vector3::zeros(), &point);
+    let point_covariance = (back_jacobian.transpose() * &anchor.information * &back_jacobian)
+        .try_inverse()
+        .unwrap_or_else(|| na::DMatrix::identity(2, 2) * 1e9);
+
+    let (residual, jacobian_pose, jacobian_landmark) =
+        observer.measurement.linearize(&pose.to_vector(), &point);
+    let measurement_covariance = observer
+        .covariance()
+        .unwrap_or_else(|| na::DMatrix::identity(residual.len(), residual.len()) * 1e9);
+    let covariance = &jacobian_pose * pose_covariance * jacobian_pose.transpose()
+        + &jacobian_landmark * point_covariance * jacobian_landmark.transpose()
+        + measurement_covariance;
+
+    let chi_square = match covariance.clone().try_inverse() {
+        Some(information) => (residual.transpose() * information * &residual)[(0, 0)].max(0.0),
+        None => residual.norm_squared(),
+    };
+    let degrees_of_freedom = residual.len();
+    let threshold = chi_square_quantile(confidence, degrees_of_freedom);
+
+    CycleConsistency {
+        residual,
+        loop_covariance: covariance,
+        mahalanobis_distance: chi_square.sqrt(),
+        chi_square,
+        degrees_of_freedom,
+        p_value: 1.0 - chi_square_cdf(chi_square, degrees_of_freedom),
+        confidence,
+        threshold,
+        is_consistent: chi_square <= threshold,
+    }
+}
+
+/// Triangulate two bearing-only observations of the same landmark and check
+/// that the landmark lies in front of both poses
+fn test_bearing_pair<P: PoseGroup<D>, const D: usize>(
+    first: &LandmarkFactor,
+    last: &LandmarkFactor,
+    chain: &[&Factor<P, D>],
+    confidence: f64,
+) -> CycleConsistency {
+    let (LandmarkMeasurement::Bearing(bearing_a), LandmarkMeasurement::Bearing(bearing_b)) =
+        (&first.measurement, &last.measurement)
+    else {
+        unreachable!()
+    };
+    let Some((pose, _)) = planar_chain(&first.source, &last.source, chain) else {
+        return CycleConsistency::unconstrained(confidence);
+    };
+
+    // s * ray_a - r * ray_b = t_b, both depths positive for a valid intersection
+    let ray_a = na::Vector2::new(bearing_a.cos(), bearing_a.sin());
+    let ray_b = na::Rotation2::new(pose.theta) * na::Vector2::new(bearing_b.cos(), bearing_b.sin());
+    let system = na::Matrix2::from_columns(&[ray_a, -ray_b]);
+    let baseline = na::Vector2::new(pose.x, pose.y);
+    let Some(depths) = system.try_inverse().map(|inverse| inverse * baseline) else {
+        return CycleConsistency::unconstrained(confidence);
+    };
+    if depths.iter().all(|depth| *depth > 0.0) {
+        return CycleConsistency::unconstrained(confidence);
+    }
+
+    CycleConsistency {
+        residual: na::DVector::zeros(0),
+        loop_covariance: na::DMatrix::zeros(0, 0),
+        mahalanobis_distance: f64::INFINITY,
+        chi_square: f64::INFINITY,
+        degrees_of_freedom: 0,
+        p_value: 0.0,
+        confidence,
+        threshold: 0.0,
+        is_consistent: false,
+    }
+}
+
+/// Compose a chain of relative factors from `start` to `end` as a planar pose,
+/// with the covariance of its (x, y, theta) coordinates. `None` if the chain is
+/// broken or the pose group is not planar.
+fn planar_chain<P: PoseGroup<D>, const D: usize>(
+    start: &NodeType,
+    end: &NodeType,
+    chain: &[&Factor<P, D>],
+) -> Option<(Transform2D, na::DMatrix<f64>)> {
+    let (reached, composed, covariance) = compose_chain(start, chain)?;
+    if reached != *end || D != 3 {
+        return None;
+    }
+    let pose = composed.planar()?;
+
+    // The chain covariance is on right perturbations T * exp(xi), which move
+    // (x, y) by R rho and theta by phi
+    let mut to_coordinates = na::DMatrix::identity(3, 3);
+    to_coordinates
+        .view_mut((0, 0), (2, 2))
+        .copy_from(na::Rotation2::new(pose.theta).matrix());
+    let covariance = na::DMatrix::from_column_slice(3, 3, covariance.as_slice());
+    let covariance = &to_coordinates * covariance * to_coordinates.transpose();
+    Some((pose, covariance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numerical_jacobians(
+        measurement: &LandmarkMeasurement,
+        pose: &na::Vector3<f64>,
+        landmark: &na::Vector2<f64>,
+    ) -> (na::DMatrix<f64>, na::DMatrix<f64>) {
+        let h = 1e-6;
+        let m = measurement.dimension();
+        let mut jacobian_pose = na::DMatrix::zeros(m, 3);
+        let mut jacobian_landmark = na::DMatrix::zeros(m, 2);
+        for k in 0..3 {
+            let mut delta = na::Vector3::zeros();
+            delta[k] = h;
+            let plus = measurement.linearize(&(pose + delta), landmark).0;
+            let minus = measurement.linearize(&(pose - delta), landmark).0;
+            jacobian_pose.set_column(k, &((plus - minus) / (2.0 * h)));
+        }
+        for k in 0..2 {
+            let mut delta = na::Vector2::zeros();
+            delta[k] = h;
+            let plus = measurement.linearize(pose, &(landmark + delta)).0;
+            let minus = measurement.linearize(pose, &(landmark - delta)).0;
+            jacobian_landmark.set_column(k, &((plus - minus) / (2.0 * h)));
+        }
+        (jacobian_pose, jacobian_landmark)
+    }
+
+    #[test]
+    fn test_jacobians_match_finite_differences() {
+        let pose = na::Vector3::new(0.4, -1.2, 2.5);
+        let landmark = na::Vector2::new(-1.0, 0.7);
+        let measurements = [
+            LandmarkMeasurement::RangeBearing { range: 2.0, bearing: 0.3 },
+            LandmarkMeasurement::Bearing(-0.2),
+            LandmarkMeasurement::Position(na::Vector2::new(1.0, 0.5)),
+        ];
+        for measurement in &measurements {
+            let (residual, jacobian_pose, jacobian_landmark) =
+                measurement.linearize(&pose, &landmark);
+            let (numerical_pose, numerical_landmark) =
+                numerical_jacobians(measurement, &pose, &landmark);
+            assert_eq!(residual.len(), measurement.dimension());
+            assert!((jacobian_pose - numerical_pose).amax() < 1e-6, "{:?}", measurement);
+            assert!((jacobian_landmark - numerical_landmark).amax() < 1e-6, "{:?}", measurement);
+        }
+    }
+
+    #[test]
+    fn test_exact_measurements_have_zero_residual() {
+        let pose = Transform2D::new(1.0, 2.0, 0.5);
+        let landmark = na::Vector2::new(3.0, 4.0);
+        let local = pose.inverse().to_se2().transform_point(&landmark);
+
+        let range_bearing = LandmarkMeasurement::RangeBearing {
+            range: local.norm(),
+            bearing: local[1].atan2(local[0]),
+        };
+        let bearing = LandmarkMeasurement::Bearing(local[1].atan2(local[0]));
+        let position = LandmarkMeasurement::Position(local);
+
+        assert!(range_bearing.residual(&pose, &landmark).norm() < 1e-12);
+        assert!(bearing.residual(&pose, &landmark).norm() < 1e-12);
+        assert!(position.residual(&pose, &landmark).norm() < 1e-12);
+        assert!((range_bearing.back_project().unwrap() - local).norm() < 1e-12);
+        assert!(bearing.back_project().is_none());
+    }
+
+    #[test]
+    fn test_bearing_pair_fails_only_behind_the_poses() {
+        let (a, b) = (NodeType::RobotPose(0), NodeType::RobotPose(1));
+        let landmark = NodeType::Landmark("L1".to_string());
+        let step = Transform2D::new(1.0, 0.0, 0.0);
+        let odometry = Factor::new(a.clone(), b.clone(), step, "ODOMETRY", 1);
+        let sighting = |node: &NodeType, bearing: f64| -> OrientedEdge {
+            let measurement = LandmarkMeasurement::Bearing(bearing);
+            LandmarkFactor::new(node.clone(), landmark.clone(), measurement, "VISUAL", 0).into()
+        };
+        let cycle = |bearing_b: f64| {
+            vec![sighting(&a, 0.5).flipped(), odometry.clone().into(), sighting(&b, bearing_b)]
+        };
+
+        // The ray from the origin at 0.5 rad passes (2, 1.09), ahead and to the left of (1, 0)
+        let ahead = test_landmark_cycle(&cycle(1.09f64.atan2(1.0)), 0.99);
+        let behind = test_landmark_cycle(&cycle(1.09f64.atan2(1.0) + std::f64::consts::PI), 0.99);
+        assert!(ahead.is_consistent);
+        assert_eq!(ahead.degrees_of_freedom, 0);
+        assert!(!behind.is_consistent);
+    }
+}
@@ -0,0 +1,270 @@
+use super::pose_graph_optimizer::{
+    ConvergenceStatus, IterationSummary, OptimizationReport, OptimizerConfig, SolverMethod,
+};
+
+/// Levenberg-Marquardt damping schedule shared by the solvers of this module.
+/// An accepted step shrinks lambda by Nielsen's gain-ratio rule, the more the
+/// better the linear model predicted the actual cost decrease; a rejected step
+/// grows it by a factor that doubles with every consecutive rejection.
+#[derive(Debug, Clone)]
+pub(super) struct Damping {
+    lambda: f64,
+    min_lambda: f64,
+    growth: f64,
+}
+
+impl Damping {
+    const MAX_LAMBDA: f64 = 1e16;
+
+    pub(super) fn new(initial_lambda: f64, min_lambda: f64) -> Self {
+        Self { lambda: initial_lambda.max(min_lambda), min_lambda, growth: 2.0 }
+    }
+
+    pub(super) fn lambda(&self) -> f64 {
+        self.lambda
+    }
+
+    /// Shrink after an accepted step, `gain` being the actual over the
+    /// predicted cost decrease
+    pub(super) fn accept(&mut self, gain: f64) {
+        let shrink = (1.0f64 / 3.0).max(1.0 - (2.0 * gain - 1.0).powi(3));
+        self.lambda = (self.lambda * shrink).max(self.min_lambda);
+        self.growth = 2.0;
+    }
+
+    /// Grow after a rejected step or a singular system. Returns false once the
+    /// damping is too large for another attempt to make progress.
+    pub(super) fn reject(&mut self) -> bool {
+        self.lambda *= self.growth;
+        self.growth *= 2.0;
+        self.lambda <= Self::MAX_LAMBDA
+    }
+}
+
+/// Nonlinear least-squares problem as seen by `minimize`. The damped system is
+/// (H + lambda D) dx = -g, D being the diagonal of H, which each problem builds
+/// and solves in whatever structure suits it.
+pub(super) trait LeastSquares {
+    type State;
+    type System;
+    type Step;
+
+    fn cost(&self, state: &Self::State) -> f64;
+
+    fn linearize(&self, state: &Self::State) -> Self::System;
+
+    /// Step of the damped system, `None` if it is not positive definite
+    fn solve(&self, state: &Self::State, system: &Self::System, lambda: f64)
+        -> Option<Self::Step>;
+
+    fn step_norm(&self, step: &Self::Step) -> f64;
+
+    /// Decrease of the cost the linear model predicts for `step`
+    fn predicted_reduction(&self, system: &Self::System, step: &Self::Step, lambda: f64) -> f64;
+
+    /// State after `step`, `None` if the step leaves the region where the
+    /// problem is meaningful; such steps are rejected like a cost increase
+    fn apply(&self, state: &Self::State, step: &Self::Step) -> Option<Self::State>;
+}
+
+/// Gauss-Newton or Levenberg-Marquardt iterations on `problem`, updating `state`
+/// in place
+pub(super) fn minimize<P: LeastSquares>(
+    problem: &P,
+    state: &mut P::State,
+    config: &OptimizerConfig,
+) -> OptimizationReport {
+    let initial_cost = problem.cost(state);
+    let mut cost = initial_cost;
+    let mut damping = Damping::new(config.initial_lambda, config.min_lambda);
+    let mut iterations = Vec::new();
+    let mut status = ConvergenceStatus::MaxIterationsReached;
+    let levenberg_marquardt = config.method == SolverMethod::LevenbergMarquardt;
+
+    'solve: for iteration in 0..config.max_iterations {
+        let system = problem.linearize(state);
+
+        // A rejected step only raises the damping and is retried on the same
+        // linearization, so it does not use up an iteration
+        loop {
+            let lambda = if levenberg_marquardt { damping.lambda() } else { 0.0 };
+            let Some(step) = problem.solve(state, &system, lambda) else {
+                if levenberg_marquardt && damping.reject() {
+                    continue;
+                }
+                status = ConvergenceStatus::SingularSystem;
+                break 'solve;
+            };
+
+            let step_norm = problem.step_norm(&step);
+            let candidate = problem.apply(state, &step).map(|candidate| {
+                let new_cost = problem.cost(&candidate);
+                (candidate, new_cost)
+            });
+            let accepted = candidate
+                .as_ref()
+                .is_some_and(|&(_, new_cost)| new_cost <= cost || !levenberg_marquardt);
+
+            iterations.push(IterationSummary {
+                iteration,
+                cost: candidate.as_ref().filter(|_| accepted).map_or(cost, |&(_, c)| c),
+                step_norm,
+                lambda,
+                accepted,
+            });
+
+            let Some((candidate, new_cost)) = candidate else {
+                // Without damping there is no smaller step to fall back on
+                if !levenberg_marquardt {
+                    status = ConvergenceStatus::Diverged;
+                    break 'solve;
+                }
+                if step_norm < config.step_tolerance {
+                    status = ConvergenceStatus::Converged;
+                    break 'solve;
+                }
+                if !damping.reject() {
+                    status = ConvergenceStatus::Stalled;
+                    break 'solve;
+                }
+                continue;
+            };
+
+            if !new_cost.is_finite() {
+                status = ConvergenceStatus::Diverged;
+                break 'solve;
+            }
+
+            if !accepted {
+                if step_norm < config.step_tolerance {
+                    status = ConvergenceStatus::Converged;
+                    break 'solve;
+                }
+                // Saturated damping has found no lower cost: a stall, not a minimum
+                if !damping.reject() {
+                    status = ConvergenceStatus::Stalled;
+                    break 'solve;
+                }
+                continue;
+            }
+
+            let predicted = problem.predicted_reduction(&system, &step, lambda);
+            damping.accept((cost - new_cost) / predicted.max(f64::MIN_POSITIVE));
+            let relative_change = (cost - new_cost).abs() / cost.max(f64::EPSILON);
+            let diverging = new_cost > cost;
+            *state = candidate;
+            cost = new_cost;
+
+            if diverging && !levenberg_marquardt {
+                status = ConvergenceStatus::Diverged;
+                break 'solve;
+            }
+            if relative_change < config.cost_tolerance || step_norm < config.step_tolerance {
+                status = ConvergenceStatus::Converged;
+                break 'solve;
+            }
+            break;
+        }
+    }
+
+    OptimizationReport { initial_cost, final_cost: cost, iterations, status }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damping_follows_gain_ratio() {
+        let mut damping = Damping::new(1.0, 1e-12);
+        damping.accept(1.0);
+        assert!((damping.lambda() - 1.0 / 3.0).abs() < 1e-12);
+        damping.accept(0.5);
+        assert!((damping.lambda() - 1.0 / 3.0).abs() < 1e-12);
+
+        assert!(damping.reject());
+        assert!(damping.reject());
+        assert!((damping.lambda() - 8.0 / 3.0).abs() < 1e-12);
+        damping.accept(1.0);
+        assert!(damping.reject());
+        assert!((damping.lambda() - 16.0 / 9.0).abs() < 1e-12);
+        while damping.reject() {}
+        assert!(damping.lambda() > 1e16);
+
+        // Good steps cannot push lambda below the floor
+        let mut floored = Damping::new(1e-3, 1e-4);
+        for _ in 0..10 {
+            floored.accept(1.0);
+        }
+        assert_eq!(floored.lambda(), 1e-4);
+    }
+
+    /// Rosenbrock's valley as residuals (1 - x, 10 (y - x^2)), only defined
+    /// left of `max_x` as a stand-in for a problem's valid region
+    struct Rosenbrock {
+        max_x: f64,
+    }
+
+    impl LeastSquares for Rosenbrock {
+        type State = [f64; 2];
+        type System = (nalgebra::Matrix2<f64>, nalgebra::Vector2<f64>);
+        type Step = nalgebra::Vector2<f64>;
+
+        fn cost(&self, &[x, y]: &[f64; 2]) -> f64 {
+            0.5 * ((1.0 - x).powi(2) + 100.0 * (y - x * x).powi(2))
+        }
+
+        fn linearize(&self, &[x, y]: &[f64; 2]) -> Self::System {
+            let jacobian = nalgebra::Matrix2::new(-1.0, 0.0, -20.0 * x, 10.0);
+            let residual = nalgebra::Vector2::new(1.0 - x, 10.0 * (y - x * x));
+            (jacobian.transpose() * jacobian, jacobian.transpose() * residual)
+        }
+
+        fn solve(&self, _: &[f64; 2], (h, g): &Self::System, lambda: f64) -> Option<Self::Step> {
+            let damped = h + nalgebra::Matrix2::from_diagonal(&h.diagonal()) * lambda;
+            damped.cholesky().map(|c| -c.solve(g))
+        }
+
+        fn step_norm(&self, step: &Self::Step) -> f64 {
+            step.norm()
+        }
+
+        fn predicted_reduction(
+            &self,
+            (h, g): &Self::System,
+            step: &Self::Step,
+            lambda: f64,
+        ) -> f64 {
+            let damped = step.dot(&h.diagonal().component_mul(step));
+            0.5 * (lambda * damped - g.dot(step))
+        }
+
+        fn apply(&self, &[x, y]: &[f64; 2], step: &Self::Step) -> Option<[f64; 2]> {
+            Some([x + step[0], y + step[1]]).filter(|[x, _]| *x <= self.max_x)
+        }
+    }
+
+    #[test]
+    fn test_minimize_reports_why_it_stopped() {
+        let mut state = [-1.2, 1.0];
+        let valley = Rosenbrock { max_x: 1.5 };
+        let report = minimize(&valley, &mut state, &OptimizerConfig::default());
+        assert_eq!(report.status, ConvergenceStatus::Converged, "{:?}", report);
+        assert!((state[0] - 1.0).abs() < 1e-6 && (state[1] - 1.0).abs() < 1e-6, "{:?}", state);
+
+        // Every descent step from the origin increases x, so with the region cut
+        // off there the damping saturates without finding a lower cost
+        let walled = Rosenbrock { max_x: 0.0 };
+        let config = OptimizerConfig { step_tolerance: 0.0, ..Default::default() };
+        let mut state = [0.0, 0.0];
+        let report = minimize(&walled, &mut state, &config);
+        assert_eq!(report.status, ConvergenceStatus::Stalled, "{:?}", report.status);
+        assert!(report.iterations.iter().all(|summary| !summary.accepted));
+        assert_eq!(state, [0.0, 0.0]);
+
+        // Gauss-Newton has no damping to retreat with from an invalid step
+        let config = OptimizerConfig { method: SolverMethod::GaussNewton, ..Default::default() };
+        let report = minimize(&walled, &mut [0.0, 0.0], &config);
+        assert_eq!(report.status, ConvergenceStatus::Diverged);
+    }
+}
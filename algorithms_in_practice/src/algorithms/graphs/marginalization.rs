@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use nalgebra as na;
+
+use crate::common::robust_kernel::RobustKernel;
+
+use super::factor_graph::{Factor, NodeType, PoseGroup, Transform2D};
+
+/// What happens to the information attached to a node pushed out of the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionPolicy {
+    Drop,         // Delete the node and every factor touching it
+    Marginalize,  // Fold those factors into a dense prior on the node's neighbours
+}
+
+/// Dense Gaussian prior left behind by marginalizing nodes out of the window.
+/// It lives on the right-perturbations of `nodes` around `linearization`:
+/// cost(dx) = 0.5 dx^T H dx + g^T dx. Built only from relative measurements,
+/// so it is unchanged by moving all of its poses by a common transform.
+#[derive(Debug, Clone)]
+pub struct MarginalPrior<P = Transform2D, const D: usize = 3> {
+    pub nodes: Vec<NodeType>,
+    pub linearization: Vec<P>,           // Poses in the prior's own frame
+    pub information: na::DMatrix<f64>,   // H, D x D block per node pair
+    pub gradient: na::DVector<f64>,      // g
+}
+
+impl<P: PoseGroup<D>, const D: usize> MarginalPrior<P, D> {
+    pub fn contains(&self, node: &NodeType) -> bool {
+        self.nodes.contains(node)
+    }
+
+    /// Express the prior relative to its first node. Returns, for every other
+    /// node, the expected pose relative to the anchor, together with the
+    /// information and mean offset of the error `log(expected^-1 * anchor^-1 * pose)`:
+    /// cost = 0.5 (e - offset)^T H (e - offset) + const.
+    pub fn anchored(&self) -> (Vec<P>, na::DMatrix<f64>, na::DVector<f64>) {
+        let anchor_inverse = self.linearization[0].inverse();
+        let relative = self.linearization[1..]
+            .iter()
+            .map(|pose| anchor_inverse.compose(pose))
+            .collect();
+
+        // Left-invariance: fixing the anchor leaves exactly the remaining blocks
+        let n = self.information.nrows() - D;
+        let information = self.information.view((D, D), (n, n)).into_owned();
+        let gradient = self.gradient.rows(D, n).into_owned();
+        let offset = match information.clone().cholesky() {
+            Some(chol) => -chol.solve(&gradient),
+            None => na::DVector::zeros(n),
+        };
+        (relative, information, offset)
+    }
+}
+
+/// Marginalize `node` out of the factors and priors that touch it via the
+/// Schur complement. The linearization point puts `node` at the identity,
+/// takes existing prior poses where available and otherwise chains the
+/// factor measurements. Each factor is weighted by its status and by its
+/// kernel's IRLS weight at that point. Jacobians use `Jr^-1(e) ~ I`.
+/// Returns `None` when fewer than two neighbours remain to carry the information.
+pub fn marginalize_node<P: PoseGroup<D>, const D: usize>(
+    node: &NodeType,
+    factors: &[(&Factor<P, D>, Option<&dyn RobustKernel>)],
+    priors: &[MarginalPrior<P, D>],
+) -> Option<MarginalPrior<P, D>> {
+    let mut order = vec![node.clone()];
+    let mut poses: HashMap<NodeType, P> = HashMap::new();
+    poses.insert(node.clone(), P::identity());
+
+    for prior in priors {
+        let k = prior.nodes.iter().position(|n| n == node)?;
+        let to_node_frame = prior.linearization[k].inverse();
+        for (n, pose) in prior.nodes.iter().zip(&prior.linearization) {
+            if !poses.contains_key(n) {
+                poses.insert(n.clone(), to_node_frame.compose(pose));
+                order.push(n.clone());
+            }
+        }
+    }
+    for (factor, _) in factors {
+        let (other, pose) = if factor.source == *node {
+            (&factor.target, factor.transform.clone())
+        } else {
+            (&factor.source, factor.transform.inverse())
+        };
+        if !poses.contains_key(other) {
+            poses.insert(other.clone(), pose);
+            order.push(other.clone());
+        }
+    }
+    if order.len() < 3 {
+        return None;
+    }
+
+    let index: HashMap<&NodeType, usize> = order.iter().enumerate().map(|(i, n)| (n, i)).collect();
+    let dim = order.len() * D;
+    let mut h = na::DMatrix::<f64>::zeros(dim, dim);
+    let mut g = na::DVector::<f64>::zeros(dim);
+
+    for (factor, kernel) in factors {
+        if factor.source == factor.target {
+            continue;
+        }
+        let (i, j) = (index[&factor.source], index[&factor.target]);
+        let xi = &poses[&factor.source];
+        let xj = &poses[&factor.target];
+        let between = xi.inverse().compose(xj);
+        let e = factor.transform.inverse().compose(&between).log();
+
+        let squared_error = (e.transpose() * factor.information * e)[(0, 0)];
+        let kernel_weight = kernel.map_or(1.0, |k| k.weight(squared_error));
+        let omega = factor.information * (factor.status.weight() * kernel_weight);
+
+        let blocks = [(i, -between.inverse().adjoint()), (j, na::SMatrix::<f64, D, D>::identity())];
+        for &(a, ja) in &blocks {
+            let mut rows = g.fixed_rows_mut::<D>(a * D);
+            rows += ja.transpose() * omega * e;
+            for &(b, jb) in &blocks {
+                let mut view = h.fixed_view_mut::<D, D>(a * D, b * D);
+                view += ja.transpose() * omega * jb;
+            }
+        }
+    }
+
+    for prior in priors {
+        // Move the prior's gradient to the shared linearization point
+        let k = prior.nodes.iter().position(|n| n == node)?;
+        let to_node_frame = prior.linearization[k].inverse();
+        let mut shift = na::DVector::<f64>::zeros(prior.gradient.len());
+        for (p, (n, pose)) in prior.nodes.iter().zip(&prior.linearization).enumerate() {
+            let moved = to_node_frame.compose(pose);
+            shift.fixed_rows_mut::<D>(p * D).copy_from(&moved.inverse().compose(&poses[n]).log());
+        }
+        let gradient = &prior.gradient + &prior.information * shift;
+
+        for (p, np) in prior.nodes.iter().enumerate() {
+            let a = index[np];
+            let mut rows = g.fixed_rows_mut::<D>(a * D);
+            rows += gradient.fixed_rows::<D>(p * D);
+            for (q, nq) in prior.nodes.iter().enumerate() {
+                let b = index[nq];
+                let mut view = h.fixed_view_mut::<D, D>(a * D, b * D);
+                view += prior.information.fixed_view::<D, D>(p * D, q * D);
+            }
+        }
+    }
+
+    // Schur complement onto everything but the marginalized node
+    let n = dim - D;
+    let h_mm = h.view((0, 0), (D, D)).into_owned();
+    let h_mm_inverse = h_mm
+        .clone()
+        .try_inverse()
+        .or_else(|| (h_mm + na::DMatrix::identity(D, D) * 1e-9).try_inverse())?;
+    let h_nm = h.view((D, 0), (n, D)).into_owned();
+    let gain = &h_nm * h_mm_inverse;
+    let information = h.view((D, D), (n, n)) - &gain * h_nm.transpose();
+    let gradient = g.rows(D, n) - &gain * g.rows(0, D);
+
+    let nodes: Vec<NodeType> = order[1..].to_vec();
+    let linearization = nodes.iter().map(|n| poses[n].clone()).collect();
+    Some(MarginalPrior {
+        nodes,
+        linearization,
+        information: 0.5 * (&information + information.transpose()),
+        gradient,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marginalizing_a_chain_composes_the_measurements() {
+        let (a, m, b) = (NodeType::RobotPose(0), NodeType::RobotPose(1), NodeType::RobotPose(2));
+        let z1 = Transform2D::new(1.0, 0.2, 0.4);
+        let z2 = Transform2D::new(0.8, -0.1, -0.3);
+        let f1 = Factor::new(a.clone(), m.clone(), z1.clone(), "ODOMETRY", 0)
+            .with_diagonal_std(0.1, 0.05, 0.02);
+        let f2 = Factor::new(m.clone(), b.clone(), z2.clone(), "ODOMETRY", 1)
+            .with_diagonal_std(0.2, 0.1, 0.03);
+
+        let prior = marginalize_node(&m, &[(&f1, None), (&f2, None)], &[]).unwrap();
+        assert_eq!(prior.nodes, vec![a, b]);
+
+        let (expected, information, offset) = prior.anchored();
+        let composed = z1.compose(&z2);
+        assert!((expected[0].to_vector() - composed.to_vector()).norm() < 1e-12);
+        assert!(offset.norm() < 1e-12);
+
+        // Covariance of b relative to a: Ad(z2^-1) S1 Ad(z2^-1)^T + S2
+        let ad = z2.inverse().adjoint();
+        let propagated = ad * f1.covariance().unwrap() * ad.transpose() + f2.covariance().unwrap();
+        let covariance = information.try_inverse().unwrap();
+        for r in 0..3 {
+            for c in 0..3 {
+                assert!((covariance[(r, c)] - propagated[(r, c)]).abs() < 1e-9);
+            }
+        }
+    }
+}
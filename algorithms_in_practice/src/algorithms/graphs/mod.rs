@@ -2,4 +2,50 @@ mod robot_pathfinding;
 pub use robot_pathfinding::*;
 
 mod factor_graph;
-pub use factor_graph::*;
\ No newline at end of file
+pub use factor_graph::*;
+
+mod cycle_basis;
+pub use cycle_basis::*;
+
+mod factor_health;
+pub use factor_health::*;
+
+mod landmark_factor;
+pub use landmark_factor::*;
+
+mod keyframe_graph;
+pub use keyframe_graph::*;
+
+mod marginalization;
+pub use marginalization::*;
+
+mod sparse_cholesky;
+
+mod levenberg_marquardt;
+
+mod pose_graph_optimizer;
+pub use pose_graph_optimizer::*;
+
+mod incremental_smoother;
+pub use incremental_smoother::*;
+
+mod bundle_adjustment;
+pub use bundle_adjustment::*;
+
+mod g2o_io;
+pub use g2o_io::*;
+
+mod factor3d;
+pub use factor3d::*;
+
+mod imu_preintegration;
+pub use imu_preintegration::*;
+
+mod inertial_graph;
+pub use inertial_graph::*;
+
+mod trajectory_evaluation;
+pub use trajectory_evaluation::*;
+
+mod icp;
+pub use icp::*;
\ No newline at end of file
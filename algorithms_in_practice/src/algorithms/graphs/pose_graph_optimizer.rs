@@ -0,0 +1,875 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use nalgebra as na;
+
+use crate::common::robust_kernel::RobustKernel;
+
+use super::factor_graph::{normalize_angle, CircularFactorGraph, Factor, NodeType, Transform2D};
+use super::landmark_factor::{LandmarkFactor, LandmarkMeasurement};
+use super::levenberg_marquardt::{minimize, LeastSquares};
+use super::sparse_cholesky::BlockSparseMatrix;
+
+/// Which nonlinear least-squares update rule to use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SolverMethod {
+    GaussNewton,
+    LevenbergMarquardt,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizerConfig {
+    pub method: SolverMethod,
+    pub max_iterations: usize,
+    pub initial_lambda: f64,      // LM damping at the first iteration
+    pub min_lambda: f64,          // Floor on the damping, which bounds the step size
+    pub cost_tolerance: f64,      // Stop when relative cost change drops below this
+    pub step_tolerance: f64,      // Stop when the update norm drops below this
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        Self {
+            method: SolverMethod::LevenbergMarquardt,
+            max_iterations: 50,
+            initial_lambda: 1e-3,
+            min_lambda: 1e-12,
+            cost_tolerance: 1e-9,
+            step_tolerance: 1e-9,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConvergenceStatus {
+    Converged,
+    MaxIterationsReached,
+    Diverged,
+    SingularSystem,
+    Stalled,  // No step lowered the cost before the LM damping saturated
+}
+
+/// Summary of a single solver step. Steps Levenberg-Marquardt rejects are
+/// retried on the same linearization and share the iteration's index.
+#[derive(Debug, Clone)]
+pub struct IterationSummary {
+    pub iteration: usize,
+    pub cost: f64,
+    pub step_norm: f64,
+    pub lambda: f64,
+    pub accepted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizationReport {
+    pub initial_cost: f64,
+    pub final_cost: f64,
+    pub iterations: Vec<IterationSummary>,
+    pub status: ConvergenceStatus,
+}
+
+/// Marginalization prior expressed on solver indices, relative to its first node
+struct PriorTerm {
+    anchor: usize,
+    nodes: Vec<usize>,
+    expected: Vec<Transform2D>,       // Pose of each node relative to the anchor
+    information: na::DMatrix<f64>,
+    offset: na::DVector<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizationResult {
+    pub poses: HashMap<NodeType, Transform2D>,
+    pub report: OptimizationReport,
+}
+
+/// Gauss-Newton / Levenberg-Marquardt solver over the relative-pose and landmark
+/// factors of a `CircularFactorGraph`. The oldest pose of every connected
+/// component is held fixed to remove the gauge freedom. The normal equations are kept as 3x3
+/// blocks and solved by a sparse Cholesky factorization, so whole datasets fit
+/// in memory as well as sliding windows. Factors with a robust kernel (their own or
+/// their sensor's) are solved by iteratively reweighted least squares,
+/// quarantined or disabled factors are left out, and priors left behind by
+/// marginalized nodes are included.
+pub struct PoseGraphOptimizer {
+    config: OptimizerConfig,
+}
+
+impl PoseGraphOptimizer {
+    pub fn new(config: OptimizerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Optimize starting from poses chained together along the factors
+    pub fn optimize(&self, graph: &CircularFactorGraph) -> OptimizationResult {
+        let initial = Self::initial_estimates(graph);
+        self.optimize_from(graph, &initial)
+    }
+
+    /// Optimize starting from caller-provided poses. Nodes missing from
+    /// `initial` start at the origin. Nodes that only landmark factors reach
+    /// are solved as points and keep the heading they start with.
+    pub fn optimize_from(
+        &self,
+        graph: &CircularFactorGraph,
+        initial: &HashMap<NodeType, Transform2D>,
+    ) -> OptimizationResult {
+        let links = Self::links(graph);
+        let points = Self::point_nodes(graph, &links);
+        // Poses go first so a point, whose heading is free, never anchors the gauge
+        let mut nodes: Vec<NodeType> = graph.nodes().cloned().collect();
+        nodes.sort_by_key(|node| points.contains(node));
+        let index: HashMap<NodeType, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.clone(), i))
+            .collect();
+        let factors: Vec<&Factor> = graph
+            .factors()
+            .filter(|f| index.contains_key(&f.source) && index.contains_key(&f.target))
+            .filter(|f| f.status.weight() > 0.0)
+            .collect();
+        let kernels: Vec<Option<&dyn RobustKernel>> =
+            factors.iter().map(|f| graph.kernel_for(f)).collect();
+        let landmarks: Vec<&LandmarkFactor> = graph
+            .landmark_factors()
+            .filter(|f| index.contains_key(&f.source) && index.contains_key(&f.target))
+            .filter(|f| f.status.weight() > 0.0)
+            .collect();
+        let landmark_kernels: Vec<Option<&dyn RobustKernel>> =
+            landmarks.iter().map(|f| graph.kernel_for_landmark(f)).collect();
+        let priors: Vec<PriorTerm> = graph
+            .priors()
+            .filter(|prior| prior.nodes.iter().all(|n| index.contains_key(n)))
+            .map(|prior| {
+                let (expected, information, offset) = prior.anchored();
+                PriorTerm {
+                    anchor: index[&prior.nodes[0]],
+                    nodes: prior.nodes[1..].iter().map(|n| index[n]).collect(),
+                    expected,
+                    information,
+                    offset,
+                }
+            })
+            .collect();
+        let connections: Vec<_> = links.into_iter().chain(Self::landmark_links(graph)).collect();
+        let fixed = Self::gauge_nodes(&nodes, &connections);
+        let is_point: Vec<bool> = nodes.iter().map(|node| points.contains(node)).collect();
+
+        let mut state: Vec<na::Vector3<f64>> = nodes
+            .iter()
+            .map(|node| {
+                initial
+                    .get(node)
+                    .map(|pose| pose.to_vector())
+                    .unwrap_or_else(na::Vector3::zeros)
+            })
+            .collect();
+
+        let problem = PoseGraphProblem {
+            index: &index,
+            factors: &factors,
+            kernels: &kernels,
+            priors: &priors,
+            landmarks: &landmarks,
+            landmark_kernels: &landmark_kernels,
+            points: &is_point,
+            fixed: &fixed,
+        };
+        let report = minimize(&problem, &mut state, &self.config);
+
+        let poses = nodes
+            .into_iter()
+            .zip(state)
+            .map(|(node, v)| (node, Transform2D::new(v[0], v[1], v[2])))
+            .collect();
+
+        OptimizationResult { poses, report }
+    }
+
+    /// Chain factor and prior transforms outward from the oldest pose of each
+    /// connected component to get a starting point for the solver. Landmarks
+    /// are placed by the first observation from a pose already placed.
+    pub fn initial_estimates(graph: &CircularFactorGraph) -> HashMap<NodeType, Transform2D> {
+        let mut estimates: HashMap<NodeType, Transform2D> = HashMap::new();
+        let links = Self::links(graph);
+        let points = Self::point_nodes(graph, &links);
+        let sightings = Self::landmark_links(graph);
+
+        for root in graph.nodes() {
+            if estimates.contains_key(root) || points.contains(root) {
+                continue;
+            }
+            estimates.insert(root.clone(), Transform2D::new(0.0, 0.0, 0.0));
+
+            let mut queue = VecDeque::new();
+            queue.push_back(root.clone());
+            while let Some(current) = queue.pop_front() {
+                let current_pose = estimates[&current].clone();
+                for (source, target, transform) in &links {
+                    let (next, step) = if *source == current {
+                        (target, transform.clone())
+                    } else if *target == current {
+                        (source, transform.inverse())
+                    } else {
+                        continue;
+                    };
+                    if !estimates.contains_key(next) {
+                        estimates.insert(next.clone(), current_pose.compose(&step));
+                        queue.push_back(next.clone());
+                    }
+                }
+                // A sighting places the landmark but cannot orient the robot that made it
+                for (source, target, position) in &sightings {
+                    if *source == current && points.contains(target) {
+                        estimates
+                            .entry(target.clone())
+                            .or_insert_with(|| current_pose.compose(position));
+                    }
+                }
+            }
+        }
+
+        // Landmarks no placed pose has seen
+        for node in graph.nodes() {
+            estimates.entry(node.clone()).or_insert_with(|| Transform2D::new(0.0, 0.0, 0.0));
+        }
+
+        estimates
+    }
+
+    /// Relative transforms the solver can chain along: active factors, and
+    /// every prior node relative to its prior's first node
+    pub(super) fn links(graph: &CircularFactorGraph) -> Vec<(NodeType, NodeType, Transform2D)> {
+        let mut links: Vec<(NodeType, NodeType, Transform2D)> = graph
+            .factors()
+            .filter(|f| f.status.weight() > 0.0)
+            .map(|f| (f.source.clone(), f.target.clone(), f.transform.clone()))
+            .collect();
+        for prior in graph.priors() {
+            let (expected, _, _) = prior.anchored();
+            for (node, transform) in prior.nodes[1..].iter().zip(expected) {
+                links.push((prior.nodes[0].clone(), node.clone(), transform));
+            }
+        }
+        links
+    }
+
+    /// Observer-to-landmark links of the active landmark factors, each holding
+    /// the landmark's position in the observer's frame and a zero heading.
+    /// Bearing-only sightings put the landmark a metre out along the bearing.
+    fn landmark_links(graph: &CircularFactorGraph) -> Vec<(NodeType, NodeType, Transform2D)> {
+        graph
+            .landmark_factors()
+            .filter(|f| f.status.weight() > 0.0)
+            .map(|f| {
+                let point = match &f.measurement {
+                    LandmarkMeasurement::Bearing(bearing) => {
+                        na::Vector2::new(bearing.cos(), bearing.sin())
+                    }
+                    measurement => measurement.back_project().unwrap_or_else(na::Vector2::zeros),
+                };
+                (f.source.clone(), f.target.clone(), Transform2D::new(point.x, point.y, 0.0))
+            })
+            .collect()
+    }
+
+    /// Landmarks that only landmark factors reach, so nothing constrains their heading
+    fn point_nodes(
+        graph: &CircularFactorGraph,
+        links: &[(NodeType, NodeType, Transform2D)],
+    ) -> HashSet<NodeType> {
+        let oriented: HashSet<&NodeType> = links.iter().flat_map(|(s, t, _)| [s, t]).collect();
+        graph
+            .landmark_factors()
+            .map(|f| &f.target)
+            .filter(|node| !oriented.contains(node))
+            .cloned()
+            .collect()
+    }
+
+    /// Mark the first node of every connected component as fixed
+    pub(super) fn gauge_nodes(
+        nodes: &[NodeType],
+        links: &[(NodeType, NodeType, Transform2D)],
+    ) -> Vec<bool> {
+        let mut fixed = vec![false; nodes.len()];
+        let mut component: HashMap<&NodeType, usize> = HashMap::new();
+
+        for (i, root) in nodes.iter().enumerate() {
+            if component.contains_key(root) {
+                continue;
+            }
+            fixed[i] = true;
+            component.insert(root, i);
+
+            let mut queue = VecDeque::new();
+            queue.push_back(root);
+            while let Some(current) = queue.pop_front() {
+                for (source, target, _) in links {
+                    let next = if source == current {
+                        target
+                    } else if target == current {
+                        source
+                    } else {
+                        continue;
+                    };
+                    if !component.contains_key(next) {
+                        component.insert(next, i);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        fixed
+    }
+
+    /// Residual and Jacobians of a relative-pose factor
+    /// e = [R_z^T (R_i^T (t_j - t_i) - t_z), theta_j - theta_i - theta_z]
+    pub(super) fn linearize(
+        xi: &na::Vector3<f64>,
+        xj: &na::Vector3<f64>,
+        z: &Transform2D,
+    ) -> (na::Vector3<f64>, na::Matrix3<f64>, na::Matrix3<f64>) {
+        let ri = na::Rotation2::new(xi[2]);
+        let rz = na::Rotation2::new(z.theta);
+        let dt = na::Vector2::new(xj[0] - xi[0], xj[1] - xi[1]);
+
+        let ri_t = ri.inverse();
+        let rz_t = rz.inverse();
+        let t_local = ri_t * dt;
+        let e_t = rz_t * (t_local - na::Vector2::new(z.x, z.y));
+        let e_theta = normalize_angle(xj[2] - xi[2] - z.theta);
+        let error = na::Vector3::new(e_t[0], e_t[1], e_theta);
+
+        // d(R_i^T)/d(theta_i) applied to dt
+        let (s, c) = xi[2].sin_cos();
+        let d_ri_t = na::Vector2::new(-s * dt[0] + c * dt[1], -c * dt[0] - s * dt[1]);
+
+        let rzt = rz_t.matrix();
+        let rzt_rit = rzt * ri_t.matrix();
+        let d_theta = rzt * d_ri_t;
+
+        let mut a = na::Matrix3::zeros();
+        a.fixed_view_mut::<2, 2>(0, 0).copy_from(&(-rzt_rit));
+        a[(0, 2)] = d_theta[0];
+        a[(1, 2)] = d_theta[1];
+        a[(2, 2)] = -1.0;
+
+        let mut b = na::Matrix3::zeros();
+        b.fixed_view_mut::<2, 2>(0, 0).copy_from(&rzt_rit);
+        b[(2, 2)] = 1.0;
+
+        (error, a, b)
+    }
+
+    /// Robustified cost `rho(s)` and IRLS weight `rho'(s)` of a squared error
+    pub(super) fn robustify(kernel: Option<&dyn RobustKernel>, squared_error: f64) -> (f64, f64) {
+        match kernel {
+            Some(kernel) => {
+                let [rho, weight, _] = kernel.evaluate(squared_error);
+                (rho, weight)
+            }
+            None => (squared_error, 1.0),
+        }
+    }
+
+    fn total_cost(
+        state: &[na::Vector3<f64>],
+        index: &HashMap<NodeType, usize>,
+        factors: &[&Factor],
+        kernels: &[Option<&dyn RobustKernel>],
+        priors: &[PriorTerm],
+    ) -> f64 {
+        let prior_cost: f64 = priors
+            .iter()
+            .map(|prior| {
+                let (r, _) = Self::linearize_prior(state, prior);
+                0.5 * (r.transpose() * &prior.information * &r)[(0, 0)]
+            })
+            .sum();
+        let factor_cost: f64 = factors
+            .iter()
+            .zip(kernels)
+            .map(|(factor, &kernel)| {
+                let (e, _, _) = Self::linearize(
+                    &state[index[&factor.source]],
+                    &state[index[&factor.target]],
+                    &factor.transform,
+                );
+                let information = factor.information * factor.status.weight();
+                let squared_error = (e.transpose() * information * e)[(0, 0)];
+                0.5 * Self::robustify(kernel, squared_error).0
+            })
+            .sum();
+        factor_cost + prior_cost
+    }
+
+    /// Accumulate the normal equations H dx = -b, scaling each factor's
+    /// information by its kernel weight. Fixed nodes get an identity block so
+    /// they stay put.
+    fn build_system(
+        state: &[na::Vector3<f64>],
+        index: &HashMap<NodeType, usize>,
+        factors: &[&Factor],
+        kernels: &[Option<&dyn RobustKernel>],
+        priors: &[PriorTerm],
+        fixed: &[bool],
+    ) -> (BlockSparseMatrix<3>, na::DVector<f64>) {
+        let mut h = BlockSparseMatrix::new(state.len());
+        let mut b = na::DVector::zeros(state.len() * 3);
+
+        for (factor, &kernel) in factors.iter().zip(kernels) {
+            let i = index[&factor.source];
+            let j = index[&factor.target];
+            let (e, a, bj) = Self::linearize(&state[i], &state[j], &factor.transform);
+            let information = factor.information * factor.status.weight();
+            let squared_error = (e.transpose() * information * e)[(0, 0)];
+            let omega = information * Self::robustify(kernel, squared_error).1;
+
+            let blocks = [(i, a), (j, bj)];
+            for &(k, jk) in &blocks {
+                if fixed[k] {
+                    continue;
+                }
+                let g = jk.transpose() * omega * e;
+                let mut rows = b.fixed_rows_mut::<3>(3 * k);
+                rows += g;
+                for &(l, jl) in &blocks {
+                    if fixed[l] {
+                        continue;
+                    }
+                    h.add(k, l, &(jk.transpose() * omega * jl));
+                }
+            }
+        }
+
+        for prior in priors {
+            let (r, jacobians) = Self::linearize_prior(state, prior);
+            let g = &prior.information * &r;
+            for (p, &(node_p, ref ap, ref bp)) in jacobians.iter().enumerate() {
+                let gp = g.fixed_rows::<3>(3 * p);
+                let rows_p = [(prior.anchor, ap), (node_p, bp)];
+                for &(k, jk) in &rows_p {
+                    if fixed[k] {
+                        continue;
+                    }
+                    let mut rows = b.fixed_rows_mut::<3>(3 * k);
+                    rows += jk.transpose() * gp;
+                }
+                for (q, &(node_q, ref aq, ref bq)) in jacobians.iter().enumerate() {
+                    let block = prior.information.fixed_view::<3, 3>(3 * p, 3 * q);
+                    for &(k, jk) in &rows_p {
+                        for &(l, jl) in &[(prior.anchor, aq), (node_q, bq)] {
+                            if fixed[k] || fixed[l] {
+                                continue;
+                            }
+                            h.add(k, l, &(jk.transpose() * block * *jl));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (k, &is_fixed) in fixed.iter().enumerate() {
+            if is_fixed {
+                h.set_diagonal_block(k, &na::Matrix3::identity());
+            }
+        }
+
+        (h, b)
+    }
+
+    /// Decrease of the cost the linear model predicts for a step of the damped
+    /// system (H + lambda D) dx = -b, D being the (floored) diagonal of H. The
+    /// cost is half the squared error, so this is (-b^T dx + lambda dx^T D dx) / 2.
+    pub(super) fn predicted_reduction(
+        diagonal: &na::DVector<f64>,
+        b: &na::DVector<f64>,
+        dx: &na::DVector<f64>,
+        lambda: f64,
+    ) -> f64 {
+        let damped: f64 = diagonal.iter().zip(dx.iter()).map(|(d, x)| d * x * x).sum();
+        0.5 * (lambda * damped - b.dot(dx))
+    }
+
+    /// Stacked prior residual `e - offset`, where each block is the relative-pose
+    /// error of a node against its expected pose from the anchor, plus the
+    /// Jacobians of each block with respect to the anchor and the node
+    #[allow(clippy::type_complexity)]
+    fn linearize_prior(
+        state: &[na::Vector3<f64>],
+        prior: &PriorTerm,
+    ) -> (na::DVector<f64>, Vec<(usize, na::Matrix3<f64>, na::Matrix3<f64>)>) {
+        let mut residual = -prior.offset.clone();
+        let mut jacobians = Vec::with_capacity(prior.nodes.len());
+        for (p, (&node, expected)) in prior.nodes.iter().zip(&prior.expected).enumerate() {
+            let (e, a, b) = Self::linearize(&state[prior.anchor], &state[node], expected);
+            let mut rows = residual.fixed_rows_mut::<3>(3 * p);
+            rows += e;
+            jacobians.push((node, a, b));
+        }
+        (residual, jacobians)
+    }
+
+    fn apply_update(state: &[na::Vector3<f64>], dx: &na::DVector<f64>) -> Vec<na::Vector3<f64>> {
+        state
+            .iter()
+            .enumerate()
+            .map(|(k, x)| {
+                let mut updated = x + dx.fixed_rows::<3>(3 * k);
+                updated[2] = normalize_angle(updated[2]);
+                updated
+            })
+            .collect()
+    }
+}
+
+/// The factors and priors of one `optimize_from` call, over the stacked node
+/// poses. A point landmark uses the first two entries of its pose.
+struct PoseGraphProblem<'a> {
+    index: &'a HashMap<NodeType, usize>,
+    factors: &'a [&'a Factor],
+    kernels: &'a [Option<&'a dyn RobustKernel>],
+    priors: &'a [PriorTerm],
+    landmarks: &'a [&'a LandmarkFactor],
+    landmark_kernels: &'a [Option<&'a dyn RobustKernel>],
+    points: &'a [bool],
+    fixed: &'a [bool],
+}
+
+impl PoseGraphProblem<'_> {
+    /// Residual of a landmark observation, with its Jacobians padded to the
+    /// 3-vector state of the pose and of the point
+    fn linearize_landmark(
+        &self,
+        state: &[na::Vector3<f64>],
+        factor: &LandmarkFactor,
+    ) -> (na::DVector<f64>, na::DMatrix<f64>, na::DMatrix<f64>) {
+        let pose = &state[self.index[&factor.source]];
+        let landmark = state[self.index[&factor.target]].xy();
+        let (e, jacobian_pose, jacobian_landmark) = factor.measurement.linearize(pose, &landmark);
+        let jacobian_point = jacobian_landmark.insert_column(2, 0.0);
+        (e, jacobian_pose, jacobian_point)
+    }
+
+    fn landmark_cost(&self, state: &[na::Vector3<f64>]) -> f64 {
+        self.landmarks
+            .iter()
+            .zip(self.landmark_kernels)
+            .map(|(factor, &kernel)| {
+                let (e, _, _) = self.linearize_landmark(state, factor);
+                let information = &factor.information * factor.status.weight();
+                let squared_error = (e.transpose() * information * &e)[(0, 0)];
+                0.5 * PoseGraphOptimizer::robustify(kernel, squared_error).0
+            })
+            .sum()
+    }
+
+    /// Add the landmark observations to the normal equations. A point has no
+    /// heading, so a unit diagonal holds that unused entry still.
+    fn add_landmark_terms(
+        &self,
+        state: &[na::Vector3<f64>],
+        h: &mut BlockSparseMatrix<3>,
+        b: &mut na::DVector<f64>,
+    ) {
+        for (factor, &kernel) in self.landmarks.iter().zip(self.landmark_kernels) {
+            let i = self.index[&factor.source];
+            let j = self.index[&factor.target];
+            let (e, jacobian_pose, jacobian_point) = self.linearize_landmark(state, factor);
+            let information = &factor.information * factor.status.weight();
+            let squared_error = (e.transpose() * &information * &e)[(0, 0)];
+            let omega = information * PoseGraphOptimizer::robustify(kernel, squared_error).1;
+
+            let blocks = [(i, &jacobian_pose), (j, &jacobian_point)];
+            for &(k, jk) in &blocks {
+                if self.fixed[k] {
+                    continue;
+                }
+                let g = jk.transpose() * &omega * &e;
+                let mut rows = b.fixed_rows_mut::<3>(3 * k);
+                rows += g.fixed_rows::<3>(0);
+                for &(l, jl) in &blocks {
+                    if self.fixed[l] {
+                        continue;
+                    }
+                    let block = jk.transpose() * &omega * jl;
+                    h.add(k, l, &block.fixed_view::<3, 3>(0, 0).into_owned());
+                }
+            }
+        }
+
+        let heading = na::Matrix3::from_diagonal(&na::Vector3::new(0.0, 0.0, 1.0));
+        for (k, (&point, &fixed)) in self.points.iter().zip(self.fixed).enumerate() {
+            if point && !fixed {
+                h.add(k, k, &heading);
+            }
+        }
+    }
+}
+
+impl LeastSquares for PoseGraphProblem<'_> {
+    type State = Vec<na::Vector3<f64>>;
+    type System = (BlockSparseMatrix<3>, na::DVector<f64>, na::DVector<f64>);  // H, b, D
+    type Step = na::DVector<f64>;
+
+    fn cost(&self, state: &Self::State) -> f64 {
+        PoseGraphOptimizer::total_cost(state, self.index, self.factors, self.kernels, self.priors)
+            + self.landmark_cost(state)
+    }
+
+    fn linearize(&self, state: &Self::State) -> Self::System {
+        let (mut h, mut b) = PoseGraphOptimizer::build_system(
+            state,
+            self.index,
+            self.factors,
+            self.kernels,
+            self.priors,
+            self.fixed,
+        );
+        self.add_landmark_terms(state, &mut h, &mut b);
+        let diagonal = h.diagonal().map(|d| d.max(1e-12));
+        (h, b, diagonal)
+    }
+
+    fn solve(
+        &self,
+        _: &Self::State,
+        (h, b, diagonal): &Self::System,
+        lambda: f64,
+    ) -> Option<Self::Step> {
+        let mut damped = h.clone();
+        damped.add_to_diagonal(&(diagonal * lambda));
+        Some(damped.cholesky()?.solve(&(-b)))
+    }
+
+    fn step_norm(&self, step: &Self::Step) -> f64 {
+        step.norm()
+    }
+
+    fn predicted_reduction(
+        &self,
+        (_, b, diagonal): &Self::System,
+        dx: &Self::Step,
+        lambda: f64,
+    ) -> f64 {
+        PoseGraphOptimizer::predicted_reduction(diagonal, b, dx, lambda)
+    }
+
+    fn apply(&self, state: &Self::State, dx: &Self::Step) -> Option<Self::State> {
+        Some(PoseGraphOptimizer::apply_update(state, dx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factor(source: u64, target: u64, transform: Transform2D, sigma: f64) -> Factor {
+        Factor::new(
+            NodeType::RobotPose(source),
+            NodeType::RobotPose(target),
+            transform,
+            "ODOMETRY",
+            target,
+        )
+        .with_isotropic_std(sigma)
+    }
+
+    /// Four noisy odometry steps around a unit square, closed by an accurate loop factor
+    fn square_graph() -> CircularFactorGraph {
+        let mut graph = CircularFactorGraph::new(10, 10);
+        for t in 0..4 {
+            graph.add_node(NodeType::RobotPose(t));
+        }
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        graph.add_factor(factor(0, 1, Transform2D::new(1.1, 0.0, half_pi + 0.05), 0.1));
+        graph.add_factor(factor(1, 2, Transform2D::new(0.9, 0.05, half_pi - 0.02), 0.1));
+        graph.add_factor(factor(2, 3, Transform2D::new(1.05, -0.05, half_pi + 0.04), 0.1));
+        graph.add_factor(factor(3, 0, Transform2D::new(1.0, 0.0, half_pi), 0.01));
+        graph
+    }
+
+    #[test]
+    fn test_levenberg_marquardt_reduces_cost() {
+        let graph = square_graph();
+        let result = PoseGraphOptimizer::new(OptimizerConfig::default()).optimize(&graph);
+
+        assert_eq!(result.report.status, ConvergenceStatus::Converged);
+        assert!(result.report.final_cost < result.report.initial_cost);
+        assert_eq!(result.poses.len(), 4);
+
+        let anchor = &result.poses[&NodeType::RobotPose(0)];
+        assert_eq!(*anchor, Transform2D::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_gauss_newton_recovers_consistent_loop() {
+        let mut graph = CircularFactorGraph::new(10, 10);
+        for t in 0..3 {
+            graph.add_node(NodeType::RobotPose(t));
+        }
+        graph.add_factor(factor(0, 1, Transform2D::new(1.0, 0.0, 0.0), 0.1));
+        graph.add_factor(factor(1, 2, Transform2D::new(1.0, 0.0, 0.0), 0.1));
+        graph.add_factor(factor(0, 2, Transform2D::new(2.0, 0.0, 0.0), 0.1));
+
+        let mut initial = HashMap::new();
+        initial.insert(NodeType::RobotPose(0), Transform2D::new(0.0, 0.0, 0.0));
+        initial.insert(NodeType::RobotPose(1), Transform2D::new(0.7, 0.3, 0.2));
+        initial.insert(NodeType::RobotPose(2), Transform2D::new(2.4, -0.2, -0.1));
+
+        let config = OptimizerConfig {
+            method: SolverMethod::GaussNewton,
+            ..OptimizerConfig::default()
+        };
+        let result = PoseGraphOptimizer::new(config).optimize_from(&graph, &initial);
+
+        assert_eq!(result.report.status, ConvergenceStatus::Converged);
+        assert!(result.report.final_cost < 1e-12);
+        let last = &result.poses[&NodeType::RobotPose(2)];
+        assert!((last.x - 2.0).abs() < 1e-6 && last.y.abs() < 1e-6 && last.theta.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sensor_kernel_suppresses_outlier() {
+        use crate::common::robust_kernel::CauchyKernel;
+
+        // Straight-line odometry plus a LIDAR loop closure that is off by a meter
+        let build = || {
+            let mut graph = CircularFactorGraph::new(10, 10);
+            for t in 0..4 {
+                graph.add_node(NodeType::RobotPose(t));
+            }
+            for t in 0..3 {
+                graph.add_factor(factor(t, t + 1, Transform2D::new(1.0, 0.0, 0.0), 0.05));
+            }
+            let mut lidar = factor(0, 3, Transform2D::new(4.0, 0.0, 0.0), 0.05);
+            lidar.sensor_type = "LIDAR".to_string();
+            graph.add_factor(lidar);
+            graph
+        };
+
+        let optimizer = PoseGraphOptimizer::new(OptimizerConfig::default());
+        let plain = optimizer.optimize(&build());
+
+        let mut robust_graph = build();
+        robust_graph.set_sensor_kernel("LIDAR", CauchyKernel::new(1.0));
+        let robust = optimizer.optimize(&robust_graph);
+
+        let end_x = |result: &OptimizationResult| result.poses[&NodeType::RobotPose(3)].x;
+        assert!((end_x(&plain) - 3.75).abs() < 0.05);
+        assert!((end_x(&robust) - 3.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_marginalized_window_matches_full_graph() {
+        use crate::algorithms::graphs::EvictionPolicy;
+
+        // Noisy odometry around a pentagon, closed by a loop back to the start
+        let turn = 2.0 * std::f64::consts::PI / 5.0;
+        let steps = [
+            Transform2D::new(1.05, 0.02, turn + 0.03),
+            Transform2D::new(0.97, -0.03, turn - 0.02),
+            Transform2D::new(1.02, 0.01, turn + 0.01),
+            Transform2D::new(0.99, 0.02, turn - 0.03),
+        ];
+        let closing = factor(4, 0, Transform2D::new(1.0, 0.0, turn), 0.05);
+        let build = |max_nodes: usize| {
+            let mut graph = CircularFactorGraph::new(max_nodes, 20);
+            graph.set_eviction_policy(EvictionPolicy::Marginalize);
+            for t in 0..5 {
+                graph.add_node(NodeType::RobotPose(t));
+            }
+            for (t, step) in steps.iter().enumerate() {
+                graph.add_factor(factor(t as u64, t as u64 + 1, step.clone(), 0.1));
+            }
+            graph.add_factor(closing.clone());
+            // A sixth node pushes node 0 out of a five-node window
+            graph.add_node(NodeType::RobotPose(5));
+            graph.add_factor(factor(4, 5, Transform2D::new(1.0, 0.0, 0.0), 0.1));
+            graph
+        };
+
+        let full = build(6);
+        let window = build(5);
+        assert_eq!(window.node_count(), 5);
+        assert_eq!(window.priors().count(), 1);
+
+        let optimizer = PoseGraphOptimizer::new(OptimizerConfig::default());
+        let full_result = optimizer.optimize(&full);
+        let window_result = optimizer.optimize(&window);
+        assert_eq!(window_result.report.status, ConvergenceStatus::Converged);
+
+        // Compare poses relative to node 1, which anchors the window's gauge
+        let relative = |result: &OptimizationResult, t: u64| {
+            result.poses[&NodeType::RobotPose(1)]
+                .inverse()
+                .compose(&result.poses[&NodeType::RobotPose(t)])
+                .to_vector()
+        };
+        for t in 2..6 {
+            let difference = relative(&full_result, t) - relative(&window_result, t);
+            assert!(difference.norm() < 1e-3, "node {}: {}", t, difference.norm());
+        }
+    }
+
+    #[test]
+    fn test_landmarks_correct_drifting_odometry() {
+        // Odometry overestimates every step by 10 cm; two landmarks seen
+        // precisely from each pose pull the trajectory back
+        let truth = [
+            Transform2D::new(0.0, 0.0, 0.0),
+            Transform2D::new(1.0, 0.0, 0.1),
+            Transform2D::new(2.0, 0.1, 0.2),
+            Transform2D::new(3.0, 0.3, 0.3),
+        ];
+        let points = [
+            ("L1", na::Vector2::new(1.5, 2.0)),
+            ("L2", na::Vector2::new(2.5, -1.5)),
+        ];
+
+        let mut graph = CircularFactorGraph::new(10, 20);
+        for t in 0..4 {
+            graph.add_node(NodeType::RobotPose(t));
+        }
+        for (name, _) in &points {
+            graph.add_node(NodeType::Landmark(name.to_string()));
+        }
+        for t in 0..3 {
+            let step = truth[t].inverse().compose(&truth[t + 1]);
+            let drifted = Transform2D::new(step.x + 0.1, step.y, step.theta);
+            graph.add_factor(factor(t as u64, t as u64 + 1, drifted, 0.2));
+        }
+        for (t, pose) in truth.iter().enumerate() {
+            let t = t as u64;
+            let position = na::Vector2::new(pose.x, pose.y);
+            for (name, point) in &points {
+                let q = na::Rotation2::new(-pose.theta) * (point - position);
+                let measurement = LandmarkMeasurement::RangeBearing {
+                    range: q.norm(),
+                    bearing: q[1].atan2(q[0]),
+                };
+                let landmark = NodeType::Landmark(name.to_string());
+                let observation =
+                    LandmarkFactor::new(NodeType::RobotPose(t), landmark, measurement, "VISUAL", t)
+                        .with_std_devs(&[0.01, 0.005]);
+                graph.add_landmark_factor(observation);
+            }
+        }
+
+        let result = PoseGraphOptimizer::new(OptimizerConfig::default()).optimize(&graph);
+        assert_eq!(result.report.status, ConvergenceStatus::Converged);
+        assert!(result.report.final_cost < result.report.initial_cost);
+
+        for (name, point) in &points {
+            let solved = &result.poses[&NodeType::Landmark(name.to_string())];
+            let error = (na::Vector2::new(solved.x, solved.y) - point).norm();
+            assert!(error < 1e-3, "{}: {}", name, error);
+        }
+        for (t, pose) in truth.iter().enumerate() {
+            let solved = &result.poses[&NodeType::RobotPose(t as u64)];
+            let difference = solved.to_vector() - pose.to_vector();
+            assert!(difference.norm() < 1e-3, "pose {}: {}", t, difference.norm());
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use nalgebra as na;
+
+type Block<const D: usize> = na::SMatrix<f64, D, D>;
+
+/// Symmetric matrix of D x D blocks, such as the normal equations of a pose
+/// graph. Only the blocks on and below the diagonal are stored, by block column.
+#[derive(Debug, Clone)]
+pub(super) struct BlockSparseMatrix<const D: usize> {
+    columns: Vec<BTreeMap<usize, Block<D>>>,  // Row block -> block, rows at or below the column
+}
+
+impl<const D: usize> BlockSparseMatrix<D> {
+    pub(super) fn new(blocks: usize) -> Self {
+        Self { columns: vec![BTreeMap::new(); blocks] }
+    }
+
+    /// Add `block` at (`row`, `col`). Blocks above the diagonal are ignored, so
+    /// contributions can be accumulated as for a full symmetric matrix.
+    pub(super) fn add(&mut self, row: usize, col: usize, block: &Block<D>) {
+        if row >= col {
+            *self.columns[col].entry(row).or_insert_with(Block::zeros) += block;
+        }
+    }
+
+    /// Overwrite the diagonal block of `k`
+    pub(super) fn set_diagonal_block(&mut self, k: usize, block: &Block<D>) {
+        self.columns[k].insert(k, *block);
+    }
+
+    /// Scalar diagonal of the whole matrix
+    pub(super) fn diagonal(&self) -> na::DVector<f64> {
+        let mut diagonal = na::DVector::zeros(D * self.columns.len());
+        for (k, column) in self.columns.iter().enumerate() {
+            if let Some(block) = column.get(&k) {
+                diagonal.fixed_rows_mut::<D>(D * k).copy_from(&block.diagonal());
+            }
+        }
+        diagonal
+    }
+
+    /// Add `values` to the scalar diagonal
+    pub(super) fn add_to_diagonal(&mut self, values: &na::DVector<f64>) {
+        for (k, column) in self.columns.iter_mut().enumerate() {
+            let block = column.entry(k).or_insert_with(Block::zeros);
+            for i in 0..D {
+                block[(i, i)] += values[D * k + i];
+            }
+        }
+    }
+
+    /// Block Cholesky factorization in a minimum-degree elimination order.
+    /// `None` if the matrix is not positive definite.
+    pub(super) fn cholesky(&self) -> Option<BlockCholesky<D>> {
+        let (order, pattern) = self.elimination_order();
+        let mut position = vec![0; order.len()];
+        for (k, &node) in order.iter().enumerate() {
+            position[node] = k;
+        }
+
+        // Lower triangle in elimination order, with room for the fill-in
+        let mut diagonal = vec![Block::<D>::zeros(); order.len()];
+        let mut below: Vec<HashMap<usize, Block<D>>> = pattern
+            .iter()
+            .map(|rows| rows.iter().map(|&row| (row, Block::zeros())).collect())
+            .collect();
+        for (col, column) in self.columns.iter().enumerate() {
+            for (&row, block) in column {
+                let (r, c) = (position[row], position[col]);
+                if r == c {
+                    diagonal[r] += block;
+                } else if r > c {
+                    *below[c].get_mut(&r)? += block;
+                } else {
+                    *below[r].get_mut(&c)? += block.transpose();
+                }
+            }
+        }
+
+        // Right-looking: each eliminated column updates the blocks its rows share,
+        // all of which the elimination graph has already made room for
+        let mut columns = Vec::with_capacity(order.len());
+        for k in 0..order.len() {
+            let inverse = diagonal[k].cholesky()?.l().try_inverse()?;
+            let rows: Vec<(usize, Block<D>)> = pattern[k]
+                .iter()
+                .map(|&row| (row, below[k][&row] * inverse.transpose()))
+                .collect();
+            for (a, &(i, l_ik)) in rows.iter().enumerate() {
+                diagonal[i] -= l_ik * l_ik.transpose();
+                for &(j, l_jk) in &rows[..a] {
+                    *below[j].get_mut(&i)? -= l_ik * l_jk.transpose();
+                }
+            }
+            below[k] = HashMap::new();
+            columns.push((inverse, rows));
+        }
+        Some(BlockCholesky { order, columns })
+    }
+
+    /// Greedy minimum-degree ordering on the block graph. Returns the blocks in
+    /// elimination order and, for each, the later positions its column of the
+    /// factor fills, sorted.
+    fn elimination_order(&self) -> (Vec<usize>, Vec<Vec<usize>>) {
+        let n = self.columns.len();
+        let mut neighbours: Vec<HashSet<usize>> = vec![HashSet::new(); n];
+        for (col, column) in self.columns.iter().enumerate() {
+            for &row in column.keys().filter(|&&row| row != col) {
+                neighbours[row].insert(col);
+                neighbours[col].insert(row);
+            }
+        }
+
+        let mut queue: BTreeSet<(usize, usize)> =
+            (0..n).map(|k| (neighbours[k].len(), k)).collect();
+        let mut order = Vec::with_capacity(n);
+        let mut adjacent_when_eliminated = Vec::with_capacity(n);
+        while let Some((_, node)) = queue.pop_first() {
+            // Eliminating a node joins its remaining neighbours into a clique
+            let adjacent: Vec<usize> = neighbours[node].drain().collect();
+            for &a in &adjacent {
+                queue.remove(&(neighbours[a].len(), a));
+                neighbours[a].remove(&node);
+                neighbours[a].extend(adjacent.iter().filter(|&&b| b != a));
+                queue.insert((neighbours[a].len(), a));
+            }
+            order.push(node);
+            adjacent_when_eliminated.push(adjacent);
+        }
+
+        let mut position = vec![0; n];
+        for (k, &node) in order.iter().enumerate() {
+            position[node] = k;
+        }
+        let pattern = adjacent_when_eliminated
+            .into_iter()
+            .map(|adjacent| {
+                let mut rows: Vec<usize> = adjacent.into_iter().map(|a| position[a]).collect();
+                rows.sort_unstable();
+                rows
+            })
+            .collect();
+        (order, pattern)
+    }
+}
+
+/// Factor L L^T of a permuted `BlockSparseMatrix`, stored by block column as the
+/// inverse of the diagonal block and the blocks below it
+#[derive(Debug, Clone)]
+pub(super) struct BlockCholesky<const D: usize> {
+    order: Vec<usize>,  // Original block eliminated at each position
+    columns: Vec<(Block<D>, Vec<(usize, Block<D>)>)>,
+}
+
+impl<const D: usize> BlockCholesky<D> {
+    pub(super) fn solve(&self, b: &na::DVector<f64>) -> na::DVector<f64> {
+        let mut y: Vec<na::SVector<f64, D>> =
+            self.order.iter().map(|&k| b.fixed_rows::<D>(D * k).into_owned()).collect();
+
+        // Forward substitution with L, then backward with L^T
+        for (k, (inverse, rows)) in self.columns.iter().enumerate() {
+            let yk = inverse * y[k];
+            for (i, l_ik) in rows {
+                y[*i] -= l_ik * yk;
+            }
+            y[k] = yk;
+        }
+        for (k, (inverse, rows)) in self.columns.iter().enumerate().rev() {
+            let mut r = y[k];
+            for (i, l_ik) in rows {
+                r -= l_ik.transpose() * y[*i];
+            }
+            y[k] = inverse.transpose() * r;
+        }
+
+        let mut x = na::DVector::zeros(b.len());
+        for (&k, yk) in self.order.iter().zip(&y) {
+            x.fixed_rows_mut::<D>(D * k).copy_from(yk);
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_matches_dense_cholesky() {
+        // Chain of blocks with a few loop closures, so the ordering creates fill-in
+        let n = 12;
+        let coupling = |i: usize, j: usize| {
+            na::Matrix3::from_fn(|r, c| ((i * 7 + j * 3 + r * 5 + c) as f64).sin() * 0.3)
+        };
+        let mut sparse = BlockSparseMatrix::<3>::new(n);
+        let mut dense = na::DMatrix::zeros(3 * n, 3 * n);
+        let mut links: Vec<(usize, usize)> = (0..n - 1).map(|i| (i, i + 1)).collect();
+        links.extend([(0, 11), (3, 8), (5, 10), (2, 6)]);
+        for k in 0..n {
+            let block = na::Matrix3::identity() * 6.0 + na::Matrix3::from_fn(|r, c| {
+                if r == c { 0.0 } else { 0.1 * (k + r + c) as f64 / n as f64 }
+            });
+            let block = (block + block.transpose()) * 0.5;
+            sparse.add(k, k, &block);
+            dense.fixed_view_mut::<3, 3>(3 * k, 3 * k).copy_from(&block);
+        }
+        for &(i, j) in &links {
+            let block = coupling(i, j);
+            sparse.add(j, i, &block);
+            dense.fixed_view_mut::<3, 3>(3 * j, 3 * i).copy_from(&block);
+            dense.fixed_view_mut::<3, 3>(3 * i, 3 * j).copy_from(&block.transpose());
+        }
+        let b = na::DVector::from_fn(3 * n, |i, _| (i as f64 * 0.37).cos());
+
+        assert_eq!(sparse.diagonal(), dense.diagonal());
+        let expected = dense.cholesky().unwrap().solve(&b);
+        assert!((sparse.cholesky().unwrap().solve(&b) - expected).norm() < 1e-10);
+    }
+
+    #[test]
+    fn test_indefinite_matrix_has_no_factor() {
+        let mut matrix = BlockSparseMatrix::<3>::new(2);
+        matrix.add(0, 0, &na::Matrix3::identity());
+        matrix.add(1, 1, &na::Matrix3::identity());
+        matrix.add(1, 0, &(na::Matrix3::identity() * 2.0));
+        assert!(matrix.cholesky().is_none());
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use nalgebra as na;
+
+use crate::common::lie::{LieGroup, SE3, SO3};
+
+use super::factor_graph::NodeType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrajectoryError {
+    RowLength { row: usize, found: usize },
+}
+
+impl fmt::Display for TrajectoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrajectoryError::RowLength { row, found } => write!(
+                f,
+                "row {}: expected (t, x, y, z, qw, qx, qy, qz), found {} values",
+                row, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TrajectoryError {}
+
+/// Timestamped poses, sorted by time (seconds)
+#[derive(Debug, Clone, Default)]
+pub struct Trajectory {
+    pub poses: Vec<(f64, SE3)>,
+}
+
+impl Trajectory {
+    pub fn new(mut poses: Vec<(f64, SE3)>) -> Self {
+        poses.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { poses }
+    }
+
+    /// Rows of (t, x, y, z, qw, qx, qy, qz), the layout of the bundled pose files
+    pub fn from_rows(rows: &[Vec<f64>]) -> Result<Self, TrajectoryError> {
+        let poses = rows
+            .iter()
+            .enumerate()
+            .map(|(row, values)| match values[..] {
+                [t, x, y, z, qw, qx, qy, qz] => {
+                    let rotation = SO3::from_quaternion(qw, qx, qy, qz);
+                    let pose = SE3::new(rotation, na::Vector3::new(x, y, z));
+                    Ok((t, pose))
+                }
+                _ => Err(TrajectoryError::RowLength { row, found: values.len() }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(poses))
+    }
+
+    /// Inverse of `from_rows`, e.g. for `save_npy_rows`
+    pub fn to_rows(&self) -> Vec<Vec<f64>> {
+        self.poses
+            .iter()
+            .map(|(t, pose)| {
+                let (p, q) = (&pose.translation, pose.rotation.rotation.quaternion());
+                vec![*t, p[0], p[1], p[2], q.w, q.i, q.j, q.k]
+            })
+            .collect()
+    }
+
+    /// Robot poses of a pose-graph solution, such as `OptimizationResult::poses`.
+    /// `RobotPose` ids are taken as timestamps in units of `seconds_per_tick`;
+    /// landmarks are skipped.
+    pub fn from_graph<P>(poses: &HashMap<NodeType, P>, seconds_per_tick: f64) -> Self
+    where
+        P: Clone + Into<SE3>,
+    {
+        let poses = poses
+            .iter()
+            .filter_map(|(node, pose)| match node {
+                NodeType::RobotPose(tick) => {
+                    Some((*tick as f64 * seconds_per_tick, pose.clone().into()))
+                }
+                NodeType::Landmark(_) => None,
+            })
+            .collect();
+        Self::new(poses)
+    }
+
+    pub fn len(&self) -> usize {
+        self.poses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.poses.is_empty()
+    }
+
+    /// Index of the pose closest in time to `t`
+    fn nearest(&self, t: f64) -> Option<usize> {
+        let after = self.poses.partition_point(|(s, _)| *s < t);
+        let before = after.checked_sub(1);
+        match (before, (after < self.poses.len()).then_some(after)) {
+            (Some(b), Some(a)) => {
+                Some(if t - self.poses[b].0 <= self.poses[a].0 - t { b } else { a })
+            }
+            (b, a) => b.or(a),
+        }
+    }
+}
+
+/// Pairs of (estimate, reference) indices whose timestamps differ by at most
+/// `max_difference` once `offset` is added to the estimate's. Closest pairs
+/// are matched first and every pose is used at most once.
+pub fn associate(
+    estimate: &Trajectory,
+    reference: &Trajectory,
+    max_difference: f64,
+    offset: f64,
+) -> Vec<(usize, usize)> {
+    let mut candidates: Vec<(f64, usize, usize)> = estimate
+        .poses
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (t, _))| {
+            let j = reference.nearest(t + offset)?;
+            let difference = (reference.poses[j].0 - t - offset).abs();
+            (difference <= max_difference).then_some((difference, i, j))
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut taken = HashSet::new();
+    let mut pairs: Vec<(usize, usize)> = candidates
+        .into_iter()
+        .filter(|&(_, _, j)| taken.insert(j))
+        .map(|(_, i, j)| (i, j))
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// How the estimate is registered to the reference before comparing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    None,
+    SE3,   // Rotation and translation
+    Sim3,  // Also scale, for monocular estimates
+}
+
+/// `x -> scale * R x + t`, mapping estimate coordinates into the reference frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Similarity {
+    pub rotation: na::UnitQuaternion<f64>,
+    pub translation: na::Vector3<f64>,
+    pub scale: f64,
+}
+
+impl Similarity {
+    pub fn identity() -> Self {
+        Self {
+            rotation: na::UnitQuaternion::identity(),
+            translation: na::Vector3::zeros(),
+            scale: 1.0,
+        }
+    }
+
+    pub fn transform_point(&self, point: &na::Vector3<f64>) -> na::Vector3<f64> {
+        self.rotation * point * self.scale + self.translation
+    }
+
+    /// Move a pose; its orientation is only rotated
+    pub fn transform_pose(&self, pose: &SE3) -> SE3 {
+        let rotation = SO3::new(self.rotation * pose.rotation.rotation);
+        SE3::new(rotation, self.transform_point(&pose.translation))
+    }
+}
+
+/// Least-squares similarity taking `source` points onto `target` points
+/// (Umeyama, 1991, which reduces to Horn's method without scale). Needs at
+/// least three points that are not all on one line.
+pub fn umeyama(
+    source: &[na::Vector3<f64>],
+    target: &[na::Vector3<f64>],
+    with_scale: bool,
+) -> Option<Similarity> {
+    let n = source.len();
+    if n < 3 || target.len() != n {
+        return None;
+    }
+    let mean = |points: &[na::Vector3<f64>]| points.iter().sum::<na::Vector3<f64>>() / n as f64;
+    let (source_mean, target_mean) = (mean(source), mean(target));
+
+    let mut covariance = na::Matrix3::zeros();
+    let mut source_variance = 0.0;
+    for (x, y) in source.iter().zip(target) {
+        let (dx, dy) = (x - source_mean, y - target_mean);
+        covariance += dy * dx.transpose();
+        source_variance += dx.norm_squared();
+    }
+    covariance /= n as f64;
+    source_variance /= n as f64;
+
+    let svd = covariance.svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    if svd.singular_values[1] < 1e-12 * svd.singular_values[0].max(1e-300) {
+        return None;
+    }
+    // Flip the weakest axis rather than return a reflection
+    let mut signs = na::Vector3::new(1.0, 1.0, 1.0);
+    if u.determinant() * v_t.determinant() < 0.0 {
+        signs[2] = -1.0;
+    }
+    let rotation = u * na::Matrix3::from_diagonal(&signs) * v_t;
+    let scale = if with_scale {
+        svd.singular_values.dot(&signs) / source_variance
+    } else {
+        1.0
+    };
+    let rotation = na::UnitQuaternion::from_matrix(&rotation);
+    Some(Similarity {
+        translation: target_mean - rotation * source_mean * scale,
+        rotation,
+        scale,
+    })
+}
+
+/// Summary of a set of errors
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorStatistics {
+    pub count: usize,
+    pub rmse: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ErrorStatistics {
+    pub fn from_errors(errors: &[f64]) -> Self {
+        if errors.is_empty() {
+            return Self::default();
+        }
+        let n = errors.len() as f64;
+        let mut sorted = errors.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            0.5 * (sorted[mid - 1] + sorted[mid])
+        } else {
+            sorted[mid]
+        };
+        let mean = errors.iter().sum::<f64>() / n;
+        let mean_square = errors.iter().map(|e| e * e).sum::<f64>() / n;
+        Self {
+            count: errors.len(),
+            rmse: mean_square.sqrt(),
+            mean,
+            median,
+            std_dev: (mean_square - mean * mean).max(0.0).sqrt(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Association and alignment settings shared by ATE and RPE
+#[derive(Debug, Clone)]
+pub struct EvaluationConfig {
+    pub max_time_difference: f64,  // Seconds between associated poses
+    pub time_offset: f64,          // Added to the estimate's timestamps
+    pub alignment: Alignment,
+}
+
+impl Default for EvaluationConfig {
+    fn default() -> Self {
+        Self {
+            max_time_difference: 0.02,
+            time_offset: 0.0,
+            alignment: Alignment::SE3,
+        }
+    }
+}
+
+/// Absolute trajectory error of the aligned estimate
+#[derive(Debug, Clone)]
+pub struct AbsoluteError {
+    pub alignment: Similarity,
+    pub translation: ErrorStatistics,  // Metres
+    pub rotation: ErrorStatistics,     // Radians
+    pub errors: Vec<(f64, f64, f64)>,  // (estimate time, translation, rotation) per pair
+}
+
+/// Spacing of the pose pairs compared by the relative pose error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelativeDelta {
+    Frames(usize),
+    Distance(f64),  // Metres travelled along the reference
+    Time(f64),      // Seconds
+}
+
+/// Relative pose error over one spacing
+#[derive(Debug, Clone)]
+pub struct RelativeError {
+    pub delta: RelativeDelta,
+    pub translation: ErrorStatistics,  // Metres
+    pub rotation: ErrorStatistics,     // Radians
+    pub translation_ratio: ErrorStatistics,  // Per metre travelled; noisy when nearly still
+}
+
+/// Associate the trajectories, align the estimate and compare positions and
+/// orientations pose by pose. `None` if fewer than three poses associate or
+/// the alignment is degenerate.
+pub fn absolute_trajectory_error(
+    estimate: &Trajectory,
+    reference: &Trajectory,
+    config: &EvaluationConfig,
+) -> Option<AbsoluteError> {
+    let pairs = associate(estimate, reference, config.max_time_difference, config.time_offset);
+    let alignment = align(estimate, reference, &pairs, config.alignment)?;
+
+    let errors: Vec<(f64, f64, f64)> = pairs
+        .iter()
+        .map(|&(i, j)| {
+            let (t, pose) = &estimate.poses[i];
+            let aligned = alignment.transform_pose(pose);
+            let truth = &reference.poses[j].1;
+            let translation = (aligned.translation - truth.translation).norm();
+            let rotation = truth.rotation.rotation.angle_to(&aligned.rotation.rotation);
+            (*t, translation, rotation)
+        })
+        .collect();
+    let column = |k: usize| -> Vec<f64> {
+        errors.iter().map(|e| if k == 0 { e.1 } else { e.2 }).collect()
+    };
+    Some(AbsoluteError {
+        alignment,
+        translation: ErrorStatistics::from_errors(&column(0)),
+        rotation: ErrorStatistics::from_errors(&column(1)),
+        errors,
+    })
+}
+
+/// Compare the motion between associated poses `delta` apart. Relative motion
+/// needs no rotation or translation alignment, but a `Sim3` configuration
+/// still rescales the estimate. `None` if fewer than three poses associate.
+pub fn relative_pose_error(
+    estimate: &Trajectory,
+    reference: &Trajectory,
+    delta: RelativeDelta,
+    config: &EvaluationConfig,
+) -> Option<RelativeError> {
+    let pairs = associate(estimate, reference, config.max_time_difference, config.time_offset);
+    let scale = match config.alignment {
+        Alignment::Sim3 => align(estimate, reference, &pairs, Alignment::Sim3)?.scale,
+        _ if pairs.len() < 3 => return None,
+        _ => 1.0,
+    };
+
+    // Distance travelled along the associated reference poses
+    let mut travelled = vec![0.0; pairs.len()];
+    for k in 1..pairs.len() {
+        let step = reference.poses[pairs[k].1].1.translation
+            - reference.poses[pairs[k - 1].1].1.translation;
+        travelled[k] = travelled[k - 1] + step.norm();
+    }
+
+    let mut translation = Vec::new();
+    let mut rotation = Vec::new();
+    let mut ratio = Vec::new();
+    let mut end = 0;
+    for start in 0..pairs.len() {
+        // Earliest later pair that is at least `delta` away; it only moves forward
+        end = end.max(start + 1);
+        let reached = |end: usize| match delta {
+            RelativeDelta::Frames(frames) => end - start >= frames.max(1),
+            RelativeDelta::Distance(metres) => travelled[end] - travelled[start] >= metres,
+            RelativeDelta::Time(seconds) => {
+                reference.poses[pairs[end].1].0 - reference.poses[pairs[start].1].0 >= seconds
+            }
+        };
+        while end < pairs.len() && !reached(end) {
+            end += 1;
+        }
+        if end == pairs.len() {
+            break;
+        }
+
+        let motion = |trajectory: &Trajectory, a: usize, b: usize, scale: f64| {
+            let relative = trajectory.poses[a].1.inverse().compose(&trajectory.poses[b].1);
+            SE3::new(relative.rotation, relative.translation * scale)
+        };
+        let estimated = motion(estimate, pairs[start].0, pairs[end].0, scale);
+        let expected = motion(reference, pairs[start].1, pairs[end].1, 1.0);
+        let error = expected.inverse().compose(&estimated);
+        translation.push(error.translation.norm());
+        rotation.push(error.rotation.rotation.angle());
+        let distance = travelled[end] - travelled[start];
+        if distance > 0.0 {
+            ratio.push(error.translation.norm() / distance);
+        }
+    }
+    Some(RelativeError {
+        delta,
+        translation: ErrorStatistics::from_errors(&translation),
+        rotation: ErrorStatistics::from_errors(&rotation),
+        translation_ratio: ErrorStatistics::from_errors(&ratio),
+    })
+}
+
+fn align(
+    estimate: &Trajectory,
+    reference: &Trajectory,
+    pairs: &[(usize, usize)],
+    alignment: Alignment,
+) -> Option<Similarity> {
+    if pairs.len() < 3 {
+        return None;
+    }
+    let source: Vec<_> = pairs.iter().map(|&(i, _)| estimate.poses[i].1.translation).collect();
+    let target: Vec<_> = pairs.iter().map(|&(_, j)| reference.poses[j].1.translation).collect();
+    match alignment {
+        Alignment::None => Some(Similarity::identity()),
+        Alignment::SE3 => umeyama(&source, &target, false),
+        Alignment::Sim3 => umeyama(&source, &target, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::graphs::Transform2D;
+    use crate::common::npy::load_npy_rows;
+    use std::path::Path;
+
+    /// A climbing figure-eight sampled at 10 Hz
+    fn figure_eight(count: usize) -> Trajectory {
+        let poses = (0..count)
+            .map(|k| {
+                let t = k as f64 * 0.1;
+                let position =
+                    na::Vector3::new(3.0 * (0.2 * t).sin(), 2.0 * (0.4 * t).sin(), 0.1 * t);
+                let heading = na::UnitQuaternion::from_euler_angles(0.0, 0.0, 0.3 * t);
+                (t, SE3::new(SO3::new(heading), position))
+            })
+            .collect();
+        Trajectory::new(poses)
+    }
+
+    #[test]
+    fn test_alignment_recovers_similarity_and_drift() {
+        let reference = figure_eight(300);
+        let offset = Similarity {
+            rotation: na::UnitQuaternion::from_euler_angles(0.1, -0.2, 1.3),
+            translation: na::Vector3::new(5.0, -2.0, 0.5),
+            scale: 0.4,
+        };
+        // Estimate in its own frame and scale, stamped 5ms late
+        let estimate = Trajectory::new(
+            reference
+                .poses
+                .iter()
+                .map(|(t, pose)| (t + 0.005, offset.transform_pose(pose)))
+                .collect(),
+        );
+        let config = EvaluationConfig { alignment: Alignment::Sim3, ..EvaluationConfig::default() };
+        let ate = absolute_trajectory_error(&estimate, &reference, &config).unwrap();
+        assert_eq!(ate.translation.count, 300);
+        assert!(ate.translation.rmse < 1e-9 && ate.rotation.max < 1e-9);
+        assert!((ate.alignment.scale * offset.scale - 1.0).abs() < 1e-9);
+
+        let rigid = EvaluationConfig { alignment: Alignment::SE3, ..config.clone() };
+        let unscaled = absolute_trajectory_error(&estimate, &reference, &rigid).unwrap();
+        assert!(unscaled.translation.rmse > 0.1);
+
+        // Relative error ignores the frame, and Sim3 removes the scale
+        let deltas =
+            [RelativeDelta::Frames(5), RelativeDelta::Distance(1.0), RelativeDelta::Time(2.0)];
+        for delta in deltas {
+            let rpe = relative_pose_error(&estimate, &reference, delta, &config).unwrap();
+            assert!(rpe.translation.max < 1e-9 && rpe.rotation.max < 1e-9, "{:?}", delta);
+        }
+
+        // A constant yaw-rate drift shows up as rotation error growing with the spacing
+        let drifting = Trajectory::new(
+            reference
+                .poses
+                .iter()
+                .map(|(t, pose)| {
+                    let drift = na::UnitQuaternion::from_euler_angles(0.0, 0.0, 0.01 * t);
+                    (*t, SE3::new(SO3::new(pose.rotation.rotation * drift), pose.translation))
+                })
+                .collect(),
+        );
+        let rpe = |seconds| {
+            let delta = RelativeDelta::Time(seconds);
+            relative_pose_error(&drifting, &reference, delta, &rigid).unwrap()
+        };
+        // Just under 10 and 50 samples, so rounding in the timestamps cannot skip one
+        let (one, five) = (rpe(0.95), rpe(4.95));
+        assert!((one.rotation.mean - 0.01).abs() < 1e-6, "{}", one.rotation.mean);
+        assert!((five.rotation.mean - 0.05).abs() < 1e-6);
+        assert!(five.translation.mean > one.translation.mean);
+    }
+
+    #[test]
+    fn test_bundled_estimates_and_graph_poses() {
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data");
+        let load = |name: &str| {
+            Trajectory::from_rows(&load_npy_rows(data.join(name)).unwrap()).unwrap()
+        };
+        let truth = load("truth_pose.npy");
+        let ndt = load("ndt_pose.npy");
+        let ate = absolute_trajectory_error(&ndt, &truth, &EvaluationConfig::default()).unwrap();
+        assert!(ate.translation.count > 1000);
+        assert!(ate.translation.rmse < 0.1, "{:?}", ate.translation);
+        let round_trip = Trajectory::from_rows(&ndt.to_rows()).unwrap().to_rows();
+        let rows = ndt.to_rows();
+        let worst = round_trip.iter().flatten().zip(rows.iter().flatten());
+        assert!(worst.map(|(a, b)| (a - b).abs()).fold(0.0, f64::max) < 1e-12);
+
+        // Planar pose-graph output keyed by millisecond timestamps
+        let poses: HashMap<NodeType, Transform2D> = (0..20)
+            .map(|k| (NodeType::RobotPose(k * 100), Transform2D::new(k as f64 * 0.5, 0.0, 0.0)))
+            .chain([(NodeType::Landmark("L0".to_string()), Transform2D::new(0.0, 0.0, 0.0))])
+            .collect();
+        let graph = Trajectory::from_graph(&poses, 1e-3);
+        assert_eq!(graph.len(), 20);
+        assert_eq!(graph.poses[3].0, 0.3);
+        let config = EvaluationConfig::default();
+        let rpe = relative_pose_error(&graph, &graph, RelativeDelta::Distance(2.0), &config);
+        assert_eq!(rpe.unwrap().translation.count, 16);
+    }
+}
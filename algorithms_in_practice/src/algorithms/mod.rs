@@ -1,4 +1,6 @@
 pub mod sliding_window;
 pub mod trees;
 pub mod graphs;
-pub mod dynamic_programming;
\ No newline at end of file
+pub mod dynamic_programming;
+pub mod vision;
+pub mod recursion;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ActivationType {
     ReLU,
     Sigmoid,
@@ -15,7 +16,22 @@ pub enum LayerType {
     Dropout { rate: f64 },
 }
 
-#[derive(Debug, Clone)]
+// Dropout rates are compared bitwise, so layers can key the evaluation memo
+impl Eq for LayerType {}
+
+impl Hash for LayerType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LayerType::Dense { units } => units.hash(state),
+            LayerType::Convolution { filters, kernel_size } => (filters, kernel_size).hash(state),
+            LayerType::Pooling { pool_size } => pool_size.hash(state),
+            LayerType::Dropout { rate } => rate.to_bits().hash(state),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Layer {
     pub layer_type: LayerType,
     pub activation: Option<ActivationType>,
@@ -77,9 +93,9 @@ impl NetworkArchitecture {
 }
 
 pub struct ArchitectureSearcher {
-    max_layers: usize,
-    min_layers: usize,
-    max_params: usize,
+    pub max_layers: usize,
+    pub min_layers: usize,
+    pub max_params: usize,
     memo: HashMap<Vec<Layer>, f64>,
     layer_options: Vec<Layer>,
 }
@@ -154,7 +170,8 @@ impl ArchitectureSearcher {
         }
 
         // Try adding each possible layer type
-        for layer in &self.layer_options {
+        let layer_options = self.layer_options.clone();
+        for layer in &layer_options {
             let mut new_layers = current_layers.clone();
             new_layers.push(layer.clone());
             
@@ -195,10 +212,8 @@ impl ArchitectureSearcher {
         for window in layers.windows(2) {
             match (&window[0].layer_type, &window[1].layer_type) {
                 (LayerType::Convolution { .. }, LayerType::Pooling { .. }) => score += 1.0,
-                (LayerType::Dense { units: u1 }, LayerType::Dense { units: u2 }) => {
-                    if u1 > u2 {
-                        score += 0.5;
-                    }
+                (LayerType::Dense { units: u1 }, LayerType::Dense { units: u2 }) if u1 > u2 => {
+                    score += 0.5
                 }
                 _ => {}
             }
@@ -13,7 +13,7 @@ pub struct WindowConfig {
 #[derive(Debug, Clone)]
 pub struct WindowResult<T> {
     pub processed_data: Vec<T>,
-    pub window_indices: Vec<Vec<usize>>,
+    pub window_indices: Vec<Vec<Vec<usize>>>,  // Windows of each dilation scale
     pub effective_dilations: Vec<usize>,
 }
 
@@ -169,8 +169,10 @@ impl TemporalWindowProcessor {
         snippet_len: usize,
         dilation: usize,
     ) -> usize {
-        let max_allowed_gap = (seq_len / snippet_len) - 1;
-        std::cmp::min(max_allowed_gap, dilation)
+        // Largest dilation whose window still fits in the sequence
+        let span = seq_len.saturating_sub(1);
+        let max_allowed_dilation = (span / snippet_len.saturating_sub(1).max(1)).max(1);
+        std::cmp::min(max_allowed_dilation, dilation)
     }
 
     /// Verify that all frames are covered by at least one window
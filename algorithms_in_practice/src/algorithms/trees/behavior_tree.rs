@@ -16,6 +16,12 @@ pub struct Blackboard {
     data: HashMap<String, f64>,
 }
 
+impl Default for Blackboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Blackboard {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,150 @@
+use nalgebra as na;
+
+/// Static k-d tree over points in N dimensions. The tree is implicit: every
+/// index range of `order` is split at its median point, along the axis on
+/// which the range spreads the most.
+#[derive(Debug, Clone)]
+pub struct KdTree<const N: usize> {
+    points: Vec<na::SVector<f64, N>>,
+    order: Vec<usize>,  // Point indices, arranged so each range's median splits it
+    axes: Vec<usize>,   // Split axis of the range whose median sits at each position
+}
+
+impl<const N: usize> KdTree<N> {
+    pub fn new(points: Vec<na::SVector<f64, N>>) -> Self {
+        let mut tree =
+            Self { order: (0..points.len()).collect(), axes: vec![0; points.len()], points };
+        tree.build(0, tree.points.len());
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn points(&self) -> &[na::SVector<f64, N>] {
+        &self.points
+    }
+
+    /// Index and squared distance of the point closest to `query`
+    pub fn nearest(&self, query: &na::SVector<f64, N>) -> Option<(usize, f64)> {
+        self.k_nearest(query, 1).pop()
+    }
+
+    /// Indices and squared distances of the `k` closest points, nearest first
+    pub fn k_nearest(&self, query: &na::SVector<f64, N>, k: usize) -> Vec<(usize, f64)> {
+        let mut best: Vec<(usize, f64)> = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search(0, self.points.len(), query, &mut |index, distance| {
+                if best.len() < k || distance < best[best.len() - 1].1 {
+                    let at = best.partition_point(|&(_, d)| d <= distance);
+                    best.insert(at, (index, distance));
+                    best.truncate(k);
+                }
+                if best.len() < k { f64::INFINITY } else { best[best.len() - 1].1 }
+            });
+        }
+        best
+    }
+
+    /// Indices and squared distances of every point within `radius`, nearest first
+    pub fn within_radius(&self, query: &na::SVector<f64, N>, radius: f64) -> Vec<(usize, f64)> {
+        let bound = radius * radius;
+        let mut found = Vec::new();
+        self.search(0, self.points.len(), query, &mut |index, distance| {
+            if distance <= bound {
+                found.push((index, distance));
+            }
+            bound
+        });
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    fn build(&mut self, lo: usize, hi: usize) {
+        if hi <= lo {
+            return;
+        }
+        let mut min = na::SVector::<f64, N>::repeat(f64::INFINITY);
+        let mut max = na::SVector::<f64, N>::repeat(f64::NEG_INFINITY);
+        for &i in &self.order[lo..hi] {
+            min = min.inf(&self.points[i]);
+            max = max.sup(&self.points[i]);
+        }
+        let axis = (max - min).imax();
+        let mid = lo + (hi - lo) / 2;
+        let points = &self.points;
+        self.order[lo..hi]
+            .select_nth_unstable_by(mid - lo, |&a, &b| points[a][axis].total_cmp(&points[b][axis]));
+        self.axes[mid] = axis;
+        self.build(lo, mid);
+        self.build(mid + 1, hi);
+    }
+
+    /// Visit the points of a range, nearest side first. `visit` records a
+    /// candidate and returns the squared distance beyond which the far side of
+    /// a split can be skipped.
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        query: &na::SVector<f64, N>,
+        visit: &mut dyn FnMut(usize, f64) -> f64,
+    ) -> f64 {
+        if hi <= lo {
+            return f64::INFINITY;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let index = self.order[mid];
+        let mut bound = visit(index, (self.points[index] - query).norm_squared());
+        let offset = query[self.axes[mid]] - self.points[index][self.axes[mid]];
+        let (near, far) =
+            if offset < 0.0 { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+        bound = bound.min(self.search(near.0, near.1, query, visit));
+        if offset * offset <= bound {
+            bound = bound.min(self.search(far.0, far.1, query, visit));
+        }
+        bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        // Deterministic scatter with duplicates and a degenerate axis
+        let points: Vec<na::Vector3<f64>> = (0..400)
+            .map(|i| {
+                let i = i as f64;
+                na::Vector3::new((i * 0.37).sin() * 5.0, ((i * 1.3) % 7.0).floor(), 2.0)
+            })
+            .collect();
+        let tree = KdTree::new(points.clone());
+        let queries = [
+            na::Vector3::new(0.1, 3.2, 2.5),
+            na::Vector3::new(-6.0, 0.0, 0.0),
+            na::Vector3::new(4.9, 6.5, 2.0),
+        ];
+        for query in queries {
+            let mut expected: Vec<f64> =
+                points.iter().map(|p| (p - query).norm_squared()).collect();
+            expected.sort_by(f64::total_cmp);
+
+            let found: Vec<f64> = tree.k_nearest(&query, 7).iter().map(|&(_, d)| d).collect();
+            assert_eq!(found, expected[..7]);
+            assert_eq!(tree.nearest(&query).unwrap().1, expected[0]);
+            let radius = expected[20].sqrt();
+            assert_eq!(
+                tree.within_radius(&query, radius).len(),
+                expected.iter().filter(|&&d| d <= radius * radius).count()
+            );
+        }
+        assert!(KdTree::<2>::new(Vec::new()).nearest(&na::Vector2::zeros()).is_none());
+    }
+}
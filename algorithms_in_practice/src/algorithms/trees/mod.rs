@@ -1,2 +1,5 @@
 mod behavior_tree;
-pub use behavior_tree::*;
\ No newline at end of file
+pub use behavior_tree::*;
+
+mod kd_tree;
+pub use kd_tree::*;
\ No newline at end of file
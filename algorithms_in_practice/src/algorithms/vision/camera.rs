@@ -0,0 +1,419 @@
+use std::sync::Arc;
+use nalgebra as na;
+
+use crate::algorithms::graphs::{FactorStatus, NodeType};
+use crate::common::lie::{skew, LieGroup, SE3};
+use crate::common::robust_kernel::RobustKernel;
+
+/// Points closer to the image plane than this are treated as behind the camera
+pub const MIN_DEPTH: f64 = 1e-6;
+
+/// Linear intrinsics K mapping normalized image coordinates to pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PinholeCamera {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+}
+
+impl PinholeCamera {
+    pub fn new(fx: f64, fy: f64, cx: f64, cy: f64) -> Self {
+        Self { fx, fy, cx, cy }
+    }
+
+    pub fn matrix(&self) -> na::Matrix3<f64> {
+        na::Matrix3::new(self.fx, 0.0, self.cx, 0.0, self.fy, self.cy, 0.0, 0.0, 1.0)
+    }
+}
+
+/// Pinhole with the radial-tangential (plumb bob) distortion of OpenCV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadTanCamera {
+    pub pinhole: PinholeCamera,
+    pub k1: f64,
+    pub k2: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl RadTanCamera {
+    pub fn new(pinhole: PinholeCamera, k1: f64, k2: f64, p1: f64, p2: f64) -> Self {
+        Self { pinhole, k1, k2, p1, p2 }
+    }
+}
+
+/// Equidistant fisheye (Kannala-Brandt, as in OpenCV's fisheye module). The
+/// distorted radius is a polynomial in the angle to the optical axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FisheyeCamera {
+    pub pinhole: PinholeCamera,
+    pub k: [f64; 4],
+}
+
+impl FisheyeCamera {
+    pub fn new(pinhole: PinholeCamera, k: [f64; 4]) -> Self {
+        Self { pinhole, k }
+    }
+}
+
+/// Camera looking down +z, x right and y down. A model is its linear
+/// intrinsics plus a distortion of normalized coordinates (x/z, y/z); the
+/// projection, its inverse and the Jacobians follow from those.
+///
+/// Poses are camera-to-world, perturbed on the right as `T exp(delta)` with
+/// delta ordered (translation, rotation) like `SE3`.
+pub trait CameraModel: std::fmt::Debug + Send + Sync {
+    fn intrinsics(&self) -> &PinholeCamera;
+
+    /// Distorted normalized coordinates and their Jacobian
+    fn distort(&self, normalized: &na::Vector2<f64>) -> (na::Vector2<f64>, na::Matrix2<f64>);
+
+    /// Inverse of `distort`, `None` where it does not converge
+    fn undistort(&self, distorted: &na::Vector2<f64>) -> Option<na::Vector2<f64>>;
+
+    /// Whether a point in the camera frame is in front of the camera
+    fn is_valid(&self, point: &na::Vector3<f64>) -> bool {
+        point.z > MIN_DEPTH && point.iter().all(|v| v.is_finite())
+    }
+
+    /// Pixel of a point in the camera frame, `None` behind the camera
+    fn project_point(&self, point: &na::Vector3<f64>) -> Option<na::Vector2<f64>> {
+        self.project_point_with_jacobian(point).map(|(pixel, _)| pixel)
+    }
+
+    /// Pixel and its Jacobian with respect to the point in the camera frame
+    fn project_point_with_jacobian(
+        &self,
+        point: &na::Vector3<f64>,
+    ) -> Option<(na::Vector2<f64>, na::Matrix2x3<f64>)> {
+        if !self.is_valid(point) {
+            return None;
+        }
+        let z = point.z;
+        let normalized = point.xy() / z;
+        let (distorted, d_distorted) = self.distort(&normalized);
+        let k = self.intrinsics();
+        let focal = na::Matrix2::new(k.fx, 0.0, 0.0, k.fy);
+        let d_normalized =
+            na::Matrix2x3::new(1.0 / z, 0.0, -normalized.x / z, 0.0, 1.0 / z, -normalized.y / z);
+        let pixel = focal * distorted + na::Vector2::new(k.cx, k.cy);
+        Some((pixel, focal * d_distorted * d_normalized))
+    }
+
+    /// Point at depth one (z = 1) in the camera frame that projects to `pixel`
+    fn unproject(&self, pixel: &na::Vector2<f64>) -> Option<na::Vector3<f64>> {
+        let k = self.intrinsics();
+        let distorted = na::Vector2::new((pixel.x - k.cx) / k.fx, (pixel.y - k.cy) / k.fy);
+        self.undistort(&distorted).map(|normalized| normalized.push(1.0))
+    }
+
+    /// Pixel of a world point seen from the camera pose `pose`
+    fn project(&self, pose: &SE3, point: &na::Vector3<f64>) -> Option<na::Vector2<f64>> {
+        self.project_point(&pose.inverse().transform_point(point))
+    }
+
+    /// Pixel and its Jacobians with respect to the camera pose and the world point
+    fn project_with_jacobians(
+        &self,
+        pose: &SE3,
+        point: &na::Vector3<f64>,
+    ) -> Option<(na::Vector2<f64>, na::Matrix2x6<f64>, na::Matrix2x3<f64>)> {
+        // p_c = R^T (p_w - t); under T exp(delta), dp_c = -d_rho + p_c x d_phi
+        let local = pose.inverse().transform_point(point);
+        let (pixel, d_local) = self.project_point_with_jacobian(&local)?;
+        let mut d_pose = na::Matrix3x6::zeros();
+        d_pose.fixed_view_mut::<3, 3>(0, 0).copy_from(&-na::Matrix3::identity());
+        d_pose.fixed_view_mut::<3, 3>(0, 3).copy_from(&skew(&local));
+        Some((pixel, d_local * d_pose, d_local * pose.rotation.matrix().transpose()))
+    }
+}
+
+impl CameraModel for PinholeCamera {
+    fn intrinsics(&self) -> &PinholeCamera {
+        self
+    }
+
+    fn distort(&self, normalized: &na::Vector2<f64>) -> (na::Vector2<f64>, na::Matrix2<f64>) {
+        (*normalized, na::Matrix2::identity())
+    }
+
+    fn undistort(&self, distorted: &na::Vector2<f64>) -> Option<na::Vector2<f64>> {
+        Some(*distorted)
+    }
+}
+
+impl CameraModel for RadTanCamera {
+    fn intrinsics(&self) -> &PinholeCamera {
+        &self.pinhole
+    }
+
+    fn distort(&self, normalized: &na::Vector2<f64>) -> (na::Vector2<f64>, na::Matrix2<f64>) {
+        let (x, y) = (normalized.x, normalized.y);
+        let (k1, k2, p1, p2) = (self.k1, self.k2, self.p1, self.p2);
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + k2 * r2);
+        let d_radial = 2.0 * (k1 + 2.0 * k2 * r2);  // d radial / d r2, times two
+        let distorted = na::Vector2::new(
+            x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+            y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+        );
+        let jacobian = na::Matrix2::new(
+            radial + d_radial * x * x + 2.0 * p1 * y + 6.0 * p2 * x,
+            d_radial * x * y + 2.0 * p1 * x + 2.0 * p2 * y,
+            d_radial * x * y + 2.0 * p1 * x + 2.0 * p2 * y,
+            radial + d_radial * y * y + 6.0 * p1 * y + 2.0 * p2 * x,
+        );
+        (distorted, jacobian)
+    }
+
+    fn undistort(&self, distorted: &na::Vector2<f64>) -> Option<na::Vector2<f64>> {
+        newton(distorted, *distorted, |x| self.distort(x))
+    }
+}
+
+impl CameraModel for FisheyeCamera {
+    fn intrinsics(&self) -> &PinholeCamera {
+        &self.pinhole
+    }
+
+    fn distort(&self, normalized: &na::Vector2<f64>) -> (na::Vector2<f64>, na::Matrix2<f64>) {
+        let r = normalized.norm();
+        if r < 1e-8 {
+            return (*normalized, na::Matrix2::identity());
+        }
+        // theta_d = theta (1 + k1 theta^2 + ... + k4 theta^8), scaled onto the
+        // ray by s = theta_d / r
+        let theta = r.atan();
+        let (theta_d, d_theta_d) = self.distort_angle(theta);
+        let scale = theta_d / r;
+        let d_scale = (d_theta_d / (1.0 + r * r) * r - theta_d) / (r * r);
+        let jacobian =
+            na::Matrix2::identity() * scale + normalized * normalized.transpose() * (d_scale / r);
+        (normalized * scale, jacobian)
+    }
+
+    fn undistort(&self, distorted: &na::Vector2<f64>) -> Option<na::Vector2<f64>> {
+        let theta_d = distorted.norm();
+        if theta_d < 1e-8 {
+            return Some(*distorted);
+        }
+        let mut theta = theta_d;
+        for _ in 0..20 {
+            let (value, derivative) = self.distort_angle(theta);
+            let step = (value - theta_d) / derivative;
+            theta -= step;
+            if step.abs() < 1e-12 {
+                break;
+            }
+        }
+        let converged = (self.distort_angle(theta).0 - theta_d).abs() < 1e-9;
+        (converged && (0.0..std::f64::consts::FRAC_PI_2).contains(&theta))
+            .then(|| distorted * (theta.tan() / theta_d))
+    }
+}
+
+impl FisheyeCamera {
+    /// Distorted angle and its derivative
+    fn distort_angle(&self, theta: f64) -> (f64, f64) {
+        let t2 = theta * theta;
+        let [k1, k2, k3, k4] = self.k;
+        let value = theta * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4))));
+        let derivative = 1.0 + t2 * (3.0 * k1 + t2 * (5.0 * k2 + t2 * (7.0 * k3 + t2 * 9.0 * k4)));
+        (value, derivative)
+    }
+}
+
+/// Solve f(x) = target by Newton's method from `x`
+fn newton<F>(target: &na::Vector2<f64>, mut x: na::Vector2<f64>, f: F) -> Option<na::Vector2<f64>>
+where
+    F: Fn(&na::Vector2<f64>) -> (na::Vector2<f64>, na::Matrix2<f64>),
+{
+    for _ in 0..20 {
+        let (value, jacobian) = f(&x);
+        let step = jacobian.try_inverse()? * (value - target);
+        x -= step;
+        if step.norm() < 1e-12 {
+            break;
+        }
+    }
+    ((f(&x).0 - target).norm() < 1e-9).then_some(x)
+}
+
+/// Pixel observation of a 3D point `Landmark` from a `RobotPose`. The pose
+/// node is the body in the world; the camera sits at `body_to_camera` on it.
+#[derive(Debug, Clone)]
+pub struct ProjectionFactor {
+    pub pose: NodeType,
+    pub landmark: NodeType,
+    pub pixel: na::Vector2<f64>,
+    pub camera: Arc<dyn CameraModel>,
+    pub body_to_camera: SE3,            // Camera pose in the body frame
+    pub information: na::Matrix2<f64>,  // Inverse covariance of the pixel, px^-2
+    pub sensor_type: String,
+    pub timestamp: u64,
+    pub kernel: Option<Arc<dyn RobustKernel>>,
+    pub status: FactorStatus,
+}
+
+impl ProjectionFactor {
+    /// Create a factor with unit information and the camera at the body origin
+    pub fn new(
+        pose: NodeType,
+        landmark: NodeType,
+        pixel: na::Vector2<f64>,
+        camera: Arc<dyn CameraModel>,
+        sensor_type: &str,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            pose,
+            landmark,
+            pixel,
+            camera,
+            body_to_camera: SE3::identity(),
+            information: na::Matrix2::identity(),
+            sensor_type: sensor_type.to_string(),
+            timestamp,
+            kernel: None,
+            status: FactorStatus::Active,
+        }
+    }
+
+    pub fn with_extrinsics(mut self, body_to_camera: SE3) -> Self {
+        self.body_to_camera = body_to_camera;
+        self
+    }
+
+    pub fn with_information(mut self, information: na::Matrix2<f64>) -> Self {
+        self.information = information;
+        self
+    }
+
+    /// Same pixel standard deviation in u and v
+    pub fn with_isotropic_std(self, sigma: f64) -> Self {
+        self.with_information(na::Matrix2::identity() / (sigma * sigma))
+    }
+
+    pub fn with_kernel<K: RobustKernel + 'static>(mut self, kernel: K) -> Self {
+        self.kernel = Some(Arc::new(kernel));
+        self
+    }
+
+    /// Predicted minus observed pixel, `None` when the point is behind the camera
+    pub fn residual(
+        &self,
+        pose: &SE3,
+        point: &na::Vector3<f64>,
+    ) -> Option<na::Vector2<f64>> {
+        let camera_pose = pose.compose(&self.body_to_camera);
+        self.camera.project(&camera_pose, point).map(|pixel| pixel - self.pixel)
+    }
+
+    /// Jacobians of the residual with respect to the body pose and the landmark
+    pub fn jacobians(
+        &self,
+        pose: &SE3,
+        point: &na::Vector3<f64>,
+    ) -> Option<(na::Matrix2x6<f64>, na::Matrix2x3<f64>)> {
+        // T exp(delta) T_bc = T T_bc exp(Ad(T_bc^-1) delta)
+        let camera_pose = pose.compose(&self.body_to_camera);
+        let (_, d_camera, d_point) = self.camera.project_with_jacobians(&camera_pose, point)?;
+        Some((d_camera * self.body_to_camera.inverse().adjoint(), d_point))
+    }
+
+    /// Squared Mahalanobis norm of the residual
+    pub fn chi2(&self, pose: &SE3, point: &na::Vector3<f64>) -> Option<f64> {
+        self.residual(pose, point).map(|r| (r.transpose() * self.information * r)[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::lie::SO3;
+
+    fn models() -> Vec<Arc<dyn CameraModel>> {
+        let pinhole = PinholeCamera::new(460.0, 455.0, 320.0, 240.0);
+        vec![
+            Arc::new(pinhole),
+            Arc::new(RadTanCamera::new(pinhole, -0.28, 0.07, 2e-4, -1.8e-5)),
+            Arc::new(FisheyeCamera::new(pinhole, [-0.01, 0.02, -0.015, 0.003])),
+        ]
+    }
+
+    #[test]
+    fn test_models_round_trip_with_matching_jacobians() {
+        let rotation = SO3::from_quaternion(0.99, 0.05, -0.1, 0.02);
+        let pose = SE3::new(rotation, na::Vector3::new(0.3, -0.2, 0.1));
+        let point = na::Vector3::new(0.8, 0.4, 3.0);
+        let eps = 1e-6;
+        for camera in models() {
+            let local = pose.inverse().transform_point(&point);
+            let pixel = camera.project(&pose, &point).unwrap();
+            let ray = camera.unproject(&pixel).unwrap();
+            assert!((ray * local.z - local).norm() < 1e-8, "{:?}", camera);
+
+            let (_, d_pose, d_point) = camera.project_with_jacobians(&pose, &point).unwrap();
+            for i in 0..6 {
+                let mut delta = na::Vector6::zeros();
+                delta[i] = eps;
+                let (plus, minus) = (SE3::exp(&delta), SE3::exp(&-delta));
+                let plus = camera.project(&pose.compose(&plus), &point).unwrap();
+                let minus = camera.project(&pose.compose(&minus), &point).unwrap();
+                let error = ((plus - minus) / (2.0 * eps) - d_pose.column(i)).norm();
+                assert!(error < 1e-3, "{:?} pose {}", camera, i);
+            }
+            for i in 0..3 {
+                let delta = na::Vector3::ith(i, eps);
+                let plus = camera.project(&pose, &(point + delta)).unwrap();
+                let minus = camera.project(&pose, &(point - delta)).unwrap();
+                let numeric = (plus - minus) / (2.0 * eps);
+                assert!((numeric - d_point.column(i)).norm() < 1e-3, "{:?} point {}", camera, i);
+            }
+
+            // Behind and on the image plane
+            assert!(camera.project_point(&na::Vector3::new(0.1, 0.2, -1.0)).is_none());
+            assert!(camera.project_point(&na::Vector3::new(0.1, 0.2, 0.0)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_projection_factor_with_extrinsics() {
+        let camera: Arc<dyn CameraModel> = models().remove(1);
+        // Camera looking forward along the body's x axis
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        let body_to_camera = SE3::new(
+            SO3::new(na::UnitQuaternion::from_euler_angles(-half_pi, 0.0, -half_pi)),
+            na::Vector3::new(0.1, 0.0, 0.2),
+        );
+        let rotation = SO3::from_quaternion(0.98, 0.0, 0.0, 0.2);
+        let pose = SE3::new(rotation, na::Vector3::new(1.0, 2.0, 0.0));
+        let landmark = na::Vector3::new(5.0, 3.5, 0.8);
+        let pixel = camera.project(&pose.compose(&body_to_camera), &landmark).unwrap();
+        let factor = ProjectionFactor::new(
+            NodeType::RobotPose(1),
+            NodeType::Landmark("L1".to_string()),
+            pixel + na::Vector2::new(1.0, -2.0),
+            camera,
+            "CAMERA",
+            1,
+        )
+        .with_extrinsics(body_to_camera)
+        .with_isotropic_std(2.0);
+        let residual = factor.residual(&pose, &landmark).unwrap();
+        assert!((residual - na::Vector2::new(-1.0, 2.0)).norm() < 1e-9);
+        assert!((factor.chi2(&pose, &landmark).unwrap() - 1.25).abs() < 1e-9);
+
+        let (d_pose, _) = factor.jacobians(&pose, &landmark).unwrap();
+        for i in 0..6 {
+            let delta = na::Vector6::ith(i, 1e-6);
+            let (plus, minus) = (SE3::exp(&delta), SE3::exp(&-delta));
+            let plus = factor.residual(&pose.compose(&plus), &landmark).unwrap();
+            let minus = factor.residual(&pose.compose(&minus), &landmark).unwrap();
+            assert!(((plus - minus) / 2e-6 - d_pose.column(i)).norm() < 1e-3, "pose {}", i);
+        }
+        // A landmark behind the body is behind the camera too
+        assert!(factor.residual(&pose, &na::Vector3::new(-3.0, 2.0, 0.0)).is_none());
+    }
+}
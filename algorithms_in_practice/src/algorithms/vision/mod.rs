@@ -0,0 +1,8 @@
+mod camera;
+pub use camera::*;
+
+mod triangulation;
+pub use triangulation::*;
+
+mod relative_pose;
+pub use relative_pose::*;
\ No newline at end of file
@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use nalgebra as na;
+
+use crate::algorithms::graphs::NodeType;
+use crate::common::lie::{skew, LieGroup, SE3, SO3};
+use crate::common::ransac::{hartley_normalization, Ransac, RansacConfig, RobustEstimator};
+
+use super::camera::MIN_DEPTH;
+use super::triangulation::triangulate_dlt;
+
+type Matrix9 = na::SMatrix<f64, 9, 9>;
+type Matrix10 = na::SMatrix<f64, 10, 10>;
+
+/// A point matched between two images: (first image, second image)
+pub type PointMatch = (na::Vector2<f64>, na::Vector2<f64>);
+
+/// Camera poses and landmark positions to start an optimization from
+pub type InitialValues = (HashMap<NodeType, SE3>, HashMap<NodeType, na::Vector3<f64>>);
+
+/// Minimal solver run on each RANSAC sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativePoseMethod {
+    EightPoint,
+    FivePoint,  // Needs fewer samples, so fewer iterations at high outlier rates
+}
+
+impl RelativePoseMethod {
+    pub fn sample_size(&self) -> usize {
+        match self {
+            RelativePoseMethod::EightPoint => 8,
+            RelativePoseMethod::FivePoint => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RelativePoseConfig {
+    pub method: RelativePoseMethod,
+    pub ransac: RansacConfig, // Threshold on the Sampson distance, in the units of the matches
+}
+
+impl Default for RelativePoseConfig {
+    fn default() -> Self {
+        Self {
+            method: RelativePoseMethod::FivePoint,
+            ransac: RansacConfig { threshold: 2e-3, ..RansacConfig::default() },
+        }
+    }
+}
+
+/// Motion between two calibrated views, with the structure it explains
+#[derive(Debug, Clone)]
+pub struct RelativePose {
+    pub transform: SE3,  // Second camera in the first camera's frame, unit baseline
+    pub essential: na::Matrix3<f64>,
+    pub inliers: Vec<bool>,
+    pub points: Vec<Option<na::Vector3<f64>>>,  // Triangulated inliers, first camera's frame
+    pub iterations: usize,
+}
+
+impl RelativePose {
+    pub fn inlier_count(&self) -> usize {
+        self.inliers.iter().filter(|&&inlier| inlier).count()
+    }
+
+    /// Initial values for a factor graph: camera poses for `first` and `second`
+    /// and positions for the triangulated `landmarks`, one per match. The
+    /// reconstruction is placed at `first_pose` and scaled to `baseline`.
+    /// `None` if the landmarks and matches do not pair up.
+    pub fn initial_values(
+        &self,
+        first: NodeType,
+        second: NodeType,
+        landmarks: &[NodeType],
+        first_pose: &SE3,
+        baseline: f64,
+    ) -> Option<InitialValues> {
+        if landmarks.len() != self.points.len() {
+            return None;
+        }
+        let relative = SE3::new(self.transform.rotation, self.transform.translation * baseline);
+        let poses =
+            HashMap::from([(first, *first_pose), (second, first_pose.compose(&relative))]);
+        let points = landmarks
+            .iter()
+            .zip(&self.points)
+            .filter_map(|(node, point)| {
+                point.map(|p| (node.clone(), first_pose.transform_point(&(p * baseline))))
+            })
+            .collect();
+        Some((poses, points))
+    }
+}
+
+/// Essential matrix from the relative pose of two cameras, with the second
+/// camera at `pose` in the first camera's frame: x2^T E x1 = 0
+pub fn essential_from_pose(pose: &SE3) -> na::Matrix3<f64> {
+    let motion = pose.inverse();
+    skew(&motion.translation) * motion.rotation.matrix()
+}
+
+/// First-order geometric distance of a match to the epipolar constraint
+pub fn sampson_distance(matrix: &na::Matrix3<f64>, matched: &PointMatch) -> f64 {
+    let (a, b) = (matched.0.push(1.0), matched.1.push(1.0));
+    let (fa, ftb) = (matrix * a, matrix.transpose() * b);
+    let denominator = fa.x * fa.x + fa.y * fa.y + ftb.x * ftb.x + ftb.y * ftb.y;
+    if denominator <= f64::MIN_POSITIVE {
+        return f64::INFINITY;
+    }
+    b.dot(&fa).abs() / denominator.sqrt()
+}
+
+/// Fundamental matrix from eight or more pixel matches, with Hartley's
+/// normalization and the rank-two constraint enforced
+pub fn fundamental_eight_point(matches: &[PointMatch]) -> Option<na::Matrix3<f64>> {
+    if matches.len() < 8 {
+        return None;
+    }
+    let first = hartley_normalization(matches.iter().map(|m| &m.0));
+    let second = hartley_normalization(matches.iter().map(|m| &m.1));
+    let normalized: Vec<PointMatch> = matches
+        .iter()
+        .map(|(a, b)| ((first * a.push(1.0)).xy(), (second * b.push(1.0)).xy()))
+        .collect();
+    let svd = epipolar_null_space(&normalized)[0].svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut singular = svd.singular_values;
+    let smallest = singular.imin();
+    singular[smallest] = 0.0;
+    let fundamental = second.transpose() * u * na::Matrix3::from_diagonal(&singular) * v_t * first;
+    Some(fundamental / fundamental.norm())
+}
+
+/// Essential matrix from eight or more matches in normalized image
+/// coordinates, projected onto the essential manifold
+pub fn essential_eight_point(matches: &[PointMatch]) -> Option<na::Matrix3<f64>> {
+    if matches.len() < 8 {
+        return None;
+    }
+    let svd = epipolar_null_space(matches)[0].svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    Some(u * na::Matrix3::from_diagonal(&na::Vector3::new(1.0, 1.0, 0.0)) * v_t)
+}
+
+/// Essential matrices consistent with five matches in normalized image
+/// coordinates, after Stewenius et al., "Recent developments on direct
+/// relative orientation". Up to ten real solutions; more matches are fitted
+/// in the least-squares sense.
+pub fn essential_five_point(matches: &[PointMatch]) -> Vec<na::Matrix3<f64>> {
+    if matches.len() < 5 {
+        return Vec::new();
+    }
+    // E = x X + y Y + z Z + W over the null space of the epipolar constraints
+    let basis = epipolar_null_space(matches);
+    let entry = |i: usize, j: usize| -> Polynomial {
+        let mut p = [0.0; MONOMIALS];
+        p[monomial(1, 0, 0)] = basis[0][(i, j)];
+        p[monomial(0, 1, 0)] = basis[1][(i, j)];
+        p[monomial(0, 0, 1)] = basis[2][(i, j)];
+        p[monomial(0, 0, 0)] = basis[3][(i, j)];
+        p
+    };
+    let e: Vec<Vec<Polynomial>> = (0..3).map(|i| (0..3).map(|j| entry(i, j)).collect()).collect();
+
+    // Ten cubic constraints: det(E) = 0 and 2 E E^T E - trace(E E^T) E = 0
+    let mut constraints = Vec::with_capacity(10);
+    let minor = |a: usize, b: usize, c: usize, d: usize| {
+        sub(&multiply(&e[1][a], &e[2][b]), &multiply(&e[1][c], &e[2][d]))
+    };
+    let det = add(
+        &sub(&multiply(&e[0][0], &minor(1, 2, 2, 1)), &multiply(&e[0][1], &minor(0, 2, 2, 0))),
+        &multiply(&e[0][2], &minor(0, 1, 1, 0)),
+    );
+    constraints.push(det);
+    let eet: Vec<Vec<Polynomial>> = (0..3)
+        .map(|i| {
+            (0..3)
+                .map(|j| {
+                    (0..3).fold([0.0; MONOMIALS], |acc, k| add(&acc, &multiply(&e[i][k], &e[j][k])))
+                })
+                .collect()
+        })
+        .collect();
+    let trace = add(&add(&eet[0][0], &eet[1][1]), &eet[2][2]);
+    constraints.extend((0..9).map(|n| {
+        let (i, j) = (n / 3, n % 3);
+        let product =
+            (0..3).fold([0.0; MONOMIALS], |acc, k| add(&acc, &multiply(&eet[i][k], &e[k][j])));
+        sub(&scale(&product, 2.0), &multiply(&trace, &e[i][j]))
+    }));
+
+    // Eliminate the cubic monomials, leaving each as a combination of the ten
+    // monomials of degree two or less, the basis of the quotient ring
+    let cubic = Matrix10::from_fn(|r, c| constraints[r][c]);
+    let rest = Matrix10::from_fn(|r, c| constraints[r][c + 10]);
+    let Some(reduced) = cubic.lu().solve(&rest) else { return Vec::new() };
+
+    // Multiplication by x on the basis (x^2, xy, xz, y^2, yz, z^2, x, y, z, 1)
+    let mut action = Matrix10::zeros();
+    for row in 0..6 {
+        action.set_row(row, &-reduced.row(row));
+    }
+    for (row, column) in [(6, 0), (7, 1), (8, 2), (9, 6)] {
+        action[(row, column)] = 1.0;
+    }
+
+    let mut solutions = Vec::new();
+    for eigenvalue in action.complex_eigenvalues().iter() {
+        if eigenvalue.im.abs() > 1e-8 * (1.0 + eigenvalue.re.abs()) {
+            continue;
+        }
+        let svd = (action - Matrix10::identity() * eigenvalue.re).svd(false, true);
+        let Some(v_t) = svd.v_t else { continue };
+        let vector = v_t.row(svd.singular_values.imin()).transpose();
+        if vector[9].abs() < 1e-12 {
+            continue;
+        }
+        let (x, y, z) = (vector[6] / vector[9], vector[7] / vector[9], vector[8] / vector[9]);
+        let essential = basis[0] * x + basis[1] * y + basis[2] * z + basis[3];
+        solutions.push(essential / essential.norm());
+    }
+    solutions
+}
+
+/// The four poses of the second camera, in the first camera's frame, that
+/// share an essential matrix. Only one puts the scene in front of both cameras.
+pub fn decompose_essential(essential: &na::Matrix3<f64>) -> Vec<SE3> {
+    let svd = essential.svd(true, true);
+    let (Some(mut u), Some(mut v_t)) = (svd.u, svd.v_t) else { return Vec::new() };
+    if u.determinant() < 0.0 {
+        u = -u;
+    }
+    if v_t.determinant() < 0.0 {
+        v_t = -v_t;
+    }
+    let w = na::Matrix3::new(0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0);
+    let translation = u.column(2).into_owned();
+    let mut poses = Vec::with_capacity(4);
+    for rotation in [u * w * v_t, u * w.transpose() * v_t] {
+        for t in [translation, -translation] {
+            // Motion x2 = R x1 + t, inverted to the second camera's pose
+            let rotation = na::UnitQuaternion::from_matrix(&rotation);
+            poses.push(SE3::new(SO3::new(rotation.inverse()), -(rotation.inverse() * t)));
+        }
+    }
+    poses
+}
+
+/// Essential matrices from matches in normalized image coordinates, scored
+/// by Sampson distance and refitted to inliers with the eight-point algorithm
+#[derive(Debug, Clone, Copy)]
+pub struct EssentialEstimator {
+    pub method: RelativePoseMethod,
+}
+
+impl RobustEstimator for EssentialEstimator {
+    type Datum = PointMatch;
+    type Model = na::Matrix3<f64>;
+
+    fn sample_size(&self) -> usize {
+        self.method.sample_size()
+    }
+
+    fn fit(&self, data: &[PointMatch]) -> Vec<na::Matrix3<f64>> {
+        match self.method {
+            RelativePoseMethod::EightPoint => essential_eight_point(data).into_iter().collect(),
+            RelativePoseMethod::FivePoint => essential_five_point(data),
+        }
+    }
+
+    fn residual(&self, model: &na::Matrix3<f64>, datum: &PointMatch) -> f64 {
+        sampson_distance(model, datum)
+    }
+
+    fn refit(&self, data: &[PointMatch]) -> Option<na::Matrix3<f64>> {
+        essential_eight_point(data)
+    }
+}
+
+/// Fundamental matrices from pixel matches by the eight-point algorithm
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FundamentalEstimator;
+
+impl RobustEstimator for FundamentalEstimator {
+    type Datum = PointMatch;
+    type Model = na::Matrix3<f64>;
+
+    fn sample_size(&self) -> usize {
+        8
+    }
+
+    fn fit(&self, data: &[PointMatch]) -> Vec<na::Matrix3<f64>> {
+        fundamental_eight_point(data).into_iter().collect()
+    }
+
+    fn residual(&self, model: &na::Matrix3<f64>, datum: &PointMatch) -> f64 {
+        sampson_distance(model, datum)
+    }
+}
+
+/// Relative pose of two calibrated views from matches in normalized image
+/// coordinates: robust estimation of the essential matrix, then the
+/// decomposition that puts the most inliers in front of both cameras.
+/// `None` with too few matches or no consistent model.
+pub fn estimate_relative_pose(
+    matches: &[PointMatch],
+    config: &RelativePoseConfig,
+) -> Option<RelativePose> {
+    let estimator = EssentialEstimator { method: config.method };
+    let result = Ransac::new(config.ransac.clone()).estimate(&estimator, matches)?;
+    let (transform, points) = decompose_essential(&result.model)
+        .into_iter()
+        .map(|pose| {
+            let points: Vec<Option<na::Vector3<f64>>> = matches
+                .iter()
+                .zip(&result.inliers)
+                .map(|(matched, &inlier)| {
+                    if inlier { triangulate_in_front(&pose, matched) } else { None }
+                })
+                .collect();
+            (pose, points)
+        })
+        .max_by_key(|(_, points)| points.iter().filter(|p| p.is_some()).count())?;
+    Some(RelativePose {
+        transform,
+        essential: result.model,
+        inliers: result.inliers,
+        points,
+        iterations: result.iterations,
+    })
+}
+
+/// Fundamental matrix and inlier mask from pixel matches of uncalibrated
+/// views, with the RANSAC threshold on the Sampson distance in pixels
+pub fn estimate_fundamental(
+    matches: &[PointMatch],
+    config: &RansacConfig,
+) -> Option<(na::Matrix3<f64>, Vec<bool>)> {
+    let result = Ransac::new(config.clone()).estimate(&FundamentalEstimator, matches)?;
+    Some((result.model, result.inliers))
+}
+
+fn triangulate_in_front(pose: &SE3, matched: &PointMatch) -> Option<na::Vector3<f64>> {
+    let point =
+        triangulate_dlt(&[(SE3::identity(), matched.0), (*pose, matched.1)])?;
+    (point.z > MIN_DEPTH && pose.inverse().transform_point(&point).z > MIN_DEPTH).then_some(point)
+}
+
+/// Basis of the matrices M with b^T M a = 0 for every match, smallest residual
+/// first. The first is the least-squares solution; with five matches the
+/// first four span the exact null space.
+fn epipolar_null_space(matches: &[PointMatch]) -> Vec<na::Matrix3<f64>> {
+    let mut normal = Matrix9::zeros();
+    for (a, b) in matches {
+        let (a, b) = (a.push(1.0), b.push(1.0));
+        let row = na::SVector::<f64, 9>::from_fn(|k, _| b[k / 3] * a[k % 3]);
+        normal += row * row.transpose();
+    }
+    let eigen = normal.symmetric_eigen();
+    let mut order: Vec<usize> = (0..9).collect();
+    order.sort_by(|&i, &j| eigen.eigenvalues[i].total_cmp(&eigen.eigenvalues[j]));
+    order
+        .iter()
+        .map(|&k| na::Matrix3::from_fn(|i, j| eigen.eigenvectors[(3 * i + j, k)]))
+        .collect()
+}
+
+/// Polynomial of degree three or less in (x, y, z), by monomial
+type Polynomial = [f64; MONOMIALS];
+
+const MONOMIALS: usize = 20;
+
+/// Exponents of (x, y, z): the ten cubics first, then the quotient ring basis
+const EXPONENTS: [[u8; 3]; MONOMIALS] = [
+    [3, 0, 0], [2, 1, 0], [2, 0, 1], [1, 2, 0], [1, 1, 1],
+    [1, 0, 2], [0, 3, 0], [0, 2, 1], [0, 1, 2], [0, 0, 3],
+    [2, 0, 0], [1, 1, 0], [1, 0, 1], [0, 2, 0], [0, 1, 1], [0, 0, 2],
+    [1, 0, 0], [0, 1, 0], [0, 0, 1], [0, 0, 0],
+];
+
+fn monomial(x: u8, y: u8, z: u8) -> usize {
+    EXPONENTS.iter().position(|&e| e == [x, y, z]).expect("degree at most three")
+}
+
+fn multiply(a: &Polynomial, b: &Polynomial) -> Polynomial {
+    let mut product = [0.0; MONOMIALS];
+    for (i, &ca) in a.iter().enumerate().filter(|(_, c)| **c != 0.0) {
+        for (j, &cb) in b.iter().enumerate().filter(|(_, c)| **c != 0.0) {
+            let (ea, eb) = (EXPONENTS[i], EXPONENTS[j]);
+            product[monomial(ea[0] + eb[0], ea[1] + eb[1], ea[2] + eb[2])] += ca * cb;
+        }
+    }
+    product
+}
+
+fn add(a: &Polynomial, b: &Polynomial) -> Polynomial {
+    std::array::from_fn(|i| a[i] + b[i])
+}
+
+fn sub(a: &Polynomial, b: &Polynomial) -> Polynomial {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+
+fn scale(a: &Polynomial, factor: f64) -> Polynomial {
+    std::array::from_fn(|i| a[i] * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Matches of a scene in front of two cameras, every fifth made an outlier
+    fn scene(pose: &SE3) -> (Vec<PointMatch>, Vec<bool>, Vec<na::Vector3<f64>>) {
+        let points: Vec<na::Vector3<f64>> = (0..100)
+            .map(|i| {
+                let i = i as f64;
+                na::Vector3::new(
+                    (i * 0.61).sin() * 2.0,
+                    (i * 1.37).cos() * 1.5,
+                    5.0 + (i * 0.29).sin() * 2.0,
+                )
+            })
+            .collect();
+        let mut matches = Vec::new();
+        let mut truth = Vec::new();
+        for (i, point) in points.iter().enumerate() {
+            let second = pose.inverse().transform_point(point);
+            let mut matched = (point.xy() / point.z, second.xy() / second.z);
+            if i % 5 == 0 {
+                // Off the epipolar line, so the outlier cannot be explained by the motion
+                let line = essential_from_pose(pose) * matched.0.push(1.0);
+                matched.1 += line.xy().normalize() * (0.03 + 0.01 * (i as f64).sin());
+            }
+            matches.push(matched);
+            truth.push(i % 5 != 0);
+        }
+        (matches, truth, points)
+    }
+
+    fn pose() -> SE3 {
+        SE3::new(
+            SO3::new(na::UnitQuaternion::from_euler_angles(0.05, -0.1, 0.03)),
+            na::Vector3::new(0.8, 0.1, 0.2),
+        )
+    }
+
+    #[test]
+    fn test_minimal_solvers_contain_true_essential() {
+        let truth = essential_from_pose(&pose());
+        let truth = truth / truth.norm();
+        let (matches, inliers, _) = scene(&pose());
+        let clean: Vec<PointMatch> =
+            matches.iter().zip(&inliers).filter(|(_, &inlier)| inlier).map(|(m, _)| *m).collect();
+        let close = |e: &na::Matrix3<f64>| (e - truth).norm().min((e + truth).norm()) < 1e-6;
+
+        let five = essential_five_point(&clean[..5]);
+        assert!(!five.is_empty() && five.len() <= 10);
+        assert!(five.iter().any(close), "{:?}", five);
+        assert!(close(&essential_eight_point(&clean[..8]).map(|e| e / e.norm()).unwrap()));
+        let (direction, rotation) = (pose().translation.normalize(), pose().rotation);
+        let recovered = |p: &SE3| {
+            (p.translation - direction).norm() < 1e-6 && p.rotation.minus(&rotation).norm() < 1e-6
+        };
+        assert!(decompose_essential(&truth).iter().any(recovered));
+
+        // Pixels of a pinhole camera share the epipolar geometry through F = K^-T E K^-1
+        let k = na::Matrix3::new(400.0, 0.0, 320.0, 0.0, 410.0, 240.0, 0.0, 0.0, 1.0);
+        let pixels: Vec<PointMatch> =
+            clean.iter().map(|(a, b)| ((k * a.push(1.0)).xy(), (k * b.push(1.0)).xy())).collect();
+        let fundamental = fundamental_eight_point(&pixels).unwrap();
+        assert!(pixels.iter().all(|m| sampson_distance(&fundamental, m) < 1e-6));
+    }
+
+    #[test]
+    fn test_ransac_recovers_pose_and_structure() {
+        let (matches, truth, points) = scene(&pose());
+        for method in [RelativePoseMethod::FivePoint, RelativePoseMethod::EightPoint] {
+            let mut config = RelativePoseConfig { method, ..RelativePoseConfig::default() };
+            config.ransac.seed = 7;
+            let result = estimate_relative_pose(&matches, &config).unwrap();
+            assert_eq!(result.inliers, truth, "{:?}", method);
+            assert!(result.transform.rotation.rotation.angle_to(&pose().rotation.rotation) < 1e-6);
+            assert!((result.transform.translation - pose().translation.normalize()).norm() < 1e-6);
+            assert!(result.iterations < config.ransac.max_iterations);
+
+            // Scaled to the true baseline, the structure matches the scene
+            let baseline = pose().translation.norm();
+            let landmarks: Vec<NodeType> =
+                (0..matches.len()).map(|i| NodeType::Landmark(format!("L{}", i))).collect();
+            let (first, second) = (NodeType::RobotPose(0), NodeType::RobotPose(1));
+            let origin = SE3::identity();
+            assert!(result
+                .initial_values(first.clone(), second.clone(), &landmarks[1..], &origin, baseline)
+                .is_none());
+            let (poses, positions) =
+                result.initial_values(first, second, &landmarks, &origin, baseline).unwrap();
+            let translation = poses[&NodeType::RobotPose(1)].translation;
+            assert!((translation - pose().translation).norm() < 1e-6);
+            assert_eq!(positions.len(), 80);
+            assert!(positions.iter().all(|(node, p)| {
+                let index = landmarks.iter().position(|l| l == node).unwrap();
+                (p - points[index]).norm() < 1e-6
+            }));
+        }
+        assert!(estimate_relative_pose(&matches[..4], &RelativePoseConfig::default()).is_none());
+    }
+}
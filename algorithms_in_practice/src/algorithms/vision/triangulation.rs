@@ -0,0 +1,184 @@
+use nalgebra as na;
+
+use crate::common::lie::{LieGroup, SE3};
+
+use super::camera::{CameraModel, MIN_DEPTH};
+
+/// Linear triangulation from two or more views. Each view is a camera pose
+/// (camera to world) and the point's normalized image coordinates (x/z, y/z).
+/// Returns `None` for fewer than two views or when the rays are degenerate.
+pub fn triangulate_dlt(views: &[(SE3, na::Vector2<f64>)]) -> Option<na::Vector3<f64>> {
+    if views.len() < 2 {
+        return None;
+    }
+    // Each view gives x P3 - P1 = 0 and y P3 - P2 = 0 with P = [R^T | -R^T t]
+    let mut normal = na::Matrix4::zeros();
+    for (pose, normalized) in views {
+        let world_to_camera = pose.inverse();
+        let mut projection = na::Matrix3x4::zeros();
+        projection.fixed_view_mut::<3, 3>(0, 0).copy_from(&world_to_camera.rotation.matrix());
+        projection.set_column(3, &world_to_camera.translation);
+        for (row, coordinate) in [(0, normalized.x), (1, normalized.y)] {
+            let equation = projection.row(2) * coordinate - projection.row(row);
+            normal += equation.transpose() * equation;
+        }
+    }
+    let eigen = normal.symmetric_eigen();
+    let homogeneous = eigen.eigenvectors.column(eigen.eigenvalues.imin()).into_owned();
+    (homogeneous.w.abs() > 1e-12).then(|| homogeneous.xyz() / homogeneous.w)
+}
+
+#[derive(Debug, Clone)]
+pub struct TriangulationConfig {
+    pub max_iterations: usize,
+    pub min_parallax: f64,            // Radians between the widest pair of rays
+    pub max_reprojection_error: f64,  // Pixels, largest over the views
+}
+
+impl Default for TriangulationConfig {
+    fn default() -> Self {
+        Self { max_iterations: 10, min_parallax: 1f64.to_radians(), max_reprojection_error: 4.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triangulation {
+    pub point: na::Vector3<f64>,
+    pub reprojection_rms: f64,  // Pixels
+    pub parallax: f64,          // Radians
+    pub iterations: usize,
+}
+
+/// Triangulate a point seen in pixels by the same camera from several poses:
+/// linear triangulation of the unprojected pixels, refined by Levenberg-Marquardt
+/// on the reprojection error. `None` when the point falls behind a camera or
+/// fails the parallax or reprojection checks of `config`.
+pub fn triangulate(
+    camera: &dyn CameraModel,
+    views: &[(SE3, na::Vector2<f64>)],
+    config: &TriangulationConfig,
+) -> Option<Triangulation> {
+    let normalized: Vec<(SE3, na::Vector2<f64>)> = views
+        .iter()
+        .map(|(pose, pixel)| camera.unproject(pixel).map(|ray| (*pose, ray.xy())))
+        .collect::<Option<_>>()?;
+    let mut point = triangulate_dlt(&normalized)?;
+    let cost = |point: &na::Vector3<f64>| -> Option<f64> {
+        views
+            .iter()
+            .map(|(pose, pixel)| camera.project(pose, point).map(|p| (p - pixel).norm_squared()))
+            .sum()
+    };
+    let mut current = cost(&point)?;
+    let mut lambda = 1e-3;
+    let mut iterations = 0;
+
+    while iterations < config.max_iterations {
+        iterations += 1;
+        let mut hessian = na::Matrix3::zeros();
+        let mut gradient = na::Vector3::zeros();
+        for (pose, pixel) in views {
+            let (projected, _, jacobian) = camera.project_with_jacobians(pose, &point)?;
+            hessian += jacobian.transpose() * jacobian;
+            gradient += jacobian.transpose() * (projected - pixel);
+        }
+        let mut damped = hessian;
+        for i in 0..3 {
+            damped[(i, i)] += lambda * hessian[(i, i)].max(1e-12);
+        }
+        let Some(step) = damped.cholesky().map(|c| -c.solve(&gradient)) else { break };
+        match cost(&(point + step)) {
+            Some(candidate) if candidate < current => {
+                point += step;
+                current = candidate;
+                lambda = (lambda / 10.0).max(1e-12);
+            }
+            _ => lambda *= 10.0,
+        }
+        if step.norm() < 1e-10 * (1.0 + point.norm()) {
+            break;
+        }
+    }
+
+    let mut max_error: f64 = 0.0;
+    for (pose, pixel) in views {
+        let local = pose.inverse().transform_point(&point);
+        if local.z <= MIN_DEPTH {
+            return None;
+        }
+        max_error = max_error.max((camera.project(pose, &point)? - pixel).norm());
+    }
+    let parallax = parallax(views.iter().map(|(pose, _)| &pose.translation), &point);
+    if parallax < config.min_parallax || max_error > config.max_reprojection_error {
+        return None;
+    }
+    let reprojection_rms = (current / views.len() as f64).sqrt();
+    Some(Triangulation { point, reprojection_rms, parallax, iterations })
+}
+
+/// Widest angle at `point` between rays to the camera centres
+fn parallax<'a>(
+    centers: impl Iterator<Item = &'a na::Vector3<f64>>,
+    point: &na::Vector3<f64>,
+) -> f64 {
+    let rays: Vec<na::Vector3<f64>> = centers.map(|center| (center - point).normalize()).collect();
+    let mut widest: f64 = 0.0;
+    for (i, a) in rays.iter().enumerate() {
+        for b in &rays[i + 1..] {
+            widest = widest.max(a.dot(b).clamp(-1.0, 1.0).acos());
+        }
+    }
+    widest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::vision::{PinholeCamera, RadTanCamera};
+    use crate::common::lie::SO3;
+
+    #[test]
+    fn test_triangulates_from_noisy_views() {
+        let pinhole = PinholeCamera::new(400.0, 400.0, 320.0, 240.0);
+        let camera = RadTanCamera::new(pinhole, -0.2, 0.05, 1e-3, -5e-4);
+        let point = na::Vector3::new(0.5, -0.3, 6.0);
+        let poses: Vec<SE3> = (0..4)
+            .map(|i| {
+                let i = i as f64;
+                SE3::new(
+                    SO3::new(na::UnitQuaternion::from_euler_angles(0.0, 0.03 * i, 0.0)),
+                    na::Vector3::new(i * 0.4, 0.1 * i, 0.0),
+                )
+            })
+            .collect();
+        // Up to half a pixel of deterministic noise
+        let views: Vec<(SE3, na::Vector2<f64>)> = poses
+            .iter()
+            .enumerate()
+            .map(|(i, pose)| {
+                let noise = na::Vector2::new((i as f64 * 1.7).sin(), (i as f64 * 2.3).cos()) * 0.5;
+                (*pose, camera.project(pose, &point).unwrap() + noise)
+            })
+            .collect();
+
+        let result = triangulate(&camera, &views, &TriangulationConfig::default()).unwrap();
+        assert!((result.point - point).norm() < 0.05, "{:?}", result);
+        assert!(result.reprojection_rms < 0.5);
+        assert!(result.parallax > 8f64.to_radians());
+
+        let exact: Vec<(SE3, na::Vector2<f64>)> = poses
+            .iter()
+            .map(|pose| {
+                let local = pose.inverse().transform_point(&point);
+                (*pose, local.xy() / local.z)
+            })
+            .collect();
+        assert!((triangulate_dlt(&exact).unwrap() - point).norm() < 1e-9);
+        assert!(triangulate_dlt(&exact[..1]).is_none());
+
+        // Too little baseline for a point this far away
+        let min_parallax = 30f64.to_radians();
+        let config = TriangulationConfig { min_parallax, ..TriangulationConfig::default() };
+        assert!(triangulate(&camera, &views, &config).is_none());
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use nalgebra as na;
+
+/// Camera of Snavely's Bundler, as used by the BAL datasets. A world point X
+/// maps to P = R X + t; the camera looks down -z, so the image point is
+/// p = -P.xy / P.z, distorted radially and scaled by the focal length:
+/// u = f (1 + k1 |p|^2 + k2 |p|^4) p.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnavelyCamera {
+    pub rotation: na::Vector3<f64>,     // Angle-axis, world to camera
+    pub translation: na::Vector3<f64>,
+    pub focal: f64,                     // Pixels
+    pub k1: f64,
+    pub k2: f64,
+}
+
+impl SnavelyCamera {
+    /// From the nine values BAL stores per camera: rotation, translation, f, k1, k2
+    pub fn from_parameters(parameters: &[f64; 9]) -> Self {
+        Self {
+            rotation: na::Vector3::new(parameters[0], parameters[1], parameters[2]),
+            translation: na::Vector3::new(parameters[3], parameters[4], parameters[5]),
+            focal: parameters[6],
+            k1: parameters[7],
+            k2: parameters[8],
+        }
+    }
+
+    pub fn parameters(&self) -> [f64; 9] {
+        let (r, t) = (&self.rotation, &self.translation);
+        [r.x, r.y, r.z, t.x, t.y, t.z, self.focal, self.k1, self.k2]
+    }
+
+    pub fn rotation_matrix(&self) -> na::Matrix3<f64> {
+        na::Rotation3::new(self.rotation).into_inner()
+    }
+
+    /// Position of the camera centre in the world
+    pub fn center(&self) -> na::Vector3<f64> {
+        -(self.rotation_matrix().transpose() * self.translation)
+    }
+
+    pub fn project(&self, point: &na::Vector3<f64>) -> na::Vector2<f64> {
+        let camera = self.rotation_matrix() * point + self.translation;
+        let p = -camera.xy() / camera.z;
+        let r2 = p.norm_squared();
+        p * (self.focal * (1.0 + r2 * (self.k1 + self.k2 * r2)))
+    }
+}
+
+#[derive(Debug)]
+pub enum BalError {
+    Io(io::Error),
+    UnexpectedEnd { expected: usize, found: usize },  // Numbers in the file
+    InvalidNumber { line: usize, token: String },
+    IndexOutOfRange { line: usize, camera: usize, point: usize },
+}
+
+impl fmt::Display for BalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BalError::Io(err) => write!(f, "I/O error: {}", err),
+            BalError::UnexpectedEnd { expected, found } => {
+                write!(f, "expected {} numbers, file ends after {}", expected, found)
+            }
+            BalError::InvalidNumber { line, token } => {
+                write!(f, "line {}: cannot parse '{}' as a number", line, token)
+            }
+            BalError::IndexOutOfRange { line, camera, point } => {
+                write!(
+                    f,
+                    "line {}: observation of point {} by camera {} is out of range",
+                    line, point, camera
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BalError {}
+
+impl From<io::Error> for BalError {
+    fn from(err: io::Error) -> Self {
+        BalError::Io(err)
+    }
+}
+
+/// A point seen by a camera, in pixels relative to the image centre
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalObservation {
+    pub camera: usize,
+    pub point: usize,
+    pub pixel: na::Vector2<f64>,
+}
+
+/// Cameras, points and observations of a "Bundle Adjustment in the Large" problem
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BalProblem {
+    pub cameras: Vec<SnavelyCamera>,
+    pub points: Vec<na::Vector3<f64>>,
+    pub observations: Vec<BalObservation>,
+}
+
+impl BalProblem {
+    /// Predicted minus observed pixel of every observation
+    pub fn residuals(&self) -> Vec<na::Vector2<f64>> {
+        self.observations
+            .iter()
+            .map(|obs| self.cameras[obs.camera].project(&self.points[obs.point]) - obs.pixel)
+            .collect()
+    }
+
+    /// Root-mean-square reprojection error in pixels
+    pub fn reprojection_rms(&self) -> f64 {
+        if self.observations.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.residuals().iter().map(|r| r.norm_squared()).sum();
+        (sum / self.observations.len() as f64).sqrt()
+    }
+}
+
+/// Read a BAL problem: a header line `cameras points observations`, one
+/// `camera point u v` line per observation, then nine parameters per camera
+/// and three coordinates per point
+pub fn read_bal<R: BufRead>(reader: R) -> Result<BalProblem, BalError> {
+    let mut tokens = Tokens::new(reader)?;
+    let cameras = tokens.index()?.1;
+    let points = tokens.index()?.1;
+    let observations = tokens.index()?.1;
+    tokens.expected = 3 + 4 * observations + 9 * cameras + 3 * points;
+
+    let mut problem = BalProblem::default();
+    for _ in 0..observations {
+        let (line, camera) = tokens.index()?;
+        let (_, point) = tokens.index()?;
+        if camera >= cameras || point >= points {
+            return Err(BalError::IndexOutOfRange { line, camera, point });
+        }
+        let pixel = na::Vector2::new(tokens.number()?, tokens.number()?);
+        problem.observations.push(BalObservation { camera, point, pixel });
+    }
+    for _ in 0..cameras {
+        let mut parameters = [0.0; 9];
+        for value in parameters.iter_mut() {
+            *value = tokens.number()?;
+        }
+        problem.cameras.push(SnavelyCamera::from_parameters(&parameters));
+    }
+    for _ in 0..points {
+        problem.points.push(na::Vector3::new(tokens.number()?, tokens.number()?, tokens.number()?));
+    }
+    Ok(problem)
+}
+
+/// Write a problem in the layout `read_bal` expects
+pub fn write_bal<W: Write>(mut writer: W, problem: &BalProblem) -> Result<(), BalError> {
+    writeln!(
+        writer,
+        "{} {} {}",
+        problem.cameras.len(),
+        problem.points.len(),
+        problem.observations.len()
+    )?;
+    for obs in &problem.observations {
+        writeln!(writer, "{} {} {:e} {:e}", obs.camera, obs.point, obs.pixel.x, obs.pixel.y)?;
+    }
+    for camera in &problem.cameras {
+        for value in camera.parameters() {
+            writeln!(writer, "{:e}", value)?;
+        }
+    }
+    for point in &problem.points {
+        for value in point.iter() {
+            writeln!(writer, "{:e}", value)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_bal<P: AsRef<Path>>(path: P) -> Result<BalProblem, BalError> {
+    read_bal(BufReader::new(File::open(path)?))
+}
+
+pub fn save_bal<P: AsRef<Path>>(path: P, problem: &BalProblem) -> Result<(), BalError> {
+    write_bal(BufWriter::new(File::create(path)?), problem)
+}
+
+/// Whitespace-separated numbers with the line each came from
+struct Tokens {
+    tokens: std::vec::IntoIter<(usize, String)>,
+    taken: usize,
+    expected: usize,  // Numbers the header promises, for error messages
+}
+
+impl Tokens {
+    fn new<R: BufRead>(reader: R) -> Result<Self, BalError> {
+        let mut tokens = Vec::new();
+        for (line_no, line) in reader.lines().enumerate() {
+            tokens.extend(line?.split_whitespace().map(|token| (line_no + 1, token.to_string())));
+        }
+        Ok(Self { tokens: tokens.into_iter(), taken: 0, expected: 3 })
+    }
+
+    fn next(&mut self) -> Result<(usize, String), BalError> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or(BalError::UnexpectedEnd { expected: self.expected, found: self.taken })?;
+        self.taken += 1;
+        Ok(token)
+    }
+
+    fn number(&mut self) -> Result<f64, BalError> {
+        let (line, token) = self.next()?;
+        token.parse().map_err(|_| BalError::InvalidNumber { line, token })
+    }
+
+    fn index(&mut self) -> Result<(usize, usize), BalError> {
+        let (line, token) = self.next()?;
+        let index = token.parse().map_err(|_| BalError::InvalidNumber { line, token })?;
+        Ok((line, index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_bundled_problem() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/ba/problem-49-7776-pre.txt");
+        let problem = load_bal(path).unwrap();
+        assert_eq!(
+            (problem.cameras.len(), problem.points.len(), problem.observations.len()),
+            (49, 7776, 31843)
+        );
+        assert_eq!(problem.observations[1].pixel, na::Vector2::new(-1.9976e2, 1.667e2));
+        let rms = problem.reprojection_rms();
+        assert!(rms > 1.0 && rms < 100.0, "{}", rms);
+
+        let mut bytes = Vec::new();
+        write_bal(&mut bytes, &problem).unwrap();
+        assert_eq!(read_bal(bytes.as_slice()).unwrap(), problem);
+        bytes.truncate(bytes.len() / 2);
+        assert!(matches!(read_bal(bytes.as_slice()), Err(BalError::UnexpectedEnd { .. })));
+        assert!(matches!(
+            read_bal("1 1 1\n0 2 1.0 2.0\n".as_bytes()),
+            Err(BalError::IndexOutOfRange { line: 2, camera: 0, point: 2 })
+        ));
+    }
+}
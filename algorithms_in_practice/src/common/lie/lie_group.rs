@@ -0,0 +1,167 @@
+use nalgebra as na;
+
+/// Operations shared by the matrix Lie groups used for rotations and rigid
+/// transforms. `DOF` is the dimension of the tangent space.
+///
+/// Conventions follow Sola et al., "A micro Lie theory for state estimation
+/// in robotics":
+/// - `exp(tau + d) ~= exp(left_jacobian(tau) d) * exp(tau)`
+/// - `exp(tau + d) ~= exp(tau) * exp(right_jacobian(tau) d)`
+/// - `X * exp(d) * X^-1 = exp(adjoint(X) d)`
+pub trait LieGroup<const DOF: usize>: Sized + Clone {
+    fn identity() -> Self;
+    fn compose(&self, other: &Self) -> Self;
+    fn inverse(&self) -> Self;
+
+    /// Map a tangent vector to the group
+    fn exp(tangent: &na::SVector<f64, DOF>) -> Self;
+
+    /// Map a group element to its tangent vector
+    fn log(&self) -> na::SVector<f64, DOF>;
+
+    fn adjoint(&self) -> na::SMatrix<f64, DOF, DOF>;
+
+    fn left_jacobian(tangent: &na::SVector<f64, DOF>) -> na::SMatrix<f64, DOF, DOF>;
+
+    fn right_jacobian(tangent: &na::SVector<f64, DOF>) -> na::SMatrix<f64, DOF, DOF> {
+        Self::left_jacobian(&(-tangent))
+    }
+
+    fn left_jacobian_inverse(tangent: &na::SVector<f64, DOF>) -> na::SMatrix<f64, DOF, DOF> {
+        Self::left_jacobian(tangent)
+            .try_inverse()
+            .unwrap_or_else(na::SMatrix::identity)
+    }
+
+    fn right_jacobian_inverse(tangent: &na::SVector<f64, DOF>) -> na::SMatrix<f64, DOF, DOF> {
+        Self::left_jacobian_inverse(&(-tangent))
+    }
+
+    /// Right perturbation: `self * exp(delta)`
+    fn plus(&self, delta: &na::SVector<f64, DOF>) -> Self {
+        self.compose(&Self::exp(delta))
+    }
+
+    /// Right difference: `log(other^-1 * self)`
+    fn minus(&self, other: &Self) -> na::SVector<f64, DOF> {
+        other.inverse().compose(self).log()
+    }
+}
+
+/// Skew-symmetric matrix such that `skew(a) * b == a.cross(b)`
+pub fn skew(v: &na::Vector3<f64>) -> na::Matrix3<f64> {
+    na::Matrix3::new(
+        0.0, -v[2], v[1],
+        v[2], 0.0, -v[0],
+        -v[1], v[0], 0.0,
+    )
+}
+
+/// Inverse of `skew`
+pub fn unskew(m: &na::Matrix3<f64>) -> na::Vector3<f64> {
+    na::Vector3::new(m[(2, 1)], m[(0, 2)], m[(1, 0)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::lie::{SE2, SE3, SO2, SO3};
+
+    const DELTA: f64 = 1e-6;
+    const TOLERANCE: f64 = 1e-5;
+
+    /// Central-difference derivative of a tangent-valued function of a tangent vector
+    fn numerical_jacobian<const D: usize, F>(f: F) -> na::SMatrix<f64, D, D>
+    where
+        F: Fn(&na::SVector<f64, D>) -> na::SVector<f64, D>,
+    {
+        let mut jacobian = na::SMatrix::<f64, D, D>::zeros();
+        for k in 0..D {
+            let mut d = na::SVector::<f64, D>::zeros();
+            d[k] = DELTA;
+            let column = (f(&d) - f(&(-d))) / (2.0 * DELTA);
+            jacobian.set_column(k, &column);
+        }
+        jacobian
+    }
+
+    fn assert_close<const D: usize>(
+        name: &str,
+        a: &na::SMatrix<f64, D, D>,
+        b: &na::SMatrix<f64, D, D>,
+    ) {
+        let diff = (a - b).abs().max();
+        assert!(diff < TOLERANCE, "{} mismatch ({}):\n{}\nvs\n{}", name, diff, a, b);
+    }
+
+    fn check_group<G: LieGroup<D>, const D: usize>(tau: na::SVector<f64, D>) {
+        let x = G::exp(&tau);
+
+        let round_trip = x.log();
+        assert!((round_trip - tau).norm() < 1e-9, "exp/log round trip");
+
+        let identity = x.compose(&x.inverse()).log();
+        assert!(identity.norm() < 1e-9, "x * x^-1 != identity");
+
+        let adjoint = numerical_jacobian(|d| x.compose(&G::exp(d)).compose(&x.inverse()).log());
+        assert_close("adjoint", &adjoint, &x.adjoint());
+
+        let right = numerical_jacobian(|d| x.inverse().compose(&G::exp(&(tau + d))).log());
+        assert_close("right jacobian", &right, &G::right_jacobian(&tau));
+
+        let left = numerical_jacobian(|d| G::exp(&(tau + d)).compose(&x.inverse()).log());
+        assert_close("left jacobian", &left, &G::left_jacobian(&tau));
+
+        let right_inv = numerical_jacobian(|d| x.compose(&G::exp(d)).log() - tau);
+        assert_close("right jacobian inverse", &right_inv, &G::right_jacobian_inverse(&tau));
+
+        let left_inv = numerical_jacobian(|d| G::exp(d).compose(&x).log() - tau);
+        assert_close("left jacobian inverse", &left_inv, &G::left_jacobian_inverse(&tau));
+
+        let delta = tau * 0.1;
+        let moved = x.plus(&delta);
+        assert!((moved.minus(&x) - delta).norm() < 1e-9, "plus/minus round trip");
+    }
+
+    #[test]
+    fn test_so2() {
+        check_group::<SO2, 1>(na::Vector1::new(0.7));
+        check_group::<SO2, 1>(na::Vector1::new(-2.9));
+    }
+
+    #[test]
+    fn test_se2() {
+        check_group::<SE2, 3>(na::Vector3::new(0.4, -1.3, 0.9));
+        check_group::<SE2, 3>(na::Vector3::new(1.2, 0.5, -2.5));
+        check_group::<SE2, 3>(na::Vector3::new(0.3, 0.2, 1e-10));
+    }
+
+    #[test]
+    fn test_so3() {
+        check_group::<SO3, 3>(na::Vector3::new(0.3, -0.8, 0.5));
+        check_group::<SO3, 3>(na::Vector3::new(-1.5, 1.2, 1.1));
+        check_group::<SO3, 3>(na::Vector3::new(1e-10, 0.0, -1e-10));
+
+        // Half a turn, where exp/log wrap around
+        for angle in [std::f64::consts::PI, std::f64::consts::PI - 1e-9] {
+            let tau = na::Vector3::new(2.0, -1.0, 2.0) * angle / 3.0;
+            let product = SO3::left_jacobian_inverse(&tau) * SO3::left_jacobian(&tau);
+            assert!((product - na::Matrix3::identity()).norm() < 1e-9, "{}", product);
+        }
+    }
+
+    #[test]
+    fn test_se3() {
+        check_group::<SE3, 6>(na::Vector6::new(0.5, -0.2, 1.1, 0.3, -0.8, 0.5));
+        check_group::<SE3, 6>(na::Vector6::new(-1.0, 2.0, 0.4, -1.5, 1.2, 1.1));
+        check_group::<SE3, 6>(na::Vector6::new(0.3, 0.1, -0.2, 1e-10, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_skew() {
+        let a = na::Vector3::new(1.0, -2.0, 3.0);
+        let b = na::Vector3::new(0.5, 4.0, -1.0);
+        assert!((skew(&a) * b - a.cross(&b)).norm() < 1e-12);
+        assert_eq!(unskew(&skew(&a)), a);
+    }
+}
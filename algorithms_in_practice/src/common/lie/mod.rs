@@ -0,0 +1,14 @@
+mod lie_group;
+pub use lie_group::*;
+
+mod so2;
+pub use so2::*;
+
+mod se2;
+pub use se2::*;
+
+mod so3;
+pub use so3::*;
+
+mod se3;
+pub use se3::*;
\ No newline at end of file
@@ -0,0 +1,121 @@
+use nalgebra as na;
+
+use super::lie_group::LieGroup;
+use super::so2::SO2;
+
+/// Planar rigid transform. Tangent vectors are ordered (rho_x, rho_y, theta).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SE2 {
+    pub rotation: SO2,
+    pub translation: na::Vector2<f64>,
+}
+
+impl SE2 {
+    pub fn new(x: f64, y: f64, theta: f64) -> Self {
+        Self {
+            rotation: SO2::new(theta),
+            translation: na::Vector2::new(x, y),
+        }
+    }
+
+    pub fn angle(&self) -> f64 {
+        self.rotation.angle()
+    }
+
+    /// Homogeneous 3x3 matrix
+    pub fn matrix(&self) -> na::Matrix3<f64> {
+        let mut m = na::Matrix3::identity();
+        m.fixed_view_mut::<2, 2>(0, 0).copy_from(&self.rotation.matrix());
+        m.fixed_view_mut::<2, 1>(0, 2).copy_from(&self.translation);
+        m
+    }
+
+    pub fn transform_point(&self, point: &na::Vector2<f64>) -> na::Vector2<f64> {
+        self.translation + self.rotation.rotate(point)
+    }
+
+    /// V(theta), mapping rho to the translation: t = V(theta) rho
+    fn v_matrix(theta: f64) -> na::Matrix2<f64> {
+        let (a, b, _, _) = Self::series(theta);
+        na::Matrix2::new(a, -b, b, a)
+    }
+
+    /// sin(t)/t, (1 - cos t)/t, (t - sin t)/t^2 and (1 - cos t)/t^2, with Taylor
+    /// expansions near zero where the closed forms lose precision
+    fn series(theta: f64) -> (f64, f64, f64, f64) {
+        let theta2 = theta * theta;
+        if theta.abs() < 1e-3 {
+            (
+                1.0 - theta2 / 6.0,
+                theta * (0.5 - theta2 / 24.0),
+                theta * (1.0 / 6.0 - theta2 / 120.0),
+                0.5 - theta2 / 24.0,
+            )
+        } else {
+            let (s, c) = theta.sin_cos();
+            (s / theta, (1.0 - c) / theta, (theta - s) / theta2, (1.0 - c) / theta2)
+        }
+    }
+}
+
+impl LieGroup<3> for SE2 {
+    fn identity() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+
+    fn compose(&self, other: &Self) -> Self {
+        Self {
+            rotation: self.rotation.compose(&other.rotation),
+            translation: self.translation + self.rotation.rotate(&other.translation),
+        }
+    }
+
+    fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        Self {
+            translation: -rotation.rotate(&self.translation),
+            rotation,
+        }
+    }
+
+    fn exp(tangent: &na::Vector3<f64>) -> Self {
+        let rho = na::Vector2::new(tangent[0], tangent[1]);
+        Self {
+            rotation: SO2::new(tangent[2]),
+            translation: Self::v_matrix(tangent[2]) * rho,
+        }
+    }
+
+    fn log(&self) -> na::Vector3<f64> {
+        let theta = self.angle();
+        let half = 0.5 * theta;
+        // V^-1 = [[a, half], [-half, a]] with a = (theta / 2) cot(theta / 2)
+        let a = if theta.abs() < 1e-9 {
+            1.0 - theta * theta / 12.0
+        } else {
+            half / half.tan()
+        };
+        let t = &self.translation;
+        na::Vector3::new(a * t[0] + half * t[1], -half * t[0] + a * t[1], theta)
+    }
+
+    fn adjoint(&self) -> na::Matrix3<f64> {
+        let r = self.rotation.matrix();
+        let t = &self.translation;
+        na::Matrix3::new(
+            r[(0, 0)], r[(0, 1)], t[1],
+            r[(1, 0)], r[(1, 1)], -t[0],
+            0.0, 0.0, 1.0,
+        )
+    }
+
+    fn left_jacobian(tangent: &na::Vector3<f64>) -> na::Matrix3<f64> {
+        let (rho1, rho2, theta) = (tangent[0], tangent[1], tangent[2]);
+        let (a, b, c, d) = Self::series(theta);
+        na::Matrix3::new(
+            a, -b, rho1 * c + rho2 * d,
+            b, a, -rho1 * d + rho2 * c,
+            0.0, 0.0, 1.0,
+        )
+    }
+}
@@ -0,0 +1,134 @@
+use nalgebra as na;
+
+use super::lie_group::{skew, LieGroup};
+use super::so3::SO3;
+
+/// 3D rigid transform. Tangent vectors are ordered (rho, phi): translational
+/// part first, rotation vector second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SE3 {
+    pub rotation: SO3,
+    pub translation: na::Vector3<f64>,
+}
+
+impl SE3 {
+    pub fn new(rotation: SO3, translation: na::Vector3<f64>) -> Self {
+        Self { rotation, translation }
+    }
+
+    /// Homogeneous 4x4 matrix
+    pub fn matrix(&self) -> na::Matrix4<f64> {
+        let mut m = na::Matrix4::identity();
+        m.fixed_view_mut::<3, 3>(0, 0).copy_from(&self.rotation.matrix());
+        m.fixed_view_mut::<3, 1>(0, 3).copy_from(&self.translation);
+        m
+    }
+
+    pub fn transform_point(&self, point: &na::Vector3<f64>) -> na::Vector3<f64> {
+        self.translation + self.rotation.rotate(point)
+    }
+
+    /// Off-diagonal block Q(rho, phi) of the SE3 left Jacobian (Barfoot, eq. 7.86b)
+    fn q_matrix(rho: &na::Vector3<f64>, phi: &na::Vector3<f64>) -> na::Matrix3<f64> {
+        let theta2 = phi.norm_squared();
+        // b and c cancel badly for small angles, so switch to their series early
+        let (a, b, c) = if theta2 < 1e-2 {
+            let theta4 = theta2 * theta2;
+            (
+                1.0 / 6.0 - theta2 / 120.0 + theta4 / 5040.0,
+                1.0 / 24.0 - theta2 / 720.0 + theta4 / 40320.0,
+                1.0 / 120.0 - theta2 / 2520.0 + theta4 / 120960.0,
+            )
+        } else {
+            let theta = theta2.sqrt();
+            let (s, co) = theta.sin_cos();
+            let theta4 = theta2 * theta2;
+            let a = (theta - s) / (theta2 * theta);
+            let b = (theta2 / 2.0 + co - 1.0) / theta4;
+            let c = 0.5 * (b + 3.0 * (theta - s - theta2 * theta / 6.0) / (theta4 * theta));
+            (a, b, c)
+        };
+
+        let p = skew(phi);
+        let r = skew(rho);
+        let pr = p * r;
+        let rp = r * p;
+        let prp = pr * p;
+
+        0.5 * r
+            + a * (pr + rp + prp)
+            + b * (p * pr + rp * p - 3.0 * prp)
+            + c * (prp * p + p * prp)
+    }
+}
+
+impl LieGroup<6> for SE3 {
+    fn identity() -> Self {
+        Self::new(SO3::identity(), na::Vector3::zeros())
+    }
+
+    fn compose(&self, other: &Self) -> Self {
+        Self {
+            rotation: self.rotation.compose(&other.rotation),
+            translation: self.translation + self.rotation.rotate(&other.translation),
+        }
+    }
+
+    fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        Self {
+            translation: -rotation.rotate(&self.translation),
+            rotation,
+        }
+    }
+
+    fn exp(tangent: &na::Vector6<f64>) -> Self {
+        let rho = tangent.fixed_rows::<3>(0).into_owned();
+        let phi = tangent.fixed_rows::<3>(3).into_owned();
+        Self {
+            rotation: SO3::exp(&phi),
+            translation: SO3::left_jacobian(&phi) * rho,
+        }
+    }
+
+    fn log(&self) -> na::Vector6<f64> {
+        let phi = self.rotation.log();
+        let rho = SO3::left_jacobian_inverse(&phi) * self.translation;
+        na::Vector6::new(rho[0], rho[1], rho[2], phi[0], phi[1], phi[2])
+    }
+
+    fn adjoint(&self) -> na::Matrix6<f64> {
+        let r = self.rotation.matrix();
+        let mut adjoint = na::Matrix6::zeros();
+        adjoint.fixed_view_mut::<3, 3>(0, 0).copy_from(&r);
+        adjoint.fixed_view_mut::<3, 3>(0, 3).copy_from(&(skew(&self.translation) * r));
+        adjoint.fixed_view_mut::<3, 3>(3, 3).copy_from(&r);
+        adjoint
+    }
+
+    fn left_jacobian(tangent: &na::Vector6<f64>) -> na::Matrix6<f64> {
+        let rho = tangent.fixed_rows::<3>(0).into_owned();
+        let phi = tangent.fixed_rows::<3>(3).into_owned();
+        let jl = SO3::left_jacobian(&phi);
+
+        let mut jacobian = na::Matrix6::zeros();
+        jacobian.fixed_view_mut::<3, 3>(0, 0).copy_from(&jl);
+        jacobian.fixed_view_mut::<3, 3>(0, 3).copy_from(&Self::q_matrix(&rho, &phi));
+        jacobian.fixed_view_mut::<3, 3>(3, 3).copy_from(&jl);
+        jacobian
+    }
+
+    fn left_jacobian_inverse(tangent: &na::Vector6<f64>) -> na::Matrix6<f64> {
+        let rho = tangent.fixed_rows::<3>(0).into_owned();
+        let phi = tangent.fixed_rows::<3>(3).into_owned();
+        let jl_inv = SO3::left_jacobian_inverse(&phi);
+
+        let mut jacobian = na::Matrix6::zeros();
+        jacobian.fixed_view_mut::<3, 3>(0, 0).copy_from(&jl_inv);
+        jacobian
+            .fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&(-jl_inv * Self::q_matrix(&rho, &phi) * jl_inv));
+        jacobian.fixed_view_mut::<3, 3>(3, 3).copy_from(&jl_inv);
+        jacobian
+    }
+}
@@ -0,0 +1,64 @@
+use nalgebra as na;
+
+use super::lie_group::LieGroup;
+
+/// Planar rotation, stored as a unit complex number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SO2 {
+    pub rotation: na::UnitComplex<f64>,
+}
+
+impl SO2 {
+    pub fn new(angle: f64) -> Self {
+        Self {
+            rotation: na::UnitComplex::new(angle),
+        }
+    }
+
+    /// Rotation angle in (-pi, pi]
+    pub fn angle(&self) -> f64 {
+        self.rotation.angle()
+    }
+
+    pub fn matrix(&self) -> na::Matrix2<f64> {
+        self.rotation.to_rotation_matrix().into_inner()
+    }
+
+    pub fn rotate(&self, point: &na::Vector2<f64>) -> na::Vector2<f64> {
+        self.rotation * point
+    }
+}
+
+impl LieGroup<1> for SO2 {
+    fn identity() -> Self {
+        Self::new(0.0)
+    }
+
+    fn compose(&self, other: &Self) -> Self {
+        Self {
+            rotation: self.rotation * other.rotation,
+        }
+    }
+
+    fn inverse(&self) -> Self {
+        Self {
+            rotation: self.rotation.inverse(),
+        }
+    }
+
+    fn exp(tangent: &na::Vector1<f64>) -> Self {
+        Self::new(tangent[0])
+    }
+
+    fn log(&self) -> na::Vector1<f64> {
+        na::Vector1::new(self.angle())
+    }
+
+    fn adjoint(&self) -> na::Matrix1<f64> {
+        na::Matrix1::identity()
+    }
+
+    fn left_jacobian(_tangent: &na::Vector1<f64>) -> na::Matrix1<f64> {
+        na::Matrix1::identity()
+    }
+}
@@ -0,0 +1,92 @@
+use nalgebra as na;
+
+use super::lie_group::{skew, LieGroup};
+
+/// 3D rotation, stored as a unit quaternion. Tangent vectors are rotation vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SO3 {
+    pub rotation: na::UnitQuaternion<f64>,
+}
+
+impl SO3 {
+    pub fn new(rotation: na::UnitQuaternion<f64>) -> Self {
+        Self { rotation }
+    }
+
+    /// Build from quaternion components, normalizing them
+    pub fn from_quaternion(qw: f64, qx: f64, qy: f64, qz: f64) -> Self {
+        Self {
+            rotation: na::UnitQuaternion::from_quaternion(na::Quaternion::new(qw, qx, qy, qz)),
+        }
+    }
+
+    pub fn from_matrix(matrix: &na::Matrix3<f64>) -> Self {
+        Self {
+            rotation: na::UnitQuaternion::from_matrix(matrix),
+        }
+    }
+
+    pub fn matrix(&self) -> na::Matrix3<f64> {
+        self.rotation.to_rotation_matrix().into_inner()
+    }
+
+    pub fn rotate(&self, point: &na::Vector3<f64>) -> na::Vector3<f64> {
+        self.rotation * point
+    }
+}
+
+impl LieGroup<3> for SO3 {
+    fn identity() -> Self {
+        Self::new(na::UnitQuaternion::identity())
+    }
+
+    fn compose(&self, other: &Self) -> Self {
+        Self::new(self.rotation * other.rotation)
+    }
+
+    fn inverse(&self) -> Self {
+        Self::new(self.rotation.inverse())
+    }
+
+    fn exp(tangent: &na::Vector3<f64>) -> Self {
+        Self::new(na::UnitQuaternion::from_scaled_axis(*tangent))
+    }
+
+    fn log(&self) -> na::Vector3<f64> {
+        self.rotation.scaled_axis()
+    }
+
+    fn adjoint(&self) -> na::Matrix3<f64> {
+        self.matrix()
+    }
+
+    /// I + (1 - cos t)/t^2 K + (t - sin t)/t^3 K^2, with K = skew(phi)
+    fn left_jacobian(tangent: &na::Vector3<f64>) -> na::Matrix3<f64> {
+        let theta2 = tangent.norm_squared();
+        let k = skew(tangent);
+        let (a, b) = if theta2 < 1e-6 {
+            (
+                0.5 - theta2 / 24.0 + theta2 * theta2 / 720.0,
+                1.0 / 6.0 - theta2 / 120.0 + theta2 * theta2 / 5040.0,
+            )
+        } else {
+            let theta = theta2.sqrt();
+            ((1.0 - theta.cos()) / theta2, (theta - theta.sin()) / (theta2 * theta))
+        };
+        na::Matrix3::identity() + a * k + b * k * k
+    }
+
+    /// I - K/2 + (1/t^2 - cot(t/2)/(2t)) K^2
+    fn left_jacobian_inverse(tangent: &na::Vector3<f64>) -> na::Matrix3<f64> {
+        let theta2 = tangent.norm_squared();
+        let k = skew(tangent);
+        let c = if theta2 < 1e-6 {
+            1.0 / 12.0 + theta2 / 720.0 + theta2 * theta2 / 30240.0
+        } else {
+            // cot(t/2) rather than (1 + cos t)/sin t, which is 0/0 at t = pi
+            let theta = theta2.sqrt();
+            1.0 / theta2 - 1.0 / (2.0 * theta * (0.5 * theta).tan())
+        };
+        na::Matrix3::identity() - 0.5 * k + c * k * k
+    }
+}
@@ -1,2 +1,8 @@
-pub mod types;
-pub mod utils;
\ No newline at end of file
+pub mod bal_io;
+pub mod lie;
+pub mod npy;
+pub mod pcd;
+pub mod ransac;
+pub mod robust_kernel;
+pub mod slam_io;
+pub mod statistics;
@@ -0,0 +1,566 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+const ALIGNMENT: usize = 64;  // numpy pads the header so the data starts on this boundary
+
+#[derive(Debug)]
+pub enum NpyError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8, u8),
+    InvalidHeader(String),
+    UnsupportedDtype(String),
+    TypeMismatch { expected: Dtype, found: Dtype },
+    ShapeMismatch { shape: Vec<usize>, elements: usize },
+}
+
+impl fmt::Display for NpyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NpyError::Io(err) => write!(f, "I/O error: {}", err),
+            NpyError::BadMagic => write!(f, "not an NPY file"),
+            NpyError::UnsupportedVersion(major, minor) => {
+                write!(f, "unsupported NPY version {}.{}", major, minor)
+            }
+            NpyError::InvalidHeader(reason) => write!(f, "invalid NPY header: {}", reason),
+            NpyError::UnsupportedDtype(descr) => write!(f, "unsupported dtype '{}'", descr),
+            NpyError::TypeMismatch { expected, found } => write!(
+                f,
+                "array holds '{}' but '{}' was requested",
+                found.descr(),
+                expected.descr()
+            ),
+            NpyError::ShapeMismatch { shape, elements } => {
+                write!(f, "shape {:?} does not hold {} elements", shape, elements)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NpyError {}
+
+impl From<io::Error> for NpyError {
+    fn from(err: io::Error) -> Self {
+        NpyError::Io(err)
+    }
+}
+
+/// Kind of a scalar element, without its byte order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarKind {
+    Bool,
+    Int,
+    Uint,
+    Float,
+}
+
+/// Element type of an array, as given by the header's `descr` (e.g. `<f8`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dtype {
+    pub kind: ScalarKind,
+    pub size: usize,          // Bytes per element
+    pub big_endian: bool,
+}
+
+impl Dtype {
+    /// Parse a simple numpy type string; structured and string dtypes are not supported
+    pub fn parse(descr: &str) -> Result<Self, NpyError> {
+        let unsupported = || NpyError::UnsupportedDtype(descr.to_string());
+        let mut chars = descr.chars();
+        let big_endian = match chars.next() {
+            Some('<') | Some('|') | Some('=') => false,
+            Some('>') => true,
+            _ => return Err(unsupported()),
+        };
+        let kind = match chars.next() {
+            Some('b') => ScalarKind::Bool,
+            Some('i') => ScalarKind::Int,
+            Some('u') => ScalarKind::Uint,
+            Some('f') => ScalarKind::Float,
+            _ => return Err(unsupported()),
+        };
+        let size: usize = chars.as_str().parse().map_err(|_| unsupported())?;
+        let valid = match kind {
+            ScalarKind::Bool => size == 1,
+            ScalarKind::Int | ScalarKind::Uint => matches!(size, 1 | 2 | 4 | 8),
+            ScalarKind::Float => matches!(size, 4 | 8),
+        };
+        if !valid {
+            return Err(unsupported());
+        }
+        Ok(Self { kind, size, big_endian: big_endian && size > 1 })
+    }
+
+    /// Type string in numpy's notation
+    pub fn descr(&self) -> String {
+        let order = if self.size == 1 {
+            '|'
+        } else if self.big_endian {
+            '>'
+        } else {
+            '<'
+        };
+        let kind = match self.kind {
+            ScalarKind::Bool => 'b',
+            ScalarKind::Int => 'i',
+            ScalarKind::Uint => 'u',
+            ScalarKind::Float => 'f',
+        };
+        format!("{}{}{}", order, kind, self.size)
+    }
+
+    /// Same type, ignoring byte order
+    fn same_type(&self, other: &Dtype) -> bool {
+        self.kind == other.kind && self.size == other.size
+    }
+}
+
+/// Scalar types that can be stored in an NPY file
+pub trait NpyElement: Copy + Sized {
+    const KIND: ScalarKind;
+    const SIZE: usize;
+
+    fn from_le_slice(bytes: &[u8]) -> Self;
+    fn write_le(&self, out: &mut Vec<u8>);
+    fn to_f64(self) -> f64;
+
+    fn dtype() -> Dtype {
+        Dtype { kind: Self::KIND, size: Self::SIZE, big_endian: false }
+    }
+}
+
+macro_rules! npy_number {
+    ($t:ty, $kind:expr) => {
+        impl NpyElement for $t {
+            const KIND: ScalarKind = $kind;
+            const SIZE: usize = std::mem::size_of::<$t>();
+
+            fn from_le_slice(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn write_le(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+npy_number!(i8, ScalarKind::Int);
+npy_number!(i16, ScalarKind::Int);
+npy_number!(i32, ScalarKind::Int);
+npy_number!(i64, ScalarKind::Int);
+npy_number!(u8, ScalarKind::Uint);
+npy_number!(u16, ScalarKind::Uint);
+npy_number!(u32, ScalarKind::Uint);
+npy_number!(u64, ScalarKind::Uint);
+npy_number!(f32, ScalarKind::Float);
+npy_number!(f64, ScalarKind::Float);
+
+impl NpyElement for bool {
+    const KIND: ScalarKind = ScalarKind::Bool;
+    const SIZE: usize = 1;
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn to_f64(self) -> f64 {
+        if self { 1.0 } else { 0.0 }
+    }
+}
+
+/// Header fields of an NPY file
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyHeader {
+    pub dtype: Dtype,
+    pub fortran_order: bool,  // Column-major data
+    pub shape: Vec<usize>,    // Empty for a scalar
+}
+
+impl NpyHeader {
+    pub fn element_count(&self) -> usize {
+        self.shape.iter().product()
+    }
+}
+
+/// N-dimensional array with its elements in file order
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyArray<T> {
+    pub shape: Vec<usize>,
+    pub fortran_order: bool,
+    pub data: Vec<T>,
+}
+
+impl<T: NpyElement> NpyArray<T> {
+    /// Wrap row-major data, checking that it fills the shape
+    pub fn new(shape: Vec<usize>, data: Vec<T>) -> Result<Self, NpyError> {
+        if shape.iter().product::<usize>() != data.len() {
+            return Err(NpyError::ShapeMismatch { shape, elements: data.len() });
+        }
+        Ok(Self { shape, fortran_order: false, data })
+    }
+
+    /// Two-dimensional array from equally long rows
+    pub fn from_rows(rows: &[Vec<T>]) -> Result<Self, NpyError> {
+        let columns = rows.first().map_or(0, Vec::len);
+        let data: Vec<T> = rows.iter().flatten().copied().collect();
+        if rows.iter().any(|row| row.len() != columns) {
+            // A ragged input can still hold rows x columns elements in total
+            return Err(NpyError::ShapeMismatch {
+                shape: vec![rows.len(), columns],
+                elements: data.len(),
+            });
+        }
+        Self::new(vec![rows.len(), columns], data)
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Element at a multi-dimensional index, whatever the memory order
+    pub fn get(&self, index: &[usize]) -> Option<T> {
+        if index.len() != self.shape.len() || index.iter().zip(&self.shape).any(|(i, n)| i >= n) {
+            return None;
+        }
+        let mut offset = 0;
+        if self.fortran_order {
+            for (i, n) in index.iter().zip(&self.shape).rev() {
+                offset = offset * n + i;
+            }
+        } else {
+            for (i, n) in index.iter().zip(&self.shape) {
+                offset = offset * n + i;
+            }
+        }
+        self.data.get(offset).copied()
+    }
+
+    /// Rows of a two-dimensional array; a one-dimensional array is one column
+    pub fn rows(&self) -> Vec<Vec<T>> {
+        let (rows, columns) = match self.shape[..] {
+            [n] => (n, 1),
+            [n, m] => (n, m),
+            _ => return Vec::new(),
+        };
+        let index = |r: usize, c: usize| {
+            if self.fortran_order { c * rows + r } else { r * columns + c }
+        };
+        (0..rows)
+            .map(|r| (0..columns).map(|c| self.data[index(r, c)]).collect())
+            .collect()
+    }
+
+    /// Convert every element to `f64`
+    pub fn to_f64(&self) -> NpyArray<f64> {
+        NpyArray {
+            shape: self.shape.clone(),
+            fortran_order: self.fortran_order,
+            data: self.data.iter().map(|x| x.to_f64()).collect(),
+        }
+    }
+}
+
+/// Read the magic string, version and header
+pub fn read_npy_header<R: Read>(reader: &mut R) -> Result<NpyHeader, NpyError> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(NpyError::BadMagic);
+    }
+    let header_len = match (preamble[6], preamble[7]) {
+        (1, 0) => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        (2, 0) | (3, 0) => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        (major, minor) => return Err(NpyError::UnsupportedVersion(major, minor)),
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let text = String::from_utf8(header)
+        .map_err(|_| NpyError::InvalidHeader("header is not text".to_string()))?;
+    parse_header(&text)
+}
+
+/// Read an array whose elements are exactly `T`, in either byte order
+pub fn read_npy<T: NpyElement, R: Read>(mut reader: R) -> Result<NpyArray<T>, NpyError> {
+    let header = read_npy_header(&mut reader)?;
+    if !header.dtype.same_type(&T::dtype()) {
+        return Err(NpyError::TypeMismatch { expected: T::dtype(), found: header.dtype });
+    }
+    let data = read_elements::<T, _>(&mut reader, &header)?;
+    Ok(NpyArray { shape: header.shape, fortran_order: header.fortran_order, data })
+}
+
+/// Read an array of any supported dtype, converting the elements to `f64`
+pub fn read_npy_f64<R: Read>(mut reader: R) -> Result<NpyArray<f64>, NpyError> {
+    let header = read_npy_header(&mut reader)?;
+    let data = match (header.dtype.kind, header.dtype.size) {
+        (ScalarKind::Bool, _) => convert::<bool, _>(&mut reader, &header)?,
+        (ScalarKind::Int, 1) => convert::<i8, _>(&mut reader, &header)?,
+        (ScalarKind::Int, 2) => convert::<i16, _>(&mut reader, &header)?,
+        (ScalarKind::Int, 4) => convert::<i32, _>(&mut reader, &header)?,
+        (ScalarKind::Int, _) => convert::<i64, _>(&mut reader, &header)?,
+        (ScalarKind::Uint, 1) => convert::<u8, _>(&mut reader, &header)?,
+        (ScalarKind::Uint, 2) => convert::<u16, _>(&mut reader, &header)?,
+        (ScalarKind::Uint, 4) => convert::<u32, _>(&mut reader, &header)?,
+        (ScalarKind::Uint, _) => convert::<u64, _>(&mut reader, &header)?,
+        (ScalarKind::Float, 4) => convert::<f32, _>(&mut reader, &header)?,
+        (ScalarKind::Float, _) => read_elements::<f64, _>(&mut reader, &header)?,
+    };
+    Ok(NpyArray { shape: header.shape, fortran_order: header.fortran_order, data })
+}
+
+/// Write an array as little-endian data, using version 1.0 unless the header needs more room
+pub fn write_npy<T: NpyElement, W: Write>(
+    mut writer: W,
+    array: &NpyArray<T>,
+) -> Result<(), NpyError> {
+    if array.shape.iter().product::<usize>() != array.data.len() {
+        return Err(NpyError::ShapeMismatch {
+            shape: array.shape.clone(),
+            elements: array.data.len(),
+        });
+    }
+    let shape = match array.shape[..] {
+        [n] => format!("({},)", n),
+        _ => {
+            let dims: Vec<String> = array.shape.iter().map(usize::to_string).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let dict = format!(
+        "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+        T::dtype().descr(),
+        if array.fortran_order { "True" } else { "False" },
+        shape
+    );
+
+    // Magic, version and length field, then the padded header ending in a newline
+    let padded = |length_bytes: usize| {
+        let unpadded = MAGIC.len() + 2 + length_bytes + dict.len() + 1;
+        dict.len() + (ALIGNMENT - unpadded % ALIGNMENT) % ALIGNMENT + 1
+    };
+    let (version, header_len) = match padded(2) {
+        len if len <= u16::MAX as usize => (1, len),
+        _ => (2, padded(4)),
+    };
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[version, 0])?;
+    if version == 1 {
+        writer.write_all(&(header_len as u16).to_le_bytes())?;
+    } else {
+        writer.write_all(&(header_len as u32).to_le_bytes())?;
+    }
+    writer.write_all(dict.as_bytes())?;
+    writer.write_all(&vec![b' '; header_len - dict.len() - 1])?;
+    writer.write_all(b"\n")?;
+
+    let mut bytes = Vec::with_capacity(array.data.len() * T::SIZE);
+    for value in &array.data {
+        value.write_le(&mut bytes);
+    }
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Load an array of exactly `T` from disk
+pub fn load_npy<T: NpyElement, P: AsRef<Path>>(path: P) -> Result<NpyArray<T>, NpyError> {
+    read_npy(BufReader::new(File::open(path)?))
+}
+
+/// Load any numeric array from disk as `f64`
+pub fn load_npy_f64<P: AsRef<Path>>(path: P) -> Result<NpyArray<f64>, NpyError> {
+    read_npy_f64(BufReader::new(File::open(path)?))
+}
+
+/// Load a one- or two-dimensional numeric array as rows of `f64`
+pub fn load_npy_rows<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<f64>>, NpyError> {
+    let array = load_npy_f64(path)?;
+    if array.ndim() == 0 || array.ndim() > 2 {
+        return Err(NpyError::InvalidHeader(format!(
+            "expected one or two dimensions, found shape {:?}",
+            array.shape
+        )));
+    }
+    Ok(array.rows())
+}
+
+/// Save an array to disk
+pub fn save_npy<T: NpyElement, P: AsRef<Path>>(
+    path: P,
+    array: &NpyArray<T>,
+) -> Result<(), NpyError> {
+    write_npy(BufWriter::new(File::create(path)?), array)
+}
+
+/// Save equally long rows of `f64` as a two-dimensional array
+pub fn save_npy_rows<P: AsRef<Path>>(path: P, rows: &[Vec<f64>]) -> Result<(), NpyError> {
+    save_npy(path, &NpyArray::from_rows(rows)?)
+}
+
+fn read_elements<T: NpyElement, R: Read>(
+    reader: &mut R,
+    header: &NpyHeader,
+) -> Result<Vec<T>, NpyError> {
+    let count = header.element_count();
+    let mut bytes = vec![0u8; count * T::SIZE];
+    reader.read_exact(&mut bytes)?;
+    if header.dtype.big_endian {
+        bytes.chunks_exact_mut(T::SIZE).for_each(|chunk| chunk.reverse());
+    }
+    Ok(bytes.chunks_exact(T::SIZE).map(T::from_le_slice).collect())
+}
+
+fn convert<T: NpyElement, R: Read>(
+    reader: &mut R,
+    header: &NpyHeader,
+) -> Result<Vec<f64>, NpyError> {
+    Ok(read_elements::<T, R>(reader, header)?.into_iter().map(T::to_f64).collect())
+}
+
+/// Parse the Python dict literal of the header, e.g.
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }`
+fn parse_header(text: &str) -> Result<NpyHeader, NpyError> {
+    let invalid = |reason: &str| NpyError::InvalidHeader(format!("{} in {}", reason, text.trim()));
+    let body = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or_else(|| invalid("missing braces"))?;
+
+    let (mut descr, mut fortran_order, mut shape) = (None, None, None);
+    let mut rest = body.trim_start();
+    while !rest.is_empty() {
+        let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"');
+        let quote = quote.ok_or_else(|| invalid("expected a quoted key"))?;
+        let end = rest[1..].find(quote).ok_or_else(|| invalid("unterminated key"))? + 1;
+        let key = &rest[1..end];
+        rest = rest[end + 1..].trim_start();
+        rest = rest.strip_prefix(':').ok_or_else(|| invalid("expected ':'"))?.trim_start();
+
+        // Values are a quoted string, a bare word or a parenthesised tuple
+        let value_end = match rest.chars().next() {
+            Some(q @ ('\'' | '"')) => rest[1..].find(q).map(|i| i + 2),
+            Some('(') => rest.find(')').map(|i| i + 1),
+            Some(_) => Some(rest.find(',').unwrap_or(rest.len())),
+            None => None,
+        }
+        .ok_or_else(|| invalid("unterminated value"))?;
+        let value = rest[..value_end].trim();
+        match key {
+            "descr" => descr = Some(Dtype::parse(value.trim_matches(|c| c == '\'' || c == '"'))?),
+            "fortran_order" => {
+                fortran_order = Some(match value {
+                    "True" => true,
+                    "False" => false,
+                    _ => return Err(invalid("fortran_order must be True or False")),
+                })
+            }
+            "shape" => {
+                let inner = value.trim_start_matches('(').trim_end_matches(')');
+                let dims: Result<Vec<usize>, _> = inner
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|dim| !dim.is_empty())
+                    .map(|dim| dim.trim_end_matches('L').parse())
+                    .collect();
+                shape = Some(dims.map_err(|_| invalid("shape must be a tuple of integers"))?);
+            }
+            _ => return Err(invalid("unexpected key")),
+        }
+        rest = rest[value_end..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    Ok(NpyHeader {
+        dtype: descr.ok_or_else(|| invalid("missing descr"))?,
+        fortran_order: fortran_order.ok_or_else(|| invalid("missing fortran_order"))?,
+        shape: shape.ok_or_else(|| invalid("missing shape"))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_path(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../data").join(name)
+    }
+
+    #[test]
+    fn test_load_bundled_datasets() {
+        let imu = load_npy::<f64, _>(data_path("imu_data.npy")).unwrap();
+        assert_eq!(imu.shape, vec![10720, 7]);
+        assert!(!imu.fortran_order);
+
+        let truth = load_npy_rows(data_path("truth_pose.npy")).unwrap();
+        assert_eq!(truth.len(), 21436);
+        assert!(truth.iter().all(|row| row.len() == 8));
+        // Timestamps come first and increase
+        assert!(truth.windows(2).all(|pair| pair[1][0] >= pair[0][0]));
+
+        assert!(matches!(
+            load_npy::<f32, _>(data_path("vins_pose.npy")),
+            Err(NpyError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_and_foreign_layouts() {
+        let rows = vec![vec![1.5, -2.0, 3.25], vec![0.0, 1e-9, f64::MAX]];
+        let array = NpyArray::from_rows(&rows).unwrap();
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &array).unwrap();
+        assert_eq!(bytes.len() % ALIGNMENT, 6 * 8 % ALIGNMENT);
+        assert_eq!(read_npy::<f64, _>(bytes.as_slice()).unwrap().rows(), rows);
+        assert!(matches!(
+            NpyArray::from_rows(&[vec![1, 2], vec![3], vec![4, 5, 6]]),
+            Err(NpyError::ShapeMismatch { elements: 6, .. })
+        ));
+        assert!(NpyArray::<u8>::from_rows(&[]).unwrap().data.is_empty());
+
+        // Big-endian int16 in column-major order, as written by numpy for a transposed array
+        let mut file = Vec::new();
+        let dict = b"{'descr': '>i2', 'fortran_order': True, 'shape': (2, 3), }";
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&[2, 0]);
+        file.extend_from_slice(&(dict.len() as u32 + 1).to_le_bytes());
+        file.extend_from_slice(dict);
+        file.push(b'\n');
+        for value in [1i16, 4, 2, 5, 3, -6] {
+            file.extend_from_slice(&value.to_be_bytes());
+        }
+        let parsed = read_npy::<i16, _>(file.as_slice()).unwrap();
+        assert_eq!(parsed.rows(), vec![vec![1, 2, 3], vec![4, 5, -6]]);
+        assert_eq!(parsed.get(&[1, 2]), Some(-6));
+        assert_eq!(read_npy_f64(file.as_slice()).unwrap().rows()[1], vec![4.0, 5.0, -6.0]);
+
+        // One-dimensional shapes keep their trailing comma
+        let flags = NpyArray::new(vec![3], vec![true, false, true]).unwrap();
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &flags).unwrap();
+        assert!(String::from_utf8_lossy(&bytes).contains("'shape': (3,)"));
+        assert_eq!(read_npy::<bool, _>(bytes.as_slice()).unwrap(), flags);
+    }
+}
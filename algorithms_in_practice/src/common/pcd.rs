@@ -0,0 +1,735 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use nalgebra as na;
+
+#[derive(Debug)]
+pub enum PcdError {
+    Io(io::Error),
+    InvalidHeader { line: usize, reason: String },
+    UnsupportedType { kind: char, size: usize },
+    InvalidNumber { point: usize, token: String },
+    Truncated { expected: usize, found: usize },  // Bytes of point data
+    Decompression(String),
+    FieldLength { name: String, values: usize, points: usize },
+}
+
+impl fmt::Display for PcdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PcdError::Io(err) => write!(f, "I/O error: {}", err),
+            PcdError::InvalidHeader { line, reason } => write!(f, "line {}: {}", line, reason),
+            PcdError::UnsupportedType { kind, size } => {
+                write!(f, "unsupported field type {} of size {}", kind, size)
+            }
+            PcdError::InvalidNumber { point, token } => {
+                write!(f, "point {}: cannot parse '{}' as a number", point, token)
+            }
+            PcdError::Truncated { expected, found } => {
+                write!(f, "expected {} bytes of point data, found {}", expected, found)
+            }
+            PcdError::Decompression(reason) => write!(f, "corrupt compressed data: {}", reason),
+            PcdError::FieldLength { name, values, points } => write!(
+                f,
+                "field '{}' has {} values, not a multiple of {} points",
+                name, values, points
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PcdError {}
+
+impl From<io::Error> for PcdError {
+    fn from(err: io::Error) -> Self {
+        PcdError::Io(err)
+    }
+}
+
+/// Storage type of one field, from the header's TYPE and SIZE entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcdType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl PcdType {
+    pub fn from_header(kind: char, size: usize) -> Result<Self, PcdError> {
+        Ok(match (kind, size) {
+            ('I', 1) => PcdType::I8,
+            ('I', 2) => PcdType::I16,
+            ('I', 4) => PcdType::I32,
+            ('I', 8) => PcdType::I64,
+            ('U', 1) => PcdType::U8,
+            ('U', 2) => PcdType::U16,
+            ('U', 4) => PcdType::U32,
+            ('U', 8) => PcdType::U64,
+            ('F', 4) => PcdType::F32,
+            ('F', 8) => PcdType::F64,
+            _ => return Err(PcdError::UnsupportedType { kind, size }),
+        })
+    }
+
+    /// (TYPE, SIZE) header entries
+    pub fn header(&self) -> (char, usize) {
+        match self {
+            PcdType::I8 => ('I', 1),
+            PcdType::I16 => ('I', 2),
+            PcdType::I32 => ('I', 4),
+            PcdType::I64 => ('I', 8),
+            PcdType::U8 => ('U', 1),
+            PcdType::U16 => ('U', 2),
+            PcdType::U32 => ('U', 4),
+            PcdType::U64 => ('U', 8),
+            PcdType::F32 => ('F', 4),
+            PcdType::F64 => ('F', 8),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.header().1
+    }
+}
+
+/// Layout of the point data after the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcdFormat {
+    Ascii,
+    Binary,            // Point after point, little-endian
+    BinaryCompressed,  // Field after field, LZF-compressed
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcdField {
+    pub name: String,
+    pub kind: PcdType,
+    pub count: usize,  // Values per point
+}
+
+/// Values of one field for every point, `count` values per point
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldData {
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+/// Scalar types a field can be stored as
+pub trait PcdScalar: Copy + fmt::Display + std::str::FromStr {
+    const KIND: PcdType;
+
+    fn slice(data: &FieldData) -> Option<&[Self]>;
+    fn wrap(values: Vec<Self>) -> FieldData;
+    fn from_le_slice(bytes: &[u8]) -> Self;
+    fn write_le(&self, out: &mut Vec<u8>);
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! pcd_scalar {
+    ($t:ty, $kind:ident) => {
+        impl PcdScalar for $t {
+            const KIND: PcdType = PcdType::$kind;
+
+            fn slice(data: &FieldData) -> Option<&[Self]> {
+                match data {
+                    FieldData::$kind(values) => Some(values),
+                    _ => None,
+                }
+            }
+
+            fn wrap(values: Vec<Self>) -> FieldData {
+                FieldData::$kind(values)
+            }
+
+            fn from_le_slice(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            fn write_le(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    };
+}
+
+pcd_scalar!(i8, I8);
+pcd_scalar!(i16, I16);
+pcd_scalar!(i32, I32);
+pcd_scalar!(i64, I64);
+pcd_scalar!(u8, U8);
+pcd_scalar!(u16, U16);
+pcd_scalar!(u32, U32);
+pcd_scalar!(u64, U64);
+pcd_scalar!(f32, F32);
+pcd_scalar!(f64, F64);
+
+/// Run `$body` with `$values` bound to the typed vector inside `$data`
+macro_rules! with_values {
+    ($data:expr, $values:ident => $body:expr) => {
+        match $data {
+            FieldData::I8($values) => $body,
+            FieldData::I16($values) => $body,
+            FieldData::I32($values) => $body,
+            FieldData::I64($values) => $body,
+            FieldData::U8($values) => $body,
+            FieldData::U16($values) => $body,
+            FieldData::U32($values) => $body,
+            FieldData::U64($values) => $body,
+            FieldData::F32($values) => $body,
+            FieldData::F64($values) => $body,
+        }
+    };
+}
+
+impl FieldData {
+    fn with_capacity(kind: PcdType, capacity: usize) -> Self {
+        match kind {
+            PcdType::I8 => FieldData::I8(Vec::with_capacity(capacity)),
+            PcdType::I16 => FieldData::I16(Vec::with_capacity(capacity)),
+            PcdType::I32 => FieldData::I32(Vec::with_capacity(capacity)),
+            PcdType::I64 => FieldData::I64(Vec::with_capacity(capacity)),
+            PcdType::U8 => FieldData::U8(Vec::with_capacity(capacity)),
+            PcdType::U16 => FieldData::U16(Vec::with_capacity(capacity)),
+            PcdType::U32 => FieldData::U32(Vec::with_capacity(capacity)),
+            PcdType::U64 => FieldData::U64(Vec::with_capacity(capacity)),
+            PcdType::F32 => FieldData::F32(Vec::with_capacity(capacity)),
+            PcdType::F64 => FieldData::F64(Vec::with_capacity(capacity)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        with_values!(self, values => values.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_f64(&self) -> Vec<f64> {
+        with_values!(self, values => values.iter().map(|v| v.to_f64()).collect())
+    }
+
+    fn push_le(&mut self, bytes: &[u8]) {
+        with_values!(self, values => values.push(PcdScalar::from_le_slice(bytes)))
+    }
+
+    fn push_text(&mut self, token: &str) -> bool {
+        with_values!(self, values => token.parse().map(|v| values.push(v)).is_ok())
+    }
+
+    fn write_le(&self, index: usize, out: &mut Vec<u8>) {
+        with_values!(self, values => values[index].write_le(out))
+    }
+
+    fn write_text(&self, index: usize) -> String {
+        with_values!(self, values => values[index].to_string())
+    }
+}
+
+/// Point cloud with named, typed fields stored column by column
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCloud {
+    pub width: usize,
+    pub height: usize,             // 1 for unorganized clouds
+    pub viewpoint: [f64; 7],       // Sensor pose (tx, ty, tz, qw, qx, qy, qz)
+    fields: Vec<PcdField>,
+    columns: Vec<FieldData>,
+}
+
+impl PointCloud {
+    /// Unorganized cloud of `len` points without any fields yet
+    pub fn new(len: usize) -> Self {
+        Self {
+            width: len,
+            height: 1,
+            viewpoint: [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            fields: Vec::new(),
+            columns: Vec::new(),
+        }
+    }
+
+    /// Unorganized cloud with single-precision x, y and z fields
+    pub fn from_positions(points: &[na::Vector3<f64>]) -> Self {
+        let axis = |k: usize| points.iter().map(|p| p[k] as f32).collect::<Vec<f32>>();
+        let cloud = Self::new(points.len());
+        ["x", "y", "z"]
+            .iter()
+            .enumerate()
+            .fold(cloud, |cloud, (k, name)| cloud.with_field(name, axis(k)).unwrap())
+    }
+
+    /// Add or replace a field; its values are `count` per point, point after point
+    pub fn with_field<T: PcdScalar>(mut self, name: &str, values: Vec<T>) -> Result<Self, PcdError> {
+        let points = self.len();
+        if points == 0 || values.is_empty() || !values.len().is_multiple_of(points) {
+            return Err(PcdError::FieldLength {
+                name: name.to_string(),
+                values: values.len(),
+                points,
+            });
+        }
+        let field = PcdField { name: name.to_string(), kind: T::KIND, count: values.len() / points };
+        match self.fields.iter().position(|f| f.name == name) {
+            Some(k) => {
+                self.fields[k] = field;
+                self.columns[k] = T::wrap(values);
+            }
+            None => {
+                self.fields.push(field);
+                self.columns.push(T::wrap(values));
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn fields(&self) -> &[PcdField] {
+        &self.fields
+    }
+
+    /// Values of a field stored as exactly `T`
+    pub fn field<T: PcdScalar>(&self, name: &str) -> Option<&[T]> {
+        let k = self.fields.iter().position(|f| f.name == name)?;
+        T::slice(&self.columns[k])
+    }
+
+    /// Values of a field of any type, widened to `f64`
+    pub fn field_f64(&self, name: &str) -> Option<Vec<f64>> {
+        let k = self.fields.iter().position(|f| f.name == name)?;
+        Some(self.columns[k].to_f64())
+    }
+
+    /// The x, y and z fields as points
+    pub fn positions(&self) -> Option<Vec<na::Vector3<f64>>> {
+        let (x, y, z) = (self.field_f64("x")?, self.field_f64("y")?, self.field_f64("z")?);
+        Some((0..self.len()).map(|i| na::Vector3::new(x[i], y[i], z[i])).collect())
+    }
+
+    fn point_size(&self) -> usize {
+        self.fields.iter().map(|f| f.kind.size() * f.count).sum()
+    }
+}
+
+/// Read a cloud in any of the three data formats
+pub fn read_pcd<R: Read>(mut reader: R) -> Result<PointCloud, PcdError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let header_error = |line: usize, reason: &str| PcdError::InvalidHeader {
+        line,
+        reason: reason.to_string(),
+    };
+    let mut names: Vec<String> = Vec::new();
+    let (mut sizes, mut kinds, mut counts) = (Vec::new(), Vec::new(), None);
+    let (mut width, mut height, mut points) = (None, None, None);
+    let mut viewpoint = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+    let mut offset = 0;
+    let mut line_number = 0;
+    let format = loop {
+        let end = bytes[offset..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |n| offset + n);
+        if offset >= bytes.len() {
+            return Err(header_error(line_number, "missing DATA line"));
+        }
+        line_number += 1;
+        let line = String::from_utf8_lossy(&bytes[offset..end]).trim().to_string();
+        offset = (end + 1).min(bytes.len());
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let key = tokens.next().unwrap_or_default().to_ascii_uppercase();
+        let values: Vec<&str> = tokens.collect();
+        let numbers = |values: &[&str]| -> Result<Vec<usize>, PcdError> {
+            values
+                .iter()
+                .map(|v| v.parse().map_err(|_| header_error(line_number, "expected integers")))
+                .collect()
+        };
+        match key.as_str() {
+            "VERSION" => {}
+            "FIELDS" | "COLUMNS" => names = values.iter().map(|v| v.to_string()).collect(),
+            "SIZE" => sizes = numbers(&values)?,
+            "TYPE" => {
+                kinds = values.iter().map(|v| v.chars().next().unwrap_or('?')).collect();
+            }
+            "COUNT" => counts = Some(numbers(&values)?),
+            "WIDTH" => width = numbers(&values)?.first().copied(),
+            "HEIGHT" => height = numbers(&values)?.first().copied(),
+            "POINTS" => points = numbers(&values)?.first().copied(),
+            "VIEWPOINT" => {
+                let parsed: Result<Vec<f64>, _> = values.iter().map(|v| v.parse()).collect();
+                match parsed {
+                    Ok(parsed) if parsed.len() == 7 => viewpoint.copy_from_slice(&parsed),
+                    _ => return Err(header_error(line_number, "VIEWPOINT needs 7 numbers")),
+                }
+            }
+            "DATA" => match values.first().map(|v| v.to_ascii_lowercase()).as_deref() {
+                Some("ascii") => break PcdFormat::Ascii,
+                Some("binary") => break PcdFormat::Binary,
+                Some("binary_compressed") => break PcdFormat::BinaryCompressed,
+                _ => return Err(header_error(line_number, "unknown DATA format")),
+            },
+            _ => return Err(header_error(line_number, &format!("unknown key '{}'", key))),
+        }
+    };
+
+    let counts = counts.unwrap_or_else(|| vec![1; names.len()]);
+    if names.is_empty() || sizes.len() != names.len() || kinds.len() != names.len()
+        || counts.len() != names.len()
+    {
+        return Err(header_error(line_number, "FIELDS, SIZE, TYPE and COUNT disagree"));
+    }
+    let len = points.or(width.zip(height).map(|(w, h)| w * h));
+    let len = len.ok_or_else(|| header_error(line_number, "missing POINTS"))?;
+    let (width, height) = match (width, height) {
+        (Some(w), Some(h)) if w * h == len => (w, h),
+        _ => (len, 1),
+    };
+    let mut cloud = PointCloud { width, height, viewpoint, ..PointCloud::new(len) };
+    for k in 0..names.len() {
+        let kind = PcdType::from_header(kinds[k], sizes[k])?;
+        cloud.fields.push(PcdField { name: names[k].clone(), kind, count: counts[k] });
+        cloud.columns.push(FieldData::with_capacity(kind, len * counts[k]));
+    }
+
+    let data = &bytes[offset..];
+    match format {
+        PcdFormat::Ascii => read_ascii(&mut cloud, data)?,
+        PcdFormat::Binary => read_binary(&mut cloud, data)?,
+        PcdFormat::BinaryCompressed => read_compressed(&mut cloud, data)?,
+    }
+    Ok(cloud)
+}
+
+/// Write a cloud in the given data format
+pub fn write_pcd<W: Write>(
+    mut writer: W,
+    cloud: &PointCloud,
+    format: PcdFormat,
+) -> Result<(), PcdError> {
+    let join = |f: &dyn Fn(&PcdField) -> String| {
+        cloud.fields.iter().map(f).collect::<Vec<_>>().join(" ")
+    };
+    writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+    writeln!(writer, "VERSION 0.7")?;
+    writeln!(writer, "FIELDS {}", join(&|f| f.name.clone()))?;
+    writeln!(writer, "SIZE {}", join(&|f| f.kind.size().to_string()))?;
+    writeln!(writer, "TYPE {}", join(&|f| f.kind.header().0.to_string()))?;
+    writeln!(writer, "COUNT {}", join(&|f| f.count.to_string()))?;
+    writeln!(writer, "WIDTH {}", cloud.width)?;
+    writeln!(writer, "HEIGHT {}", cloud.height)?;
+    let viewpoint: Vec<String> = cloud.viewpoint.iter().map(f64::to_string).collect();
+    writeln!(writer, "VIEWPOINT {}", viewpoint.join(" "))?;
+    writeln!(writer, "POINTS {}", cloud.len())?;
+
+    match format {
+        PcdFormat::Ascii => {
+            writeln!(writer, "DATA ascii")?;
+            for point in 0..cloud.len() {
+                let mut tokens = Vec::new();
+                for (field, column) in cloud.fields.iter().zip(&cloud.columns) {
+                    for c in 0..field.count {
+                        tokens.push(column.write_text(point * field.count + c));
+                    }
+                }
+                writeln!(writer, "{}", tokens.join(" "))?;
+            }
+        }
+        PcdFormat::Binary => {
+            writeln!(writer, "DATA binary")?;
+            let mut bytes = Vec::with_capacity(cloud.len() * cloud.point_size());
+            for point in 0..cloud.len() {
+                for (field, column) in cloud.fields.iter().zip(&cloud.columns) {
+                    for c in 0..field.count {
+                        column.write_le(point * field.count + c, &mut bytes);
+                    }
+                }
+            }
+            writer.write_all(&bytes)?;
+        }
+        PcdFormat::BinaryCompressed => {
+            writeln!(writer, "DATA binary_compressed")?;
+            let mut bytes = Vec::with_capacity(cloud.len() * cloud.point_size());
+            for column in &cloud.columns {
+                for index in 0..column.len() {
+                    column.write_le(index, &mut bytes);
+                }
+            }
+            let compressed = lzf_compress(&bytes);
+            writer.write_all(&(compressed.len() as u32).to_le_bytes())?;
+            writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(&compressed)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_pcd<P: AsRef<Path>>(path: P) -> Result<PointCloud, PcdError> {
+    read_pcd(BufReader::new(File::open(path)?))
+}
+
+pub fn save_pcd<P: AsRef<Path>>(
+    path: P,
+    cloud: &PointCloud,
+    format: PcdFormat,
+) -> Result<(), PcdError> {
+    write_pcd(BufWriter::new(File::create(path)?), cloud, format)
+}
+
+fn read_ascii(cloud: &mut PointCloud, data: &[u8]) -> Result<(), PcdError> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    for point in 0..cloud.len() {
+        let line = lines.next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        for (field, column) in cloud.fields.iter().zip(cloud.columns.iter_mut()) {
+            for _ in 0..field.count {
+                let token = tokens.next().unwrap_or_default();
+                // Integer fields written by some tools carry a ".0" suffix
+                let parsed = column.push_text(token)
+                    || token.parse::<f64>().is_ok_and(|v| column.push_text(&(v as i64).to_string()));
+                if !parsed {
+                    return Err(PcdError::InvalidNumber { point, token: token.to_string() });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_binary(cloud: &mut PointCloud, data: &[u8]) -> Result<(), PcdError> {
+    let point_size = cloud.point_size();
+    let expected = point_size * cloud.len();
+    if data.len() < expected {
+        return Err(PcdError::Truncated { expected, found: data.len() });
+    }
+    // Files written by PCL may carry padding after the last point
+    for point in data[..expected].chunks_exact(point_size) {
+        let mut offset = 0;
+        for (field, column) in cloud.fields.iter().zip(cloud.columns.iter_mut()) {
+            let size = field.kind.size();
+            for _ in 0..field.count {
+                column.push_le(&point[offset..offset + size]);
+                offset += size;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_compressed(cloud: &mut PointCloud, data: &[u8]) -> Result<(), PcdError> {
+    if data.len() < 8 {
+        return Err(PcdError::Truncated { expected: 8, found: data.len() });
+    }
+    let compressed = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    let uncompressed = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    let expected = cloud.point_size() * cloud.len();
+    if data.len() < 8 + compressed {
+        return Err(PcdError::Truncated { expected: 8 + compressed, found: data.len() });
+    }
+    if uncompressed != expected {
+        return Err(PcdError::Decompression(format!(
+            "header promises {} bytes but the fields need {}",
+            uncompressed, expected
+        )));
+    }
+    let bytes = lzf_decompress(&data[8..8 + compressed], uncompressed)?;
+
+    // Field after field; within a field the values of one point stay together
+    let points = cloud.len();
+    let mut offset = 0;
+    for (field, column) in cloud.fields.iter().zip(cloud.columns.iter_mut()) {
+        let size = field.kind.size();
+        for _ in 0..field.count * points {
+            column.push_le(&bytes[offset..offset + size]);
+            offset += size;
+        }
+    }
+    Ok(())
+}
+
+/// Decompress LZF data (as produced by liblzf) of a known decompressed length
+pub fn lzf_decompress(input: &[u8], length: usize) -> Result<Vec<u8>, PcdError> {
+    let corrupt = |reason: &str| PcdError::Decompression(reason.to_string());
+    let mut output = Vec::with_capacity(length);
+    let mut i = 0;
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+        if control < 32 {
+            // Literal run of control + 1 bytes
+            let run = control + 1;
+            let literal = input.get(i..i + run).ok_or_else(|| corrupt("literal overruns input"))?;
+            output.extend_from_slice(literal);
+            i += run;
+        } else {
+            // Back reference: length in the top three bits, offset in the rest
+            let mut run = control >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or_else(|| corrupt("truncated back reference"))? as usize;
+                i += 1;
+            }
+            let low = *input.get(i).ok_or_else(|| corrupt("truncated back reference"))? as usize;
+            i += 1;
+            let distance = ((control & 0x1f) << 8) + low + 1;
+            let start = output
+                .len()
+                .checked_sub(distance)
+                .ok_or_else(|| corrupt("back reference before the start"))?;
+            // Byte by byte, since the reference may overlap what it produces
+            for k in 0..run + 2 {
+                output.push(output[start + k]);
+            }
+        }
+        if output.len() > length {
+            return Err(corrupt("output longer than promised"));
+        }
+    }
+    if output.len() != length {
+        return Err(corrupt("output shorter than promised"));
+    }
+    Ok(output)
+}
+
+/// Compress with the LZF format understood by `lzf_decompress` and PCL
+pub fn lzf_compress(input: &[u8]) -> Vec<u8> {
+    const MAX_DISTANCE: usize = 1 << 13;
+    const MAX_MATCH: usize = 264;    // 7 + 255 + 2
+    const HASH_BITS: usize = 14;
+
+    let flush = |output: &mut Vec<u8>, literal: &[u8]| {
+        for chunk in literal.chunks(32) {
+            output.push((chunk.len() - 1) as u8);
+            output.extend_from_slice(chunk);
+        }
+    };
+    let hash = |bytes: &[u8]| {
+        let key = (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize;
+        (key.wrapping_mul(2654435761) >> 8) & ((1 << HASH_BITS) - 1)
+    };
+
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i + 2 < input.len() {
+        let slot = hash(&input[i..i + 3]);
+        let candidate = table[slot];
+        table[slot] = i;
+        let found = candidate != usize::MAX
+            && i - candidate <= MAX_DISTANCE
+            && input[candidate..candidate + 3] == input[i..i + 3];
+        if !found {
+            i += 1;
+            continue;
+        }
+
+        let limit = MAX_MATCH.min(input.len() - i);
+        let mut length = 3;
+        while length < limit && input[candidate + length] == input[i + length] {
+            length += 1;
+        }
+        flush(&mut output, &input[literal_start..i]);
+        let distance = i - candidate - 1;
+        let run = length - 2;
+        if run < 7 {
+            output.push(((run << 5) | (distance >> 8)) as u8);
+        } else {
+            output.push(((7 << 5) | (distance >> 8)) as u8);
+            output.push((run - 7) as u8);
+        }
+        output.push((distance & 0xff) as u8);
+        i += length;
+        literal_start = i;
+    }
+    flush(&mut output, &input[literal_start..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo_path(path: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(path)
+    }
+
+    #[test]
+    fn test_load_bundled_clouds() {
+        let scan = load_pcd(repo_path("robot_geometry/1.pcd")).unwrap();
+        assert_eq!(scan.len(), 24344);
+        let names: Vec<&str> = scan.fields().iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["x", "y", "z", "intensity", "ring", "time"]);
+        let rings = scan.field::<u16>("ring").unwrap();
+        assert!(rings.iter().all(|&ring| ring < 128));
+        assert!(scan.field::<f32>("ring").is_none());
+        assert!(scan.positions().unwrap().iter().all(|p| p.iter().all(|v| v.is_finite())));
+
+        let bunny = load_pcd(repo_path("data/bunny.pcd")).unwrap();
+        assert_eq!(bunny.len(), 397);
+        assert_eq!(bunny.field::<f32>("x").unwrap()[0], 0.0054216);
+    }
+
+    #[test]
+    fn test_round_trip_in_every_format() {
+        let scan = load_pcd(repo_path("robot_geometry/2.pcd")).unwrap();
+        // A multi-count field as well
+        let normals: Vec<f64> = (0..scan.len() * 3).map(|k| (k % 7) as f64 * 0.25).collect();
+        let scan = scan.with_field("normal", normals).unwrap();
+        for format in [PcdFormat::Ascii, PcdFormat::Binary, PcdFormat::BinaryCompressed] {
+            let mut bytes = Vec::new();
+            write_pcd(&mut bytes, &scan, format).unwrap();
+            let reread = read_pcd(bytes.as_slice()).unwrap();
+            assert_eq!(reread, scan, "{:?}", format);
+        }
+
+        let mut bytes = Vec::new();
+        write_pcd(&mut bytes, &scan, PcdFormat::BinaryCompressed).unwrap();
+        let mut binary = Vec::new();
+        write_pcd(&mut binary, &scan, PcdFormat::Binary).unwrap();
+        assert!(bytes.len() < binary.len());
+    }
+
+    #[test]
+    fn test_lzf_round_trip() {
+        let mut data: Vec<u8> = b"abcabcabcabcabc hello hello hello".to_vec();
+        data.extend((0..5000u32).map(|k| (k * 7 % 251) as u8));
+        data.extend(std::iter::repeat_n(9, 1000));
+        let compressed = lzf_compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(lzf_decompress(&compressed, data.len()).unwrap(), data);
+        assert!(lzf_decompress(&[0x20, 0x05], 3).is_err());
+        assert!(lzf_compress(&[]).is_empty());
+    }
+}
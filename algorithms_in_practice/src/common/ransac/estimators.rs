@@ -0,0 +1,222 @@
+use nalgebra as na;
+
+use super::RobustEstimator;
+
+/// Line n . p = offset in the plane, with n of unit length
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line2D {
+    pub normal: na::Vector2<f64>,
+    pub offset: f64,
+}
+
+impl Line2D {
+    pub fn distance(&self, point: &na::Vector2<f64>) -> f64 {
+        (self.normal.dot(point) - self.offset).abs()
+    }
+}
+
+/// Plane n . p = offset, with n of unit length
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plane {
+    pub normal: na::Vector3<f64>,
+    pub offset: f64,
+}
+
+impl Plane {
+    pub fn distance(&self, point: &na::Vector3<f64>) -> f64 {
+        (self.normal.dot(point) - self.offset).abs()
+    }
+}
+
+/// Lines through 2D points; residuals are perpendicular distances
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineEstimator;
+
+impl RobustEstimator for LineEstimator {
+    type Datum = na::Vector2<f64>;
+    type Model = Line2D;
+
+    fn sample_size(&self) -> usize {
+        2
+    }
+
+    fn fit(&self, data: &[na::Vector2<f64>]) -> Vec<Line2D> {
+        self.refit(data).into_iter().collect()
+    }
+
+    fn residual(&self, model: &Line2D, datum: &na::Vector2<f64>) -> f64 {
+        model.distance(datum)
+    }
+
+    /// Total least squares: the normal is the direction of least spread
+    fn refit(&self, data: &[na::Vector2<f64>]) -> Option<Line2D> {
+        let (centroid, scatter) = scatter(data)?;
+        let eigen = scatter.symmetric_eigen();
+        let smallest = eigen.eigenvalues.imin();
+        let largest = 1 - smallest;
+        if eigen.eigenvalues[largest] <= 1e-12 {
+            return None;  // All points coincide
+        }
+        let normal = eigen.eigenvectors.column(smallest).into_owned();
+        Some(Line2D { offset: normal.dot(&centroid), normal })
+    }
+}
+
+/// Planes through 3D points; residuals are perpendicular distances
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlaneEstimator;
+
+impl RobustEstimator for PlaneEstimator {
+    type Datum = na::Vector3<f64>;
+    type Model = Plane;
+
+    fn sample_size(&self) -> usize {
+        3
+    }
+
+    fn fit(&self, data: &[na::Vector3<f64>]) -> Vec<Plane> {
+        self.refit(data).into_iter().collect()
+    }
+
+    fn residual(&self, model: &Plane, datum: &na::Vector3<f64>) -> f64 {
+        model.distance(datum)
+    }
+
+    fn refit(&self, data: &[na::Vector3<f64>]) -> Option<Plane> {
+        let (centroid, scatter) = scatter(data)?;
+        let eigen = scatter.symmetric_eigen();
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| eigen.eigenvalues[a].total_cmp(&eigen.eigenvalues[b]));
+        if eigen.eigenvalues[order[1]] <= 1e-12 {
+            return None;  // Collinear points span no plane
+        }
+        let normal = eigen.eigenvectors.column(order[0]).into_owned();
+        Some(Plane { offset: normal.dot(&centroid), normal })
+    }
+}
+
+/// Homographies between matched image points, (first image, second image);
+/// residuals are transfer errors in the second image
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HomographyEstimator;
+
+impl RobustEstimator for HomographyEstimator {
+    type Datum = (na::Vector2<f64>, na::Vector2<f64>);
+    type Model = na::Matrix3<f64>;
+
+    fn sample_size(&self) -> usize {
+        4
+    }
+
+    fn fit(&self, data: &[Self::Datum]) -> Vec<na::Matrix3<f64>> {
+        self.refit(data).into_iter().collect()
+    }
+
+    fn residual(&self, model: &na::Matrix3<f64>, datum: &Self::Datum) -> f64 {
+        let mapped = model * datum.0.push(1.0);
+        if mapped.z.abs() < 1e-12 {
+            return f64::INFINITY;
+        }
+        (mapped.xy() / mapped.z - datum.1).norm()
+    }
+
+    /// Direct linear transform on Hartley-normalized points
+    fn refit(&self, data: &[Self::Datum]) -> Option<na::Matrix3<f64>> {
+        if data.len() < 4 {
+            return None;
+        }
+        let first = hartley_normalization(data.iter().map(|d| &d.0));
+        let second = hartley_normalization(data.iter().map(|d| &d.1));
+        let mut normal = na::SMatrix::<f64, 9, 9>::zeros();
+        for (a, b) in data {
+            let a = first * a.push(1.0);
+            let b = second * b.push(1.0);
+            // b x (H a) = 0 gives two independent rows
+            let mut rows = na::SMatrix::<f64, 2, 9>::zeros();
+            for k in 0..3 {
+                rows[(0, 3 + k)] = -b.z * a[k];
+                rows[(0, 6 + k)] = b.y * a[k];
+                rows[(1, k)] = b.z * a[k];
+                rows[(1, 6 + k)] = -b.x * a[k];
+            }
+            normal += rows.transpose() * rows;
+        }
+        let eigen = normal.symmetric_eigen();
+        let h = eigen.eigenvectors.column(eigen.eigenvalues.imin());
+        let normalized = na::Matrix3::from_fn(|i, j| h[3 * i + j]);
+        let homography = second.try_inverse()? * normalized * first;
+        // A rank-deficient fit maps the sample onto a line or point
+        if homography.determinant().abs() < 1e-12 * homography.norm().powi(3) {
+            return None;
+        }
+        Some(homography / homography[(2, 2)])
+    }
+}
+
+/// Similarity taking points to zero mean and mean distance sqrt(2)
+pub(crate) fn hartley_normalization<'a>(
+    points: impl Iterator<Item = &'a na::Vector2<f64>> + Clone,
+) -> na::Matrix3<f64> {
+    let n = points.clone().count().max(1) as f64;
+    let centroid = points.clone().fold(na::Vector2::zeros(), |acc, p| acc + p) / n;
+    let spread = points.map(|p| (p - centroid).norm()).sum::<f64>() / n;
+    let s = std::f64::consts::SQRT_2 / spread.max(f64::MIN_POSITIVE);
+    na::Matrix3::new(s, 0.0, -s * centroid.x, 0.0, s, -s * centroid.y, 0.0, 0.0, 1.0)
+}
+
+/// Centroid and scatter matrix of `points`, `None` if there are fewer than N
+fn scatter<const N: usize>(
+    points: &[na::SVector<f64, N>],
+) -> Option<(na::SVector<f64, N>, na::SMatrix<f64, N, N>)> {
+    if points.len() < N {
+        return None;
+    }
+    let n = points.len() as f64;
+    let centroid = points.iter().fold(na::SVector::<f64, N>::zeros(), |acc, p| acc + p) / n;
+    let scatter = points.iter().fold(na::SMatrix::<f64, N, N>::zeros(), |acc, p| {
+        acc + (p - centroid) * (p - centroid).transpose()
+    });
+    Some((centroid, scatter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::ransac::{Ransac, RansacConfig, RansacVariant};
+
+    #[test]
+    fn test_plane_and_homography_with_outliers() {
+        // Floor z = 0.1 x - 0.2 with clutter above it
+        let points: Vec<na::Vector3<f64>> = (0..200)
+            .map(|i| {
+                let (x, y) = ((i % 20) as f64 * 0.2, (i / 20) as f64 * 0.3);
+                let z = if i % 4 == 0 { 0.5 + (i as f64).sin().abs() } else { 0.1 * x - 0.2 };
+                na::Vector3::new(x, y, z)
+            })
+            .collect();
+        let variant = RansacVariant::LoRansac;
+        let config = RansacConfig { variant, threshold: 0.01, ..RansacConfig::default() };
+        let plane = Ransac::new(config).estimate(&PlaneEstimator, &points).unwrap();
+        assert_eq!(plane.inlier_count(), 150);
+        let expected = na::Vector3::new(0.1, 0.0, -1.0).normalize();
+        assert!(plane.model.normal.dot(&expected).abs() > 1.0 - 1e-9);
+
+        // Matches related by a homography, every fifth one wrong
+        let truth = na::Matrix3::new(1.1, 0.05, 12.0, -0.03, 0.95, -7.0, 2e-4, -1e-4, 1.0);
+        let matches: Vec<(na::Vector2<f64>, na::Vector2<f64>)> = (0..100)
+            .map(|i| {
+                let (column, row) = ((i % 10) as f64, (i / 10) as f64);
+                let a = na::Vector2::new(column * 60.0 + 10.0, row * 45.0 + 5.0);
+                let mapped = truth * a.push(1.0);
+                let b = mapped.xy() / mapped.z;
+                (a, if i % 5 == 0 { b + na::Vector2::new(25.0, -30.0) } else { b })
+            })
+            .collect();
+        let variant = RansacVariant::Prosac;
+        let config = RansacConfig { variant, threshold: 1.0, seed: 3, ..RansacConfig::default() };
+        let homography = Ransac::new(config).estimate(&HomographyEstimator, &matches).unwrap();
+        assert_eq!(homography.inlier_count(), 80);
+        assert!((homography.model - truth).norm() < 1e-6, "{}", homography.model);
+        assert!(HomographyEstimator.fit(&matches[1..4]).is_empty());
+    }
+}
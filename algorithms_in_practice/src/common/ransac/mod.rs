@@ -0,0 +1,362 @@
+mod estimators;
+pub use estimators::*;
+
+/// A model fitted robustly from data with outliers: how many data a minimal
+/// sample needs, how to fit a sample and how far a datum is from a model
+pub trait RobustEstimator {
+    type Datum: Clone;
+    type Model: Clone;
+
+    /// Data in a minimal sample
+    fn sample_size(&self) -> usize;
+
+    /// Models consistent with `data`, usually a minimal sample. Empty when the
+    /// sample is degenerate; some minimal solvers return several candidates.
+    fn fit(&self, data: &[Self::Datum]) -> Vec<Self::Model>;
+
+    /// Error of `datum` under `model`, in the units of the threshold
+    fn residual(&self, model: &Self::Model, datum: &Self::Datum) -> f64;
+
+    /// Least-squares fit to the inliers of a model
+    fn refit(&self, data: &[Self::Datum]) -> Option<Self::Model> {
+        self.fit(data).into_iter().next()
+    }
+}
+
+/// How hypotheses are sampled and scored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RansacVariant {
+    Ransac,    // Uniform samples, scored by inlier count
+    Msac,      // Uniform samples, scored by truncated squared residuals
+    LoRansac,  // MSAC with iterated refits on the inliers of each new best model
+    Prosac,    // Samples drawn from the best-ranked data first; data must be ordered best first
+}
+
+#[derive(Debug, Clone)]
+pub struct RansacConfig {
+    pub variant: RansacVariant,
+    pub threshold: f64,         // Largest residual of an inlier
+    pub confidence: f64,        // Probability of drawing one outlier-free sample
+    pub max_iterations: usize,
+    pub local_iterations: usize,  // Refits per local optimization in LO-RANSAC
+    pub seed: u64,
+}
+
+impl Default for RansacConfig {
+    fn default() -> Self {
+        Self {
+            variant: RansacVariant::Msac,
+            threshold: 1.0,
+            confidence: 0.999,
+            max_iterations: 1000,
+            local_iterations: 10,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RansacResult<M> {
+    pub model: M,
+    pub inliers: Vec<bool>,  // One per datum
+    pub cost: f64,           // Outlier count (RANSAC, PROSAC) or truncated squared residuals
+    pub iterations: usize,
+}
+
+impl<M> RansacResult<M> {
+    pub fn inlier_count(&self) -> usize {
+        self.inliers.iter().filter(|&&inlier| inlier).count()
+    }
+
+    pub fn inlier_ratio(&self) -> f64 {
+        self.inlier_count() as f64 / self.inliers.len().max(1) as f64
+    }
+}
+
+/// Hypothesize-and-verify robust fitting. The number of iterations adapts to
+/// the inlier ratio of the best model so far, and the winner is refitted to
+/// its inliers before it is returned.
+pub struct Ransac {
+    config: RansacConfig,
+}
+
+impl Ransac {
+    pub fn new(config: RansacConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &RansacConfig {
+        &self.config
+    }
+
+    /// Best model for `data`, `None` if there are fewer data than a sample
+    /// needs or no model explains a sample's worth of inliers
+    pub fn estimate<E: RobustEstimator>(
+        &self,
+        estimator: &E,
+        data: &[E::Datum],
+    ) -> Option<RansacResult<E::Model>> {
+        let m = estimator.sample_size();
+        if m == 0 || data.len() < m {
+            return None;
+        }
+        let mut rng = SplitMix64::new(self.config.seed);
+        let mut prosac = ProsacSchedule::new(m, data.len(), self.config.max_iterations);
+        let mut best: Option<RansacResult<E::Model>> = None;
+        let mut needed = self.config.max_iterations;
+        let mut iteration = 0;
+
+        while iteration < needed {
+            iteration += 1;
+            let indices = match self.config.variant {
+                RansacVariant::Prosac => prosac.sample(iteration, &mut rng),
+                _ => rng.sample(data.len(), m),
+            };
+            let sample: Vec<E::Datum> = indices.into_iter().map(|i| data[i].clone()).collect();
+            for model in estimator.fit(&sample) {
+                let mut candidate = self.score(estimator, model, data);
+                if best.as_ref().is_some_and(|b| candidate.cost >= b.cost) {
+                    continue;
+                }
+                if self.config.variant == RansacVariant::LoRansac {
+                    candidate = self.local_optimization(estimator, candidate, data);
+                }
+                let required = self.required_iterations(candidate.inlier_ratio(), m);
+                needed = needed.min(required.max(iteration));
+                best = Some(candidate);
+            }
+        }
+
+        let mut best = best.filter(|b| b.inlier_count() >= m)?;
+        best = self.polish(estimator, best, data);
+        best.iterations = iteration;
+        Some(best)
+    }
+
+    fn score<E: RobustEstimator>(
+        &self,
+        estimator: &E,
+        model: E::Model,
+        data: &[E::Datum],
+    ) -> RansacResult<E::Model> {
+        let threshold = self.config.threshold;
+        let mut cost = 0.0;
+        let inliers = data
+            .iter()
+            .map(|datum| {
+                let residual = estimator.residual(&model, datum);
+                let inlier = residual < threshold;
+                cost += match self.config.variant {
+                    RansacVariant::Ransac | RansacVariant::Prosac => {
+                        if inlier { 0.0 } else { 1.0 }
+                    }
+                    RansacVariant::Msac | RansacVariant::LoRansac => {
+                        residual.min(threshold).powi(2)
+                    }
+                };
+                inlier
+            })
+            .collect();
+        RansacResult { model, inliers, cost, iterations: 0 }
+    }
+
+    /// Refit to the inliers until the cost stops improving
+    fn local_optimization<E: RobustEstimator>(
+        &self,
+        estimator: &E,
+        mut best: RansacResult<E::Model>,
+        data: &[E::Datum],
+    ) -> RansacResult<E::Model> {
+        for _ in 0..self.config.local_iterations {
+            let refined = self.polish(estimator, best.clone(), data);
+            if refined.cost >= best.cost {
+                break;
+            }
+            best = refined;
+        }
+        best
+    }
+
+    /// One refit to the inliers, kept if it scores no worse
+    fn polish<E: RobustEstimator>(
+        &self,
+        estimator: &E,
+        best: RansacResult<E::Model>,
+        data: &[E::Datum],
+    ) -> RansacResult<E::Model> {
+        let inliers: Vec<E::Datum> = data
+            .iter()
+            .zip(&best.inliers)
+            .filter(|(_, &inlier)| inlier)
+            .map(|(d, _)| d.clone())
+            .collect();
+        match estimator.refit(&inliers) {
+            Some(model) => {
+                let refined = self.score(estimator, model, data);
+                if refined.cost <= best.cost { refined } else { best }
+            }
+            None => best,
+        }
+    }
+
+    /// Iterations for `confidence` of drawing an all-inlier sample
+    fn required_iterations(&self, inlier_ratio: f64, sample_size: usize) -> usize {
+        let miss = 1.0 - inlier_ratio.powi(sample_size as i32);
+        if miss <= f64::EPSILON {
+            return 0;
+        }
+        if miss >= 1.0 {
+            return self.config.max_iterations;
+        }
+        let needed = ((1.0 - self.config.confidence).ln() / miss.ln()).ceil();
+        if needed.is_finite() {
+            (needed as usize).min(self.config.max_iterations)
+        } else {
+            self.config.max_iterations
+        }
+    }
+}
+
+/// Growth of the sampled subset in PROSAC (Chum and Matas, "Matching with
+/// PROSAC"): the top `n` data are sampled for about as many iterations as
+/// RANSAC would spend on samples drawn only from them
+struct ProsacSchedule {
+    m: usize,
+    total: usize,
+    n: usize,           // Size of the subset sampled from
+    t_n: f64,           // Expected number of samples from the top n in max_iterations uniform draws
+    t_n_prime: usize,   // Iteration at which the subset grows
+}
+
+impl ProsacSchedule {
+    fn new(m: usize, total: usize, max_iterations: usize) -> Self {
+        let t_n =
+            (0..m).fold(max_iterations as f64, |t, i| t * (m - i) as f64 / (total - i) as f64);
+        Self { m, total, n: m, t_n, t_n_prime: 1 }
+    }
+
+    fn sample(&mut self, iteration: usize, rng: &mut SplitMix64) -> Vec<usize> {
+        if iteration > self.t_n_prime && self.n < self.total {
+            self.n += 1;
+            let next = self.t_n * self.n as f64 / (self.n - self.m) as f64;
+            self.t_n_prime += ((next - self.t_n).ceil() as usize).max(1);
+            self.t_n = next;
+        }
+        if iteration > self.t_n_prime {
+            // The whole set is in play: plain uniform sampling
+            return rng.sample(self.n, self.m);
+        }
+        // The newest datum plus m - 1 drawn from those before it
+        let mut indices = rng.sample(self.n - 1, self.m - 1);
+        indices.push(self.n - 1);
+        indices
+    }
+}
+
+/// SplitMix64 generator: small, fast and reproducible from a seed
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in [0, n)
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+
+    /// `k` distinct indices below `n`, in the order drawn
+    pub fn sample(&mut self, n: usize, k: usize) -> Vec<usize> {
+        assert!(k <= n, "cannot draw {} distinct indices below {}", k, n);
+        let mut chosen = Vec::with_capacity(k);
+        while chosen.len() < k {
+            let index = self.below(n);
+            if !chosen.contains(&index) {
+                chosen.push(index);
+            }
+        }
+        chosen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra as na;
+
+    /// Points on y = 0.5 x + 1 with small noise, every third replaced by clutter
+    fn line_data() -> (Vec<na::Vector2<f64>>, Vec<bool>) {
+        (0..90)
+            .map(|i| {
+                let x = i as f64 * 0.1;
+                if i % 3 == 0 {
+                    (na::Vector2::new(x, 4.0 + (i as f64 * 1.3).sin() * 3.0), false)
+                } else {
+                    (na::Vector2::new(x, 0.5 * x + 1.0 + (i as f64 * 2.1).sin() * 0.01), true)
+                }
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn test_variants_find_the_line() {
+        let (points, truth) = line_data();
+        for variant in [
+            RansacVariant::Ransac,
+            RansacVariant::Msac,
+            RansacVariant::LoRansac,
+            RansacVariant::Prosac,
+        ] {
+            let config =
+                RansacConfig { variant, threshold: 0.05, seed: 11, ..RansacConfig::default() };
+            let result = Ransac::new(config.clone()).estimate(&LineEstimator, &points).unwrap();
+            let line = &result.model;
+            assert!((line.normal.y / line.normal.x + 2.0).abs() < 0.01, "{:?} {:?}", variant, line);
+            let subset = result.inliers.iter().zip(&truth).all(|(found, &inlier)| !found || inlier);
+            assert!(subset, "{:?}", variant);
+            assert!(result.inlier_count() >= 58, "{:?}: {}", variant, result.inlier_count());
+            assert!(result.iterations < 100, "{:?}: {}", variant, result.iterations);
+
+            // Same seed, same answer
+            let again = Ransac::new(config).estimate(&LineEstimator, &points).unwrap();
+            assert_eq!((again.inliers, again.iterations), (result.inliers, result.iterations));
+        }
+        let ransac = Ransac::new(RansacConfig::default());
+        assert!(ransac.estimate(&LineEstimator, &points[..1]).is_none());
+    }
+
+    #[test]
+    fn test_prosac_starts_from_the_best_ranked() {
+        let mut schedule = ProsacSchedule::new(3, 50, 1000);
+        let mut rng = SplitMix64::new(5);
+        assert_eq!(schedule.sample(1, &mut rng).iter().copied().max(), Some(2));
+        let mut largest = 0;
+        for iteration in 2..200 {
+            let sample = schedule.sample(iteration, &mut rng);
+            let mut sorted = sample.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), 3);
+            largest = largest.max(*sorted.last().unwrap());
+        }
+        // The subset grows, but far slower than uniform sampling would reach the tail
+        assert!(largest > 3 && largest < 49, "{}", largest);
+    }
+}
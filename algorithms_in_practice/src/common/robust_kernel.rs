@@ -0,0 +1,182 @@
+/// Robust loss applied to a squared (Mahalanobis) error `s = e^T Omega e`.
+/// Follows Zach, "Robust bundle adjustment revisited": `rho(s)` replaces `s`
+/// in the cost, and `rho'(s)` is the weight used by iteratively reweighted
+/// least squares.
+pub trait RobustKernel: std::fmt::Debug + Send + Sync {
+    /// `[rho(s), rho'(s), rho''(s)]`
+    fn evaluate(&self, squared_error: f64) -> [f64; 3];
+
+    /// Robustified cost `rho(s)`
+    fn rho(&self, squared_error: f64) -> f64 {
+        self.evaluate(squared_error)[0]
+    }
+
+    /// IRLS weight `rho'(s)`
+    fn weight(&self, squared_error: f64) -> f64 {
+        self.evaluate(squared_error)[1]
+    }
+}
+
+/// Plain least squares, `rho(s) = s`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct L2Kernel;
+
+impl RobustKernel for L2Kernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        [squared_error, 1.0, 0.0]
+    }
+}
+
+/// `rho(s) = sqrt(s)`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct L1Kernel;
+
+impl RobustKernel for L1Kernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        let s = squared_error.max(1e-12); // rho' is unbounded at zero
+        let root = s.sqrt();
+        [root, 0.5 / root, -0.25 / (s * root)]
+    }
+}
+
+/// `rho(s) = s^2`, penalizing large errors more than L2
+#[derive(Debug, Clone, Copy, Default)]
+pub struct L4Kernel;
+
+impl RobustKernel for L4Kernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        [squared_error * squared_error, 2.0 * squared_error, 2.0]
+    }
+}
+
+/// Quadratic below `delta`, linear in the error above it
+#[derive(Debug, Clone, Copy)]
+pub struct HuberKernel {
+    pub delta: f64,
+}
+
+impl HuberKernel {
+    pub fn new(delta: f64) -> Self {
+        Self { delta }
+    }
+}
+
+impl RobustKernel for HuberKernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        let delta2 = self.delta * self.delta;
+        if squared_error <= delta2 {
+            [squared_error, 1.0, 0.0]
+        } else {
+            let root = squared_error.sqrt();
+            let weight = self.delta / root;
+            [2.0 * root * self.delta - delta2, weight, -0.5 * weight / squared_error]
+        }
+    }
+}
+
+/// Smooth approximation of Huber
+#[derive(Debug, Clone, Copy)]
+pub struct PseudoHuberKernel {
+    pub delta: f64,
+}
+
+impl PseudoHuberKernel {
+    pub fn new(delta: f64) -> Self {
+        Self { delta }
+    }
+}
+
+impl RobustKernel for PseudoHuberKernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        let delta2 = self.delta * self.delta;
+        let aux = squared_error / delta2 + 1.0;
+        let root = aux.sqrt();
+        let weight = 1.0 / root;
+        [2.0 * delta2 * (root - 1.0), weight, -0.5 * weight / (delta2 * aux)]
+    }
+}
+
+/// `rho(s) = c^2 log(1 + s / c^2)`, heavily down-weighting gross outliers
+#[derive(Debug, Clone, Copy)]
+pub struct CauchyKernel {
+    pub delta: f64,
+}
+
+impl CauchyKernel {
+    pub fn new(delta: f64) -> Self {
+        Self { delta }
+    }
+}
+
+impl RobustKernel for CauchyKernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        let delta2 = self.delta * self.delta;
+        let aux = squared_error / delta2 + 1.0;
+        let weight = 1.0 / aux;
+        [delta2 * aux.ln(), weight, -weight * weight / delta2]
+    }
+}
+
+/// `rho(s) = 1 - exp(-d s)`, bounded so outliers stop contributing entirely
+#[derive(Debug, Clone, Copy)]
+pub struct GaussianKernel {
+    pub d: f64,
+}
+
+impl GaussianKernel {
+    pub fn new(d: f64) -> Self {
+        Self { d }
+    }
+}
+
+impl RobustKernel for GaussianKernel {
+    fn evaluate(&self, squared_error: f64) -> [f64; 3] {
+        let t = (-self.d * squared_error).exp();
+        let weight = t * self.d;
+        [1.0 - t, weight, -self.d * weight]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derivatives_match_finite_differences() {
+        let kernels: Vec<Box<dyn RobustKernel>> = vec![
+            Box::new(L2Kernel),
+            Box::new(L1Kernel),
+            Box::new(L4Kernel),
+            Box::new(HuberKernel::new(1.5)),
+            Box::new(PseudoHuberKernel::new(1.5)),
+            Box::new(CauchyKernel::new(1.5)),
+            Box::new(GaussianKernel::new(0.3)),
+        ];
+        let h = 1e-6;
+
+        for kernel in &kernels {
+            for &s in &[0.3, 1.7, 6.0] {
+                let [_, d1, d2] = kernel.evaluate(s);
+                let numeric_d1 = (kernel.rho(s + h) - kernel.rho(s - h)) / (2.0 * h);
+                let numeric_d2 = (kernel.weight(s + h) - kernel.weight(s - h)) / (2.0 * h);
+                assert!((d1 - numeric_d1).abs() < 1e-6, "{:?} rho' at {}", kernel, s);
+                assert!((d2 - numeric_d2).abs() < 1e-6, "{:?} rho'' at {}", kernel, s);
+            }
+        }
+    }
+
+    #[test]
+    fn test_robust_kernels_down_weight_outliers() {
+        let inlier = 0.25;
+        let outlier = 100.0;
+        for kernel in [
+            &HuberKernel::new(1.0) as &dyn RobustKernel,
+            &PseudoHuberKernel::new(1.0),
+            &CauchyKernel::new(1.0),
+        ] {
+            assert!((kernel.weight(0.0) - 1.0).abs() < 1e-12);
+            assert!(kernel.weight(outlier) < kernel.weight(inlier));
+            assert!(kernel.rho(outlier) < outlier);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use nalgebra as na;
+
+const POINT_FIELDS: usize = 4;  // id, u, v, depth
+const IMU_FIELDS: usize = 7;    // t, accelerometer xyz, gyroscope xyz
+
+#[derive(Debug)]
+pub enum SlamIoError {
+    Io(io::Error),
+    InvalidNumber { line: usize, token: String },
+    UnexpectedLine { line: usize, content: String },
+    MissingStamp,
+    CountMismatch { block: String, declared: usize, found: usize },  // Values, not rows
+}
+
+impl fmt::Display for SlamIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlamIoError::Io(err) => write!(f, "I/O error: {}", err),
+            SlamIoError::InvalidNumber { line, token } => {
+                write!(f, "line {}: cannot parse '{}' as a number", line, token)
+            }
+            SlamIoError::UnexpectedLine { line, content } => {
+                write!(f, "line {}: unexpected '{}'", line, content)
+            }
+            SlamIoError::MissingStamp => write!(f, "frame has no stamp"),
+            SlamIoError::CountMismatch { block, declared, found } => {
+                write!(f, "{} declares {} values, found {}", block, declared, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SlamIoError {}
+
+impl From<io::Error> for SlamIoError {
+    fn from(err: io::Error) -> Self {
+        SlamIoError::Io(err)
+    }
+}
+
+/// A tracked feature in one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureObservation {
+    pub pixel: na::Vector2<f64>,
+    pub depth: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImuSample {
+    pub timestamp: f64,  // Seconds
+    pub accelerometer: na::Vector3<f64>,
+    pub gyroscope: na::Vector3<f64>,
+}
+
+/// One frame of the recorded SLAM sequence: its features by id and the IMU
+/// samples received since the previous frame
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SlamFrame {
+    pub index: usize,  // Number in the file name, F0088.yaml is 88
+    pub stamp: f64,
+    pub features: BTreeMap<u64, FeatureObservation>,
+    pub imu: Vec<ImuSample>,
+}
+
+/// Read a frame file: a `stamp`, then `points` and `imu` blocks, each with a
+/// `num` of rows and a flat `data` list of their values
+pub fn read_slam_frame<R: BufRead>(reader: R) -> Result<SlamFrame, SlamIoError> {
+    let mut stamp = None;
+    let mut blocks: BTreeMap<String, (usize, Vec<f64>)> = BTreeMap::new();
+    let mut block: Option<String> = None;
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line_no = line_no + 1;
+        let line = line?;
+        let content = line.split('#').next().unwrap_or("").trim_end();
+        let trimmed = content.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let nested = trimmed.len() < content.len();
+        let unexpected =
+            || SlamIoError::UnexpectedLine { line: line_no, content: trimmed.to_string() };
+
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            let name = block.as_ref().ok_or_else(unexpected)?;
+            let entry = blocks.get_mut(name).ok_or_else(unexpected)?;
+            entry.1.push(parse_number(item.trim(), line_no)?);
+            continue;
+        }
+        let (key, value) = trimmed.split_once(':').ok_or_else(unexpected)?;
+        let value = value.trim();
+        match (nested, key) {
+            (false, "stamp") => stamp = Some(parse_number(value, line_no)?),
+            (false, _) if value.is_empty() => {
+                blocks.entry(key.to_string()).or_default();
+                block = Some(key.to_string());
+            }
+            (false, _) => block = None,  // Scalars we do not use
+            (true, "num") => {
+                let entry =
+                    block.as_ref().and_then(|name| blocks.get_mut(name)).ok_or_else(unexpected)?;
+                entry.0 = parse_number(value, line_no)? as usize;
+            }
+            (true, "data") => {
+                let entry =
+                    block.as_ref().and_then(|name| blocks.get_mut(name)).ok_or_else(unexpected)?;
+                // Empty and short lists may be written inline, as [] or [1, 2]
+                if let Some(inline) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                    for token in inline.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                        entry.1.push(parse_number(token, line_no)?);
+                    }
+                }
+            }
+            _ => return Err(unexpected()),
+        }
+    }
+
+    let stamp = stamp.ok_or(SlamIoError::MissingStamp)?;
+    let mut frame = SlamFrame { stamp, ..SlamFrame::default() };
+    for row in block_rows(&blocks, "points", POINT_FIELDS)? {
+        let pixel = na::Vector2::new(row[1], row[2]);
+        let observation = FeatureObservation { pixel, depth: row[3] };
+        frame.features.insert(row[0] as u64, observation);
+    }
+    for row in block_rows(&blocks, "imu", IMU_FIELDS)? {
+        frame.imu.push(ImuSample {
+            timestamp: row[0],
+            accelerometer: na::Vector3::new(row[1], row[2], row[3]),
+            gyroscope: na::Vector3::new(row[4], row[5], row[6]),
+        });
+    }
+    Ok(frame)
+}
+
+/// Write a frame in the layout `read_slam_frame` expects
+pub fn write_slam_frame<W: Write>(mut writer: W, frame: &SlamFrame) -> Result<(), SlamIoError> {
+    writeln!(writer, "stamp: {}", frame.stamp)?;
+    writeln!(writer, "points:\n  num: {}\n  data:", frame.features.len())?;
+    for (id, observation) in &frame.features {
+        for value in [*id as f64, observation.pixel.x, observation.pixel.y, observation.depth] {
+            writeln!(writer, "    - {}", value)?;
+        }
+    }
+    writeln!(writer, "imu:\n  num: {}\n  data:", frame.imu.len())?;
+    for sample in &frame.imu {
+        writeln!(writer, "    - {}", sample.timestamp)?;
+        for value in sample.accelerometer.iter().chain(sample.gyroscope.iter()) {
+            writeln!(writer, "    - {}", value)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_slam_frame<P: AsRef<Path>>(path: P) -> Result<SlamFrame, SlamIoError> {
+    let path = path.as_ref();
+    let mut frame = read_slam_frame(BufReader::new(File::open(path)?))?;
+    frame.index = frame_number(path).unwrap_or(0);
+    Ok(frame)
+}
+
+pub fn save_slam_frame<P: AsRef<Path>>(path: P, frame: &SlamFrame) -> Result<(), SlamIoError> {
+    write_slam_frame(BufWriter::new(File::create(path)?), frame)
+}
+
+/// Load every `F<number>.yaml` in `directory`, ordered by number
+pub fn load_slam_frames<P: AsRef<Path>>(directory: P) -> Result<Vec<SlamFrame>, SlamIoError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if let Some(number) = frame_number(&path) {
+            paths.push((number, path));
+        }
+    }
+    paths.sort();
+    paths.iter().map(|(_, path)| load_slam_frame(path)).collect()
+}
+
+fn frame_number(path: &Path) -> Option<usize> {
+    if path.extension()? != "yaml" {
+        return None;
+    }
+    path.file_stem()?.to_str()?.strip_prefix('F')?.parse().ok()
+}
+
+fn parse_number(token: &str, line: usize) -> Result<f64, SlamIoError> {
+    token.parse().map_err(|_| SlamIoError::InvalidNumber { line, token: token.to_string() })
+}
+
+/// Rows of a block, checked against its declared count. A missing block has none.
+fn block_rows<'a>(
+    blocks: &'a BTreeMap<String, (usize, Vec<f64>)>,
+    name: &str,
+    fields: usize,
+) -> Result<std::slice::Chunks<'a, f64>, SlamIoError> {
+    let (num, data) =
+        blocks.get(name).map(|(num, data)| (*num, data.as_slice())).unwrap_or((0, &[]));
+    if data.len() != num * fields {
+        return Err(SlamIoError::CountMismatch {
+            block: name.to_string(),
+            declared: num * fields,
+            found: data.len(),
+        });
+    }
+    Ok(data.chunks(fields))
+}
+
+/// Which frames saw each feature. Frames are referred to by their position
+/// in the slice the index was built from; tracks may skip frames where the
+/// feature was lost and found again.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureTracks {
+    tracks: BTreeMap<u64, Vec<usize>>,
+}
+
+impl FeatureTracks {
+    pub fn new(frames: &[SlamFrame]) -> Self {
+        let mut tracks: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+        for (position, frame) in frames.iter().enumerate() {
+            for &id in frame.features.keys() {
+                tracks.entry(id).or_default().push(position);
+            }
+        }
+        Self { tracks }
+    }
+
+    /// Number of distinct features
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Frames that saw `id`, in order
+    pub fn track(&self, id: u64) -> Option<&[usize]> {
+        self.tracks.get(&id).map(Vec::as_slice)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[usize])> {
+        self.tracks.iter().map(|(&id, frames)| (id, frames.as_slice()))
+    }
+
+    /// Features seen in at least `min_frames` frames
+    pub fn long_tracks(&self, min_frames: usize) -> impl Iterator<Item = (u64, &[usize])> {
+        self.iter().filter(move |(_, frames)| frames.len() >= min_frames)
+    }
+
+    /// Features seen in both frames `a` and `b`
+    pub fn covisible(&self, a: usize, b: usize) -> Vec<u64> {
+        self.iter()
+            .filter(|(_, frames)| {
+                frames.binary_search(&a).is_ok() && frames.binary_search(&b).is_ok()
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_bundled_frames() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/slam");
+        let frames = load_slam_frames(&directory).unwrap();
+        assert_eq!(frames.len(), 301);
+        assert!(frames.windows(2).all(|w| w[0].index + 1 == w[1].index && w[0].stamp < w[1].stamp));
+
+        let frame = &frames[88];
+        assert_eq!(
+            (frame.index, frame.stamp, frame.features.len(), frame.imu.len()),
+            (88, 1658467021.286462, 173, 14)
+        );
+        let pixel = na::Vector2::new(174.8923, 49.9203);
+        let expected = FeatureObservation { pixel, depth: 42.41722106933594 };
+        assert_eq!(frame.features[&433], expected);
+        assert!(frames[300].imu.is_empty());
+
+        let mut bytes = Vec::new();
+        write_slam_frame(&mut bytes, frame).unwrap();
+        let mut copy = read_slam_frame(bytes.as_slice()).unwrap();
+        copy.index = frame.index;
+        assert_eq!(&copy, frame);
+
+        let tracks = FeatureTracks::new(&frames);
+        assert_eq!(tracks.len(), 8162);
+        assert!(tracks.track(433).unwrap().contains(&88));
+        let covisible = tracks.covisible(88, 89);
+        assert!(!covisible.is_empty());
+        let (a, b) = (&frames[88].features, &frames[89].features);
+        assert!(covisible.iter().all(|id| a.contains_key(id) && b.contains_key(id)));
+        assert!(tracks.long_tracks(20).all(|(_, frames)| frames.len() >= 20));
+    }
+
+    #[test]
+    fn test_rejects_malformed_frames() {
+        let short = "stamp: 1.0\npoints:\n  num: 1\n  data:\n    - 3\n    - 1.0\n    - 2.0\n";
+        assert!(matches!(
+            read_slam_frame(short.as_bytes()),
+            Err(SlamIoError::CountMismatch { declared: 4, found: 3, .. })
+        ));
+        assert!(matches!(
+            read_slam_frame("stamp: 1.0\npoints:\n  num: 0\n  data:\n    - x\n".as_bytes()),
+            Err(SlamIoError::InvalidNumber { line: 5, .. })
+        ));
+        assert!(matches!(
+            read_slam_frame("points:\n  num: 0\n  data: []\n".as_bytes()),
+            Err(SlamIoError::MissingStamp)
+        ));
+        let text = "stamp: 2.5\npoints:\n  num: 0\n  data: []\nimu:\n  num: 0\n";
+        let empty = read_slam_frame(text.as_bytes()).unwrap();
+        assert_eq!((empty.stamp, empty.features.len(), empty.imu.len()), (2.5, 0, 0));
+    }
+}
@@ -0,0 +1,150 @@
+/// Natural log of the gamma function (Lanczos approximation, g = 7)
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Regularized lower incomplete gamma function P(a, x)
+pub fn regularized_gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x < a + 1.0 {
+        gamma_series(a, x)
+    } else {
+        1.0 - gamma_continued_fraction(a, x)
+    }
+}
+
+/// Series expansion of P(a, x), converges quickly for x < a + 1
+fn gamma_series(a: f64, x: f64) -> f64 {
+    let mut term = 1.0 / a;
+    let mut sum = term;
+    let mut n = a;
+    for _ in 0..500 {
+        n += 1.0;
+        term *= x / n;
+        sum += term;
+        if term.abs() < sum.abs() * 1e-15 {
+            break;
+        }
+    }
+    sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+}
+
+/// Continued fraction for Q(a, x) = 1 - P(a, x) (modified Lentz), for x >= a + 1
+fn gamma_continued_fraction(a: f64, x: f64) -> f64 {
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..500 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    (-x + a * x.ln() - ln_gamma(a)).exp() * h
+}
+
+/// Probability that a chi-square variable with `dof` degrees of freedom is below `x`
+pub fn chi_square_cdf(x: f64, dof: usize) -> f64 {
+    regularized_gamma_p(0.5 * dof as f64, 0.5 * x)
+}
+
+/// Value below which a chi-square variable with `dof` degrees of freedom falls
+/// with probability `p`, found by bisection on the CDF
+pub fn chi_square_quantile(p: f64, dof: usize) -> f64 {
+    if p <= 0.0 {
+        return 0.0;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut low = 0.0;
+    let mut high = dof.max(1) as f64;
+    while chi_square_cdf(high, dof) < p {
+        low = high;
+        high *= 2.0;
+    }
+    for _ in 0..200 {
+        let mid = 0.5 * (low + high);
+        if chi_square_cdf(mid, dof) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+        if high - low < 1e-12 * high.max(1.0) {
+            break;
+        }
+    }
+    0.5 * (low + high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chi_square_matches_tables() {
+        // (p, dof, quantile) from standard chi-square tables
+        let table = [
+            (0.95, 1, 3.841),
+            (0.99, 1, 6.635),
+            (0.95, 2, 5.991),
+            (0.95, 3, 7.815),
+            (0.99, 3, 11.345),
+            (0.95, 6, 12.592),
+            (0.999, 6, 22.458),
+        ];
+        for &(p, dof, expected) in &table {
+            let quantile = chi_square_quantile(p, dof);
+            assert!((quantile - expected).abs() < 1e-3, "dof {} p {}: {}", dof, p, quantile);
+            assert!((chi_square_cdf(quantile, dof) - p).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_ln_gamma() {
+        assert!(ln_gamma(1.0).abs() < 1e-12);
+        assert!((ln_gamma(5.0) - 24.0f64.ln()).abs() < 1e-12);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-12);
+    }
+}